@@ -0,0 +1,265 @@
+// api.rs
+//
+// The embedding API: assemble programs in-process and run them
+//
+// This is what the crate exports for autograders, web IDEs and other
+// programs that use risclet as a library. Assembly takes source text rather
+// than file names and returns every diagnostic instead of printing it. The
+// resulting ELF loads into a `Machine`, which runs one instruction per
+// `Machine::step`; `CheckABI` checks each step against the calling
+// convention, as `--check-abi` does.
+//
+//     let executable = assemble(&[("prog.s", text)], &Options::new())
+//         .expect("prog.s assembles");
+//     let mut machine = executable.machine()?;
+//     while !machine.step()?.terminate {}
+
+use std::fmt;
+
+use crate::assembler::assemble_collecting_warnings;
+use crate::config::{Config, Relax};
+use crate::error::{Result, RiscletError};
+use crate::execution::Machine;
+use crate::lint::{Lint, LintConfig, Warning};
+
+/// Settings for `assemble`, starting from the command line's defaults and
+/// changed with the `with_*` methods
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    text_start: u32,
+    relax: Relax,
+    keep_locals: bool,
+    warnings: LintConfig,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        let config = Config::assemble_default();
+        Options {
+            text_start: config.text_start,
+            relax: config.relax,
+            keep_locals: config.keep_locals,
+            warnings: config.warnings,
+        }
+    }
+
+    /// Where the text segment starts (`-t`)
+    pub fn with_text_start(mut self, address: u32) -> Self {
+        self.text_start = address;
+        self
+    }
+
+    /// Turn every relaxation on or off (`--relax`, `--no-relax`)
+    pub fn with_relaxation(mut self, enabled: bool) -> Self {
+        self.relax = Relax {
+            gp: Some(enabled),
+            pseudo: enabled,
+            compressed: enabled,
+            branches: enabled,
+        };
+        self
+    }
+
+    /// Whether `la` and friends may use the global pointer
+    /// (`--relax-gp`); it is used by default if the program sets gp up
+    pub fn with_gp_relaxation(mut self, enabled: bool) -> Self {
+        self.relax.gp = Some(enabled);
+        self
+    }
+
+    /// Whether `call` and `tail` may become a single `jal`
+    /// (`--relax-pseudo`)
+    pub fn with_pseudo_relaxation(mut self, enabled: bool) -> Self {
+        self.relax.pseudo = enabled;
+        self
+    }
+
+    /// Whether instructions may be compressed to RV32C
+    /// (`--relax-compressed`)
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.relax.compressed = enabled;
+        self
+    }
+
+    /// Whether out-of-range branches and jumps are expanded
+    /// (`--relax-branches`)
+    pub fn with_branch_relaxation(mut self, enabled: bool) -> Self {
+        self.relax.branches = enabled;
+        self
+    }
+
+    /// Whether `.L` labels go in the symbol table (`--keep-locals`)
+    pub fn with_keep_locals(mut self, enabled: bool) -> Self {
+        self.keep_locals = enabled;
+        self
+    }
+
+    /// Turn one warning on or off (`-W<id>`, `-Wno-<id>`)
+    pub fn with_warning(mut self, lint: Lint, enabled: bool) -> Self {
+        self.warnings.set(lint, enabled);
+        self
+    }
+
+    /// Turn every warning on or off (`-Wall`, `-w`)
+    pub fn with_warnings(mut self, enabled: bool) -> Self {
+        for lint in Lint::ALL {
+            self.warnings.set(lint, enabled);
+        }
+        self
+    }
+
+    fn config(&self) -> Config {
+        let mut config = Config::assemble_default();
+        config.text_start = self.text_start;
+        config.relax = self.relax;
+        config.keep_locals = self.keep_locals;
+        config.warnings = self.warnings.clone();
+        config
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options::new()
+    }
+}
+
+/// An assembled program
+#[derive(Debug, Clone)]
+pub struct Executable {
+    /// The ELF executable, as `risclet assemble` would write it
+    pub elf: Vec<u8>,
+    /// The warnings found while assembling it
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Executable {
+    /// A machine loaded with the program, ready to run from its entry point
+    pub fn machine(&self) -> Result<Machine> {
+        // risclet writes nothing the loader would skip
+        Machine::from_elf(&self.elf, &mut Vec::new())
+    }
+}
+
+/// Whether a diagnostic stopped assembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning found while assembling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The source file it is about, if it is about one
+    pub file: Option<String>,
+    /// The line it is about, counting from 1 (0 without a file)
+    pub line: usize,
+    /// The columns it points at, counting from 0 and ending before `.1`, or
+    /// `None` for the whole line
+    pub columns: Option<(usize, usize)>,
+    /// The warning's id, as used by `-W<id>`
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    /// One diagnostic for each error in `error`
+    fn from_error(error: RiscletError) -> Vec<Diagnostic> {
+        match error {
+            RiscletError::Multiple(errors) => {
+                errors.into_iter().flat_map(Diagnostic::from_error).collect()
+            }
+            RiscletError::Assembly { location, message } => vec![Diagnostic {
+                severity: Severity::Error,
+                message,
+                file: Some(location.file),
+                line: location.line,
+                columns: location.span.map(|span| (span.start, span.end)),
+                code: None,
+            }],
+            error => vec![Diagnostic {
+                severity: Severity::Error,
+                message: error.message(),
+                file: None,
+                line: 0,
+                columns: None,
+                code: None,
+            }],
+        }
+    }
+
+    fn from_warning(warning: Warning) -> Diagnostic {
+        let location = warning.location;
+        Diagnostic {
+            severity: Severity::Warning,
+            message: warning.message,
+            file: Some(location.file),
+            line: location.line,
+            columns: location.span.map(|span| (span.start, span.end)),
+            code: Some(warning.lint.id()),
+        }
+    }
+}
+
+/// `file:line:column: severity: message`, as compilers print them
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:{}:", file, self.line)?;
+            if let Some((start, _)) = self.columns {
+                write!(f, "{}:", start + 1)?;
+            }
+            write!(f, " ")?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.message)?;
+        if let Some(code) = self.code {
+            write!(f, " [-W{}]", code)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assemble and link source files, given as (file name, text) pairs, into
+/// an executable
+///
+/// Every error found is returned, not just the first; warnings come back
+/// with the executable.
+///
+/// ```
+/// use risclet::{Options, assemble};
+///
+/// let text = ".globl _start\n_start:\n li a0, 42\n li a7, 93\n ecall\n";
+/// let executable = assemble(&[("prog.s", text)], &Options::new())
+///     .expect("prog.s assembles");
+/// let mut machine = executable.machine()?;
+/// while !machine.step()?.terminate {}
+/// assert_eq!(machine.get_reg(10), 42);
+/// # Ok::<(), risclet::RiscletError>(())
+/// ```
+pub fn assemble(
+    sources: &[(&str, &str)],
+    options: &Options,
+) -> std::result::Result<Executable, Vec<Diagnostic>> {
+    let mut config = options.config();
+    let sources = sources
+        .iter()
+        .map(|&(name, text)| (name.to_string(), text.to_string()))
+        .collect();
+    let mut warnings = Vec::new();
+    match assemble_collecting_warnings(&mut config, sources, &mut warnings) {
+        Ok(elf) => Ok(Executable {
+            elf,
+            warnings: warnings
+                .into_iter()
+                .map(Diagnostic::from_warning)
+                .collect(),
+        }),
+        Err(error) => Err(Diagnostic::from_error(error)),
+    }
+}
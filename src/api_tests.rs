@@ -0,0 +1,167 @@
+// api_tests.rs
+//
+// Tests for the library's embedding API
+
+use crate::api::{Options, Severity, assemble};
+use crate::checkabi::CheckABI;
+use crate::lint::Lint;
+use crate::test_helpers::run_to_exit;
+
+const EXIT_42: &str = "
+.global _start
+_start:
+    li a0, 42
+    li a7, 93
+    ecall
+";
+
+#[test]
+fn test_assemble_and_step_to_exit() {
+    let executable =
+        assemble(&[("prog.s", EXIT_42)], &Options::new()).expect("assembles");
+    assert!(executable.warnings.is_empty());
+    let mut machine = executable.machine().expect("loads");
+    assert_eq!(run_to_exit(&mut machine), 42);
+}
+
+#[test]
+fn test_public_types_print_with_debug() {
+    let executable = assemble(&[("prog.s", EXIT_42)], &Options::new()).unwrap();
+    let mut machine = executable.machine().unwrap();
+    assert!(format!("{:?}", machine).starts_with("Machine"));
+    let effects = loop {
+        let effects = machine.step().unwrap();
+        if effects.terminate {
+            break effects;
+        }
+    };
+    assert_eq!(format!("{:?}", effects.syscall), "Some(Exit(42))");
+    assert!(format!("{:?}", effects).contains("Ecall"));
+}
+
+#[test]
+fn test_assemble_several_sources() {
+    let main = "
+.global _start
+_start:
+    call answer
+    li a7, 93
+    ecall
+";
+    let answer = "
+.global answer
+answer:
+    li a0, 7
+    ret
+";
+    let executable =
+        assemble(&[("main.s", main), ("answer.s", answer)], &Options::new())
+            .expect("assembles");
+    let mut machine = executable.machine().expect("loads");
+    assert_eq!(run_to_exit(&mut machine), 7);
+}
+
+#[test]
+fn test_errors_come_back_as_diagnostics() {
+    let source = "
+.global _start
+_start:
+    addi a0, a0, nowhere
+    frob a1
+";
+    let errors =
+        assemble(&[("bad.s", source)], &Options::new()).expect_err("fails");
+    assert!(!errors.is_empty());
+    for error in &errors {
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(error.file.as_deref(), Some("bad.s"));
+    }
+    assert!(errors.iter().any(|e| e.line == 5), "{:?}", errors);
+    let shown = errors[0].to_string();
+    assert!(shown.starts_with("bad.s:"), "{}", shown);
+    assert!(shown.contains("error: "), "{}", shown);
+}
+
+#[test]
+fn test_warnings_come_back_with_the_executable() {
+    let source = "
+.global _start
+_start:
+    li x0, 5
+    li a0, 0
+    li a7, 93
+    ecall
+";
+    let options = Options::new().with_warning(Lint::WriteX0, true);
+    let executable = assemble(&[("warn.s", source)], &options).unwrap();
+    let warning = executable
+        .warnings
+        .iter()
+        .find(|w| w.code == Some("write-x0"))
+        .expect("write-x0 warning");
+    assert_eq!(warning.severity, Severity::Warning);
+    assert_eq!(warning.line, 4);
+    assert!(warning.to_string().ends_with("[-Wwrite-x0]"));
+
+    let quiet = Options::new().with_warnings(false);
+    let executable = assemble(&[("warn.s", source)], &quiet).unwrap();
+    assert!(executable.warnings.is_empty());
+}
+
+#[test]
+fn test_options_change_the_output() {
+    // Every instruction here has a compressed form
+    let source = "
+.global _start
+_start:
+    li a0, 4
+    mv a1, a0
+    add a0, a0, a1
+    ret
+";
+    let compressed =
+        assemble(&[("prog.s", source)], &Options::new().with_compression(true))
+            .unwrap()
+            .elf;
+    let uncompressed =
+        assemble(&[("prog.s", source)], &Options::new()).unwrap().elf;
+    assert!(compressed.len() < uncompressed.len());
+
+    let options = Options::new().with_text_start(0x20000);
+    let executable = assemble(&[("prog.s", EXIT_42)], &options).unwrap();
+    let machine = executable.machine().unwrap();
+    assert_eq!(machine.pc() & !0xfff, 0x20000);
+}
+
+#[test]
+fn test_check_abi_while_stepping() {
+    let source = "
+.global _start
+_start:
+    call f
+    li a7, 93
+    ecall
+f:
+    mv a0, s1
+    ret
+";
+    let executable = assemble(&[("abi.s", source)], &Options::new()).unwrap();
+    let mut machine = executable.machine().unwrap();
+    let mut abi = CheckABI::for_machine(&machine);
+    let mut violation = None;
+    for _ in 0..100 {
+        let mut effects = machine.step().unwrap();
+        if effects.terminate {
+            break;
+        }
+        let instruction = effects.instruction.clone();
+        if let Err(msg) =
+            abi.check_instruction(&machine, &instruction, &mut effects)
+        {
+            violation = Some(msg);
+            break;
+        }
+    }
+    let violation = violation.expect("reading s1 is reported");
+    assert!(violation.contains("s1"), "{}", violation);
+}
@@ -4,30 +4,55 @@
 
 use crate::ast::{
     Expression, Instruction, Line, LineContent, Location, PseudoOp, Register,
-    Source, SourceFile,
+    Source, SourceFile, Span, Token,
 };
-use crate::config::Config;
+use crate::config::{Config, object_file_name};
 use crate::dump::{dump_ast, dump_code, dump_elf, dump_symbols, dump_values};
 use crate::elf_builder::ElfBuilder;
 use crate::encoder::encode;
 use crate::error::{Result, RiscletError};
 use crate::expressions::{SymbolValues, eval_symbol_values};
-use crate::layout::{Layout, LineSizes, approximate_line_sizes};
-use crate::parser::parse;
+use crate::image::write_images;
+use crate::layout::{
+    Layout, LineRelax, LineSizes, Placement, approximate_line_sizes,
+};
+use crate::lint::{Warning, lint, print_warnings};
+use crate::listing::{ListingInput, write_listing};
+use crate::map::{MapInput, write_map};
+use crate::memory_map::SegmentAddresses;
+use crate::object_builder::{
+    ObjectBuilder, RELOCATED_LINE_RELAX, common_symbols, extract_common_blocks,
+    find_relocation_sites, reserve_alignment_padding,
+};
+use crate::object_loader::load_object;
+use crate::parser::parse_with_spans;
 use crate::symbols::{
     BUILTIN_FILE_NAME, SPECIAL_GLOBAL_POINTER, SymbolLinks,
-    create_builtin_symbols_file, link_symbols,
+    create_builtin_symbols_file, create_external_symbols_file,
+    find_undefined_symbols, link_symbols_recovering,
 };
-use crate::tokenizer::tokenize;
+use crate::tokenizer::{tokenize_with_comment, tokenize_with_spans};
 use std::fs::File;
 use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 
 /// Assemble source files and write ELF to disk
+///
+/// With `-c`, each source file is written to its own relocatable object
+/// instead of linking everything into one executable.
 pub fn assemble_and_save(config: &mut Config) -> Result<()> {
+    if config.object_only {
+        return assemble_objects_and_save(config);
+    }
+
     // Generate ELF bytes (handles all phases and dump checkpoints)
     let elf_bytes = assemble_files(config)?;
 
+    // Memory images have already been written in place of the ELF
+    if config.image_format.is_some() {
+        return Ok(());
+    }
+
     // Write to output file
     let mut file = File::create(&config.output_file)
         .map_err(|e| RiscletError::io(e.to_string()))?;
@@ -45,25 +70,208 @@ pub fn assemble_and_save(config: &mut Config) -> Result<()> {
     Ok(())
 }
 
+/// Assemble each source file into a relocatable object on disk
+///
+/// A single input is written to the output file (which defaults to the
+/// input name with a `.o` extension); several inputs are each written to
+/// `<name>.o`.
+fn assemble_objects_and_save(config: &mut Config) -> Result<()> {
+    let input_files = config.input_files.clone();
+    for file_path in &input_files {
+        let content = read_source_file(file_path)?;
+        let object_bytes = assemble_object(config, file_path, &content)?;
+
+        let output_file = if input_files.len() == 1 {
+            config.output_file.clone()
+        } else {
+            object_file_name(file_path)
+        };
+        std::fs::write(&output_file, object_bytes).map_err(|e| {
+            RiscletError::io(format!(
+                "could not write file '{}': {}",
+                output_file, e
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Assemble one source file into a relocatable ELF object
+///
+/// Symbols used but not defined in the file are left undefined, and every
+/// operand that refers to an address gets a relocation. `risclet link`
+/// resolves them.
+pub fn assemble_object(
+    config: &mut Config,
+    file_name: &str,
+    source_code: &str,
+) -> Result<Vec<u8>> {
+    let mut warnings = Vec::new();
+    let result = assemble_object_collecting_warnings(
+        config,
+        file_name,
+        source_code,
+        &mut warnings,
+    );
+    print_warnings(&warnings);
+    result
+}
+
+/// Assemble an object like `assemble_object`, but add the warnings to
+/// `warnings` instead of printing them
+pub fn assemble_object_collecting_warnings(
+    config: &mut Config,
+    file_name: &str,
+    source_code: &str,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<u8>> {
+    let (mut source_file, mut errors) =
+        parse_source_recovering(file_name, source_code);
+    let common_names = extract_common_blocks(&mut source_file);
+
+    // Undefined symbols become weak declarations in a synthetic file, so
+    // they link and evaluate (to 0) like undefined weak symbols
+    let mut source = Source { files: vec![source_file] };
+    let externals = find_undefined_symbols(&source);
+    source.files.push(create_external_symbols_file(&externals));
+    let (symbol_links, link_errors, _) = link_symbols_recovering(&source);
+    errors.extend(link_errors);
+    let (mut line_relax, option_errors) =
+        LineRelax::compute(&source, config.relax);
+    errors.extend(option_errors);
+    if !errors.is_empty() {
+        return Err(RiscletError::from_errors(errors));
+    }
+    warnings.extend(lint(&source, &symbol_links, &config.warnings));
+
+    if config.verbose {
+        print_input_statistics(&source, &symbol_links);
+    }
+
+    // Find the relocated operands using a rough layout; only the types of
+    // the values matter here. Relocated lines keep their full-size encoding.
+    let initial_line_sizes = approximate_line_sizes(&source);
+    let rough_layout = Layout::from_sizes(
+        &source,
+        &initial_line_sizes,
+        &line_relax,
+        &Placement::Relocatable,
+    );
+    let rough_values =
+        eval_symbol_values(&source, &symbol_links, &rough_layout)?;
+    let mut sites = find_relocation_sites(
+        &source,
+        &symbol_links,
+        &rough_values,
+        &rough_layout,
+        &line_relax,
+    )?;
+    for &pointer in sites.keys() {
+        line_relax.set(pointer, RELOCATED_LINE_RELAX);
+    }
+    reserve_alignment_padding(
+        &mut source,
+        &symbol_links,
+        &rough_values,
+        &rough_layout,
+        &line_relax,
+        &mut sites,
+    )?;
+
+    let RelaxedAssembly { layout, symbol_values, text_bytes, data_bytes } =
+        relaxation_loop_with(
+            config,
+            &source,
+            &symbol_links,
+            initial_line_sizes,
+            &line_relax,
+            &Placement::Relocatable,
+        )?;
+
+    let commons =
+        common_symbols(&source, &symbol_links, &symbol_values, &common_names)?;
+    ObjectBuilder::new(
+        &source,
+        &symbol_links,
+        &symbol_values,
+        &layout,
+        text_bytes,
+        data_bytes,
+        config.keep_locals,
+    )
+    .build(&sites, &commons)
+}
+
 /// Core assembler: takes unparsed source strings and produces ELF bytes
 ///
 /// Takes a vector of (filename, source code) pairs and assembles them into an ELF binary.
-/// This is the main assembly pipeline - all assembly operations go through this function.
-/// Handles all phases: parsing, symbol linking, relaxation, and ELF generation.
+/// This is the main assembly pipeline: it parses the sources, then hands them to
+/// the later phases for symbol linking, relaxation, and ELF generation.
+///
+/// Errors do not stop assembly right away: bad lines are skipped and
+/// undefined symbols are poisoned, so one run reports every error it can
+/// find (see `RiscletError::Multiple`).
+#[cfg(test)]
 pub fn assemble(
     config: &mut Config,
     sources: Vec<(String, String)>,
+) -> Result<Vec<u8>> {
+    let mut warnings = Vec::new();
+    let result = assemble_collecting_warnings(config, sources, &mut warnings);
+    print_warnings(&warnings);
+    result
+}
+
+/// Assemble like `assemble`, but add the warnings to `warnings` instead of
+/// printing them
+pub fn assemble_collecting_warnings(
+    config: &mut Config,
+    sources: Vec<(String, String)>,
+    warnings: &mut Vec<Warning>,
 ) -> Result<Vec<u8>> {
     // ========================================================================
     // Phase 1: Parse source code from strings into AST
     // ========================================================================
-    let mut source = Source { files: Vec::new() };
-
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
     for (file_name, source_code) in sources {
-        let source_file = parse_source_from_string(&file_name, &source_code)?;
-        source.files.push(source_file);
+        let (file, file_errors) =
+            parse_source_recovering(&file_name, &source_code);
+        files.push(file);
+        errors.extend(file_errors);
     }
 
+    assemble_parsed_with_errors(config, files, errors, warnings)
+}
+
+/// Assemble parsed source files (and linked objects) into ELF bytes
+///
+/// This runs every phase after parsing; see `assemble`.
+#[cfg(test)]
+pub fn assemble_parsed(
+    config: &mut Config,
+    files: Vec<SourceFile>,
+) -> Result<Vec<u8>> {
+    let mut warnings = Vec::new();
+    let result =
+        assemble_parsed_with_errors(config, files, Vec::new(), &mut warnings);
+    print_warnings(&warnings);
+    result
+}
+
+/// Assemble parsed source files, given the errors found while parsing them
+///
+/// If there are any errors, the later phases still run to find more, and
+/// then all of them are returned together. Warnings are only looked for
+/// once there are no errors, and are added to `warnings`.
+fn assemble_parsed_with_errors(
+    config: &mut Config,
+    files: Vec<SourceFile>,
+    parse_errors: Vec<RiscletError>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<u8>> {
+    let mut source = Source { files };
+
     // Add builtin symbols file (provides __global_pointer$ definition)
     source.files.push(create_builtin_symbols_file());
 
@@ -79,7 +287,33 @@ pub fn assemble(
     // ========================================================================
     // Phase 2: Link symbols (connect symbol uses to their definitions)
     // ========================================================================
-    let symbol_links = &link_symbols(&source)?;
+    let (mut symbol_links, link_errors, undefined) =
+        link_symbols_recovering(&source);
+    let mut errors = parse_errors;
+    errors.extend(link_errors);
+
+    // Auto-detect GP initialization and resolve relaxation setting
+    apply_gp_auto_detection(config, &source);
+
+    if !errors.is_empty() {
+        // Poison undefined symbols by declaring them weak (so they evaluate
+        // to 0), then encode anyway to collect errors from the other lines
+        if !undefined.is_empty() {
+            source.files.push(create_external_symbols_file(&undefined));
+            symbol_links = link_symbols_recovering(&source).0;
+        }
+        if let Err(e) = relaxation_loop(
+            config,
+            &source,
+            &symbol_links,
+            approximate_line_sizes(&source),
+        ) {
+            errors.push(e);
+        }
+        return Err(RiscletError::from_errors(errors));
+    }
+    let symbol_links = &symbol_links;
+    warnings.extend(lint(&source, symbol_links, &config.warnings));
 
     // Checkpoint: dump symbol linking if requested
     if should_dump_phase(config, Phase::SymbolLinking) {
@@ -90,9 +324,6 @@ pub fn assemble(
         println!(); // Separator between phase dumps
     }
 
-    // Auto-detect GP initialization and resolve relaxation setting
-    apply_gp_auto_detection(config, &source);
-
     // ========================================================================
     // Phase 3: Relaxation - iteratively compute offsets and encode until stable
     // ========================================================================
@@ -106,6 +337,35 @@ pub fn assemble(
     let RelaxedAssembly { layout, symbol_values, text_bytes, data_bytes } =
         relaxation_loop(config, &source, symbol_links, initial_line_sizes)?;
 
+    if let Some(listing_file) = &config.listing {
+        write_listing(
+            listing_file,
+            &ListingInput {
+                source: &source,
+                symbol_links,
+                symbol_values: &symbol_values,
+                layout: &layout,
+                text_bytes: &text_bytes,
+                data_bytes: &data_bytes,
+            },
+        )?;
+    }
+
+    if let Some(map_file) = &config.map_file {
+        write_map(
+            map_file,
+            &MapInput {
+                source: &source,
+                symbol_links,
+                symbol_values: &symbol_values,
+                layout: &layout,
+                text_bytes: &text_bytes,
+                output_file: &config.output_file,
+                keep_locals: config.keep_locals,
+            },
+        )?;
+    }
+
     // Checkpoint: after relaxation, check if we should exit before ELF generation
     if should_dump_phase(config, Phase::Relaxation)
         && is_terminal_phase(config, Phase::Relaxation)
@@ -120,7 +380,13 @@ pub fn assemble(
     let mut elf_builder = ElfBuilder::new(&layout, text_bytes, data_bytes);
 
     // Build symbol table
-    elf_builder.build_symbol_table(&source, symbol_links, &symbol_values)?;
+    elf_builder.build_symbol_table(
+        &source,
+        symbol_links,
+        &symbol_values,
+        config.keep_locals,
+    )?;
+    elf_builder.build_debug_info(&source, symbol_links);
 
     // Checkpoint: dump ELF if requested
     if should_dump_phase(config, Phase::Elf) {
@@ -148,6 +414,15 @@ pub fn assemble(
         }
     }?;
 
+    if let Some(format) = config.image_format {
+        write_images(
+            config,
+            format,
+            &elf_builder.image_segments(),
+            entry_point,
+        )?;
+    }
+
     elf_builder.build(entry_point)
 }
 
@@ -158,20 +433,47 @@ fn dump_mode_no_elf_generated<T>() -> Result<T> {
 
 /// Read source files from config and assemble to ELF bytes
 pub fn assemble_files(config: &mut Config) -> Result<Vec<u8>> {
-    // Read all input files into (filename, content) pairs
-    let mut sources = Vec::new();
+    let mut warnings = Vec::new();
+    let result = assemble_files_collecting_warnings(config, &mut warnings);
+    print_warnings(&warnings);
+    result
+}
+
+/// Assemble like `assemble_files`, but add the warnings to `warnings`
+/// instead of printing them
+pub fn assemble_files_collecting_warnings(
+    config: &mut Config,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<u8>> {
+    // Parse source files and turn relocatable objects back into source
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
     for file_path in &config.input_files {
-        let content = std::fs::read_to_string(file_path).map_err(|e| {
-            RiscletError::io(format!(
-                "could not read file '{}': {}",
-                file_path, e
-            ))
-        })?;
-        sources.push((file_path.clone(), content));
+        if file_path.ends_with(".o") {
+            let bytes = std::fs::read(file_path).map_err(|e| {
+                RiscletError::io(format!(
+                    "could not read file '{}': {}",
+                    file_path, e
+                ))
+            })?;
+            files.push(load_object(file_path, &bytes)?);
+        } else {
+            let content = read_source_file(file_path)?;
+            let (file, file_errors) =
+                parse_source_recovering(file_path, &content);
+            files.push(file);
+            errors.extend(file_errors);
+        }
     }
 
     // Call core assembler
-    assemble(config, sources)
+    assemble_parsed_with_errors(config, files, errors, warnings)
+}
+
+fn read_source_file(file_path: &str) -> Result<String> {
+    std::fs::read_to_string(file_path).map_err(|e| {
+        RiscletError::io(format!("could not read file '{}': {}", file_path, e))
+    })
 }
 
 /// The stable products of the relaxation phase.
@@ -198,6 +500,61 @@ pub fn relaxation_loop(
     source: &Source,
     symbol_links: &SymbolLinks,
     initial_line_sizes: LineSizes,
+) -> Result<RelaxedAssembly> {
+    // Errors in the .option directives are reported along with any from
+    // encoding
+    let (line_relax, option_errors) = LineRelax::compute(source, config.relax);
+    let placement = match &config.memory_map {
+        Some(memory_map) => Placement::Regions(memory_map.clone()),
+        None if config.image_format.is_some() => {
+            Placement::Image(config.text_start)
+        }
+        None => Placement::Executable(config.text_start),
+    };
+    let relaxed = relaxation_loop_with(
+        config,
+        source,
+        symbol_links,
+        initial_line_sizes,
+        &line_relax,
+        &placement,
+    );
+    if !option_errors.is_empty() {
+        let mut errors = option_errors;
+        errors.extend(relaxed.err());
+        return Err(RiscletError::from_errors(errors));
+    }
+    let relaxed = relaxed?;
+
+    // Only the final sizes have to fit in their memory regions
+    if let Some(memory_map) = &config.memory_map {
+        let layout = &relaxed.layout;
+        let addresses = SegmentAddresses {
+            text_start: layout.text_start,
+            data_start: layout.data_start,
+            data_load_start: layout.data_load_start,
+            bss_start: layout.bss_start,
+        };
+        memory_map
+            .check(
+                &addresses,
+                layout.text_size,
+                layout.data_size,
+                layout.bss_size,
+            )
+            .map_err(RiscletError::elf)?;
+    }
+    Ok(relaxed)
+}
+
+/// The relaxation loop with explicit per-line settings and segment placement.
+pub fn relaxation_loop_with(
+    config: &Config,
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    initial_line_sizes: LineSizes,
+    line_relax: &LineRelax,
+    placement: &Placement,
 ) -> Result<RelaxedAssembly> {
     const MAX_ITERATIONS: usize = 10;
     let mut line_sizes = initial_line_sizes;
@@ -213,7 +570,7 @@ pub fn relaxation_loop(
 
         // Step 1: Calculate a complete layout from the current size estimates
         let mut layout =
-            Layout::from_sizes(source, &line_sizes, config.text_start);
+            Layout::from_sizes(source, &line_sizes, line_relax, placement);
 
         if config.verbose {
             eprintln!(
@@ -234,8 +591,7 @@ pub fn relaxation_loop(
         }
 
         // Step 3: Encode everything and produce the next size estimates
-        let encoded =
-            encode(config, source, symbol_links, &symbol_values, &layout)?;
+        let encoded = encode(source, symbol_links, &symbol_values, &layout)?;
         let any_changed = encoded.line_sizes != line_sizes;
         layout.set_line_sizes(&encoded.line_sizes);
 
@@ -329,26 +685,80 @@ fn is_terminal_phase(config: &Config, phase: Phase) -> bool {
 }
 
 /// Parse source code from a string (for in-memory assembly in tests)
-fn parse_source_from_string(
+#[cfg(test)]
+pub fn parse_source_from_string(
     file_name: &str,
     source_code: &str,
 ) -> Result<SourceFile> {
+    let (source_file, errors) = parse_source_recovering(file_name, source_code);
+    if errors.is_empty() {
+        Ok(source_file)
+    } else {
+        Err(RiscletError::from_errors(errors))
+    }
+}
+
+/// Parse source code, skipping lines that do not parse
+///
+/// Returns the lines that parsed and an error for each line that did not.
+/// A label at the start of a bad line is still defined, so its uses are not
+/// reported as undefined too.
+pub fn parse_source_recovering(
+    file_name: &str,
+    source_code: &str,
+) -> (SourceFile, Vec<RiscletError>) {
     let mut lines: Vec<Line> = Vec::new();
+    let mut errors = Vec::new();
+    let mut warning_flags = Vec::new();
 
     for (line_num, line_text) in source_code.lines().enumerate() {
         if line_text.trim().is_empty() {
             continue;
         }
 
-        let location =
-            Location { file: file_name.to_string(), line: line_num + 1 };
+        let location = Location::new(file_name, line_num + 1);
 
-        let tokens = tokenize(line_text)
-            .map_err(|e| RiscletError::from_context(e, location.clone()))?;
+        let (tokens, spans) = match tokenize_with_comment(line_text) {
+            Ok((tokens, spans, comment)) => {
+                warning_flags.extend(
+                    comment
+                        .iter()
+                        .flat_map(|comment| comment_warning_flags(comment)),
+                );
+                (tokens, spans)
+            }
+            Err((e, span)) => {
+                errors.push(RiscletError::from_context(
+                    e,
+                    location.with_span(span),
+                ));
+                let prefix: String =
+                    line_text.chars().take(span.start).collect();
+                if let Ok((tokens, spans)) = tokenize_with_spans(&prefix) {
+                    lines.extend(salvage_label(
+                        &tokens,
+                        &spans,
+                        file_name,
+                        line_num + 1,
+                    ));
+                }
+                continue;
+            }
+        };
 
         if !tokens.is_empty() {
-            let parsed_lines =
-                parse(&tokens, file_name.to_string(), line_num + 1)?;
+            let parsed_lines = match parse_with_spans(
+                &tokens,
+                &spans,
+                file_name.to_string(),
+                line_num + 1,
+            ) {
+                Ok(parsed_lines) => parsed_lines,
+                Err(e) => {
+                    errors.push(e);
+                    salvage_label(&tokens, &spans, file_name, line_num + 1)
+                }
+            };
 
             for parsed_line in parsed_lines {
                 // Segment and size will be set in the layout phase
@@ -357,7 +767,39 @@ fn parse_source_from_string(
         }
     }
 
-    Ok(SourceFile { file: file_name.to_string(), lines })
+    (SourceFile { file: file_name.to_string(), lines, warning_flags }, errors)
+}
+
+/// The warning flags in a `# risclet: -Wno-<id> ...` comment
+fn comment_warning_flags(comment: &str) -> Vec<String> {
+    comment
+        .trim_start_matches('#')
+        .trim_start()
+        .strip_prefix("risclet:")
+        .map_or_else(Vec::new, |flags| {
+            flags.split_whitespace().map(str::to_string).collect()
+        })
+}
+
+/// The label that starts a line that failed to parse, if there is one
+fn salvage_label(
+    tokens: &[Token],
+    spans: &[Span],
+    file_name: &str,
+    line: usize,
+) -> Vec<Line> {
+    match tokens {
+        [Token::Identifier(_) | Token::Integer(_), Token::Colon, _, ..] => {
+            parse_with_spans(
+                &tokens[..2],
+                &spans[..2],
+                file_name.to_string(),
+                line,
+            )
+            .unwrap_or_default()
+        }
+        _ => Vec::new(),
+    }
 }
 
 /// Print input statistics during verbose assembly
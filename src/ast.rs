@@ -19,8 +19,9 @@ use std::fmt;
 //    A single source line containing both a label and an instruction/directive will
 //    produce two `Line` nodes: one for the label and one for the instruction/directive.
 //    Some pseudo-instructions are desugared directly into multiple instructions as well.
-// 5. If any error is detected during tokenization or parsing, the process will abort
-//    immediately and return the error along with its `Location`.
+// 5. If any error is detected during tokenization or parsing, the line is skipped and
+//    the error is recorded along with its `Location`, including the column span of the
+//    offending token when it is known.
 // 6. The final output is a `Vec<Line>`, representing the complete program AST.
 //
 
@@ -28,10 +29,48 @@ use std::fmt;
 /// The parser will attach this to every `Line` to provide context for errors.
 ///
 /// **Grammar Rule:** N/A (this is a data structure for parser context)
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Location {
     pub file: String,
     pub line: usize,
+    /// The columns an error refers to; `None` means the whole line
+    pub span: Option<Span>,
+}
+
+impl Location {
+    pub fn new(file: &str, line: usize) -> Self {
+        Location { file: file.to_string(), line, span: None }
+    }
+
+    /// The same line, narrowed to the given columns
+    pub fn with_span(&self, span: Span) -> Self {
+        Location { span: Some(span), ..self.clone() }
+    }
+
+    /// Checks if two locations are on the same source line
+    pub fn same_line(&self, other: &Location) -> bool {
+        self.file == other.file && self.line == other.line
+    }
+}
+
+/// A range of columns within a source line
+///
+/// Columns count characters from 0, and `end` is exclusive. Tokens and the
+/// parts of each `Line` carry spans so diagnostics can point at them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The smallest span covering both spans
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
 }
 
 /// An enum representing the three segments in the assembler output.
@@ -47,6 +86,9 @@ pub enum Segment {
 pub struct SourceFile {
     pub file: String,
     pub lines: Vec<Line>,
+    /// Warning flags from `# risclet: -Wno-<id>` comments, which apply to
+    /// this file only
+    pub warning_flags: Vec<String>,
 }
 
 /// The top-level structure containing all source files.
@@ -120,6 +162,11 @@ impl Register {
 pub enum DirectiveOp {
     Global,
     Equ,
+    Equiv,
+    Weak,
+    Local,
+    Comm,
+    Lcomm,
     Text,
     Data,
     Bss,
@@ -130,6 +177,30 @@ pub enum DirectiveOp {
     TwoByte,
     FourByte,
     Balign,
+    Option,
+    Type,
+    Size,
+}
+
+/// The argument of an `.option` directive.
+///
+/// `push` and `pop` save and restore the current settings; the others adjust
+/// the relaxation settings for the lines that follow.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum OptionArg {
+    Push,
+    Pop,
+    Rvc,
+    NoRvc,
+    Relax,
+    NoRelax,
+}
+
+/// The kind of symbol named by a `.type` directive.
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum SymbolType {
+    Function,
+    Object,
 }
 
 /// An enum for all supported operators.
@@ -148,6 +219,17 @@ pub enum OperatorOp {
     BitwiseAnd,
     BitwiseXor,
     BitwiseNot,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+    LogicalNot,
+    /// The `?` of a conditional `c ? a : b` (the `:` is a `Token::Colon`)
+    Question,
 }
 
 /// Represents a single token produced by the tokenizer.
@@ -205,6 +287,61 @@ pub struct Line {
     pub location: Location,
     /// The content of the line, which can be an instruction or a directive.
     pub content: LineContent,
+    /// Where the parts of the content appear in the source line.
+    pub spans: LineSpans,
+}
+
+/// Column spans of the parts of a `Line`, for pointing diagnostics at them
+///
+/// Lines that do not come from source text (builtin symbols, loaded
+/// objects) have no spans.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineSpans {
+    /// The label, mnemonic or directive name
+    pub head: Option<Span>,
+    /// Each comma-separated operand, in order
+    pub operands: Vec<Span>,
+    /// Each symbol named in the operands, in order (numeric label references
+    /// are named as in `extract_references_from_line`, e.g. "1f")
+    pub symbols: Vec<(String, Span)>,
+    /// Each register named in the operands, in order, with the index of
+    /// the operand it is in
+    pub registers: Vec<(Register, usize)>,
+}
+
+impl Line {
+    /// The location of the label, mnemonic or directive name
+    pub fn head_location(&self) -> Location {
+        match self.spans.head {
+            Some(span) => self.location.with_span(span),
+            None => self.location.clone(),
+        }
+    }
+
+    /// The location of the operand with the given index
+    pub fn operand_location(&self, index: usize) -> Location {
+        match self.spans.operands.get(index) {
+            Some(&span) => self.location.with_span(span),
+            None => self.location.clone(),
+        }
+    }
+
+    /// The location of the last operand, which holds the immediate, offset
+    /// or target of every instruction that has one
+    pub fn last_operand_location(&self) -> Location {
+        match self.spans.operands.last() {
+            Some(&span) => self.location.with_span(span),
+            None => self.location.clone(),
+        }
+    }
+
+    /// The location of the first use of a symbol on this line
+    pub fn symbol_location(&self, symbol: &str) -> Location {
+        match self.spans.symbols.iter().find(|(name, _)| name == symbol) {
+            Some(&(_, span)) => self.location.with_span(span),
+            None => self.location.clone(),
+        }
+    }
 }
 
 /// The content of a line, which is either a label, an instruction, or a directive.
@@ -367,6 +504,7 @@ pub enum Instruction {
 ///
 /// **Pseudo-ops and Desugaring:**
 /// - `neg rd, rs` desugars to `sub rd, x0, rs`.
+/// - `sgt rd, rs1, rs2` desugars to `slt rd, rs2, rs1` (and `sgtu` to `sltu`).
 ///
 /// **Variants:**
 /// - `M` extension variants like `mul`, `div`, `rem`: these are part of the optional M extension.
@@ -403,10 +541,10 @@ pub enum RTypeOp {
 /// - `mv rs, rt` desugars to `addi rs, rt, 0`.
 /// - `nop` desugars to `addi x0, x0, 0`.
 /// - `not rs, rt` desugars to `xori rs, rt, -1`.
-/// - `jr rs` desugars to `jalr x0, rs, 0`.
+/// - `jr rs`, `jr rs, offset` and `jr offset(rs)` desugar to `jalr x0, rs, offset`.
+/// - `jalr rs` and `jalr offset(rs)` desugar to `jalr ra, rs, offset`.
 /// - `ret` desugars to `jalr x0, ra, 0`.
 /// - `zext.b rd, rs` desugars to `andi rd, rs, 0xff`.
-/// - `zext.h rd, rs` desugars to `andi rd, rs, 0xffff`.
 /// - `seqz rd, rs` desugars to `sltiu rd, rs, 1`.
 /// - `snez rd, rs` desugars to `sltu rd, x0, rs`.
 /// - `sltz rd, rs` desugars to `slt rd, rs, x0`.
@@ -532,6 +670,13 @@ pub enum LoadStoreOp {
     Sw,
 }
 
+impl LoadStoreOp {
+    /// Whether the operation writes memory rather than reading it
+    pub fn is_store(self) -> bool {
+        matches!(self, LoadStoreOp::Sb | LoadStoreOp::Sh | LoadStoreOp::Sw)
+    }
+}
+
 /// Atomic instruction operations (A extension).
 ///
 /// **Grammar Rule and Example:**
@@ -601,15 +746,55 @@ pub enum PseudoOp {
     /// the size of the immediate.
     Li(Register, Box<Expression>),
     /// `la rd, symbol`: Generic load address. Desugars to `addi` using `gp` or `auipc` and `addi`.
+    /// `lla` and `lga` are the same: executables are linked statically, so
+    /// there is no global offset table for `lga` to load from.
     La(Register, Box<Expression>),
     /// `l(b|h|w|d|bu|hu|wu) rd, expression`: Desugars to `auipc` and the corresponding load instruction.
     LoadGlobal(LoadStoreOp, Register, Box<Expression>),
     /// `s(b|h|w|d) rs, expression, t0`: Desugars to `auipc` and the corresponding store instruction.  Requires a temporary register.
     StoreGlobal(LoadStoreOp, Register, Box<Expression>, Register),
-    /// `call expression`: Desugars to `auipc` and `jalr`.
-    Call(Box<Expression>),
-    /// `tail expression`: Desugars to `auipc` and `jalr x0, ...`.
-    Tail(Box<Expression>),
+    /// `call [rd,] expression`: Desugars to `auipc rd` and `jalr rd, rd, ...`.
+    /// The link register is `ra` unless given.
+    Call(Register, Box<Expression>),
+    /// `tail expression [, temp]`: Desugars to `auipc temp` and
+    /// `jalr x0, temp, ...`. The temporary is `t1` unless given.
+    Tail(Box<Expression>, Register),
+    /// `jump expression, temp`: Desugars like `tail`, with the temporary
+    /// always given.
+    Jump(Box<Expression>, Register),
+    /// `sext.b`, `sext.h` or `zext.h rd, rs`: Desugars to a left shift of `rs`
+    /// into `rd` and a right shift of `rd` by the same amount.
+    Extend(ExtendOp, Register, Register),
+    /// `sle rd, rs1, rs2` (with `Slt`) or `sleu` (with `Sltu`): Desugars to
+    /// `slt rd, rs2, rs1` and `xori rd, rd, 1`.
+    Sle(RTypeOp, Register, Register, Register),
+}
+
+/// The sign and zero extensions that take two base instructions.
+/// (`zext.b` is a single `andi rd, rs, 0xff`.)
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum ExtendOp {
+    SextB,
+    SextH,
+    ZextH,
+}
+
+impl ExtendOp {
+    /// How far the value is shifted left and back
+    pub fn shift(self) -> i64 {
+        match self {
+            ExtendOp::SextB => 24,
+            ExtendOp::SextH | ExtendOp::ZextH => 16,
+        }
+    }
+
+    /// The shift back: arithmetic to sign-extend, logical to zero-extend
+    pub fn right_shift(self) -> ITypeOp {
+        match self {
+            ExtendOp::SextB | ExtendOp::SextH => ITypeOp::Srai,
+            ExtendOp::ZextH => ITypeOp::Srli,
+        }
+    }
 }
 
 /// A node in the AST representing an assembler directive.
@@ -619,6 +804,11 @@ pub enum PseudoOp {
 /// `directive:`
 /// `| Global Identifier`
 /// `| Equ Identifier Comma expression`
+/// `| Equiv Identifier Comma expression`
+/// `| Weak list_of_identifiers`
+/// `| Local list_of_identifiers`
+/// `| Comm Identifier Comma expression [Comma expression]`
+/// `| Lcomm Identifier Comma expression [Comma expression]`
 /// `| Text | Data | Bss`
 /// `| Space expression`
 /// `| String list_of_strings`
@@ -626,6 +816,8 @@ pub enum PseudoOp {
 /// `| Byte list_of_expressions`
 /// `| TwoByte list_of_expressions`
 /// `| FourByte list_of_expressions`
+/// `| Option option_arg`
+/// `| Type Identifier Comma symbol_type`
 ///
 /// **Parsing Notes:**
 /// The parser must check for a label preceding a directive. A label can only precede
@@ -635,8 +827,18 @@ pub enum PseudoOp {
 pub enum Directive {
     /// .global symbol
     Global(Vec<String>),
-    /// .equ symbol, expression
+    /// .equ symbol, expression (also spelled .set)
     Equ(String, Expression),
+    /// .equiv symbol, expression (like .equ, but may not redefine a symbol)
+    Equiv(String, Expression),
+    /// .weak symbol
+    Weak(Vec<String>),
+    /// .local symbol
+    Local(Vec<String>),
+    /// .comm symbol, size [, alignment]
+    Comm(String, Expression, Option<Expression>),
+    /// .lcomm symbol, size [, alignment]
+    Lcomm(String, Expression, Option<Expression>),
     /// .text
     Text,
     /// .data
@@ -653,6 +855,12 @@ pub enum Directive {
     Byte(Vec<Expression>),
     TwoByte(Vec<Expression>),
     FourByte(Vec<Expression>),
+    /// .option push | pop | rvc | norvc | relax | norelax
+    Option(OptionArg),
+    /// .type symbol, @function | @object
+    Type(String, SymbolType),
+    /// .size symbol, expression
+    Size(String, Expression),
 }
 
 /// An expression, which can be a single literal or a complex combination of
@@ -669,6 +877,7 @@ pub enum Directive {
 /// `multiplicative_expr: unary ( ('*' | '/') unary )*`
 /// `unary:               '-' operand | '~' operand | operand`
 /// `operand:             Literal | Identifier | Register | '(' expression ')'`
+/// `                   | '%hi' '(' expression ')' | '%lo' '(' expression ')'`
 ///
 /// **Parsing Notes:**
 /// The parser will use the grammar's structure to handle operator precedence automatically.
@@ -726,6 +935,48 @@ pub enum Expression {
         lhs: Box<Expression>,
         rhs: Box<Expression>,
     },
+    /// Comparisons, which give -1 (all bits set) when true and 0 when false,
+    /// as in GNU as.
+    EqualOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    NotEqualOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    LessOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    LessEqualOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    GreaterOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    GreaterEqualOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    /// Logical operations, which give 1 when true and 0 when false. The
+    /// right-hand side is only evaluated when it decides the result.
+    LogicalAndOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    LogicalOrOp {
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    /// `condition ? then_expr : else_expr`, evaluating only the chosen side.
+    ConditionalOp {
+        condition: Box<Expression>,
+        then_expr: Box<Expression>,
+        else_expr: Box<Expression>,
+    },
     /// Unary operations with a single operand.
     NegateOp {
         expr: Box<Expression>,
@@ -733,6 +984,18 @@ pub enum Expression {
     BitwiseNotOp {
         expr: Box<Expression>,
     },
+    LogicalNotOp {
+        expr: Box<Expression>,
+    },
+    /// `%hi(expr)`: the upper 20 bits of a value, rounded so that adding
+    /// `%lo(expr)` reconstructs it (for use with `lui`).
+    HiOp {
+        expr: Box<Expression>,
+    },
+    /// `%lo(expr)`: the sign-extended lower 12 bits of a value.
+    LoOp {
+        expr: Box<Expression>,
+    },
     /// A parenthesized sub-expression. `Box` is used to prevent infinite recursion
     /// and to store the expression on the heap.
     Parenthesized(Box<Expression>),
@@ -748,7 +1011,12 @@ pub enum Expression {
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}:{}]", self.file, self.line)
+        match self.span {
+            Some(span) => {
+                write!(f, "[{}:{}:{}]", self.file, self.line, span.start + 1)
+            }
+            None => write!(f, "[{}:{}]", self.file, self.line),
+        }
     }
 }
 
@@ -818,6 +1086,16 @@ impl fmt::Display for OperatorOp {
             OperatorOp::BitwiseXor => "^",
             OperatorOp::BitwiseNot => "~",
             OperatorOp::Modulo => "%",
+            OperatorOp::Equal => "==",
+            OperatorOp::NotEqual => "!=",
+            OperatorOp::Less => "<",
+            OperatorOp::LessEqual => "<=",
+            OperatorOp::Greater => ">",
+            OperatorOp::GreaterEqual => ">=",
+            OperatorOp::LogicalAnd => "&&",
+            OperatorOp::LogicalOr => "||",
+            OperatorOp::LogicalNot => "!",
+            OperatorOp::Question => "?",
         };
         write!(f, "{}", s)
     }
@@ -828,6 +1106,11 @@ impl fmt::Display for DirectiveOp {
         let s = match self {
             DirectiveOp::Global => ".global",
             DirectiveOp::Equ => ".equ",
+            DirectiveOp::Equiv => ".equiv",
+            DirectiveOp::Weak => ".weak",
+            DirectiveOp::Local => ".local",
+            DirectiveOp::Comm => ".comm",
+            DirectiveOp::Lcomm => ".lcomm",
             DirectiveOp::Text => ".text",
             DirectiveOp::Data => ".data",
             DirectiveOp::Bss => ".bss",
@@ -838,6 +1121,9 @@ impl fmt::Display for DirectiveOp {
             DirectiveOp::Byte => ".byte",
             DirectiveOp::TwoByte => ".2byte",
             DirectiveOp::FourByte => ".4byte",
+            DirectiveOp::Option => ".option",
+            DirectiveOp::Type => ".type",
+            DirectiveOp::Size => ".size",
         };
         write!(f, "{}", s)
     }
@@ -931,6 +1217,16 @@ impl fmt::Display for MemoryOrdering {
     }
 }
 
+impl fmt::Display for ExtendOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtendOp::SextB => write!(f, "sext.b"),
+            ExtendOp::SextH => write!(f, "sext.h"),
+            ExtendOp::ZextH => write!(f, "zext.h"),
+        }
+    }
+}
+
 impl fmt::Display for PseudoOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -942,8 +1238,28 @@ impl fmt::Display for PseudoOp {
             PseudoOp::StoreGlobal(op, rs, expr, temp) => {
                 write!(f, "{:<7} {}, {}, {}", op.to_string(), rs, expr, temp)
             }
-            PseudoOp::Call(expr) => write!(f, "{:<7} {}", "call", expr),
-            PseudoOp::Tail(expr) => write!(f, "{:<7} {}", "tail", expr),
+            PseudoOp::Call(Register::X1, expr) => {
+                write!(f, "{:<7} {}", "call", expr)
+            }
+            PseudoOp::Call(rd, expr) => {
+                write!(f, "{:<7} {}, {}", "call", rd, expr)
+            }
+            PseudoOp::Tail(expr, Register::X6) => {
+                write!(f, "{:<7} {}", "tail", expr)
+            }
+            PseudoOp::Tail(expr, temp) => {
+                write!(f, "{:<7} {}, {}", "tail", expr, temp)
+            }
+            PseudoOp::Jump(expr, temp) => {
+                write!(f, "{:<7} {}, {}", "jump", expr, temp)
+            }
+            PseudoOp::Extend(op, rd, rs) => {
+                write!(f, "{:<7} {}, {}", op.to_string(), rd, rs)
+            }
+            PseudoOp::Sle(op, rd, rs1, rs2) => {
+                let name = if *op == RTypeOp::Sltu { "sleu" } else { "sle" };
+                write!(f, "{:<7} {}, {}, {}", name, rd, rs1, rs2)
+            }
         }
     }
 }
@@ -1100,6 +1416,21 @@ fn format_compressed_instruction(
     }
 }
 
+impl fmt::Display for OptionArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for SymbolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolType::Function => write!(f, "@function"),
+            SymbolType::Object => write!(f, "@object"),
+        }
+    }
+}
+
 impl fmt::Display for Directive {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1109,6 +1440,27 @@ impl fmt::Display for Directive {
             Directive::Equ(name, expr) => {
                 write!(f, "{:<7} {}, {}", ".equ", name, expr)
             }
+            Directive::Equiv(name, expr) => {
+                write!(f, "{:<7} {}, {}", ".equiv", name, expr)
+            }
+            Directive::Weak(s) => write!(f, "{:<7} {}", ".weak", s.join(", ")),
+            Directive::Local(s) => {
+                write!(f, "{:<7} {}", ".local", s.join(", "))
+            }
+            Directive::Comm(name, size, align) => {
+                write!(f, "{:<7} {}, {}", ".comm", name, size)?;
+                if let Some(align) = align {
+                    write!(f, ", {}", align)?;
+                }
+                Ok(())
+            }
+            Directive::Lcomm(name, size, align) => {
+                write!(f, "{:<7} {}, {}", ".lcomm", name, size)?;
+                if let Some(align) = align {
+                    write!(f, ", {}", align)?;
+                }
+                Ok(())
+            }
             Directive::Text => write!(f, ".text"),
             Directive::Data => write!(f, ".data"),
             Directive::Bss => write!(f, ".bss"),
@@ -1154,6 +1506,13 @@ impl fmt::Display for Directive {
                     .join(", ");
                 write!(f, "{:<7} {}", ".4byte", formatted)
             }
+            Directive::Option(arg) => write!(f, "{:<7} {}", ".option", arg),
+            Directive::Type(name, kind) => {
+                write!(f, "{:<7} {}, {}", ".type", name, kind)
+            }
+            Directive::Size(name, expr) => {
+                write!(f, "{:<7} {}, {}", ".size", name, expr)
+            }
         }
     }
 }
@@ -1185,8 +1544,34 @@ impl fmt::Display for Expression {
             Expression::BitwiseXorOp { lhs, rhs } => {
                 write!(f, "{} ^ {}", lhs, rhs)
             }
+            Expression::EqualOp { lhs, rhs } => write!(f, "{} == {}", lhs, rhs),
+            Expression::NotEqualOp { lhs, rhs } => {
+                write!(f, "{} != {}", lhs, rhs)
+            }
+            Expression::LessOp { lhs, rhs } => write!(f, "{} < {}", lhs, rhs),
+            Expression::LessEqualOp { lhs, rhs } => {
+                write!(f, "{} <= {}", lhs, rhs)
+            }
+            Expression::GreaterOp { lhs, rhs } => {
+                write!(f, "{} > {}", lhs, rhs)
+            }
+            Expression::GreaterEqualOp { lhs, rhs } => {
+                write!(f, "{} >= {}", lhs, rhs)
+            }
+            Expression::LogicalAndOp { lhs, rhs } => {
+                write!(f, "{} && {}", lhs, rhs)
+            }
+            Expression::LogicalOrOp { lhs, rhs } => {
+                write!(f, "{} || {}", lhs, rhs)
+            }
+            Expression::ConditionalOp { condition, then_expr, else_expr } => {
+                write!(f, "{} ? {} : {}", condition, then_expr, else_expr)
+            }
             Expression::NegateOp { expr } => write!(f, "-{}", expr),
             Expression::BitwiseNotOp { expr } => write!(f, "~{}", expr),
+            Expression::LogicalNotOp { expr } => write!(f, "!{}", expr),
+            Expression::HiOp { expr } => write!(f, "%hi({})", expr),
+            Expression::LoOp { expr } => write!(f, "%lo({})", expr),
             Expression::Parenthesized(expr) => write!(f, "({})", expr),
             Expression::CurrentAddress => write!(f, "."),
             Expression::NumericLabelRef(nlr) => write!(f, "{}", nlr),
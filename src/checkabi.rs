@@ -1,3 +1,4 @@
+use std::fmt;
 use std::rc::Rc;
 
 use crate::execution::{Instruction, Machine};
@@ -74,7 +75,31 @@ pub struct CheckABI {
     next_n: usize,
 }
 
+// The shadow memory is left out; it is too big to print
+impl fmt::Debug for CheckABI {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CheckABI")
+            .field("registers", &self.registers)
+            .field("valid", &self.valid)
+            .field("call_depth", &self.stack.len())
+            .finish_non_exhaustive()
+    }
+}
+
 impl CheckABI {
+    /// A checker for a machine about to run from its entry point
+    pub fn for_machine(m: &Machine) -> Self {
+        CheckABI::new(
+            m.get_reg(2) as u32,
+            m.text_start(),
+            m.text_end(),
+            m.data_start(),
+            m.data_end(),
+            m.stack_start(),
+            m.stack_end(),
+        )
+    }
+
     pub fn new(
         at_entry_sp: u32,
         text_start: u32,
@@ -21,18 +21,16 @@ enum AbiTestResult {
 fn make_test_config(check_abi: bool) -> Config {
     Config {
         mode: Mode::Run,
-        verbose: false,
         max_steps: 1_000_000,
-        executable: "a.out".to_string(),
         check_abi,
-        hex_mode: false,
-        show_addresses: false,
-        verbose_instructions: false,
         input_files: vec!["test.s".to_string()],
-        output_file: "a.out".to_string(),
-        text_start: 0x10000,
-        dump: crate::dump::DumpConfig::new(),
-        relax: Relax { gp: Some(true), pseudo: true, compressed: false },
+        relax: Relax {
+            gp: Some(true),
+            pseudo: true,
+            compressed: false,
+            branches: true,
+        },
+        ..Config::assemble_default()
     }
 }
 
@@ -61,7 +59,7 @@ fn run_with_abi_check(source: &str) -> AbiTestResult {
     config.mode = Mode::Run;
 
     // Load ELF from bytes
-    let mut m = match load_elf(ElfInput::Bytes(&elf_bytes)) {
+    let mut m = match load_elf(ElfInput::Bytes(&elf_bytes), &mut Vec::new()) {
         Ok(machine) => machine,
         Err(e) => {
             return AbiTestResult::RuntimeError(format!("Load error: {}", e));
@@ -82,6 +80,7 @@ fn run_with_abi_check(source: &str) -> AbiTestResult {
                     pseudo_index: 0,
                     verbose_fields: Vec::new(),
                     pseudo_fields: Vec::new(),
+                    source: None,
                 };
                 instructions.push(instruction);
                 pc += length;
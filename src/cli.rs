@@ -0,0 +1,107 @@
+// cli.rs
+//
+// The command-line interface of the risclet binary: parse the arguments and
+// dispatch to the subcommand they name
+
+use crate::assembler::{assemble_and_save, assemble_files};
+use crate::config::{Mode, parse_cli_args};
+use crate::elf_loader::ElfInput;
+use crate::error::RiscletError;
+use crate::formatter::format_files;
+use crate::image::decode_hex_words;
+use crate::lsp::run_language_server;
+use crate::simulator::run_simulator;
+use crate::watch::watch;
+
+/// Run risclet with the given arguments (not including the program name),
+/// returning the status to exit with
+pub fn run(args: &[String]) -> i32 {
+    // Parse CLI arguments using unified parser
+    let mut config = match parse_cli_args(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    if config.watch {
+        if let Err(e) = watch(&config) {
+            eprintln!("{}", e);
+            return 1;
+        }
+        return 0;
+    }
+
+    // Dispatch based on mode
+    match config.mode {
+        Mode::Assemble | Mode::Link => {
+            if let Err(e) = assemble_and_save(&mut config) {
+                eprintln!("{}", e);
+                return 1;
+            }
+            0
+        }
+
+        Mode::Format => match format_files(&config) {
+            Ok(true) => 0,
+            Ok(false) if config.check_format => 1,
+            Ok(false) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+
+        Mode::Lsp => match run_language_server() {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+
+        Mode::Run | Mode::Debug | Mode::Disassemble | Mode::Trace => {
+            // Check if we have .s files to assemble first
+            let result = if !config.input_files.is_empty() {
+                // We have .s files - assemble them in-memory, then run simulator
+                let elf_bytes = match assemble_files(&mut config) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return 1;
+                    }
+                };
+
+                // Pass in-memory ELF to simulator
+                run_simulator(&config, ElfInput::Bytes(&elf_bytes))
+            } else if config.hex_words {
+                // Instruction words from stdin, loaded as a raw image
+                std::io::read_to_string(std::io::stdin())
+                    .map_err(|e| {
+                        RiscletError::io(format!("failed to read stdin: {}", e))
+                    })
+                    .and_then(|text| decode_hex_words(&text))
+                    .and_then(|bytes| {
+                        run_simulator(&config, ElfInput::Bytes(&bytes))
+                    })
+            } else {
+                // No .s files - load executable and run simulator
+                run_simulator(&config, ElfInput::File(&config.executable))
+            };
+            match result {
+                Ok(code) => code,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    1
+                }
+            }
+        }
+
+        Mode::Default => {
+            // This mode should no longer be used, but keep for compatibility
+            eprintln!("Error: Default mode is deprecated");
+            1
+        }
+    }
+}
@@ -3,6 +3,10 @@
 // Unified configuration and CLI argument parsing for risclet
 
 use crate::dump;
+use crate::formatter::RegisterNames;
+use crate::image::{ImageFormat, parse_memh_width};
+use crate::lint::LintConfig;
+use crate::memory_map::MemoryMap;
 
 /// Operating mode for risclet
 #[derive(Debug, Clone, PartialEq)]
@@ -11,6 +15,8 @@ pub enum Mode {
     Default,
     /// Explicit assemble mode
     Assemble,
+    /// Link relocatable objects (and source files) into an executable
+    Link,
     /// Run mode: execute and exit
     Run,
     /// Debug mode: interactive TUI
@@ -19,6 +25,10 @@ pub enum Mode {
     Disassemble,
     /// Trace mode: execute and print each instruction with effects
     Trace,
+    /// Format mode: reformat source files
+    Format,
+    /// Language server mode: serve an editor over stdin and stdout
+    Lsp,
 }
 
 /// Complete unified configuration for risclet
@@ -39,6 +49,14 @@ pub struct Config {
     pub hex_mode: bool,
     pub show_addresses: bool,
     pub verbose_instructions: bool,
+    /// Show source lines (from debug info) in place of disassembly
+    pub show_source: bool,
+    /// Disassemble to source that assembles back to the same executable
+    /// (disassemble --source)
+    pub source_output: bool,
+    /// Disassemble in the layout of GNU objdump -d (disassemble
+    /// --format=objdump)
+    pub objdump_output: bool,
 
     // Assembler-specific options
     pub input_files: Vec<String>,
@@ -46,10 +64,50 @@ pub struct Config {
     pub text_start: u32,
     pub dump: dump::DumpConfig,
     pub relax: Relax,
+    /// Write one relocatable object per source file instead of linking (-c)
+    pub object_only: bool,
+    /// Memory regions and section placement from a `--layout` file
+    /// (replaces `-t` placement)
+    pub memory_map: Option<MemoryMap>,
+    /// Where to write an assembler listing (-a for stdout, or
+    /// --listing=FILE)
+    pub listing: Option<String>,
+    /// Where to write a map of segments, sections and symbols (--map FILE)
+    pub map_file: Option<String>,
+    /// Write a memory image instead of an ELF executable (--format), or
+    /// load the executable as one (from its extension)
+    pub image_format: Option<ImageFormat>,
+    /// Bytes per word in memh images (--memh-width=BITS)
+    pub memh_width: u32,
+    /// Write text and data to separate images (--split)
+    pub split_image: bool,
+    /// Where a raw or memh image is loaded (--base; defaults to -t)
+    pub image_base: Option<u32>,
+    /// Load the executable as a raw image whatever its name
+    /// (disassemble --raw FILE)
+    pub raw_image: bool,
+    /// Read the program as hex instruction words from stdin
+    /// (disassemble --hex-words)
+    pub hex_words: bool,
+    /// A file of `address name` lines that name addresses in an image
+    /// (--symbols FILE)
+    pub symbol_file: Option<String>,
+    /// Which warnings to report (-W<id>, -Wno-<id>)
+    pub warnings: LintConfig,
+    /// Write assembler-local `.L` labels to the symbol table (--keep-locals)
+    pub keep_locals: bool,
+
+    // Formatter options
+    /// List unformatted files instead of rewriting them (--check)
+    pub check_format: bool,
+    /// How the formatter writes register names (--registers=abi|numeric)
+    pub register_names: RegisterNames,
+    /// Re-assemble and re-run whenever an input file changes (--watch)
+    pub watch: bool,
 }
 
 /// Relaxation settings for instruction optimization
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Relax {
     /// GP-relative la optimization
     /// - None = auto-detect based on GP initialization
@@ -60,6 +118,9 @@ pub struct Relax {
     pub pseudo: bool,
     /// Enable automatic RV32C compressed encoding
     pub compressed: bool,
+    /// Rewrite branches and jumps whose target is out of range as longer
+    /// sequences (an inverted branch over a `jal`, or `auipc`+`jalr`)
+    pub branches: bool,
 }
 
 impl Relax {
@@ -70,7 +131,7 @@ impl Relax {
 }
 
 const MAX_STEPS_DEFAULT: usize = 100_000_000;
-const TEXT_START_DEFAULT: u32 = 0x10000;
+pub const TEXT_START_DEFAULT: u32 = 0x10000;
 const OUTPUT_FILE_DEFAULT: &str = "a.out";
 const EXECUTABLE_DEFAULT: &str = "a.out";
 
@@ -86,11 +147,35 @@ impl Config {
             hex_mode: false,
             show_addresses: false,
             verbose_instructions: false,
+            show_source: false,
+            source_output: false,
+            objdump_output: false,
             input_files: Vec::new(),
             output_file: OUTPUT_FILE_DEFAULT.to_string(),
             text_start: TEXT_START_DEFAULT,
             dump: dump::DumpConfig::new(),
-            relax: Relax { gp: None, pseudo: true, compressed: false },
+            relax: Relax {
+                gp: None,
+                pseudo: true,
+                compressed: false,
+                branches: true,
+            },
+            object_only: false,
+            memory_map: None,
+            listing: None,
+            map_file: None,
+            image_format: None,
+            memh_width: 4,
+            split_image: false,
+            image_base: None,
+            raw_image: false,
+            hex_words: false,
+            symbol_file: None,
+            warnings: LintConfig::new(),
+            keep_locals: false,
+            check_format: false,
+            register_names: RegisterNames::Abi,
+            watch: false,
         }
     }
 
@@ -108,11 +193,35 @@ impl Config {
             hex_mode: false,
             show_addresses,
             verbose_instructions: false,
+            show_source: false,
+            source_output: false,
+            objdump_output: false,
             input_files: Vec::new(),
             output_file: OUTPUT_FILE_DEFAULT.to_string(),
             text_start: TEXT_START_DEFAULT,
             dump: dump::DumpConfig::new(),
-            relax: Relax { gp: None, pseudo: true, compressed: false },
+            relax: Relax {
+                gp: None,
+                pseudo: true,
+                compressed: false,
+                branches: true,
+            },
+            object_only: false,
+            memory_map: None,
+            listing: None,
+            map_file: None,
+            image_format: None,
+            memh_width: 4,
+            split_image: false,
+            image_base: None,
+            raw_image: false,
+            hex_words: false,
+            symbol_file: None,
+            warnings: LintConfig::new(),
+            keep_locals: false,
+            check_format: false,
+            register_names: RegisterNames::Abi,
+            watch: false,
         }
     }
 }
@@ -126,11 +235,14 @@ pub fn parse_cli_args(args: &[String]) -> Result<Config, String> {
 
     // Check for subcommands
     match args[0].as_str() {
-        "assemble" => parse_assemble_mode(&args[1..]),
+        "assemble" => parse_assemble_mode(&args[1..], Mode::Assemble),
+        "link" => parse_assemble_mode(&args[1..], Mode::Link),
         "run" => parse_simulator_mode(&args[1..], Mode::Run),
         "debug" => parse_simulator_mode(&args[1..], Mode::Debug),
         "disassemble" => parse_simulator_mode(&args[1..], Mode::Disassemble),
         "trace" => parse_simulator_mode(&args[1..], Mode::Trace),
+        "fmt" => parse_format_mode(&args[1..]),
+        "lsp" => parse_lsp_mode(&args[1..]),
         "-h" | "--help" | "help" => Err(print_main_help()),
         "--version" => {
             println!("risclet {}", env!("CARGO_PKG_VERSION"));
@@ -153,7 +265,17 @@ pub fn parse_cli_args(args: &[String]) -> Result<Config, String> {
 }
 
 fn is_explicit_subcommand(arg: &str) -> bool {
-    matches!(arg, "assemble" | "run" | "debug" | "disassemble" | "trace")
+    matches!(
+        arg,
+        "assemble"
+            | "link"
+            | "run"
+            | "debug"
+            | "disassemble"
+            | "trace"
+            | "fmt"
+            | "lsp"
+    )
 }
 
 fn option_value_after_equals(arg: &str) -> &str {
@@ -190,12 +312,21 @@ fn parse_dump_option(arg: &str, config: &mut Config) -> Result<bool, String> {
 fn parse_relax_option(arg: &str, relax: &mut Relax) -> bool {
     match arg {
         "--relax" => {
-            *relax = Relax { gp: Some(true), pseudo: true, compressed: true };
+            *relax = Relax {
+                gp: Some(true),
+                pseudo: true,
+                compressed: true,
+                branches: true,
+            };
             true
         }
         "--no-relax" => {
-            *relax =
-                Relax { gp: Some(false), pseudo: false, compressed: false };
+            *relax = Relax {
+                gp: Some(false),
+                pseudo: false,
+                compressed: false,
+                branches: false,
+            };
             true
         }
         "--relax-gp" => {
@@ -222,6 +353,14 @@ fn parse_relax_option(arg: &str, relax: &mut Relax) -> bool {
             relax.compressed = false;
             true
         }
+        "--relax-branches" => {
+            relax.branches = true;
+            true
+        }
+        "--no-relax-branches" => {
+            relax.branches = false;
+            true
+        }
         _ => false,
     }
 }
@@ -238,9 +377,19 @@ fn require_option_value(
     Ok(args[*i].clone())
 }
 
-/// Parse arguments for assemble mode
-fn parse_assemble_mode(args: &[String]) -> Result<Config, String> {
+/// The default object file for a source file: its name with a `.o`
+/// extension, in the current directory
+pub fn object_file_name(input: &str) -> String {
+    let path = std::path::Path::new(input);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(input);
+    format!("{}.o", stem)
+}
+
+/// Parse arguments for assemble and link modes
+fn parse_assemble_mode(args: &[String], mode: Mode) -> Result<Config, String> {
     let mut config = Config::assemble_default();
+    config.mode = mode.clone();
+    let mut has_output_file = false;
     let mut i = 0;
 
     while i < args.len() {
@@ -253,19 +402,65 @@ fn parse_assemble_mode(args: &[String]) -> Result<Config, String> {
                 "-o" => {
                     config.output_file =
                         require_option_value(args, &mut i, "-o")?;
+                    has_output_file = true;
+                }
+                "-c" if mode == Mode::Assemble => {
+                    config.object_only = true;
+                }
+                "--watch" if mode == Mode::Assemble => {
+                    config.watch = true;
                 }
                 "-t" => {
                     let value = require_option_value(args, &mut i, "-t")?;
                     config.text_start = parse_address(&value)?;
                 }
+                "--layout" => {
+                    let value = require_option_value(args, &mut i, "--layout")?;
+                    config.memory_map = Some(MemoryMap::load(&value)?);
+                }
+                "-a" => {
+                    config.listing = Some("-".to_string());
+                }
+                "--map" => {
+                    config.map_file =
+                        Some(require_option_value(args, &mut i, "--map")?);
+                }
+                "--split" => {
+                    config.split_image = true;
+                }
+                _ if arg.starts_with("--format=") => {
+                    config.image_format =
+                        ImageFormat::parse(option_value_after_equals(arg))?;
+                }
+                _ if arg.starts_with("--memh-width=") => {
+                    config.memh_width =
+                        parse_memh_width(option_value_after_equals(arg))?;
+                }
+                "-L" | "--keep-locals" => {
+                    config.keep_locals = true;
+                }
+                _ if arg.starts_with("--listing=") => {
+                    let value = option_value_after_equals(arg);
+                    if value.is_empty() {
+                        return Err(
+                            "Error: --listing requires a file name".to_string()
+                        );
+                    }
+                    config.listing = Some(value.to_string());
+                }
                 "-v" | "--verbose" => {
                     config.verbose = true;
                 }
+                "-h" | "--help" if mode == Mode::Link => {
+                    return Err(print_link_help(&config));
+                }
                 "-h" | "--help" => {
                     return Err(print_assemble_help(&config));
                 }
                 _ => {
-                    if parse_relax_option(arg, &mut config.relax) {
+                    if parse_relax_option(arg, &mut config.relax)
+                        || config.warnings.parse_flag(arg)?
+                    {
                         i += 1;
                         continue;
                     }
@@ -283,6 +478,71 @@ fn parse_assemble_mode(args: &[String]) -> Result<Config, String> {
         return Err("Error: no input files specified".to_string());
     }
 
+    if config.object_only
+        && let Some(object) =
+            config.input_files.iter().find(|f| f.ends_with(".o"))
+    {
+        return Err(format!(
+            "Error: {} is already an object file and cannot be assembled with -c",
+            object
+        ));
+    }
+
+    if config.object_only {
+        if config.memory_map.is_some() {
+            return Err(
+                "Error: --layout applies to executables and cannot be combined with -c"
+                    .to_string(),
+            );
+        }
+        if config.listing.is_some() {
+            return Err(
+                "Error: listings (-a, --listing) cannot be combined with -c"
+                    .to_string(),
+            );
+        }
+        if config.map_file.is_some() {
+            return Err(
+                "Error: --map describes a linked executable and cannot be combined with -c"
+                    .to_string(),
+            );
+        }
+        if config.image_format.is_some() {
+            return Err(
+                "Error: --format describes a linked executable and cannot be combined with -c"
+                    .to_string(),
+            );
+        }
+        if config.dump.has_dumps() {
+            return Err(
+                "Error: dump options (--dump-*) cannot be combined with -c"
+                    .to_string(),
+            );
+        }
+        if has_output_file && config.input_files.len() > 1 {
+            return Err(
+                "Error: cannot specify -o with -c and multiple input files"
+                    .to_string(),
+            );
+        }
+        if !has_output_file {
+            config.output_file = object_file_name(&config.input_files[0]);
+        }
+    }
+
+    match config.image_format {
+        Some(format) if !has_output_file => {
+            config.output_file = format!("a.{}", format.extension());
+        }
+        None if config.split_image => {
+            return Err(
+                "Error: --split needs an image --format (bin, ihex or memh)"
+                    .to_string(),
+            );
+        }
+        _ => {}
+    }
+
     Ok(config)
 }
 
@@ -319,16 +579,67 @@ fn parse_simulator_mode(args: &[String], mode: Mode) -> Result<Config, String> {
             "--no-show-addresses" => config.show_addresses = false,
             "--verbose-instructions" => config.verbose_instructions = true,
             "--no-verbose-instructions" => config.verbose_instructions = false,
+            "--source" if config.mode == Mode::Disassemble => {
+                config.source_output = true
+            }
+            "--source" => config.show_source = true,
+            "--no-source" if config.mode == Mode::Disassemble => {
+                config.source_output = false;
+                config.show_source = false;
+            }
+            "--no-source" => config.show_source = false,
+            "--format=objdump" if config.mode == Mode::Disassemble => {
+                config.objdump_output = true
+            }
+            _ if arg.starts_with("--format=")
+                && config.mode == Mode::Disassemble =>
+            {
+                return Err(format!(
+                    "Error: disassemble --format must be objdump, not '{}'",
+                    option_value_after_equals(arg)
+                ));
+            }
+            "--keep-locals" => config.keep_locals = true,
+            "--watch" if config.mode != Mode::Disassemble => {
+                config.watch = true
+            }
             "-v" | "--verbose" => config.verbose = true,
             "-t" => {
                 let value = require_option_value(args, &mut i, "-t")?;
                 config.text_start = parse_address(&value)?;
             }
+            "--layout" => {
+                let value = require_option_value(args, &mut i, "--layout")?;
+                config.memory_map = Some(MemoryMap::load(&value)?);
+            }
+            "--base" => {
+                let value = require_option_value(args, &mut i, "--base")?;
+                config.image_base = Some(parse_address(&value)?);
+            }
+            "--raw" if config.mode == Mode::Disassemble => {
+                config.executable =
+                    require_option_value(args, &mut i, "--raw")?;
+                config.raw_image = true;
+                has_explicit_executable = true;
+            }
+            "--hex-words" if config.mode == Mode::Disassemble => {
+                config.hex_words = true
+            }
+            "--symbols" => {
+                config.symbol_file =
+                    Some(require_option_value(args, &mut i, "--symbols")?);
+            }
+            _ if arg.starts_with("--memh-width=") => {
+                config.memh_width =
+                    parse_memh_width(option_value_after_equals(arg))?;
+            }
             "-h" | "--help" => {
                 return Err(print_simulator_help(&config));
             }
             _ => {
-                if parse_relax_option(arg, &mut config.relax) {
+                if parse_relax_option(arg, &mut config.relax)
+                    || config.warnings.parse_flag(arg)?
+                {
                     i += 1;
                     continue;
                 } else if arg.starts_with("--dump-") {
@@ -336,7 +647,7 @@ fn parse_simulator_mode(args: &[String], mode: Mode) -> Result<Config, String> {
                 } else if arg.starts_with('-') {
                     return Err(format!("Error: unknown option: {}", arg));
                 } else {
-                    // Positional argument: could be .s file or executable
+                    // Positional argument: could be .s/.o file or executable
                     config.input_files.push(arg.clone());
                 }
             }
@@ -350,30 +661,43 @@ fn parse_simulator_mode(args: &[String], mode: Mode) -> Result<Config, String> {
             .to_string());
     }
 
+    // Hex words come from stdin, so there is nothing else to load
+    if config.hex_words {
+        if has_explicit_executable || !config.input_files.is_empty() {
+            return Err(
+                "Error: --hex-words reads stdin and takes no files".to_string()
+            );
+        }
+        config.executable = "<stdin>".to_string();
+        config.image_format = Some(ImageFormat::Bin);
+        return Ok(config);
+    }
+
     // Determine input type and set appropriate fields
     if !config.input_files.is_empty() {
-        // Check if all files are .s files or all are executables
+        // Check if all files are .s/.o files or all are executables
+        let is_input = |f: &&String| f.ends_with(".s") || f.ends_with(".o");
         let s_files: Vec<_> =
-            config.input_files.iter().filter(|f| f.ends_with(".s")).collect();
+            config.input_files.iter().filter(is_input).collect();
         let non_s_files: Vec<_> =
-            config.input_files.iter().filter(|f| !f.ends_with(".s")).collect();
+            config.input_files.iter().filter(|f| !is_input(f)).collect();
 
         if !s_files.is_empty() && !non_s_files.is_empty() {
             return Err(
-                "Error: cannot mix .s files and executables as positional arguments"
+                "Error: cannot mix .s/.o files and executables as positional arguments"
                     .to_string(),
             );
         }
 
         if !s_files.is_empty() {
-            // All are .s files - will assemble in memory
+            // All are .s/.o files - will assemble and link in memory
             // config.input_files stays as-is for assembler
         } else if non_s_files.len() == 1 {
             // Single executable
             config.executable = config.input_files[0].clone();
             config.input_files.clear();
         } else {
-            // Multiple non-.s files
+            // Multiple executables
             return Err(
                 "Error: can only specify one executable as a positional argument"
                     .to_string(),
@@ -387,6 +711,70 @@ fn parse_simulator_mode(args: &[String], mode: Mode) -> Result<Config, String> {
         }
     }
 
+    // Memory images are recognized by their extension
+    if config.raw_image {
+        config.image_format = Some(ImageFormat::Bin);
+    } else if config.input_files.is_empty() {
+        config.image_format = ImageFormat::from_file_name(&config.executable);
+    }
+
+    Ok(config)
+}
+
+/// Parse arguments for the fmt subcommand
+fn parse_format_mode(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::assemble_default();
+    config.mode = Mode::Format;
+
+    for arg in args {
+        match arg.as_str() {
+            "--check" => config.check_format = true,
+            "-h" | "--help" => return Err(print_format_help()),
+            _ if arg.starts_with("--registers=") => {
+                config.register_names = match option_value_after_equals(arg) {
+                    "abi" => RegisterNames::Abi,
+                    "numeric" => RegisterNames::Numeric,
+                    value => {
+                        return Err(format!(
+                            "Error: --registers must be 'abi' or 'numeric', not '{}'",
+                            value
+                        ));
+                    }
+                };
+            }
+            _ if arg.starts_with('-') => {
+                return Err(format!("Error: unknown option: {}", arg));
+            }
+            _ => config.input_files.push(arg.clone()),
+        }
+    }
+
+    if config.input_files.is_empty() {
+        config.input_files = find_assembly_files()?;
+        if config.input_files.is_empty() {
+            return Err(
+                "Error: no assembly files (*.s) found in current directory"
+                    .to_string(),
+            );
+        }
+    }
+    Ok(config)
+}
+
+/// Parse arguments for the lsp subcommand
+fn parse_lsp_mode(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::assemble_default();
+    config.mode = Mode::Lsp;
+
+    for arg in args {
+        match arg.as_str() {
+            // Editors pass this to say which transport to use; stdio is the
+            // only one
+            "--stdio" => {}
+            "-h" | "--help" => return Err(print_lsp_help()),
+            _ => return Err(format!("Error: unknown option: {}", arg)),
+        }
+    }
     Ok(config)
 }
 
@@ -441,21 +829,26 @@ fn print_main_help() -> String {
 Default behavior (no subcommand):
   - With no arguments: auto-detects *.s files in current directory or a.out, then debugs
   - With .s files: assembles them in-memory and debugs
+  - With .o files (from 'assemble -c' or a GNU assembler): links them in, too
   - With executable: debugs the executable
   Default subcommand is 'debug'
 
 Subcommands:
   assemble      Assemble RISC-V source files to executable on disk
+  link          Link relocatable objects (from 'assemble -c') into an executable
   run           Run executable or .s files and exit (default if no subcommand)
   debug         Debug executable or .s files with interactive TUI
   disassemble   Disassemble executable or .s files
   trace         Execute and print each instruction with effects
+  fmt           Reformat .s files in place
+  lsp           Run a language server for editors on stdin/stdout
   help, -h      Show this help message
   --version Show version information
 
 File Arguments:
   - One or more .s files: assembles in-memory, then runs/debugs/etc.
-  - One executable (no .s extension): runs/debugs/disassembles that file
+  - .o files: relocatable objects linked in with the .s files
+  - One executable (no .s/.o extension): runs/debugs/disassembles that file
   - No files: auto-detects *.s files in current directory, or uses a.out
 
 Common Options:
@@ -464,16 +857,21 @@ Common Options:
   --hex / --no-hex              Display values in hexadecimal
   --show-addresses              Show addresses in disassembly
   --verbose-instructions        Show strict instructions (not pseudo)
+  --watch                       Reload and re-run whenever an input file changes
   -h, --help                    Show this help
 
 Assembler Options:
   -v, --verbose                 Show assembly statistics
   -t <address>                  Set text start address (default: 0x{:x})
+  --layout <file.toml>          Place sections in memory regions (instead of -t)
+  --keep-locals                 Keep .L labels in the symbol table
   --relax                       Enable all relaxations
   --no-relax                    Disable all relaxations
   --relax-gp / --no-relax-gp    GP-relative optimization (default: auto-detect)
   --relax-pseudo / --no-relax-pseudo    call/tail optimization
   --relax-compressed / --no-relax-compressed    RV32C compression
+  --relax-branches / --no-relax-branches    out-of-range branch expansion
+  -Wno-<warning>                Disable a warning (see 'assemble --help')
 
 Examples:
   risclet                          # Auto-detect *.s or a.out, debug (default)
@@ -481,9 +879,13 @@ Examples:
   risclet prog.s lib.s             # Assemble both files and debug
   risclet a.out                    # Debug a.out
   risclet run prog.s               # Assemble and run prog.s (exit after completion)
+  risclet run --watch prog.s       # Re-run prog.s each time it is saved
   risclet trace a.out --check-abi  # Trace a.out with ABI checking
   risclet disassemble prog.s       # Assemble and disassemble
   risclet assemble -o prog prog.s  # Assemble to disk as 'prog'
+  risclet assemble -c lib.s        # Assemble to relocatable object lib.o
+  risclet link -o prog prog.s lib.o  # Link objects (and sources) to 'prog'
+  risclet fmt --check *.s          # List .s files that need formatting
 
 Use 'risclet <subcommand> --help' for subcommand-specific help.",
         if defaults.check_abi { "true" } else { "false" },
@@ -495,11 +897,21 @@ Use 'risclet <subcommand> --help' for subcommand-specific help.",
 /// Print assembler help message
 fn print_assemble_help(config: &Config) -> String {
     format!(
-        "Usage: risclet assemble [options] <file.s> [file.s...]
+        "Usage: risclet assemble [options] <file.s|file.o> [file.s|file.o...]
 
 Options:
     -o <file>            Write output to <file> (default: {})
+    -c                   Write each file to a relocatable object (<file>.o) instead of linking
     -t <address>         Set text start address (default: 0x{:x})
+    --layout <file.toml> Place sections in memory regions (instead of -t)
+    -a                   Print a listing of source, addresses and code
+    --listing=<file>     Write the listing to <file> instead
+    --map <file>         Write a map of sections and symbols to <file>
+    --format=<format>    Write elf (default), or a bin, ihex or memh memory image
+    --memh-width=<bits>  Word width of memh images: 8, 16, 32 (default) or 64
+    --split              Write text and data to separate images (a.text.bin, ...)
+    -L, --keep-locals    Keep .L labels in the symbol table
+    --watch              Re-assemble whenever an input file changes
     -v, --verbose        Show input statistics and relaxation progress
     --relax              Enable all relaxations
     --no-relax           Disable all relaxations
@@ -509,8 +921,52 @@ Options:
     --no-relax-pseudo    Disable 'call'/'tail' pseudo-instruction optimization
     --relax-compressed   Enable automatic RV32C compressed encoding (default: {})
     --no-relax-compressed Disable automatic RV32C compressed encoding
+    --relax-branches     Expand branches and jumps whose target is out of range (default: {})
+    --no-relax-branches  Report out-of-range branches and jumps as errors
+    -W<warning>          Enable a warning (all are enabled by default)
+    -Wno-<warning>       Disable a warning
+    -Wall, -w            Enable all warnings, or disable all of them
     -h, --help           Show this help message
 
+Memory Layout:
+  A --layout file declares memory regions and the region each section runs
+  from. .data may be stored in a different region (its load address, LMA)
+  from the one it runs in (its VMA); the program headers record both.
+
+    [regions.flash]
+    origin = 0x00000000
+    length = 0x10000
+
+    [regions.ram]
+    origin = 0x80000000
+    length = 0x4000
+
+    [sections.text]
+    region = \"flash\"
+
+    [sections.data]
+    region = \"ram\"
+    load_region = \"flash\"
+
+    [sections.bss]
+    region = \"ram\"
+
+  An optional [stack] table (region, size) tells the simulator where to put
+  the stack; it defaults to the top of the .bss region.
+
+Warnings:
+  Suspicious but legal code is reported on stderr with its warning name,
+  which the -W flags take:
+    unused-symbol         A label or .equ symbol that is never used
+    unreachable-code      Code after j, ret or tail that no label leads to
+    write-x0              A result written to x0, except in nop, j and ret
+    temp-across-call      A t register read after a call (which may change it)
+    missing-global-start  A _start label without '.global _start'
+    data-in-text          Data directives in .text
+    li-wrap               A number above 0x7fffffff negated, divided or
+                          shifted right in li, where it counts as negative
+  A '# risclet: -Wno-<warning> ...' comment applies the flags to its own file.
+
 Output Behavior:
   By default, successful assembly produces no output
   Use -v to see input statistics and relaxation progress during assembly.
@@ -549,8 +1005,72 @@ Examples:
   risclet assemble --dump-code program.s            # Dump generated code (no stats)
   risclet assemble -v --dump-code program.s         # Show stats AND code dump
   risclet assemble --dump-elf=headers,symbols prog.s # Dump ELF metadata
+  risclet assemble -c lib.s                         # Write relocatable object lib.o
+  risclet assemble --layout board.toml prog.s       # Code in flash, data in RAM
+  risclet assemble --listing=prog.lst prog.s        # Also write a listing
+  risclet assemble --map prog.map prog.s            # Also write a symbol map
+  risclet assemble --format=memh -t 0 prog.s        # $readmemh image for an FPGA
 
 Note: When any --dump-* option is used, no output file is generated.",
+        config.output_file,
+        config.text_start,
+        if config.relax.pseudo { "on" } else { "off" },
+        if config.relax.compressed { "on" } else { "off" },
+        if config.relax.branches { "on" } else { "off" }
+    )
+}
+
+/// Print linker help message
+fn print_link_help(config: &Config) -> String {
+    format!(
+        "Usage: risclet link [options] <file.o|file.s> [file.o|file.s...]
+
+Links relocatable objects into an executable. Objects may come from
+'risclet assemble -c' or from a GNU assembler (riscv64-unknown-elf-as
+-march=rv32imac). Source files may be listed too; they are assembled as if
+by 'assemble'. 'assemble', 'run', 'debug' and 'trace' accept objects too.
+
+Options:
+    -o <file>            Write output to <file> (default: {})
+    -t <address>         Set text start address (default: 0x{:x})
+    --layout <file.toml> Place sections in memory regions (instead of -t)
+    -a                   Print a listing of source, addresses and code
+    --listing=<file>     Write the listing to <file> instead
+    --map <file>         Write a map of sections and symbols to <file>
+    --format=<format>    Write elf (default), or a bin, ihex or memh memory image
+    --memh-width=<bits>  Word width of memh images: 8, 16, 32 (default) or 64
+    --split              Write text and data to separate images (a.text.bin, ...)
+    -L, --keep-locals    Keep .L labels in the symbol table
+    -v, --verbose        Show input statistics and relaxation progress
+    --relax              Enable all relaxations
+    --no-relax           Disable all relaxations
+    --relax-gp           Enable GP-relative 'la' optimization (default: auto)
+    --no-relax-gp        Disable GP-relative 'la' optimization
+    --relax-pseudo       Enable 'call'/'tail' pseudo-instruction optimization (default: {})
+    --no-relax-pseudo    Disable 'call'/'tail' pseudo-instruction optimization
+    --relax-compressed   Enable automatic RV32C compressed encoding (default: {})
+    --no-relax-compressed Disable automatic RV32C compressed encoding
+    -W<warning>          Enable a warning (all are enabled by default)
+    -Wno-<warning>       Disable a warning
+    -Wall, -w            Enable all warnings, or disable all of them
+    -h, --help           Show this help message
+
+Relaxation:
+  Instructions an object marks with R_RISCV_RELAX are relaxed according to the
+  options above, exactly as if the source had been assembled in one step.
+  Instructions without it keep the form they had in the object.
+
+  Label differences (such as 'end - start') in risclet objects are computed
+  when each object is assembled, so they do not account for relaxation done
+  by the linker. GNU objects record them as relocations and stay exact.
+
+The --dump-* options of 'assemble' are also accepted.
+
+Examples:
+  risclet assemble -c main.s lib.s                  # Write main.o and lib.o
+  risclet link -o prog main.o lib.o                 # Link them into 'prog'
+  risclet link --relax-compressed main.o lib.o      # Link with RV32C relaxation
+  risclet run main.s helpers.o                      # Link a GNU object and run",
         config.output_file,
         config.text_start,
         if config.relax.pseudo { "on" } else { "off" },
@@ -558,6 +1078,58 @@ Note: When any --dump-* option is used, no output file is generated.",
     )
 }
 
+/// Print fmt help message
+fn print_format_help() -> String {
+    "Usage: risclet fmt [options] [file.s...]
+
+Reformats source files in place. With no files, formats the *.s files in the
+current directory. If a file does not parse, its errors are reported and no
+file is changed.
+
+Labels go in the first column, mnemonics and directives in column 17, and
+trailing comments in column 41. Operands are separated by ', ' and binary
+operators surrounded by spaces. Numbers, symbols and comments keep their
+spelling, and pseudo-instructions are not expanded. Formatting a formatted
+file changes nothing.
+
+Options:
+    --check              List the files that need formatting instead of
+                         rewriting them, and fail if there are any
+    --registers=abi      Write register names as ABI names, such as a0 and sp
+                         (default)
+    --registers=numeric  Write register names as x0-x31
+    -h, --help           Show this help message
+
+Examples:
+  risclet fmt                          # Format every .s file here
+  risclet fmt --check prog.s           # Fail if prog.s is not formatted
+  risclet fmt --registers=numeric lab.s  # Use x10 rather than a0"
+        .to_string()
+}
+
+/// Print lsp help message
+fn print_lsp_help() -> String {
+    "Usage: risclet lsp [--stdio]
+
+Runs a Language Server Protocol server on stdin and stdout, for editors to
+start. Each document is assembled on its own. For a program split across
+files, list them in the client's initialization options, relative to the
+workspace root:
+
+    \"initializationOptions\": {\"programs\": [[\"main.s\", \"lib.s\"]]}
+
+Features:
+  - Errors and warnings as you type
+  - Go to definition and find references for labels and .equ symbols
+  - Hover for a symbol's value or address, or an instruction's encoding
+  - Completion of mnemonics, registers and symbols
+
+Options:
+    --stdio      Communicate over stdin and stdout (the default)
+    -h, --help   Show this help message"
+        .to_string()
+}
+
 /// Print simulator help message
 fn print_simulator_help(config: &Config) -> String {
     let mode_str = match config.mode {
@@ -572,10 +1144,12 @@ fn print_simulator_help(config: &Config) -> String {
         format!("Usage: risclet {} [files...] [options]\n\n", mode_str);
 
     help.push_str("File Arguments:\n");
-    help.push_str("  One or more .s files          Assemble in-memory, then ");
+    help.push_str(
+        "  One or more .s/.o files       Assemble and link in-memory, then ",
+    );
     help.push_str(mode_str);
     help.push('\n');
-    help.push_str("  One executable (no .s ext)    ");
+    help.push_str("  One executable (no .s/.o ext) ");
     help.push_str(match config.mode {
         Mode::Run => "Run",
         Mode::Debug => "Debug",
@@ -590,6 +1164,28 @@ fn print_simulator_help(config: &Config) -> String {
     help.push_str(
         "  -e, --executable <path>       Explicitly specify executable\n",
     );
+    help.push_str(
+        "  A .bin, .hex or .memh image   Load a memory image instead of an ELF\n",
+    );
+    help.push_str(
+        "  --base <address>              Load .bin and .memh images here (default: -t)\n",
+    );
+    help.push_str(
+        "  --memh-width=<bits>           Word width of .memh images (default: 32)\n",
+    );
+    help.push_str(
+        "  --symbols <file>              Name addresses in an image from 'address name'\n",
+    );
+    help.push_str("                                lines\n");
+    if config.mode == Mode::Disassemble {
+        help.push_str(
+            "  --raw <file>                  Load a file as raw bytes at --base, whatever its\n",
+        );
+        help.push_str("                                name\n");
+        help.push_str(
+            "  --hex-words                   Read instruction words in hex from stdin\n",
+        );
+    }
     help.push('\n');
 
     help.push_str("Simulator Options:\n");
@@ -601,6 +1197,9 @@ fn print_simulator_help(config: &Config) -> String {
         "  -s, --steps <count>           Max execution steps (default: {})\n",
         config.max_steps
     ));
+    if config.mode != Mode::Disassemble {
+        help.push_str("  --watch                       Reload and re-run whenever an input file changes\n");
+    }
 
     if config.mode == Mode::Debug
         || config.mode == Mode::Disassemble
@@ -625,6 +1224,29 @@ fn print_simulator_help(config: &Config) -> String {
                 "off"
             }
         ));
+        if config.mode == Mode::Disassemble {
+            help.push_str("  --source                      Write source that assembles back to the same\n");
+            help.push_str(
+                "                                executable, data included\n",
+            );
+        } else {
+            help.push_str("  --source                      Show source lines in place of disassembly\n");
+            help.push_str("                                (needs debug info, as from .s files)\n");
+        }
+        if config.mode == Mode::Disassemble {
+            help.push_str(
+                "  --no-source                   Write disassembly (default)\n",
+            );
+        } else {
+            help.push_str(&format!(
+                "  --no-source                   Show disassembly (default: {})\n",
+                if config.show_source { "off" } else { "on" }
+            ));
+        }
+        if config.mode == Mode::Disassemble {
+            help.push_str("  --format=objdump              Lay out disassembly as objdump -d does, to diff\n");
+            help.push_str("                                against it (--verbose-instructions for no-aliases)\n");
+        }
     }
 
     help.push('\n');
@@ -634,6 +1256,8 @@ fn print_simulator_help(config: &Config) -> String {
         "  -t <address>                  Set text start address (default: 0x{:x})\n",
         config.text_start
     ));
+    help.push_str("  --layout <file.toml>          Place sections in memory regions (instead of -t);\n");
+    help.push_str("                                the stack goes at the top of a region, too\n");
     help.push_str("  --relax                       Enable all relaxations\n");
     help.push_str("  --no-relax                    Disable all relaxations\n");
     help.push_str("  --relax-gp / --no-relax-gp    GP-relative optimization (default: auto-detect)\n");
@@ -645,6 +1269,14 @@ fn print_simulator_help(config: &Config) -> String {
         "  --relax-compressed / --no-relax-compressed    RV32C compression (default: {})\n",
         if config.relax.compressed { "on" } else { "off" }
     ));
+    help.push_str(&format!(
+        "  --relax-branches / --no-relax-branches    Out-of-range branch expansion (default: {})\n",
+        if config.relax.branches { "on" } else { "off" }
+    ));
+    help.push_str("  -Wno-<warning>, -w            Disable a warning, or all of them (see\n");
+    help.push_str(
+        "                                'risclet assemble --help')\n",
+    );
 
     help.push('\n');
     help.push_str("Other:\n");
@@ -653,7 +1285,8 @@ fn print_simulator_help(config: &Config) -> String {
     if config.mode == Mode::Debug {
         help.push_str("\nInteractive Controls (in debugger):\n");
         help.push_str("  Press '?' in the debugger for keyboard shortcuts\n");
-        help.push_str("  Key toggles: x (hex), v (verbose), a (addresses), r/o/s/d (panels)\n");
+        help.push_str("  Key toggles: x (hex), v (verbose), a (addresses), l (source lines),\n");
+        help.push_str("               r/o/s/d (panels)\n");
     }
 
     help.push_str("\nExamples:\n");
@@ -689,6 +1322,20 @@ fn print_simulator_help(config: &Config) -> String {
             mode_str
         ));
     }
+    if config.mode == Mode::Disassemble {
+        help.push_str(
+            "  risclet disassemble --source a.out > a.s   # Source to re-assemble\n",
+        );
+        help.push_str(
+            "  risclet disassemble --format=objdump a.out # Compare with objdump -d\n",
+        );
+        help.push_str(
+            "  echo 00a50533 | risclet disassemble --hex-words   # Decode words\n",
+        );
+        help.push_str(
+            "  risclet disassemble --raw dump --base 0x80000000  # Memory dump\n",
+        );
+    }
 
     help
 }
@@ -705,7 +1352,7 @@ fn parse_address(s: &str) -> Result<u32, String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Mode, parse_cli_args};
+    use super::{ImageFormat, Mode, parse_cli_args};
 
     #[test]
     fn parse_assemble_relax_enables_all_relaxations() {
@@ -750,6 +1397,158 @@ mod tests {
         assert_eq!(config.relax.gp, Some(false));
         assert!(!config.relax.pseudo);
         assert!(!config.relax.compressed);
+        assert!(!config.relax.branches);
+    }
+
+    #[test]
+    fn parse_relax_branches_is_on_by_default() {
+        let args = vec!["assemble".to_string(), "prog.s".to_string()];
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert!(config.relax.branches);
+
+        let args = vec![
+            "assemble".to_string(),
+            "--no-relax-branches".to_string(),
+            "prog.s".to_string(),
+        ];
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert!(!config.relax.branches);
+        assert!(config.relax.pseudo);
+    }
+
+    #[test]
+    fn parse_watch_for_run_and_assemble_but_not_disassemble() {
+        for mode in ["run", "trace", "debug", "assemble"] {
+            let args = vec![
+                mode.to_string(),
+                "--watch".to_string(),
+                "prog.s".to_string(),
+            ];
+            let config = parse_cli_args(&args).expect("parse should succeed");
+            assert!(config.watch, "{}", mode);
+        }
+
+        let args = vec![
+            "disassemble".to_string(),
+            "--watch".to_string(),
+            "prog.s".to_string(),
+        ];
+        assert!(parse_cli_args(&args).is_err());
+    }
+
+    #[test]
+    fn parse_map_file() {
+        let args = vec![
+            "assemble".to_string(),
+            "--map".to_string(),
+            "prog.map".to_string(),
+            "prog.s".to_string(),
+        ];
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert_eq!(config.map_file.as_deref(), Some("prog.map"));
+        assert_eq!(config.input_files, vec!["prog.s"]);
+
+        let args = vec![
+            "assemble".to_string(),
+            "-c".to_string(),
+            "--map".to_string(),
+            "prog.map".to_string(),
+            "prog.s".to_string(),
+        ];
+        assert!(parse_cli_args(&args).is_err());
+    }
+
+    #[test]
+    fn parse_image_formats() {
+        let args: Vec<String> = ["assemble", "--format=bin", "prog.s"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert_eq!(config.image_format, Some(ImageFormat::Bin));
+        assert_eq!(config.output_file, "a.bin");
+
+        let args: Vec<String> = [
+            "assemble",
+            "--format=memh",
+            "--memh-width=16",
+            "--split",
+            "prog.s",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert_eq!(config.image_format, Some(ImageFormat::Memh));
+        assert_eq!(config.memh_width, 2);
+        assert!(config.split_image);
+
+        for bad in [
+            &["assemble", "--split", "prog.s"][..],
+            &["assemble", "-c", "--format=ihex", "prog.s"][..],
+            &["assemble", "--format=srec", "prog.s"][..],
+        ] {
+            let args: Vec<String> = bad.iter().map(|s| s.to_string()).collect();
+            assert!(parse_cli_args(&args).is_err(), "{:?}", bad);
+        }
+
+        let args: Vec<String> = ["run", "--base", "0x80000000", "prog.hex"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert_eq!(config.image_format, Some(ImageFormat::Ihex));
+        assert_eq!(config.image_base, Some(0x8000_0000));
+    }
+
+    #[test]
+    fn parse_raw_images_and_hex_words() {
+        let args: Vec<String> = [
+            "disassemble",
+            "--raw",
+            "dump.hex",
+            "--base",
+            "0x80000000",
+            "--symbols",
+            "dump.sym",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert_eq!(config.executable, "dump.hex");
+        assert_eq!(config.image_format, Some(ImageFormat::Bin));
+        assert_eq!(config.symbol_file.as_deref(), Some("dump.sym"));
+
+        let args: Vec<String> = ["disassemble", "--hex-words"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = parse_cli_args(&args).expect("parse should succeed");
+        assert!(config.hex_words);
+        assert!(config.input_files.is_empty());
+        assert_eq!(config.image_format, Some(ImageFormat::Bin));
+
+        for bad in [
+            &["disassemble", "--hex-words", "a.out"][..],
+            &["disassemble", "--raw", "dump.bin", "a.out"][..],
+            &["run", "--raw", "dump.bin"][..],
+        ] {
+            let args: Vec<String> = bad.iter().map(|s| s.to_string()).collect();
+            assert!(parse_cli_args(&args).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_simulator_links_object_files() {
+        let args = vec![
+            "run".to_string(),
+            "prog.s".to_string(),
+            "helpers.o".to_string(),
+        ];
+        let config = parse_cli_args(&args).expect("parse should succeed");
+
+        assert_eq!(config.input_files, vec!["prog.s", "helpers.o"]);
     }
 
     #[test]
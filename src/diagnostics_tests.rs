@@ -0,0 +1,260 @@
+// diagnostics_tests.rs
+//
+// Tests for assembly error reporting: several errors from one run, column
+// spans and "did you mean" suggestions
+
+use crate::assembler::{assemble, assemble_object};
+use crate::config::Config;
+use crate::error::{MAX_REPORTED_ERRORS, RiscletError};
+use crate::suggest::closest_matches;
+use crate::tokenizer::REGISTER_NAMES;
+
+fn assemble_error(files: &[(&str, &str)]) -> RiscletError {
+    let sources = files
+        .iter()
+        .map(|(name, code)| (name.to_string(), code.to_string()))
+        .collect();
+    assemble(&mut Config::assemble_default(), sources).unwrap_err()
+}
+
+/// The (file, line) of each error, in reporting order
+fn error_lines(error: &RiscletError) -> Vec<(String, usize)> {
+    let errors = match error {
+        RiscletError::Multiple(errors) => errors.as_slice(),
+        error => std::slice::from_ref(error),
+    };
+    errors
+        .iter()
+        .map(|e| {
+            let location = e.location().expect("error without a location");
+            (location.file.clone(), location.line)
+        })
+        .collect()
+}
+
+const PROGRAM: &str = "        .text
+        .globl _start
+_start: li   a0, 1
+        addi a0, a0, nowhere
+        frob a1, a2
+bad:    add  a0, a1
+        beq  a0, a1, bad
+        lw   a0, 5000(a1)
+        j    nowhere
+        j    1f
+";
+
+#[test]
+fn test_errors_from_every_phase() {
+    let error = assemble_error(&[("bad.s", PROGRAM)]);
+    let lines: Vec<usize> =
+        error_lines(&error).into_iter().map(|(_, line)| line).collect();
+    assert_eq!(lines, [4, 5, 6, 8, 9, 10]);
+
+    let report = error.to_string();
+    assert!(report.contains("Unknown instruction 'frob'"), "{}", report);
+    assert!(report.contains("got 5000"), "{}", report);
+    assert!(report.contains("'1f' (forward)"), "{}", report);
+    assert!(report.trim_end().ends_with("6 errors"), "{}", report);
+}
+
+#[test]
+fn test_label_on_bad_line_is_still_defined() {
+    // `bad` is used on line 7, which must not be reported as undefined
+    let error = assemble_error(&[("bad.s", PROGRAM)]);
+    assert!(!error.to_string().contains("Symbol 'bad'"));
+}
+
+#[test]
+fn test_undefined_symbol_reported_at_each_use_only() {
+    // The poisoned symbol does not cause knock-on errors on the same lines
+    let error = assemble_error(&[(
+        "undef.s",
+        ".globl _start\n_start: call helper\n beq a0, a1, helper\n",
+    )]);
+    assert_eq!(
+        error_lines(&error),
+        [("undef.s".to_string(), 2), ("undef.s".to_string(), 3)]
+    );
+}
+
+#[test]
+fn test_errors_sorted_across_files() {
+    let error = assemble_error(&[
+        ("b.s", "addi a0, a0, 99999\n"),
+        ("a.s", ".globl _start\n_start:\n nop\n frob\n"),
+    ]);
+    assert_eq!(
+        error_lines(&error),
+        [("a.s".to_string(), 4), ("b.s".to_string(), 1)]
+    );
+}
+
+#[test]
+fn test_single_error_is_not_wrapped() {
+    let error =
+        assemble_error(&[("one.s", ".globl _start\n_start: frob a0\n")]);
+    assert!(matches!(error, RiscletError::Assembly { .. }));
+    assert!(!error.to_string().contains("errors"));
+}
+
+#[test]
+fn test_error_report_is_limited() {
+    let code = format!(".globl _start\n_start:\n{}", " frob\n".repeat(30));
+    let error = assemble_error(&[("many.s", &code)]);
+    assert_eq!(error_lines(&error).len(), 30);
+
+    let report = error.to_string();
+    assert_eq!(
+        report.matches("Unknown instruction").count(),
+        MAX_REPORTED_ERRORS
+    );
+    assert!(
+        report.trim_end().ends_with("30 errors (only the first 20 are shown)"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn test_object_reports_every_error() {
+    let error = assemble_object(
+        &mut Config::assemble_default(),
+        "obj.s",
+        "call external\n frob\n1: j 2f\n beq a0, a1, 1b\n",
+    )
+    .unwrap_err();
+    let lines: Vec<usize> =
+        error_lines(&error).into_iter().map(|(_, line)| line).collect();
+    assert_eq!(lines, [2, 3]);
+}
+
+// ============================================================================
+// COLUMNS AND SUGGESTIONS
+// ============================================================================
+
+/// The one error from assembling a single file, with its message and the
+/// source text its span covers
+fn single_error(code: &str) -> (String, String) {
+    let error = assemble_error(&[("one.s", code)]);
+    let location = error.location().expect("expected a single error");
+    let span = location.span.expect("error without a column span");
+    let line = code.lines().nth(location.line - 1).unwrap();
+    let text = line.chars().skip(span.start).take(span.end - span.start);
+    (error.message(), text.collect())
+}
+
+#[test]
+fn test_parse_error_columns() {
+    let (message, text) =
+        single_error(".globl _start\n_start: add a0, a1, 4\n");
+    assert!(message.contains("Expected a register"), "{}", message);
+    assert_eq!(text, "4");
+
+    let (message, text) = single_error(".globl _start\n_start: li a0\n");
+    assert!(message.contains("reached end of line"), "{}", message);
+    assert_eq!(text, "");
+
+    let (_, text) = single_error(".globl _start\n_start: .byte 1 = 2\n");
+    assert_eq!(text, "=");
+}
+
+#[test]
+fn test_operand_columns() {
+    let (message, text) =
+        single_error(".globl _start\n_start:\n\tlw a0, 5000(a1)\n");
+    assert!(message.contains("got 5000"), "{}", message);
+    assert_eq!(text, "5000(a1)");
+
+    let (_, text) =
+        single_error(".globl _start\n_start: beq a0, a1, 1f  # forward\n");
+    assert_eq!(text, "1f");
+
+    let (_, text) = single_error(".globl _start, helper\n_start: nop\n");
+    assert_eq!(text, "helper");
+}
+
+#[test]
+fn test_line_errors_point_at_mnemonic() {
+    // Errors about the whole line do not underline an operand
+    let (message, text) =
+        single_error(".globl _start\n_start: nop\n.bss\n\tli a0, 1\n");
+    assert!(message.contains("Instructions cannot be used"), "{}", message);
+    assert_eq!(text, "li");
+
+    let (_, text) =
+        single_error(".globl _start\n_start: nop\n.bss\n\t.byte 1, 2\n");
+    assert_eq!(text, ".byte");
+}
+
+#[test]
+fn test_error_report_underlines_span() {
+    let path = std::env::temp_dir().join("risclet_diagnostics_caret.s");
+    let code = ".globl _start\n_start:\n\tadd a0, a1, 4\n";
+    std::fs::write(&path, code).unwrap();
+    let error = assemble_error(&[(path.to_str().unwrap(), code)]);
+    let report = error.to_string();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(report.contains(":3:14]"), "{}", report);
+    let lines: Vec<&str> = report.lines().collect();
+    let marked = lines.iter().position(|l| l.starts_with(">>>")).unwrap();
+    assert_eq!(lines[marked], ">>>    3: \tadd a0, a1, 4");
+    assert_eq!(lines[marked + 1], "          \t            ^");
+}
+
+#[test]
+fn test_mnemonic_suggestions() {
+    let (message, text) =
+        single_error(".globl _start\n_start: addd a0, a0, 1\n");
+    assert!(message.contains("(did you mean 'add' or 'addi'?)"), "{}", message);
+    assert_eq!(text, "addd");
+
+    let (message, _) = single_error(".globl _start\n_start: ecal\n");
+    assert!(message.contains("(did you mean 'ecall'?)"), "{}", message);
+
+    let (message, _) =
+        single_error(".globl _start\n_start: amoadd a0, a1, (a2)\n");
+    assert!(message.contains("'amoadd.w'"), "{}", message);
+
+    let (message, _) = single_error(".globl _start\n_start: frobnicate\n");
+    assert!(!message.contains("did you mean"), "{}", message);
+}
+
+#[test]
+fn test_register_suggestions() {
+    let (message, text) = single_error(".globl _start\n_start: mv a0, zer\n");
+    assert!(message.contains("(did you mean 'zero'?)"), "{}", message);
+    assert_eq!(text, "zer");
+
+    // Too many registers are equally close to a9 for a useful suggestion
+    let (message, _) = single_error(".globl _start\n_start: mv a0, a9\n");
+    assert!(!message.contains("did you mean"), "{}", message);
+}
+
+#[test]
+fn test_symbol_suggestions() {
+    let (message, text) =
+        single_error(".globl _start\n_start: j  lop\nloop: nop\n");
+    assert_eq!(
+        message,
+        "Symbol 'lop' is used but never defined (did you mean 'loop'?)"
+    );
+    assert_eq!(text, "lop");
+
+    // Globals from other files are suggested too
+    let error = assemble_error(&[
+        ("a.s", ".globl _start\n_start: call printint\n"),
+        ("b.s", ".globl print_int\nprint_int: ret\n"),
+    ]);
+    assert!(error.message().contains("(did you mean 'print_int'?)"));
+}
+
+#[test]
+fn test_closest_matches() {
+    assert_eq!(closest_matches("lop", ["loop", "lw", "top"]), ["loop", "top"]);
+    assert_eq!(closest_matches("LOOP", ["loop"]), ["loop"]);
+    assert_eq!(closest_matches("aad", ["add"]), ["add"]);
+    assert!(closest_matches("x", ["abc"]).is_empty());
+    assert!(closest_matches("a9", REGISTER_NAMES.iter().copied()).is_empty());
+}
@@ -0,0 +1,648 @@
+// disassembly.rs
+//
+// Disassembly to source that assembles back to the same executable
+// (disassemble --source)
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use crate::config::TEXT_START_DEFAULT;
+use crate::elf::compute_header_size;
+use crate::execution::Machine;
+use crate::memory::Segment;
+use crate::riscv::{Field, Op, R};
+
+/// Runs of zeros at least this long become `.space`
+const MIN_ZERO_RUN: usize = 8;
+
+/// Runs of printable characters at least this long, ending in a NUL,
+/// become `.asciz`
+const MIN_STRING: usize = 4;
+
+/// The loaded segments, in address order: code, initialized data and
+/// zero-filled data
+struct Sections<'a> {
+    text: Vec<&'a Segment>,
+    data: Vec<&'a Segment>,
+    bss: Vec<&'a Segment>,
+}
+
+impl<'a> Sections<'a> {
+    fn new(m: &'a Machine) -> Self {
+        let mut segments: Vec<&Segment> = m.loaded_segments().iter().collect();
+        segments.sort_by_key(|segment| segment.start());
+        let (text, rest): (Vec<_>, Vec<_>) =
+            segments.into_iter().partition(|segment| segment.is_executable());
+        let (data, bss) =
+            rest.into_iter().partition(|segment| !segment.init().is_empty());
+        Sections { text, data, bss }
+    }
+}
+
+/// A run of sections as the assembler lays them out: one block starting
+/// at `address`, with zeros wherever there was a gap between them
+struct Area {
+    address: u32,
+    bytes: Vec<u8>,
+}
+
+impl Area {
+    fn new(sections: &[&Segment], address: u32) -> Self {
+        let end = sections.last().map_or(address, |section| section.end());
+        let mut bytes = vec![0; end.saturating_sub(address) as usize];
+        for section in sections {
+            let Some(offset) = section.start().checked_sub(address) else {
+                continue;
+            };
+            let init = section.init();
+            let offset = offset as usize;
+            bytes[offset..offset + init.len()].copy_from_slice(init);
+        }
+        Area { address, bytes }
+    }
+
+    fn end(&self) -> u32 {
+        self.address + self.bytes.len() as u32
+    }
+}
+
+/// One line of the text segment
+enum TextItem {
+    Instruction {
+        op: Op,
+        inst: i32,
+    },
+    /// Bytes that do not decode to an instruction risclet knows
+    Raw,
+}
+
+/// The `-t` address that puts the disassembled source's text where the
+/// executable has it, if it leaves room for the ELF headers
+pub fn nominal_text_start(m: &Machine) -> Option<u32> {
+    let sections = Sections::new(m);
+    let text_start =
+        sections.text.first().map_or(m.text_start(), |section| section.start());
+    let has_data = !sections.data.is_empty() || !sections.bss.is_empty();
+    text_start.checked_sub(compute_header_size(if has_data { 3 } else { 2 }))
+}
+
+/// Disassemble a program to source that risclet assembles back to the same
+/// code and data at the same addresses
+///
+/// Instructions are written as they were encoded, with no pseudo-
+/// instructions and compressed ones spelled out (`c.addi`), so nothing
+/// depends on how the assembler chooses encodings. Branch targets get
+/// labels. Data is written as strings where it looks like text, and as
+/// words and bytes otherwise. If the data started further along than the
+/// assembler would put it, `.space` fills the gap.
+pub fn disassemble_to_source(m: &Machine) -> String {
+    let sections = Sections::new(m);
+    let text_start =
+        sections.text.first().map_or(m.text_start(), |section| section.start());
+    let text = Area::new(&sections.text, text_start);
+    let items = decode_text(&text);
+
+    let data_start = (text.end() + 4095) & !4095;
+    let data = sections
+        .data
+        .first()
+        .map(|first| Area::new(&sections.data, data_start.min(first.start())));
+    let bss_start = data.as_ref().map_or(data_start, |data| data.end());
+    let bss = sections
+        .bss
+        .first()
+        .map(|first| Area::new(&sections.bss, bss_start.min(first.start())));
+
+    // A label where one area ends and the next starts goes with the next
+    let mut labels = Labels::new(m, &items);
+    let starts = |end: u32| {
+        [&data, &bss].into_iter().flatten().any(|area| area.address == end)
+    };
+    let mut body = String::new();
+    writeln!(body, "        .text").unwrap();
+    write_text(&mut body, &text, &items, &mut labels);
+    if !starts(text.end()) {
+        labels.write(&mut body, text.end());
+    }
+    let mut notes = Vec::new();
+    if let Some(data) = &data {
+        writeln!(body, "\n        .data").unwrap();
+        write_data(&mut body, data, &mut labels);
+        if !starts(data.end()) {
+            labels.write(&mut body, data.end());
+        }
+        if data.address != data_start {
+            notes.push(format!(
+                "# .data starts at 0x{:x}, but will move to 0x{:x}",
+                data.address, data_start
+            ));
+        }
+    }
+    if let Some(bss) = &bss {
+        writeln!(body, "\n        .bss").unwrap();
+        write_bss(&mut body, bss, &mut labels);
+        labels.write(&mut body, bss.end());
+        if bss.address != bss_start {
+            notes.push(format!(
+                "# .bss starts at 0x{:x}, but will move to 0x{:x}",
+                bss.address, bss_start
+            ));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "# Disassembled by risclet").unwrap();
+    // Labels nothing names, code after jumps that only the executable's
+    // addresses reach, hints and undecoded bytes are all as the executable
+    // has them
+    writeln!(
+        out,
+        "# risclet: -Wno-unused-symbol -Wno-unreachable-code -Wno-write-x0 \
+         -Wno-data-in-text"
+    )
+    .unwrap();
+    match nominal_text_start(m) {
+        Some(TEXT_START_DEFAULT) => {}
+        Some(nominal) => writeln!(
+            out,
+            "# Assemble with -t 0x{:x} to keep .text at 0x{:x}",
+            nominal, text_start
+        )
+        .unwrap(),
+        None => writeln!(
+            out,
+            "# Assemble with --format=bin -t 0x{:x} to keep .text there",
+            text_start
+        )
+        .unwrap(),
+    }
+    for note in notes {
+        writeln!(out, "{}", note).unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "        .option norvc").unwrap();
+    writeln!(out, "        .option norelax").unwrap();
+    writeln!(out, "        .globl  _start").unwrap();
+    // Only global constants go in the symbol table
+    for (name, value) in labels.unplaced(m) {
+        writeln!(out, "        .globl  {}", name).unwrap();
+        writeln!(out, "        .equ    {}, 0x{:x}", name, value).unwrap();
+    }
+    writeln!(out).unwrap();
+    out.push_str(&body);
+    out
+}
+
+/// Split the text segment into instructions, stopping wherever the bytes
+/// do not decode
+fn decode_text(text: &Area) -> Vec<(u32, u32, TextItem)> {
+    let bytes = &text.bytes;
+    let mut items = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let address = text.address + offset as u32;
+        let (inst, length) = match rest {
+            [low, _, _, _, ..] if low & 3 == 3 => {
+                (i32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]), 4)
+            }
+            [low, high, ..] if low & 3 != 3 => {
+                (u16::from_le_bytes([*low, *high]) as i32, 2)
+            }
+            _ => {
+                // Part of an instruction at the end: keep it as it is
+                let length = rest.len().min(2) as u32;
+                items.push((address, length, TextItem::Raw));
+                offset += length as usize;
+                continue;
+            }
+        };
+        let item = match Op::new(inst) {
+            Op::Unimplemented { .. } => TextItem::Raw,
+            op => TextItem::Instruction { op, inst },
+        };
+        items.push((address, length, item));
+        offset += length as usize;
+    }
+    items
+}
+
+/// The labels the source defines: the executable's symbols, `_start` at the
+/// entry point, and `.L` labels for branch targets with no symbol
+struct Labels {
+    names: BTreeMap<u32, String>,
+    placed: HashSet<u32>,
+}
+
+impl Labels {
+    fn new(m: &Machine, items: &[(u32, u32, TextItem)]) -> Self {
+        let mut symbols: Vec<(&u32, &String)> = m
+            .address_symbols
+            .iter()
+            .filter(|(_, name)| {
+                is_label_name(name)
+                    && name.as_str() != "__global_pointer$"
+                    && name.as_str() != "_start"
+            })
+            .collect();
+        symbols.sort();
+
+        let mut names = BTreeMap::new();
+        let mut used = HashSet::from(["_start".to_string()]);
+        names.insert(m.entry_point(), "_start".to_string());
+        for (&address, name) in symbols {
+            if !names.contains_key(&address) && used.insert(name.clone()) {
+                names.insert(address, name.clone());
+            }
+        }
+
+        let starts: HashSet<u32> =
+            items.iter().map(|(address, ..)| *address).collect();
+        let mut targets: Vec<u32> = items
+            .iter()
+            .filter_map(|(address, _, item)| match item {
+                TextItem::Instruction { op, .. } => jump_target(op, *address),
+                TextItem::Raw => None,
+            })
+            .filter(|target| starts.contains(target))
+            .collect();
+        targets.sort();
+        let mut next = 1;
+        for target in targets {
+            if names.contains_key(&target) {
+                continue;
+            }
+            let name = loop {
+                let name = format!(".L{}", next);
+                next += 1;
+                if used.insert(name.clone()) {
+                    break name;
+                }
+            };
+            names.insert(target, name);
+        }
+
+        Labels { names, placed: HashSet::new() }
+    }
+
+    /// Write the label at `address`, if there is one and it is not written
+    /// yet
+    fn write(&mut self, out: &mut String, address: u32) {
+        if let Some(name) = self.names.get(&address)
+            && self.placed.insert(address)
+        {
+            writeln!(out, "{}:", name).unwrap();
+        }
+    }
+
+    /// The addresses of labels after `start` and before `end`
+    fn between(&self, start: u32, end: u32) -> Vec<u32> {
+        self.names
+            .range(start..end)
+            .map(|(&address, _)| address)
+            .filter(|&address| address > start)
+            .collect()
+    }
+
+    /// Symbols that are not at the start of a line, with the executable's
+    /// constants, to define with `.equ`
+    fn unplaced(&self, m: &Machine) -> Vec<(String, u32)> {
+        let mut constants: Vec<(String, u32)> = self
+            .names
+            .iter()
+            .filter(|(address, _)| !self.placed.contains(address))
+            .map(|(&address, name)| (name.clone(), address))
+            .collect();
+        let used: HashSet<String> = self.names.values().cloned().collect();
+        constants.extend(
+            m.other_symbols
+                .iter()
+                .filter(|(name, _)| {
+                    is_label_name(name) && !used.contains(*name)
+                })
+                .map(|(name, &value)| (name.clone(), value)),
+        );
+        constants.sort();
+        constants
+    }
+}
+
+/// Where a branch or jump goes
+fn jump_target(op: &Op, pc: u32) -> Option<u32> {
+    match *op {
+        Op::Beq { offset, .. }
+        | Op::Bne { offset, .. }
+        | Op::Blt { offset, .. }
+        | Op::Bge { offset, .. }
+        | Op::Bltu { offset, .. }
+        | Op::Bgeu { offset, .. }
+        | Op::Jal { offset, .. } => Some(pc.wrapping_add(offset as u32)),
+        _ => None,
+    }
+}
+
+/// Whether the assembler accepts a symbol's name as a label
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '_' | '.'))
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.'))
+}
+
+fn write_text(
+    out: &mut String,
+    text: &Area,
+    items: &[(u32, u32, TextItem)],
+    labels: &mut Labels,
+) {
+    for (address, length, item) in items {
+        labels.write(out, *address);
+        let line = match item {
+            TextItem::Instruction { op, inst } if *length == 2 => {
+                compressed_instruction(op, *inst, *address, labels)
+            }
+            TextItem::Instruction { op, .. } => {
+                Some(instruction(op, *address, labels))
+            }
+            TextItem::Raw => None,
+        };
+        let line = line.unwrap_or_else(|| {
+            let offset = (address - text.address) as usize;
+            let bytes = &text.bytes[offset..offset + *length as usize];
+            match *bytes {
+                [a, b, c, d] => format!(
+                    ".4byte  0x{:08x}",
+                    u32::from_le_bytes([a, b, c, d])
+                ),
+                [a, b] => {
+                    format!(".2byte  0x{:04x}", u16::from_le_bytes([a, b]))
+                }
+                _ => format!(".byte   0x{:02x}", bytes[0]),
+            }
+        });
+        writeln!(out, "        {}", line).unwrap();
+    }
+}
+
+/// A base instruction, with its operands as they were encoded
+fn instruction(op: &Op, pc: u32, labels: &Labels) -> String {
+    let fields = op.to_fields();
+    let upper = matches!(op, Op::Lui { .. } | Op::Auipc { .. });
+    let ordering = ordering_suffix(op);
+    let operands: Vec<String> = fields[1..]
+        .iter()
+        .map(|field| match field {
+            Field::Imm(imm) if upper => ((*imm as u32) >> 12).to_string(),
+            Field::PCRelAddr(offset) => target(pc, *offset, labels),
+            // Atomics take a bare base register
+            Field::Indirect(_, reg) if ordering.is_some() => {
+                format!("({})", R[*reg])
+            }
+            Field::Indirect(offset, reg) => format!("{}({})", offset, R[*reg]),
+            field => field.to_string(pc, 0, false, true, &Default::default()),
+        })
+        .collect();
+    let Field::Opcode(mnemonic) = fields[0] else {
+        unreachable!("instruction fields start with the opcode");
+    };
+    line(&format!("{}{}", mnemonic, ordering.unwrap_or("")), &operands)
+}
+
+/// A compressed instruction spelled out as the `c.` instruction it was,
+/// or None if the assembler has no way to write it (a hint)
+fn compressed_instruction(
+    op: &Op,
+    inst: i32,
+    pc: u32,
+    labels: &Labels,
+) -> Option<String> {
+    let quadrant = inst & 3;
+    let funct3 = (inst >> 13) & 7;
+    let r = |reg: usize| R[reg].to_string();
+    let (mnemonic, operands) = match (quadrant, funct3, op) {
+        (0, 0, &Op::Addi { rd, imm, .. }) => {
+            ("c.addi4spn", vec![r(rd), r(2), imm.to_string()])
+        }
+        (0, 2, &Op::Lw { rd, rs1, offset }) => {
+            ("c.lw", vec![r(rd), format!("{}({})", offset, R[rs1])])
+        }
+        (0, 6, &Op::Sw { rs1, rs2, offset }) => {
+            ("c.sw", vec![r(rs2), format!("{}({})", offset, R[rs1])])
+        }
+        (1, 0, &Op::Addi { rd: 0, imm: 0, .. }) => ("c.nop", vec![]),
+        (1, 0, &Op::Addi { rd, imm, .. }) if rd != 0 && imm != 0 => {
+            ("c.addi", vec![r(rd), imm.to_string()])
+        }
+        (1, 1, &Op::Jal { offset, .. }) => {
+            ("c.jal", vec![target(pc, offset, labels)])
+        }
+        (1, 2, &Op::Addi { rd, imm, .. }) if rd != 0 => {
+            ("c.li", vec![r(rd), imm.to_string()])
+        }
+        (1, 3, &Op::Addi { imm, .. }) => {
+            ("c.addi16sp", vec![r(2), imm.to_string()])
+        }
+        (1, 3, &Op::Lui { rd, imm }) if rd != 0 => {
+            ("c.lui", vec![r(rd), (imm >> 12).to_string()])
+        }
+        (1, 4, &Op::Srli { rd, shamt, .. }) => {
+            ("c.srli", vec![r(rd), shamt.to_string()])
+        }
+        (1, 4, &Op::Srai { rd, shamt, .. }) => {
+            ("c.srai", vec![r(rd), shamt.to_string()])
+        }
+        (1, 4, &Op::Andi { rd, imm, .. }) => {
+            ("c.andi", vec![r(rd), imm.to_string()])
+        }
+        (1, 4, &Op::Sub { rd, rs2, .. }) => ("c.sub", vec![r(rd), r(rs2)]),
+        (1, 4, &Op::Xor { rd, rs2, .. }) => ("c.xor", vec![r(rd), r(rs2)]),
+        (1, 4, &Op::Or { rd, rs2, .. }) => ("c.or", vec![r(rd), r(rs2)]),
+        (1, 4, &Op::And { rd, rs2, .. }) => ("c.and", vec![r(rd), r(rs2)]),
+        (1, 5, &Op::Jal { offset, .. }) => {
+            ("c.j", vec![target(pc, offset, labels)])
+        }
+        (1, 6, &Op::Beq { rs1, offset, .. }) => {
+            ("c.beqz", vec![r(rs1), target(pc, offset, labels)])
+        }
+        (1, 7, &Op::Bne { rs1, offset, .. }) => {
+            ("c.bnez", vec![r(rs1), target(pc, offset, labels)])
+        }
+        (2, 0, &Op::Slli { rd, shamt, .. }) if rd != 0 && shamt != 0 => {
+            ("c.slli", vec![r(rd), shamt.to_string()])
+        }
+        (2, 2, &Op::Lw { rd, offset, .. }) => {
+            ("c.lwsp", vec![r(rd), format!("{}(sp)", offset)])
+        }
+        (2, 4, &Op::Jalr { rd: 0, rs1, .. }) => ("c.jr", vec![r(rs1)]),
+        (2, 4, &Op::Jalr { rs1, .. }) => ("c.jalr", vec![r(rs1)]),
+        (2, 4, &Op::Ebreak) => ("c.ebreak", vec![]),
+        (2, 4, &Op::Add { rd, rs1: 0, rs2 }) if rd != 0 => {
+            ("c.mv", vec![r(rd), r(rs2)])
+        }
+        (2, 4, &Op::Add { rd, rs2, .. }) if rd != 0 => {
+            ("c.add", vec![r(rd), r(rs2)])
+        }
+        (2, 6, &Op::Sw { rs2, offset, .. }) => {
+            ("c.swsp", vec![r(rs2), format!("{}(sp)", offset)])
+        }
+        _ => return None,
+    };
+    Some(line(mnemonic, &operands))
+}
+
+/// The `.aq`, `.rel` or `.aqrl` suffix of an atomic instruction, or None
+/// for other instructions
+fn ordering_suffix(op: &Op) -> Option<&'static str> {
+    Some(match ordering(op)? {
+        (true, true) => ".aqrl",
+        (true, false) => ".aq",
+        (false, true) => ".rel",
+        (false, false) => "",
+    })
+}
+
+/// The acquire and release bits of an atomic instruction, or None for
+/// other instructions
+pub fn ordering(op: &Op) -> Option<(bool, bool)> {
+    match *op {
+        Op::LrW { aq, rl, .. }
+        | Op::ScW { aq, rl, .. }
+        | Op::AmoswapW { aq, rl, .. }
+        | Op::AmoaddW { aq, rl, .. }
+        | Op::AmoxorW { aq, rl, .. }
+        | Op::AmoandW { aq, rl, .. }
+        | Op::AmoorW { aq, rl, .. }
+        | Op::AmominW { aq, rl, .. }
+        | Op::AmomaxW { aq, rl, .. }
+        | Op::AmominuW { aq, rl, .. }
+        | Op::AmomaxuW { aq, rl, .. } => Some((aq, rl)),
+        _ => None,
+    }
+}
+
+/// The label at a branch target, or its offset from the branch
+fn target(pc: u32, offset: i32, labels: &Labels) -> String {
+    let address = pc.wrapping_add(offset as u32);
+    match labels.names.get(&address) {
+        Some(name) => name.clone(),
+        None if offset < 0 => format!(". - {}", offset.unsigned_abs()),
+        None => format!(". + {}", offset),
+    }
+}
+
+fn line(mnemonic: &str, operands: &[String]) -> String {
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{:<7} {}", mnemonic, operands.join(", "))
+    }
+}
+
+/// Write initialized data, starting a new line at every label
+fn write_data(out: &mut String, data: &Area, labels: &mut Labels) {
+    let mut start = data.address;
+    for end in labels.between(data.address, data.end()) {
+        labels.write(out, start);
+        let offset = (start - data.address) as usize;
+        let length = (end - start) as usize;
+        write_bytes(out, &data.bytes[offset..offset + length]);
+        start = end;
+    }
+    labels.write(out, start);
+    let offset = (start - data.address) as usize;
+    write_bytes(out, &data.bytes[offset..]);
+}
+
+/// Write zero-filled data as `.space`, starting a new line at every label
+fn write_bss(out: &mut String, bss: &Area, labels: &mut Labels) {
+    let mut start = bss.address;
+    for end in labels.between(bss.address, bss.end()) {
+        labels.write(out, start);
+        writeln!(out, "        .space  {}", end - start).unwrap();
+        start = end;
+    }
+    labels.write(out, start);
+    if bss.end() > start {
+        writeln!(out, "        .space  {}", bss.end() - start).unwrap();
+    }
+}
+
+/// Write bytes as strings where they look like text, `.space` for runs of
+/// zeros, and words or bytes otherwise
+///
+/// Words line up with the label the bytes start at, which need not be
+/// aligned (`.4byte` does not align).
+fn write_bytes(out: &mut String, bytes: &[u8]) {
+    let aligned = |i: usize| i.is_multiple_of(4);
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if let Some(length) = string_length(rest) {
+            writeln!(out, "        .asciz  \"{}\"", escape(&rest[..length]))
+                .unwrap();
+            i += length + 1;
+        } else if zero_run(rest) >= MIN_ZERO_RUN {
+            writeln!(out, "        .space  {}", zero_run(rest)).unwrap();
+            i += zero_run(rest);
+        } else if aligned(i) && rest.len() >= 4 {
+            let mut words = Vec::new();
+            while words.len() < 4
+                && bytes.len() - i >= 4
+                && (words.is_empty() || !starts_run(&bytes[i..]))
+            {
+                let word = [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+                words.push(format!("0x{:08x}", u32::from_le_bytes(word)));
+                i += 4;
+            }
+            writeln!(out, "        .4byte  {}", words.join(", ")).unwrap();
+        } else {
+            let mut values = Vec::new();
+            while i < bytes.len()
+                && values.len() < 8
+                && (values.is_empty()
+                    || !(starts_run(&bytes[i..])
+                        || aligned(i) && bytes.len() - i >= 4))
+            {
+                values.push(format!("0x{:02x}", bytes[i]));
+                i += 1;
+            }
+            writeln!(out, "        .byte   {}", values.join(", ")).unwrap();
+        }
+    }
+}
+
+/// Whether bytes start with a string or a run of zeros
+fn starts_run(bytes: &[u8]) -> bool {
+    string_length(bytes).is_some() || zero_run(bytes) >= MIN_ZERO_RUN
+}
+
+/// The length of the NUL-terminated string the bytes start with, if they
+/// look like text
+fn string_length(bytes: &[u8]) -> Option<usize> {
+    let length = bytes
+        .iter()
+        .take_while(|&&b| (0x20..0x7f).contains(&b) || b"\n\t\r".contains(&b))
+        .count();
+    (length >= MIN_STRING && bytes.get(length) == Some(&0)).then_some(length)
+}
+
+fn zero_run(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| b == 0).count()
+}
+
+/// A string literal's contents, with the escapes the tokenizer knows
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &b in bytes {
+        match b {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b'\r' => text.push_str("\\r"),
+            _ => text.push(b as char),
+        }
+    }
+    text
+}
@@ -0,0 +1,207 @@
+// disassembly_tests.rs
+//
+// Tests for disassembly to source (disassemble --source)
+
+use crate::assembler::assemble_collecting_warnings;
+use crate::config::{Config, parse_cli_args};
+use crate::disassembly::{disassemble_to_source, nominal_text_start};
+use crate::execution::Machine;
+use crate::test_helpers::run_to_exit;
+
+/// Assemble source to an executable, at the text address `text_start`
+fn assemble_at(source: &str, text_start: u32) -> Vec<u8> {
+    let mut config = Config::assemble_default();
+    config.text_start = text_start;
+    let files = vec![("prog.s".to_string(), source.to_string())];
+    assemble_collecting_warnings(&mut config, files, &mut Vec::new())
+        .unwrap_or_else(|e| panic!("{}\n{}", e, source))
+}
+
+/// Assemble disassembled source as its header says to
+fn reassemble(m: &Machine, source: &str) -> Vec<u8> {
+    assemble_at(source, nominal_text_start(m).unwrap())
+}
+
+/// Check that every loaded byte of `original` is at the same address in
+/// `copy`
+fn assert_same_contents(name: &str, original: &Machine, copy: &mut Machine) {
+    for segment in original.loaded_segments() {
+        let size = segment.end() - segment.start();
+        let mut expected = segment.init().to_vec();
+        expected.resize(size as usize, 0);
+        let actual = copy.load(segment.start(), size).unwrap_or_else(|e| {
+            panic!("{}: 0x{:x}: {}", name, segment.start(), e)
+        });
+        assert!(
+            actual == expected,
+            "{}: contents differ at 0x{:x}",
+            name,
+            segment.start()
+        );
+    }
+}
+
+/// Check that disassembled source assembles back to the same contents,
+/// and disassembles again to the same source
+fn assert_round_trip(m: &Machine, source: &str) {
+    let mut copy =
+        Machine::from_elf(&reassemble(m, source), &mut Vec::new()).unwrap();
+    assert_same_contents("reassembled", m, &mut copy);
+    assert_eq!(disassemble_to_source(&copy), source);
+}
+
+#[test]
+fn test_binaries_round_trip() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/test_binaries");
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file() && path.extension().is_none())
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert!(names.len() > 50);
+
+    for name in names {
+        let bytes = std::fs::read(format!("{}/{}", dir, name)).unwrap();
+        let original = Machine::from_elf(&bytes, &mut Vec::new()).unwrap();
+        let source = disassemble_to_source(&original);
+        let executable = reassemble(&original, &source);
+
+        let mut copy = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+        assert_same_contents(&name, &original, &mut copy);
+        assert_eq!(run_to_exit(&mut copy), 0, "{}", name);
+
+        // Disassembling what risclet built gives the same source back, and
+        // that assembles to the same executable
+        let copy = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+        assert_eq!(disassemble_to_source(&copy), source, "{}", name);
+        assert!(reassemble(&copy, &source) == executable, "{}", name);
+    }
+}
+
+#[test]
+fn test_data_is_written_as_strings_words_and_bytes() {
+    let program = "
+        .globl _start
+_start: la   a0, message
+        li   a7, 93
+        ecall
+        .data
+message: .asciz \"Hello, \\\"world\\\"\\n\"
+        .balign 4
+numbers: .4byte 1, -1
+flags:  .byte 1, 2, 3
+        .space 12
+        .bss
+buffer: .space 64
+";
+    let executable = assemble_at(program, 0x10000);
+    let m = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+    let source = disassemble_to_source(&m);
+    assert_eq!(nominal_text_start(&m), Some(0x10000));
+
+    assert!(
+        source.contains(
+            "message:\n        .asciz  \"Hello, \\\"world\\\"\\n\"\n"
+        ),
+        "{}",
+        source
+    );
+    assert!(
+        source.contains(
+            "numbers:\n        .4byte  0x00000001, 0xffffffff\n\
+         flags:\n        .4byte  0x00030201\n        .space  11\n"
+        ),
+        "{}",
+        source
+    );
+    assert!(source.contains("\n        .bss\nbuffer:\n        .space  64\n"));
+    assert!(source.contains("_start:\n        auipc   a0, 1\n"));
+    assert_round_trip(&m, &source);
+}
+
+#[test]
+fn test_disassembled_source_assembles_without_warnings() {
+    let program = "
+        .globl _start
+_start: la   a0, word
+        lw   a0, 0(a0)
+        li   a7, 93
+        ecall
+        j    _start
+        .data
+message: .asciz \"hello\"
+word:   .4byte 0x12345678, 7
+after:  .4byte 0x11111111
+";
+    let executable = assemble_at(program, 0x10000);
+    let m = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+    let source = disassemble_to_source(&m);
+    assert!(
+        source.contains(
+            "word:\n        .4byte  0x12345678, 0x00000007\n\
+             after:\n        .4byte  0x11111111\n"
+        ),
+        "{}",
+        source
+    );
+
+    let mut config = Config::assemble_default();
+    let files = vec![("prog.s".to_string(), source.clone())];
+    let mut warnings = Vec::new();
+    assemble_collecting_warnings(&mut config, files, &mut warnings).unwrap();
+    assert!(warnings.is_empty(), "{:?}\n{}", warnings, source);
+    assert_round_trip(&m, &source);
+}
+
+#[test]
+fn test_branch_targets_get_labels_and_compressed_code_stays_compressed() {
+    let program = "
+        .globl _start
+_start: c.li  a0, 5
+        li    t0, 0
+1:      c.addi t0, 1
+        bne   t0, a0, 1b
+        jal   done
+        nop
+done:   li    a7, 93
+        ecall
+";
+    let executable = assemble_at(program, 0x10000);
+    let m = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+    let source = disassemble_to_source(&m);
+
+    assert!(source.contains("_start:\n        c.li    a0, 5\n"), "{}", source);
+    assert!(source.contains(".L1:\n        c.addi  t0, 1\n"), "{}", source);
+    assert!(source.contains("        bne     t0, a0, .L1\n"));
+    assert!(source.contains("        jal     ra, done\n"));
+    assert_round_trip(&m, &source);
+}
+
+#[test]
+fn test_source_flag_in_disassemble_mode() {
+    let args: Vec<String> = ["disassemble", "--source", "a.out"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let config = parse_cli_args(&args).unwrap();
+    assert!(config.source_output);
+    assert!(!config.show_source);
+
+    // --no-source turns it back off
+    let args: Vec<String> = ["disassemble", "--source", "--no-source", "a.out"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let config = parse_cli_args(&args).unwrap();
+    assert!(!config.source_output);
+    assert!(!config.show_source);
+
+    // Elsewhere, --source still shows source lines in place of disassembly
+    let args: Vec<String> =
+        ["trace", "--source", "a.out"].iter().map(|s| s.to_string()).collect();
+    let config = parse_cli_args(&args).unwrap();
+    assert!(!config.source_output);
+    assert!(config.show_source);
+}
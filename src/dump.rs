@@ -7,10 +7,10 @@ use crate::ast::{
     Directive, Expression, Instruction, Line, LineContent, LinePointer,
     Location, PseudoOp, Segment, Source, SourceFile,
 };
-use crate::config::Config;
+use crate::config::{Config, Relax};
 use crate::elf_builder::ElfBuilder;
 use crate::layout::{Layout, LineLayout};
-use crate::symbols::{BUILTIN_FILE_NAME, SymbolLinks};
+use crate::symbols::{BUILTIN_FILE_NAME, SymbolLinks, is_undefined_weak};
 
 /// Check if a file is the builtin symbols file that should be hidden from dumps
 fn is_builtin_file(file: &SourceFile) -> bool {
@@ -384,15 +384,26 @@ fn dump_pseudo_ast(pseudo: &PseudoOp) {
             dump_expression_ast(addr);
             print!(" {})", temp);
         }
-        PseudoOp::Call(target) => {
-            print!("(pseudo call ");
+        PseudoOp::Call(rd, target) => {
+            print!("(pseudo call {} ", rd);
             dump_expression_ast(target);
             print!(")");
         }
-        PseudoOp::Tail(target) => {
+        PseudoOp::Tail(target, temp) => {
             print!("(pseudo tail ");
             dump_expression_ast(target);
-            print!(")");
+            print!(" {})", temp);
+        }
+        PseudoOp::Jump(target, temp) => {
+            print!("(pseudo jump ");
+            dump_expression_ast(target);
+            print!(" {})", temp);
+        }
+        PseudoOp::Extend(op, rd, rs) => {
+            print!("(pseudo {} {} {})", op, rd, rs);
+        }
+        PseudoOp::Sle(op, rd, rs1, rs2) => {
+            print!("(pseudo sle {} {} {} {})", op, rd, rs1, rs2);
         }
     }
 }
@@ -411,6 +422,40 @@ fn dump_directive_ast(dir: &Directive) {
             dump_expression_ast(expr);
             print!(")");
         }
+        Directive::Equiv(name, expr) => {
+            print!("(directive equiv \"{}\" ", name);
+            dump_expression_ast(expr);
+            print!(")");
+        }
+        Directive::Weak(symbols) => {
+            print!("(directive weak");
+            for sym in symbols {
+                print!(" \"{}\"", sym);
+            }
+            print!(")");
+        }
+        Directive::Local(symbols) => {
+            print!("(directive local");
+            for sym in symbols {
+                print!(" \"{}\"", sym);
+            }
+            print!(")");
+        }
+        Directive::Comm(name, size, align)
+        | Directive::Lcomm(name, size, align) => {
+            let kind = if matches!(dir, Directive::Comm(..)) {
+                "comm"
+            } else {
+                "lcomm"
+            };
+            print!("(directive {} \"{}\" ", kind, name);
+            dump_expression_ast(size);
+            if let Some(align) = align {
+                print!(" ");
+                dump_expression_ast(align);
+            }
+            print!(")");
+        }
         Directive::Text => print!("(directive text)"),
         Directive::Data => print!("(directive data)"),
         Directive::Bss => print!("(directive bss)"),
@@ -462,6 +507,15 @@ fn dump_directive_ast(dir: &Directive) {
             }
             print!(")");
         }
+        Directive::Option(arg) => print!("(directive option {})", arg),
+        Directive::Type(name, kind) => {
+            print!("(directive type \"{}\" {})", name, kind)
+        }
+        Directive::Size(name, expr) => {
+            print!("(directive size \"{}\" ", name);
+            dump_expression_ast(expr);
+            print!(")");
+        }
     }
 }
 
@@ -539,6 +593,76 @@ fn dump_expression_ast(expr: &Expression) {
             dump_expression_ast(rhs);
             print!(")");
         }
+        Expression::EqualOp { lhs, rhs } => {
+            print!("(== ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::NotEqualOp { lhs, rhs } => {
+            print!("(!= ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::LessOp { lhs, rhs } => {
+            print!("(< ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::LessEqualOp { lhs, rhs } => {
+            print!("(<= ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::GreaterOp { lhs, rhs } => {
+            print!("(> ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::GreaterEqualOp { lhs, rhs } => {
+            print!("(>= ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::LogicalAndOp { lhs, rhs } => {
+            print!("(&& ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::LogicalOrOp { lhs, rhs } => {
+            print!("(|| ");
+            dump_expression_ast(lhs);
+            print!(" ");
+            dump_expression_ast(rhs);
+            print!(")");
+        }
+        Expression::ConditionalOp { condition, then_expr, else_expr } => {
+            print!("(?: ");
+            dump_expression_ast(condition);
+            print!(" ");
+            dump_expression_ast(then_expr);
+            print!(" ");
+            dump_expression_ast(else_expr);
+            print!(")");
+        }
+        Expression::LogicalNotOp { expr } => {
+            print!("(! ");
+            dump_expression_ast(expr);
+            print!(")");
+        }
         Expression::NegateOp { expr } => {
             print!("(neg ");
             dump_expression_ast(expr);
@@ -549,6 +673,16 @@ fn dump_expression_ast(expr: &Expression) {
             dump_expression_ast(expr);
             print!(")");
         }
+        Expression::HiOp { expr } => {
+            print!("(%hi ");
+            dump_expression_ast(expr);
+            print!(")");
+        }
+        Expression::LoOp { expr } => {
+            print!("(%lo ");
+            dump_expression_ast(expr);
+            print!(")");
+        }
         Expression::Parenthesized(expr) => {
             print!("(paren ");
             dump_expression_ast(expr);
@@ -633,10 +767,20 @@ pub fn dump_symbols(
             let decl_line =
                 &decl_file.lines[global.declaration_pointer.line_index];
 
-            println!(
-                "  {} → defined at {}, declared at {}",
-                global.symbol, def_line.location, decl_line.location
-            );
+            if is_undefined_weak(source, global) {
+                println!(
+                    "  {} ({}) → undefined (resolves to 0), declared at {}",
+                    global.symbol, global.binding, decl_line.location
+                );
+            } else {
+                println!(
+                    "  {} ({}) → defined at {}, declared at {}",
+                    global.symbol,
+                    global.binding,
+                    def_line.location,
+                    decl_line.location
+                );
+            }
         }
         println!();
     }
@@ -757,7 +901,8 @@ pub fn dump_code(
         for (line_index, line) in file.lines.iter().enumerate() {
             // Get absolute address and encoded bytes from layout
             let pointer = LinePointer { file_index, line_index };
-            let &LineLayout { segment, offset, size } = layout.get(pointer);
+            let &LineLayout { segment, offset, size, relax } =
+                layout.get(pointer);
 
             let abs_addr = layout.get_line_address(pointer);
             let encoded_bytes = get_encoded_bytes_with_layout(
@@ -804,6 +949,17 @@ pub fn dump_code(
                         if bytes_printed < 16 { 16 - bytes_printed } else { 1 };
                     print!("{:<width$}", "", width = instruction_padding);
                     print!("{}", inst);
+                    if encoded_bytes.len() > 4 {
+                        match inst {
+                            Instruction::BType(..) => print!(
+                                "  # out of range: inverted branch over jal"
+                            ),
+                            Instruction::JType(..) => {
+                                print!("  # out of range: auipc + jalr")
+                            }
+                            _ => {}
+                        }
+                    }
 
                     // If more than 4 bytes, print continuation lines with 4 bytes each
                     if encoded_bytes.len() > 4 {
@@ -836,10 +992,17 @@ pub fn dump_code(
                         // Directives with no encoded bytes (e.g., .text, .data, .global)
                         let formatted_addr =
                             format_address(abs_addr, addr_width, segment);
-                        println!(
+                        print!(
                             "{}{} {}: {}",
                             loc_str, padding, formatted_addr, line.content
                         );
+                        // Show the settings that apply from here on
+                        if let LineContent::Directive(Directive::Option(_)) =
+                            &line.content
+                        {
+                            print!("  # {}", format_relax(&relax));
+                        }
+                        println!();
                     } else {
                         // Directives with bytes: handle alignment
                         println!(
@@ -1135,6 +1298,7 @@ fn dump_elf_symbols<'a>(builder: &ElfBuilder<'a>) {
         let bind_str = match bind {
             0 => "LOCAL",
             1 => "GLOBAL",
+            2 => "WEAK",
             _ => "UNKNOWN",
         };
 
@@ -1164,6 +1328,16 @@ fn dump_elf_symbols<'a>(builder: &ElfBuilder<'a>) {
 // Helper Functions
 // ============================================================================
 
+/// Format relaxation settings the way `.option` spells them
+fn format_relax(relax: &Relax) -> String {
+    format!(
+        "{}, {}{}",
+        if relax.compressed { "rvc" } else { "norvc" },
+        if relax.pseudo { "relax" } else { "norelax" },
+        if relax.effective_gp() { ", gp" } else { "" }
+    )
+}
+
 /// Calculate the maximum line number width in a single source file for alignment purposes
 fn calculate_max_line_width_for_file(file: &SourceFile) -> usize {
     let mut max_line_num = 0;
@@ -1215,7 +1389,11 @@ fn calculate_address_width(text_start: u32) -> usize {
 /// addr_width: number of hex digits to use
 /// addr: the address to format
 /// segment_suffix: ".t", ".d", or ".b"
-fn format_address(addr: u32, addr_width: usize, segment: Segment) -> String {
+pub fn format_address(
+    addr: u32,
+    addr_width: usize,
+    segment: Segment,
+) -> String {
     let suffix = match segment {
         Segment::Text => ".t",
         Segment::Data => ".d",
@@ -1281,8 +1459,9 @@ fn collect_expression_values(line: &Line) -> Vec<String> {
                 Instruction::Pseudo(pseudo) => match pseudo {
                     PseudoOp::La(_, expr)
                     | PseudoOp::Li(_, expr)
-                    | PseudoOp::Call(expr)
-                    | PseudoOp::Tail(expr) => {
+                    | PseudoOp::Call(_, expr)
+                    | PseudoOp::Tail(expr, _)
+                    | PseudoOp::Jump(expr, _) => {
                         if let Expression::Literal(val) = expr.as_ref() {
                             results.push(format!("{}", val));
                         }
@@ -1293,11 +1472,15 @@ fn collect_expression_values(line: &Line) -> Vec<String> {
                             results.push(format!("{}", val));
                         }
                     }
+                    PseudoOp::Extend(..) | PseudoOp::Sle(..) => {}
                 },
                 _ => {}
             }
         }
-        LineContent::Directive(Directive::Equ(_, Expression::Literal(val))) => {
+        LineContent::Directive(
+            Directive::Equ(_, Expression::Literal(val))
+            | Directive::Equiv(_, Expression::Literal(val)),
+        ) => {
             // For .equ directives with literal values, show the value
             results.push(format!("= {}", val));
         }
@@ -0,0 +1,807 @@
+// dwarf.rs
+//
+// DWARF 5 debug information for assembled executables
+//
+// Each source file becomes one compile unit with a line-number program that
+// maps every address in .text back to the line that produced it, so tools
+// like gdb and `objdump -dl` can show the source. Functions (global labels
+// in .text, and labels marked with `.type name, @function`) get a
+// DW_TAG_subprogram entry each.
+//
+// Reference: DWARF Debugging Information Format, Version 5
+// https://dwarfstd.org/doc/DWARF5.pdf
+
+use std::collections::HashSet;
+
+use crate::ast::{
+    Directive, LineContent, LinePointer, Segment, Source, SourceFile,
+    SymbolType,
+};
+use crate::layout::Layout;
+use crate::symbols::SymbolLinks;
+
+// ============================================================================
+// DWARF Constants
+// ============================================================================
+
+const DWARF_VERSION: u16 = 5;
+const DW_UT_COMPILE: u8 = 0x01;
+const ADDRESS_SIZE: u8 = 4;
+
+const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_CHILDREN_NO: u8 = 0;
+const DW_CHILDREN_YES: u8 = 1;
+
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_LANGUAGE: u64 = 0x13;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_AT_EXTERNAL: u64 = 0x3f;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+
+const DW_LANG_MIPS_ASSEMBLER: u16 = 0x8001;
+
+const DW_LNCT_PATH: u64 = 0x1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 0x2;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Line program header settings (the same as GNU as uses)
+const LINE_BASE: i8 = -5;
+const LINE_RANGE: u8 = 14;
+const OPCODE_BASE: u8 = 13;
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Abbreviation codes used in .debug_info
+const ABBREV_COMPILE_UNIT: u64 = 1;
+const ABBREV_COMPILE_UNIT_NO_CODE: u64 = 2;
+const ABBREV_SUBPROGRAM: u64 = 3;
+const ABBREV_EXTERNAL_SUBPROGRAM: u64 = 4;
+
+const PRODUCER: &str = concat!("risclet ", env!("CARGO_PKG_VERSION"));
+
+// ============================================================================
+// Debug Sections
+// ============================================================================
+
+/// The contents of the debug sections for an executable
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugSections {
+    pub debug_abbrev: Vec<u8>,
+    pub debug_info: Vec<u8>,
+    pub debug_line: Vec<u8>,
+}
+
+impl DebugSections {
+    /// The sections in the order they go in the ELF file
+    pub fn sections(&self) -> [(&'static str, &[u8]); 3] {
+        [
+            (".debug_info", &self.debug_info),
+            (".debug_abbrev", &self.debug_abbrev),
+            (".debug_line", &self.debug_line),
+        ]
+    }
+}
+
+/// One row of the line table: the bytes a source line produced
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Row {
+    address: u32,
+    line: u32,
+    size: u32,
+}
+
+/// A function in one compile unit
+#[derive(Debug, Clone, PartialEq)]
+struct Subprogram {
+    name: String,
+    low_pc: u32,
+    high_pc: u32,
+    external: bool,
+}
+
+/// Build the debug sections for the source files of a linked program
+///
+/// Files that are not assembly source (linked objects and the assembler's
+/// own symbol files) get no compile unit.
+pub fn build_debug_sections(
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    layout: &Layout,
+) -> DebugSections {
+    let comp_dir = std::env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+
+    let mut debug_info = Vec::new();
+    let mut debug_line = Vec::new();
+    for (file_index, file) in source.files.iter().enumerate() {
+        if file.file.starts_with('<') || file.file.ends_with(".o") {
+            continue;
+        }
+        let rows = line_rows(file_index, file, layout);
+        let stmt_list = debug_line.len() as u32;
+        write_line_program(&mut debug_line, &file.file, &comp_dir, &rows);
+
+        let code_range = code_range(&rows);
+        let subprograms = match code_range {
+            Some((_, end)) => {
+                subprograms(file_index, file, symbol_links, layout, end)
+            }
+            None => Vec::new(),
+        };
+        write_compile_unit(
+            &mut debug_info,
+            &file.file,
+            &comp_dir,
+            stmt_list,
+            code_range,
+            &subprograms,
+        );
+    }
+
+    DebugSections { debug_abbrev: abbreviations(), debug_info, debug_line }
+}
+
+/// A row for every line in .text that produces bytes, in address order
+fn line_rows(
+    file_index: usize,
+    file: &SourceFile,
+    layout: &Layout,
+) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for (line_index, line) in file.lines.iter().enumerate() {
+        let pointer = LinePointer { file_index, line_index };
+        let line_layout = layout.get(pointer);
+        if line_layout.segment != Segment::Text || line_layout.size == 0 {
+            continue;
+        }
+        rows.push(Row {
+            address: layout.get_line_address(pointer),
+            line: line.location.line as u32,
+            size: line_layout.size,
+        });
+    }
+    rows.sort_by_key(|row| row.address);
+    rows
+}
+
+/// The addresses from the first to just past the last byte of the rows
+fn code_range(rows: &[Row]) -> Option<(u32, u32)> {
+    let start = rows.first()?.address;
+    let end = rows.iter().map(|row| row.address + row.size).max()?;
+    Some((start, end))
+}
+
+/// The functions defined in a file's .text: its global labels, and labels
+/// given `.type name, @function`. Each runs until the next one, or to the
+/// end of the file's code.
+fn subprograms(
+    file_index: usize,
+    file: &SourceFile,
+    symbol_links: &SymbolLinks,
+    layout: &Layout,
+    code_end: u32,
+) -> Vec<Subprogram> {
+    let typed_functions: HashSet<&str> = file
+        .lines
+        .iter()
+        .filter_map(|line| match &line.content {
+            LineContent::Directive(Directive::Type(
+                name,
+                SymbolType::Function,
+            )) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut subprograms = Vec::new();
+    for (line_index, line) in file.lines.iter().enumerate() {
+        let LineContent::Label(name) = &line.content else {
+            continue;
+        };
+        let pointer = LinePointer { file_index, line_index };
+        if layout.get(pointer).segment != Segment::Text {
+            continue;
+        }
+        let external = symbol_links
+            .global_symbols
+            .iter()
+            .any(|g| g.definition_pointer == pointer);
+        if external || typed_functions.contains(name.as_str()) {
+            let low_pc = layout.get_line_address(pointer);
+            subprograms.push(Subprogram {
+                name: name.clone(),
+                low_pc,
+                high_pc: code_end,
+                external,
+            });
+        }
+    }
+
+    subprograms.sort_by_key(|subprogram| subprogram.low_pc);
+    let starts: Vec<u32> = subprograms.iter().map(|s| s.low_pc).collect();
+    for subprogram in &mut subprograms {
+        if let Some(&next) =
+            starts.iter().find(|&&start| start > subprogram.low_pc)
+        {
+            subprogram.high_pc = next;
+        }
+    }
+    subprograms
+}
+
+// ============================================================================
+// .debug_abbrev and .debug_info
+// ============================================================================
+
+fn abbreviations() -> Vec<u8> {
+    let compile_unit = [
+        (DW_AT_PRODUCER, DW_FORM_STRING),
+        (DW_AT_LANGUAGE, DW_FORM_DATA2),
+        (DW_AT_NAME, DW_FORM_STRING),
+        (DW_AT_COMP_DIR, DW_FORM_STRING),
+        (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+    ];
+    let code_range =
+        [(DW_AT_LOW_PC, DW_FORM_ADDR), (DW_AT_HIGH_PC, DW_FORM_DATA4)];
+    let subprogram = [(DW_AT_NAME, DW_FORM_STRING)];
+
+    let mut abbrev = Vec::new();
+    let mut declare =
+        |code: u64, tag: u64, children: u8, attributes: &[(u64, u64)]| {
+            write_uleb128(&mut abbrev, code);
+            write_uleb128(&mut abbrev, tag);
+            abbrev.push(children);
+            for &(attribute, form) in attributes {
+                write_uleb128(&mut abbrev, attribute);
+                write_uleb128(&mut abbrev, form);
+            }
+            abbrev.extend_from_slice(&[0, 0]);
+        };
+    declare(
+        ABBREV_COMPILE_UNIT,
+        DW_TAG_COMPILE_UNIT,
+        DW_CHILDREN_YES,
+        &[&compile_unit[..], &code_range[..]].concat(),
+    );
+    declare(
+        ABBREV_COMPILE_UNIT_NO_CODE,
+        DW_TAG_COMPILE_UNIT,
+        DW_CHILDREN_NO,
+        &compile_unit,
+    );
+    declare(
+        ABBREV_SUBPROGRAM,
+        DW_TAG_SUBPROGRAM,
+        DW_CHILDREN_NO,
+        &[&subprogram[..], &code_range[..]].concat(),
+    );
+    declare(
+        ABBREV_EXTERNAL_SUBPROGRAM,
+        DW_TAG_SUBPROGRAM,
+        DW_CHILDREN_NO,
+        &[
+            &subprogram[..],
+            &[(DW_AT_EXTERNAL, DW_FORM_FLAG_PRESENT)],
+            &code_range[..],
+        ]
+        .concat(),
+    );
+    abbrev.push(0);
+    abbrev
+}
+
+fn write_compile_unit(
+    out: &mut Vec<u8>,
+    file_name: &str,
+    comp_dir: &str,
+    stmt_list: u32,
+    code_range: Option<(u32, u32)>,
+    subprograms: &[Subprogram],
+) {
+    let unit_start = out.len();
+    out.extend_from_slice(&[0; 4]); // unit_length, patched below
+    out.extend_from_slice(&DWARF_VERSION.to_le_bytes());
+    out.push(DW_UT_COMPILE);
+    out.push(ADDRESS_SIZE);
+    out.extend_from_slice(&0u32.to_le_bytes()); // debug_abbrev_offset
+
+    let abbrev_code = match code_range {
+        Some(_) => ABBREV_COMPILE_UNIT,
+        None => ABBREV_COMPILE_UNIT_NO_CODE,
+    };
+    write_uleb128(out, abbrev_code);
+    write_string(out, PRODUCER);
+    out.extend_from_slice(&DW_LANG_MIPS_ASSEMBLER.to_le_bytes());
+    write_string(out, file_name);
+    write_string(out, comp_dir);
+    out.extend_from_slice(&stmt_list.to_le_bytes());
+
+    if let Some((low_pc, high_pc)) = code_range {
+        out.extend_from_slice(&low_pc.to_le_bytes());
+        out.extend_from_slice(&(high_pc - low_pc).to_le_bytes());
+        for subprogram in subprograms {
+            write_uleb128(
+                out,
+                if subprogram.external {
+                    ABBREV_EXTERNAL_SUBPROGRAM
+                } else {
+                    ABBREV_SUBPROGRAM
+                },
+            );
+            write_string(out, &subprogram.name);
+            out.extend_from_slice(&subprogram.low_pc.to_le_bytes());
+            out.extend_from_slice(
+                &(subprogram.high_pc - subprogram.low_pc).to_le_bytes(),
+            );
+        }
+        out.push(0); // End of the compile unit's children
+    }
+
+    let unit_length = (out.len() - unit_start - 4) as u32;
+    out[unit_start..unit_start + 4].copy_from_slice(&unit_length.to_le_bytes());
+}
+
+// ============================================================================
+// .debug_line
+// ============================================================================
+
+fn write_line_program(
+    out: &mut Vec<u8>,
+    file_name: &str,
+    comp_dir: &str,
+    rows: &[Row],
+) {
+    let unit_start = out.len();
+    out.extend_from_slice(&[0; 4]); // unit_length, patched below
+    out.extend_from_slice(&DWARF_VERSION.to_le_bytes());
+    out.push(ADDRESS_SIZE);
+    out.push(0); // segment_selector_size
+
+    let header_length_at = out.len();
+    out.extend_from_slice(&[0; 4]); // header_length, patched below
+    let header_start = out.len();
+    out.push(1); // minimum_instruction_length
+    out.push(1); // maximum_operations_per_instruction
+    out.push(1); // default_is_stmt
+    out.push(LINE_BASE as u8);
+    out.push(LINE_RANGE);
+    out.push(OPCODE_BASE);
+    out.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+
+    // Directory 0 is the compilation directory
+    out.push(1); // directory_entry_format_count
+    write_uleb128(out, DW_LNCT_PATH);
+    write_uleb128(out, DW_FORM_STRING);
+    write_uleb128(out, 1); // directories_count
+    write_string(out, comp_dir);
+
+    // File 0 is the primary source file. It is repeated as file 1, the
+    // default file register, for consumers of earlier DWARF versions.
+    out.push(2); // file_name_entry_format_count
+    write_uleb128(out, DW_LNCT_PATH);
+    write_uleb128(out, DW_FORM_STRING);
+    write_uleb128(out, DW_LNCT_DIRECTORY_INDEX);
+    write_uleb128(out, DW_FORM_UDATA);
+    write_uleb128(out, 2); // file_names_count
+    for _ in 0..2 {
+        write_string(out, file_name);
+        write_uleb128(out, 0);
+    }
+
+    let header_length = (out.len() - header_start) as u32;
+    out[header_length_at..header_length_at + 4]
+        .copy_from_slice(&header_length.to_le_bytes());
+
+    // The program: one row per line, then the end of the sequence
+    if let Some((start, end)) = code_range(rows) {
+        out.push(0); // Extended opcode
+        write_uleb128(out, 1 + ADDRESS_SIZE as u64);
+        out.push(DW_LNE_SET_ADDRESS);
+        out.extend_from_slice(&start.to_le_bytes());
+
+        let mut address = start;
+        let mut line = 1i64;
+        for row in rows {
+            advance_pc(out, &mut address, row.address);
+            if row.line as i64 != line {
+                out.push(DW_LNS_ADVANCE_LINE);
+                write_sleb128(out, row.line as i64 - line);
+                line = row.line as i64;
+            }
+            out.push(DW_LNS_COPY);
+        }
+
+        advance_pc(out, &mut address, end);
+        out.push(0); // Extended opcode
+        write_uleb128(out, 1);
+        out.push(DW_LNE_END_SEQUENCE);
+    }
+
+    let unit_length = (out.len() - unit_start - 4) as u32;
+    out[unit_start..unit_start + 4].copy_from_slice(&unit_length.to_le_bytes());
+}
+
+fn advance_pc(out: &mut Vec<u8>, address: &mut u32, to: u32) {
+    if to != *address {
+        out.push(DW_LNS_ADVANCE_PC);
+        write_uleb128(out, (to - *address) as u64);
+        *address = to;
+    }
+}
+
+// ============================================================================
+// Encoding Helpers
+// ============================================================================
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0)
+            || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+// ============================================================================
+// Reading Line Tables
+// ============================================================================
+
+const DW_LNS_SET_FILE: u8 = 0x04;
+const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+/// A range of code addresses and the source line that produced them
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineRange {
+    pub start: u32,
+    pub end: u32,
+    /// The source file, joined to its directory from the line table
+    pub file: String,
+    pub line: u32,
+}
+
+/// Read the line tables of an executable back into address ranges
+///
+/// Versions 2 through 5 are understood, so executables from GNU as work as
+/// well as our own. Debug information is optional: decoding stops quietly
+/// at the first unit it cannot make sense of, keeping what came before.
+pub fn read_line_table(
+    debug_line: &[u8],
+    debug_line_str: &[u8],
+) -> Vec<LineRange> {
+    let mut ranges = Vec::new();
+    let mut reader = Reader { bytes: debug_line, at: 0 };
+    while reader.at < debug_line.len() {
+        if read_line_program(&mut reader, debug_line_str, &mut ranges).is_none()
+        {
+            break;
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+/// Decode one line-number program, leaving the reader at the next one
+fn read_line_program(
+    reader: &mut Reader,
+    debug_line_str: &[u8],
+    ranges: &mut Vec<LineRange>,
+) -> Option<()> {
+    let unit_length = reader.u32()?;
+    if unit_length == 0xffff_ffff {
+        return None; // 64-bit DWARF
+    }
+    let unit_end = reader.at.checked_add(unit_length as usize)?;
+    let version = reader.u16()?;
+    if !(2..=5).contains(&version) {
+        return None;
+    }
+    if version >= 5 {
+        reader.u8()?; // address_size
+        reader.u8()?; // segment_selector_size
+    }
+    let header_length = reader.u32()?;
+    let program_start = reader.at.checked_add(header_length as usize)?;
+    let min_inst_length = reader.u8()? as u32;
+    if version >= 4 {
+        reader.u8()?; // maximum_operations_per_instruction
+    }
+    reader.u8()?; // default_is_stmt
+    let line_base = reader.u8()? as i8 as i64;
+    let line_range = reader.u8()?;
+    let opcode_base = reader.u8()?;
+    if line_range == 0 || opcode_base == 0 {
+        return None;
+    }
+    let mut opcode_lengths = Vec::new();
+    for _ in 1..opcode_base {
+        opcode_lengths.push(reader.u8()?);
+    }
+
+    // Before version 5, directory 0 and file 0 are implicit
+    let mut files = Vec::new();
+    if version >= 5 {
+        let directories = read_entries(reader, debug_line_str)?;
+        let directories: Vec<String> =
+            directories.into_iter().map(|(path, _)| path).collect();
+        for (name, directory) in read_entries(reader, debug_line_str)? {
+            let directory = directories.get(directory).map(String::as_str);
+            files.push(join_path(directory.unwrap_or(""), &name));
+        }
+    } else {
+        let mut directories = vec![String::new()];
+        loop {
+            let directory = reader.string()?;
+            if directory.is_empty() {
+                break;
+            }
+            directories.push(directory);
+        }
+        files.push(String::new());
+        loop {
+            let name = reader.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let directory = reader.uleb128()? as usize;
+            reader.uleb128()?; // modification time
+            reader.uleb128()?; // length
+            let directory = directories.get(directory).map(String::as_str);
+            files.push(join_path(directory.unwrap_or(""), &name));
+        }
+    }
+
+    // Run the state machine, turning each pair of rows into a range
+    reader.at = program_start;
+    let mut address = 0u32;
+    let mut file = 1u64;
+    let mut line = 1i64;
+    let mut previous: Option<(u32, u64, i64)> = None;
+    let mut emit = |address: u32, file: u64, line: i64, end: bool| {
+        if let Some((start, file, line)) = previous
+            && start < address
+            && let Some(name) = files.get(file as usize)
+        {
+            ranges.push(LineRange {
+                start,
+                end: address,
+                file: name.clone(),
+                line: line as u32,
+            });
+        }
+        previous = (!end).then_some((address, file, line));
+    };
+    while reader.at < unit_end {
+        let opcode = reader.u8()?;
+        if opcode >= opcode_base {
+            let adjusted = opcode - opcode_base;
+            address = address
+                .wrapping_add((adjusted / line_range) as u32 * min_inst_length);
+            line += line_base + (adjusted % line_range) as i64;
+            emit(address, file, line, false);
+            continue;
+        }
+        match opcode {
+            0 => {
+                let length = reader.uleb128()? as usize;
+                let next = reader.at.checked_add(length)?;
+                match reader.u8()? {
+                    DW_LNE_END_SEQUENCE => {
+                        emit(address, file, line, true);
+                        address = 0;
+                        file = 1;
+                        line = 1;
+                    }
+                    DW_LNE_SET_ADDRESS => {
+                        address = reader.u32()?;
+                    }
+                    // Including DW_LNE_define_file, which no producer we
+                    // know of still writes
+                    _ => {}
+                }
+                reader.at = next;
+            }
+            DW_LNS_COPY => emit(address, file, line, false),
+            DW_LNS_ADVANCE_PC => {
+                let delta = reader.uleb128()? as u32;
+                address = address.wrapping_add(delta * min_inst_length);
+            }
+            DW_LNS_ADVANCE_LINE => line += reader.sleb128()?,
+            DW_LNS_SET_FILE => file = reader.uleb128()?,
+            DW_LNS_CONST_ADD_PC => {
+                let adjusted = 255 - opcode_base;
+                address = address.wrapping_add(
+                    (adjusted / line_range) as u32 * min_inst_length,
+                );
+            }
+            DW_LNS_FIXED_ADVANCE_PC => {
+                address = address.wrapping_add(reader.u16()? as u32);
+            }
+            _ => {
+                for _ in 0..opcode_lengths[opcode as usize - 1] {
+                    reader.uleb128()?;
+                }
+            }
+        }
+    }
+    reader.at = unit_end;
+    Some(())
+}
+
+/// Read a version 5 directory or file name table as (path, directory index)
+/// pairs
+fn read_entries(
+    reader: &mut Reader,
+    debug_line_str: &[u8],
+) -> Option<Vec<(String, usize)>> {
+    let mut formats = Vec::new();
+    for _ in 0..reader.u8()? {
+        formats.push((reader.uleb128()?, reader.uleb128()?));
+    }
+    let mut entries = Vec::new();
+    for _ in 0..reader.uleb128()? {
+        let mut path = String::new();
+        let mut directory = 0;
+        for &(content, form) in &formats {
+            let mut value = 0;
+            match form {
+                DW_FORM_STRING => path = reader.string()?,
+                DW_FORM_LINE_STRP => {
+                    let offset = reader.u32()? as usize;
+                    let mut strings =
+                        Reader { bytes: debug_line_str, at: offset };
+                    path = strings.string()?;
+                }
+                // .debug_str is not read, so the name is unknown
+                DW_FORM_STRP => {
+                    reader.u32()?;
+                }
+                DW_FORM_UDATA => value = reader.uleb128()?,
+                DW_FORM_DATA1 => value = reader.u8()? as u64,
+                DW_FORM_DATA2 => value = reader.u16()? as u64,
+                DW_FORM_DATA4 => value = reader.u32()? as u64,
+                DW_FORM_DATA8 => reader.skip(8)?,
+                DW_FORM_DATA16 => reader.skip(16)?,
+                DW_FORM_BLOCK => {
+                    let length = reader.uleb128()? as usize;
+                    reader.skip(length)?;
+                }
+                _ => return None,
+            }
+            if content == DW_LNCT_DIRECTORY_INDEX {
+                directory = value as usize;
+            }
+        }
+        entries.push((path, directory));
+    }
+    Some(entries)
+}
+
+fn join_path(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else {
+        std::path::Path::new(directory).join(name).display().to_string()
+    }
+}
+
+// ============================================================================
+// Decoding Helpers
+// ============================================================================
+
+/// A cursor over little-endian DWARF data that returns None past the end
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Option<&[u8]> {
+        let bytes = self.bytes.get(self.at..self.at.checked_add(length)?)?;
+        self.at += length;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Option<()> {
+        self.take(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let rest = self.bytes.get(self.at..)?;
+        let length = rest.iter().position(|&b| b == 0)?;
+        let s = String::from_utf8_lossy(&rest[..length]).into_owned();
+        self.at += length + 1;
+        Some(s)
+    }
+}
@@ -0,0 +1,303 @@
+// dwarf_tests.rs
+//
+// Tests for the DWARF debug sections of assembled executables
+
+use crate::assembler::assemble;
+use crate::config::Config;
+use crate::dwarf::{LineRange, read_line_table};
+use crate::elf::{ElfHeader, ElfSectionHeader};
+use crate::elf_loader::{ElfInput, load_elf};
+use crate::execution::{Instruction, SourceLine, instruction_to_string};
+use crate::riscv::Op;
+use std::collections::HashMap;
+
+const MAIN: &str = "        .text
+        .globl _start
+_start: li   a0, 1
+        call helper
+
+        # exit
+        li   a7, 93
+        ecall
+
+        .type helper, @function
+helper: addi a0, a0, 1
+loop:   bnez a0, loop
+        ret
+        .data
+value:  .4byte 5
+";
+
+const OTHER: &str = "        .text
+        .globl other
+other:  la   a0, value2
+        ret
+        .data
+value2: .4byte 7
+";
+
+fn assemble_files(files: &[(&str, &str)]) -> Vec<u8> {
+    let sources = files
+        .iter()
+        .map(|(name, code)| (name.to_string(), code.to_string()))
+        .collect();
+    let mut config = Config::assemble_default();
+    config.warnings.parse_flag("-w").unwrap();
+    assemble(&mut config, sources).unwrap()
+}
+
+/// The contents of the section with the given name
+fn section<'a>(elf: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let header = ElfHeader::decode(elf).unwrap();
+    let section_header = |index: usize| {
+        let offset =
+            header.e_shoff as usize + index * header.e_shentsize as usize;
+        ElfSectionHeader::decode(&elf[offset..]).unwrap()
+    };
+    let names = section_header(header.e_shstrndx as usize);
+    (0..header.e_shnum as usize).map(section_header).find_map(|sh| {
+        let start = (names.sh_offset + sh.sh_name) as usize;
+        let end = start + elf[start..].iter().position(|&b| b == 0).unwrap();
+        (&elf[start..end] == name.as_bytes()).then(|| {
+            &elf[sh.sh_offset as usize..(sh.sh_offset + sh.sh_size) as usize]
+        })
+    })
+}
+
+fn read_uleb128(bytes: &[u8], at: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+fn read_sleb128(bytes: &[u8], at: &mut usize) -> i64 {
+    let mut value = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as i64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1 << shift;
+            }
+            return value;
+        }
+    }
+}
+
+/// The (address, line) rows of each line-number program, decoding only the
+/// opcodes the assembler writes. The end of each sequence is a row with
+/// line 0.
+fn line_tables(debug_line: &[u8]) -> Vec<Vec<(u32, u32)>> {
+    let mut tables = Vec::new();
+    let mut unit = 0;
+    while unit < debug_line.len() {
+        let word = |at: usize| {
+            u32::from_le_bytes(debug_line[at..at + 4].try_into().unwrap())
+        };
+        let unit_end = unit + 4 + word(unit) as usize;
+        assert_eq!(debug_line[unit + 4], 5, "DWARF version");
+        let mut at = unit + 12 + word(unit + 8) as usize;
+
+        let mut rows = Vec::new();
+        let mut address = 0;
+        let mut line = 1i64;
+        while at < unit_end {
+            let opcode = debug_line[at];
+            at += 1;
+            match opcode {
+                0 => {
+                    let length = read_uleb128(debug_line, &mut at) as usize;
+                    match debug_line[at] {
+                        1 => {
+                            rows.push((address, 0));
+                            line = 1;
+                        }
+                        2 => address = word(at + 1),
+                        other => panic!("extended opcode {}", other),
+                    }
+                    at += length;
+                }
+                1 => rows.push((address, line as u32)),
+                2 => address += read_uleb128(debug_line, &mut at) as u32,
+                3 => line += read_sleb128(debug_line, &mut at),
+                other => panic!("opcode {}", other),
+            }
+        }
+        tables.push(rows);
+        unit = unit_end;
+    }
+    tables
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    let needle = format!("{}\0", needle);
+    haystack.windows(needle.len()).any(|window| window == needle.as_bytes())
+}
+
+#[test]
+fn test_debug_sections_are_written() {
+    let elf = assemble_files(&[("main.s", MAIN)]);
+    for name in [".debug_info", ".debug_abbrev", ".debug_line"] {
+        assert!(section(&elf, name).is_some(), "missing {}", name);
+    }
+}
+
+#[test]
+fn test_line_table_maps_code_to_source_lines() {
+    let elf = assemble_files(&[("main.s", MAIN)]);
+    let start = ElfHeader::decode(&elf).unwrap().e_entry;
+    let tables = line_tables(section(&elf, ".debug_line").unwrap());
+    assert_eq!(
+        tables,
+        [[
+            (start, 3),
+            (start + 4, 4),
+            (start + 8, 7),
+            (start + 12, 8),
+            (start + 16, 11),
+            (start + 20, 12),
+            (start + 24, 13),
+            (start + 28, 0)
+        ]]
+    );
+}
+
+#[test]
+fn test_one_compile_unit_per_file() {
+    let elf = assemble_files(&[("main.s", MAIN), ("other.s", OTHER)]);
+    let tables = line_tables(section(&elf, ".debug_line").unwrap());
+    assert_eq!(tables.len(), 2);
+    // la is two instructions on one line
+    let start = tables[1][0].0;
+    assert_eq!(tables[1], [(start, 3), (start + 8, 4), (start + 12, 0)]);
+
+    let debug_info = section(&elf, ".debug_info").unwrap();
+    assert!(contains(debug_info, "main.s"));
+    assert!(contains(debug_info, "other.s"));
+}
+
+#[test]
+fn test_subprograms_for_functions() {
+    let elf = assemble_files(&[("main.s", MAIN), ("other.s", OTHER)]);
+    let debug_info = section(&elf, ".debug_info").unwrap();
+    // Global labels and .type functions, but not other labels
+    assert!(contains(debug_info, "_start"));
+    assert!(contains(debug_info, "helper"));
+    assert!(contains(debug_info, "other"));
+    assert!(!contains(debug_info, "loop"));
+    assert!(!contains(debug_info, "value"));
+}
+
+#[test]
+fn test_read_line_table_back() {
+    let elf = assemble_files(&[("main.s", MAIN)]);
+    let start = ElfHeader::decode(&elf).unwrap().e_entry;
+    let ranges = read_line_table(section(&elf, ".debug_line").unwrap(), &[]);
+    let file = std::env::current_dir().unwrap().join("main.s");
+    let file = file.display().to_string();
+    let expected: Vec<LineRange> = [(0, 3), (4, 4), (8, 7), (12, 8)]
+        .iter()
+        .chain(&[(16, 11), (20, 12), (24, 13)])
+        .map(|&(offset, line)| LineRange {
+            start: start + offset,
+            end: start + offset + 4,
+            file: file.clone(),
+            line,
+        })
+        .collect();
+    assert_eq!(ranges, expected);
+
+    // The loader reads them, too
+    let machine = load_elf(ElfInput::Bytes(&elf), &mut Vec::new()).unwrap();
+    assert_eq!(machine.line_table, expected);
+}
+
+#[test]
+fn test_read_version_4_line_table() {
+    // As GNU as might write it: include directories, special opcodes,
+    // const_add_pc and fixed_advance_pc
+    let mut header = vec![1, 1, 1, (-5i8) as u8, 14, 13];
+    header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    header.extend_from_slice(b"src\0\0a.s\0\x01\0\0\0");
+    let program = [
+        &[0, 5, 2, 0x00, 0x10, 0, 0][..], // set_address 0x1000
+        &[20],                            // line 3
+        &[75],                            // address +4, line 4
+        &[8, 9, 3, 0],                    // address +17, +3
+        &[3, 0x7d, 1],                    // line 1
+        &[2, 2, 0, 1, 1],                 // end at +2
+    ]
+    .concat();
+    let mut unit = vec![4, 0];
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend_from_slice(&header);
+    unit.extend_from_slice(&program);
+    let mut debug_line = (unit.len() as u32).to_le_bytes().to_vec();
+    debug_line.extend_from_slice(&unit);
+
+    let file = std::path::Path::new("src").join("a.s").display().to_string();
+    let range =
+        |start, end, line| LineRange { start, end, file: file.clone(), line };
+    assert_eq!(
+        read_line_table(&debug_line, &[]),
+        [
+            range(0x1000, 0x1004, 3),
+            range(0x1004, 0x1018, 4),
+            range(0x1018, 0x101a, 1)
+        ]
+    );
+
+    // A truncated table keeps what could be read
+    assert_eq!(read_line_table(&debug_line[..8], &[]), []);
+}
+
+#[test]
+fn test_source_line_in_place_of_disassembly() {
+    let mut config = Config::simulator_default(crate::config::Mode::Trace);
+    let op = Op::new(0x00100513); // li a0, 1
+    let fields = op.to_pseudo_fields();
+    let mut instruction = Instruction {
+        address: 0x10000,
+        op,
+        length: 4,
+        pseudo_index: 0,
+        verbose_fields: Vec::new(),
+        pseudo_fields: Vec::new(),
+        source: Some(SourceLine {
+            file: "main.s".to_string(),
+            line: 3,
+            text: Some("li   a0, 1  # one".to_string()),
+        }),
+    };
+    let symbols = HashMap::from([(0x10000, "_start".to_string())]);
+    let line = |config: &Config, instruction: &Instruction| {
+        instruction_to_string(config, instruction, &fields, 0, None, &symbols)
+    };
+
+    assert!(
+        line(&config, &instruction).contains("_start:         li      a0, 1")
+    );
+    config.show_source = true;
+    assert!(
+        line(&config, &instruction)
+            .contains("_start:         main.s:3     li   a0, 1  # one")
+    );
+
+    // Without the file, the disassembly stands in for the text
+    instruction.source.as_mut().unwrap().text = None;
+    assert!(
+        line(&config, &instruction)
+            .contains("_start:         main.s:3     li      a0, 1")
+    );
+}
@@ -25,6 +25,7 @@ pub const EI_OSABI: u8 = 0; // ELFOSABI_SYSV
 pub const EI_ABIVERSION: u8 = 0;
 
 // ELF File Types
+pub const ET_REL: u16 = 1; // Relocatable object file
 pub const ET_EXEC: u16 = 2; // Executable file
 
 // Machine Type
@@ -41,6 +42,8 @@ pub const SHT_NULL: u32 = 0;
 pub const SHT_PROGBITS: u32 = 1;
 pub const SHT_SYMTAB: u32 = 2;
 pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOTE: u32 = 7;
 pub const SHT_NOBITS: u32 = 8;
 pub const SHT_RISCV_ATTRIBUTES: u32 = 0x7000_0003;
 
@@ -48,6 +51,7 @@ pub const SHT_RISCV_ATTRIBUTES: u32 = 0x7000_0003;
 pub const SHF_WRITE: u32 = 0x1;
 pub const SHF_ALLOC: u32 = 0x2;
 pub const SHF_EXECINSTR: u32 = 0x4;
+pub const SHF_INFO_LINK: u32 = 0x40;
 
 // Program Header Types
 pub const PT_LOAD: u32 = 1;
@@ -61,20 +65,47 @@ pub const PF_R: u32 = 0x4; // Read
 // Symbol Binding
 pub const STB_LOCAL: u8 = 0;
 pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
 
 // Symbol Types
 pub const STT_NOTYPE: u8 = 0;
+pub const STT_FUNC: u8 = 2;
 pub const STT_SECTION: u8 = 3;
 pub const STT_FILE: u8 = 4;
 
 // Special Section Indices
 pub const SHN_UNDEF: u16 = 0;
 pub const SHN_ABS: u16 = 0xfff1;
+pub const SHN_COMMON: u16 = 0xfff2;
 
 // Header and entry sizes (32-bit ELF)
 pub const ELF_HEADER_SIZE: u32 = 52;
 pub const PROGRAM_HEADER_SIZE: u32 = 32;
 pub const SYMBOL_ENTRY_SIZE: usize = 16;
+pub const RELA_ENTRY_SIZE: usize = 12;
+
+// RISC-V Relocation Types (RISC-V ELF psABI)
+pub const R_RISCV_32: u8 = 1;
+pub const R_RISCV_BRANCH: u8 = 16;
+pub const R_RISCV_JAL: u8 = 17;
+pub const R_RISCV_CALL: u8 = 18;
+pub const R_RISCV_CALL_PLT: u8 = 19;
+pub const R_RISCV_PCREL_HI20: u8 = 23;
+pub const R_RISCV_PCREL_LO12_I: u8 = 24;
+pub const R_RISCV_PCREL_LO12_S: u8 = 25;
+pub const R_RISCV_HI20: u8 = 26;
+pub const R_RISCV_LO12_I: u8 = 27;
+pub const R_RISCV_LO12_S: u8 = 28;
+pub const R_RISCV_ADD8: u8 = 33;
+pub const R_RISCV_ADD16: u8 = 34;
+pub const R_RISCV_ADD32: u8 = 35;
+pub const R_RISCV_SUB8: u8 = 37;
+pub const R_RISCV_SUB16: u8 = 38;
+pub const R_RISCV_SUB32: u8 = 39;
+pub const R_RISCV_ALIGN: u8 = 43;
+pub const R_RISCV_RVC_BRANCH: u8 = 44;
+pub const R_RISCV_RVC_JUMP: u8 = 45;
+pub const R_RISCV_RELAX: u8 = 51;
 
 // ============================================================================
 // ELF Data Structures
@@ -420,6 +451,57 @@ impl ElfSymbol {
     }
 }
 
+/// ELF-32 Relocation Entry with explicit addend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfRela {
+    pub r_offset: u32, // Offset of the relocated field within its section
+    pub r_info: u32,   // Symbol index and relocation type
+    pub r_addend: i32, // Constant addend
+}
+
+impl ElfRela {
+    /// Create a relocation entry against a symbol table index
+    pub fn new(r_offset: u32, symbol: u32, typ: u8, r_addend: i32) -> Self {
+        Self { r_offset, r_info: (symbol << 8) | typ as u32, r_addend }
+    }
+
+    /// The symbol table index this relocation refers to
+    pub fn symbol(&self) -> u32 {
+        self.r_info >> 8
+    }
+
+    /// The relocation type (one of the R_RISCV_* constants)
+    pub fn typ(&self) -> u8 {
+        (self.r_info & 0xff) as u8
+    }
+
+    /// Encode relocation to 12 bytes of little-endian binary
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(RELA_ENTRY_SIZE);
+        bytes.extend_from_slice(&self.r_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.r_info.to_le_bytes());
+        bytes.extend_from_slice(&self.r_addend.to_le_bytes());
+        bytes
+    }
+
+    /// Decode relocation from 12 bytes of little-endian binary
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < RELA_ENTRY_SIZE {
+            return Err(RiscletError::elf(
+                "Relocation entry too short".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            r_offset: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            r_info: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            r_addend: i32::from_le_bytes([
+                data[8], data[9], data[10], data[11],
+            ]),
+        })
+    }
+}
+
 /// Helper to create st_info field from binding and type
 pub fn make_st_info(bind: u8, typ: u8) -> u8 {
     (bind << 4) | (typ & 0xf)
@@ -447,6 +529,11 @@ impl StringTable {
         Self { strings: vec![0], offsets: HashMap::new() }
     }
 
+    /// Wrap the raw contents of a string table section read from a file
+    pub fn from_data(data: &[u8]) -> Self {
+        Self { strings: data.to_vec(), offsets: HashMap::new() }
+    }
+
     /// Add a string and return its offset
     pub fn add(&mut self, s: &str) -> u32 {
         if let Some(&offset) = self.offsets.get(s) {
@@ -6,18 +6,23 @@
 // Reference: ELF-32 Object File Format, Version 1.5 Draft 2
 // https://refspecs.linuxfoundation.org/elf/elf.pdf
 
-use crate::ast::{LineContent, LinePointer, Segment, Source};
+use crate::ast::{Directive, LineContent, LinePointer, Segment, Source};
+use crate::dwarf::{DebugSections, build_debug_sections};
 use crate::elf::{
     ElfHeader, ElfProgramHeader, ElfSectionHeader, ElfSymbol, PF_R, PF_W, PF_X,
     PT_LOAD, PT_RISCV_ATTRIBUTES, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHN_ABS,
-    SHT_NOBITS, SHT_PROGBITS, SHT_RISCV_ATTRIBUTES, SHT_STRTAB, SHT_SYMTAB,
-    STB_GLOBAL, STB_LOCAL, STT_NOTYPE, StringTable, generate_riscv_attributes,
-    make_st_info,
+    SHN_UNDEF, SHT_NOBITS, SHT_PROGBITS, SHT_RISCV_ATTRIBUTES, SHT_STRTAB,
+    SHT_SYMTAB, STB_GLOBAL, STB_LOCAL, STB_WEAK, STT_NOTYPE, StringTable,
+    generate_riscv_attributes, make_st_info,
 };
 use crate::error::{Result, RiscletError};
 use crate::expressions::{EvaluatedValue, SymbolValues};
+use crate::image::ImageSegment;
 use crate::layout::{Layout, LineLayout};
-use crate::symbols::{SymbolDefinition, SymbolLinks};
+use crate::symbols::{
+    SymbolBinding, SymbolDefinition, SymbolLinks, is_assembler_local,
+    is_undefined_weak,
+};
 
 // ============================================================================
 // ELF Builder
@@ -33,6 +38,7 @@ pub struct ElfBuilder<'a> {
     pub text_data: Vec<u8>,
     pub data_data: Vec<u8>,
     pub riscv_attributes: Vec<u8>,
+    pub debug_sections: Option<DebugSections>,
     layout: &'a Layout,
 }
 
@@ -52,15 +58,46 @@ impl<'a> ElfBuilder<'a> {
             text_data,
             data_data,
             riscv_attributes: generate_riscv_attributes(),
+            debug_sections: None,
             layout,
         }
     }
 
+    /// What the loaded segments put in memory, for writing memory images
+    /// (.bss is left for the program to clear)
+    pub fn image_segments(&self) -> Vec<ImageSegment> {
+        let mut segments = vec![ImageSegment {
+            name: ".text",
+            address: self.layout.text_start,
+            load_address: self.layout.text_start,
+            bytes: self.text_data.clone(),
+        }];
+        if !self.data_data.is_empty() {
+            segments.push(ImageSegment {
+                name: ".data",
+                address: self.layout.data_start,
+                load_address: self.layout.data_load_start,
+                bytes: self.data_data.clone(),
+            });
+        }
+        segments
+    }
+
     /// Add a symbol to the symbol table
     pub fn add_symbol(&mut self, symbol: ElfSymbol) {
         self.symbol_table.push(symbol);
     }
 
+    /// Add DWARF line and function information for the source files
+    pub fn build_debug_info(
+        &mut self,
+        source: &Source,
+        symbol_links: &SymbolLinks,
+    ) {
+        self.debug_sections =
+            Some(build_debug_sections(source, symbol_links, self.layout));
+    }
+
     /// Build the complete ELF file
     pub fn build(mut self, entry_point: u32) -> Result<Vec<u8>> {
         self.header.e_entry = entry_point;
@@ -77,6 +114,11 @@ impl<'a> ElfBuilder<'a> {
             self.section_names.add(".bss");
         }
         self.section_names.add(".riscv.attributes");
+        if let Some(debug_sections) = &self.debug_sections {
+            for (name, _) in debug_sections.sections() {
+                self.section_names.add(name);
+            }
+        }
         self.section_names.add(".symtab");
         self.section_names.add(".strtab");
         self.section_names.add(".shstrtab");
@@ -99,33 +141,57 @@ impl<'a> ElfBuilder<'a> {
         output.resize(output.len() + ph_size as usize, 0);
 
         // --- Section Layout ---
+        // Each loaded segment's file offset must equal its address modulo
+        // the page size, so loaders can map it directly.
         let page_size = 0x1000;
+        let pad_to_address = |output: &mut Vec<u8>, address: u32| {
+            let current_len = output.len() as u32;
+            let padding = address.wrapping_sub(current_len) % page_size;
+            output.resize(output.len() + padding as usize, 0);
+            output.len() as u32
+        };
 
-        // .text section starts right after the program headers
+        // .text section starts right after the program headers, unless the
+        // headers are not mapped in front of it
+        if !self.layout.headers_mapped {
+            pad_to_address(&mut output, self.layout.text_start);
+        }
         let text_offset = output.len() as u32;
         output.extend_from_slice(&self.text_data);
 
-        // .data section is page-aligned in the file to support mmap.
-        // Pad the file with zeros to align the data offset.
-        let data_offset = if !self.data_data.is_empty()
-            || self.layout.bss_size > 0
-        {
-            let current_len = output.len() as u32;
-            let padding = (page_size - (current_len % page_size)) % page_size;
-            output.resize(output.len() + padding as usize, 0);
-            Some(output.len() as u32)
-        } else {
-            None
-        };
+        // .data section (and a .bss that follows it) is page-aligned in the
+        // file to support mmap. Pad the file with zeros to align the data offset.
+        let data_offset =
+            if !self.data_data.is_empty() || self.layout.bss_size > 0 {
+                Some(pad_to_address(&mut output, self.data_segment_address()))
+            } else {
+                None
+            };
 
         if let Some(_offset) = data_offset {
             output.extend_from_slice(&self.data_data);
         }
 
+        // A .bss that doesn't follow .data gets its own (empty) file range
+        let bss_offset = if self.layout.has_separate_bss_segment() {
+            Some(pad_to_address(&mut output, self.layout.bss_start))
+        } else {
+            data_offset
+        };
+
         // .riscv.attributes section (not loaded into memory)
         let riscv_attrs_offset = output.len() as u32;
         output.extend_from_slice(&self.riscv_attributes);
 
+        // Debug sections (not loaded into memory)
+        let mut debug_offsets = Vec::new();
+        if let Some(debug_sections) = &self.debug_sections {
+            for (_, data) in debug_sections.sections() {
+                debug_offsets.push(output.len() as u32);
+                output.extend_from_slice(data);
+            }
+        }
+
         // Build symbol table
         if self.symbol_table.is_empty() {
             self.symbol_table.push(ElfSymbol::null());
@@ -149,7 +215,9 @@ impl<'a> ElfBuilder<'a> {
         self.build_section_headers(
             text_offset,
             data_offset,
+            bss_offset,
             riscv_attrs_offset,
+            &debug_offsets,
             symtab_offset,
             strtab_offset,
             shstrtab_offset,
@@ -176,13 +244,17 @@ impl<'a> ElfBuilder<'a> {
 
         // Program Header 1: LOAD .text
         if let Some(ph) = self.program_headers.get_mut(1) {
-            // This segment starts at the base virtual address and includes the
-            // ELF and program headers in its memory mapping.
-            ph.p_vaddr = base_vaddr;
-            ph.p_paddr = base_vaddr;
-            ph.p_offset = 0;
-            ph.p_filesz = text_offset + self.text_data.len() as u32;
-            ph.p_memsz = ph.p_filesz;
+            if self.layout.headers_mapped {
+                // This segment starts at the base virtual address and includes
+                // the ELF and program headers in its memory mapping.
+                ph.p_vaddr = base_vaddr;
+                ph.p_paddr = base_vaddr;
+                ph.p_offset = 0;
+                ph.p_filesz = text_offset + self.text_data.len() as u32;
+                ph.p_memsz = ph.p_filesz;
+            } else {
+                ph.p_offset = text_offset;
+            }
         }
 
         // Program Header 2: LOAD .data/.bss
@@ -192,6 +264,11 @@ impl<'a> ElfBuilder<'a> {
             // and set during the initial program header creation.
         }
 
+        // Program Header 3: LOAD .bss, when it doesn't follow .data
+        if let Some(ph) = self.program_headers.get_mut(3) {
+            ph.p_offset = bss_offset.unwrap_or(0);
+        }
+
         // Update ELF header
         self.header.e_phoff = phoff;
         self.header.e_phnum = self.program_headers.len() as u16;
@@ -239,29 +316,68 @@ impl<'a> ElfBuilder<'a> {
             p_align: 0x1000,
         });
 
-        // LOAD segment for .data + .bss (if present)
+        // LOAD segment for .data + .bss (if present). The physical address
+        // is where the initial contents of .data are stored (its LMA).
+        let separate_bss = self.layout.has_separate_bss_segment();
         if !self.data_data.is_empty() || self.layout.bss_size > 0 {
             let data_filesz = self.data_data.len() as u32;
-            let data_memsz = data_filesz + self.layout.bss_size;
+            let data_memsz = if separate_bss {
+                data_filesz
+            } else {
+                data_filesz + self.layout.bss_size
+            };
+            let vaddr = self.data_segment_address();
+            let paddr = if self.data_data.is_empty() {
+                vaddr
+            } else {
+                self.layout.data_load_start
+            };
 
             self.program_headers.push(ElfProgramHeader {
                 p_type: PT_LOAD,
                 p_offset: 0, // Will be set during build
-                p_vaddr: self.layout.data_start,
-                p_paddr: self.layout.data_start,
+                p_vaddr: vaddr,
+                p_paddr: paddr,
                 p_filesz: data_filesz,
                 p_memsz: data_memsz,
                 p_flags: PF_R | PF_W,
                 p_align: 0x1000,
             });
         }
+
+        // LOAD segment for a .bss placed away from .data
+        if separate_bss {
+            self.program_headers.push(ElfProgramHeader {
+                p_type: PT_LOAD,
+                p_offset: 0, // Will be set during build
+                p_vaddr: self.layout.bss_start,
+                p_paddr: self.layout.bss_start,
+                p_filesz: 0,
+                p_memsz: self.layout.bss_size,
+                p_flags: PF_R | PF_W,
+                p_align: 0x1000,
+            });
+        }
+    }
+
+    /// The address of the segment holding .data, or of .bss if there is no
+    /// .data
+    fn data_segment_address(&self) -> u32 {
+        if self.data_data.is_empty() {
+            self.layout.bss_start
+        } else {
+            self.layout.data_start
+        }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn build_section_headers(
         &mut self,
         text_offset: u32,
         data_offset: Option<u32>,
+        bss_offset: Option<u32>,
         riscv_attrs_offset: u32,
+        debug_offsets: &[u32],
         symtab_offset: u32,
         strtab_offset: u32,
         shstrtab_offset: u32,
@@ -316,7 +432,7 @@ impl<'a> ElfBuilder<'a> {
                 sh_type: SHT_NOBITS,
                 sh_flags: SHF_WRITE | SHF_ALLOC,
                 sh_addr: self.layout.bss_start,
-                sh_offset: data_offset
+                sh_offset: bss_offset
                     .unwrap_or(text_offset + self.text_data.len() as u32),
                 sh_size: self.layout.bss_size,
                 sh_link: 0,
@@ -342,12 +458,33 @@ impl<'a> ElfBuilder<'a> {
         });
         section_index += 1;
 
+        // Sections: .debug_info, .debug_abbrev, .debug_line
+        if let Some(debug_sections) = &self.debug_sections {
+            for ((name, data), &offset) in
+                debug_sections.sections().into_iter().zip(debug_offsets)
+            {
+                self.section_headers.push(ElfSectionHeader {
+                    sh_name: self.section_names.add(name),
+                    sh_type: SHT_PROGBITS,
+                    sh_flags: 0, // Not allocated
+                    sh_addr: 0,
+                    sh_offset: offset,
+                    sh_size: data.len() as u32,
+                    sh_link: 0,
+                    sh_info: 0,
+                    sh_addralign: 1,
+                    sh_entsize: 0,
+                });
+                section_index += 1;
+            }
+        }
+
         // Section: .symtab
         let strtab_section_index = section_index + 1; // .strtab comes next
         let first_global =
             self.symbol_table
                 .iter()
-                .position(|sym| (sym.st_info >> 4) == STB_GLOBAL)
+                .position(|sym| (sym.st_info >> 4) != STB_LOCAL)
                 .unwrap_or(self.symbol_table.len()) as u32;
 
         self.section_headers.push(ElfSectionHeader {
@@ -358,7 +495,7 @@ impl<'a> ElfBuilder<'a> {
             sh_offset: symtab_offset,
             sh_size: (self.symbol_table.len() * 16) as u32,
             sh_link: strtab_section_index as u32,
-            sh_info: first_global, // Index of first global symbol
+            sh_info: first_global, // Index of first global or weak symbol
             sh_addralign: 8,
             sh_entsize: 16,
         });
@@ -402,13 +539,14 @@ impl<'a> ElfBuilder<'a> {
     /// 3. For each source file:
     ///    a. FILE symbol
     ///    b. Special $xrv32i2p1_m2p0_a2p1_c2p0 marker symbol
-    ///    c. Local labels from that file
-    /// 4. Global symbols (including linker-provided symbols)
+    ///    c. Local labels from that file (`.L` labels only if `keep_locals`)
+    /// 4. Global and weak symbols (including linker-provided symbols)
     pub fn build_symbol_table(
         &mut self,
         source: &Source,
         symbol_links: &SymbolLinks,
         symbol_values: &SymbolValues,
+        keep_locals: bool,
     ) -> Result<()> {
         // Infer has_data and has_bss from layout
         let has_data = self.layout.data_size > 0;
@@ -479,9 +617,13 @@ impl<'a> ElfBuilder<'a> {
                 st_shndx: text_section_index,
             });
 
-            // Add local labels (non-global labels and non-.equ symbols)
+            // Add local labels and .lcomm blocks (non-global, non-.equ symbols)
             for (line_index, line) in source_file.lines.iter().enumerate() {
-                if let LineContent::Label(name) = &line.content {
+                if let LineContent::Label(name)
+                | LineContent::Directive(
+                    Directive::Comm(name, _, _) | Directive::Lcomm(name, _, _),
+                ) = &line.content
+                {
                     // Skip if this label is declared global
                     let is_global =
                         symbol_links.global_symbols.iter().any(|g| {
@@ -495,8 +637,20 @@ impl<'a> ElfBuilder<'a> {
                         continue;
                     }
 
+                    // Skip assembler-local labels (.Lloop) unless kept
+                    if is_assembler_local(name) && !keep_locals {
+                        continue;
+                    }
+
+                    // Skip weak and common definitions that lost to another file
+                    let pointer = LinePointer { file_index, line_index };
+                    if symbol_links.overridden_symbols.contains(
+                        &SymbolDefinition { symbol: name.clone(), pointer },
+                    ) {
+                        continue;
+                    }
+
                     if !is_global {
-                        let pointer = LinePointer { file_index, line_index };
                         let line_layout = self.layout.get(pointer);
                         let (addr, section_idx) = match line_layout.segment {
                             Segment::Text => (
@@ -522,6 +676,21 @@ impl<'a> ElfBuilder<'a> {
                                 (bss_start + line_layout.offset, idx)
                             }
                         };
+
+                        // A common block starts after its alignment padding
+                        let addr = match &line.content {
+                            LineContent::Label(_) => addr,
+                            _ => {
+                                let def = SymbolDefinition {
+                                    symbol: name.clone(),
+                                    pointer,
+                                };
+                                match symbol_values.get(&def) {
+                                    Some(EvaluatedValue::Address(a)) => a,
+                                    _ => addr,
+                                }
+                            }
+                        };
                         let name_idx = self.symbol_names.add(name);
 
                         self.add_symbol(ElfSymbol {
@@ -551,13 +720,32 @@ impl<'a> ElfBuilder<'a> {
             st_size: 0,
         });
 
-        // Add user-defined global symbols
+        // Add user-defined global and weak symbols
         for global in &symbol_links.global_symbols {
             // Skip __global_pointer$ - it's already emitted above as a linker-provided symbol
             if global.symbol == "__global_pointer$" {
                 continue;
             }
 
+            let binding = match global.binding {
+                SymbolBinding::Weak => STB_WEAK,
+                SymbolBinding::Global | SymbolBinding::Common => STB_GLOBAL,
+            };
+
+            // A weak symbol that is never defined stays undefined with value 0
+            if is_undefined_weak(source, global) {
+                let name_idx = self.symbol_names.add(&global.symbol);
+                self.add_symbol(ElfSymbol {
+                    st_name: name_idx,
+                    st_info: make_st_info(binding, STT_NOTYPE),
+                    st_other: 0,
+                    st_shndx: SHN_UNDEF,
+                    st_value: 0,
+                    st_size: 0,
+                });
+                continue;
+            }
+
             let file_index = global.definition_pointer.file_index;
             let line_index = global.definition_pointer.line_index;
 
@@ -593,7 +781,7 @@ impl<'a> ElfBuilder<'a> {
 
             self.add_symbol(ElfSymbol {
                 st_name: name_idx,
-                st_info: make_st_info(STB_GLOBAL, STT_NOTYPE),
+                st_info: make_st_info(binding, STT_NOTYPE),
                 st_other: 0,
                 st_shndx,
                 st_value,
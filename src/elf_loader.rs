@@ -1,15 +1,20 @@
 // ELF binary format loading for the RISC-V simulator
 //
-// This module loads ELF executables into memory for simulation.
+// This module loads ELF executables into memory for simulation, from their
+// program headers, so executables from gcc and other toolchains load too.
 // All byte-level parsing is delegated to the elf module's decode methods.
 
 use std::collections::HashMap;
 
+use crate::dwarf::read_line_table;
 use crate::elf::{
-    ElfHeader, ElfProgramHeader, ElfSectionHeader, ElfSymbol, PT_LOAD, SHN_ABS,
-    SHT_STRTAB, SHT_SYMTAB, STT_FILE, SYMBOL_ENTRY_SIZE, StringTable,
+    ElfHeader, ElfProgramHeader, ElfSectionHeader, ElfSymbol, PF_W, PF_X,
+    PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHN_ABS, SHT_NOBITS, SHT_NOTE, SHT_NULL,
+    SHT_PROGBITS, SHT_RISCV_ATTRIBUTES, SHT_STRTAB, SHT_SYMTAB, STT_FILE,
+    SYMBOL_ENTRY_SIZE, StringTable,
 };
 use crate::error::{Result, RiscletError};
+use crate::riscv::{Op, RA};
 use crate::{Machine, memory::Segment};
 
 /// Input source for loading an ELF file
@@ -20,17 +25,34 @@ pub enum ElfInput<'a> {
     Bytes(&'a [u8]),
 }
 
-/// Load an ELF file from either a filesystem path or a byte slice
-pub fn load_elf(input: ElfInput) -> Result<Machine> {
-    let raw = match input {
-        ElfInput::File(filename) => std::fs::read(filename).map_err(|e| {
-            RiscletError::io(format!(
-                "failed to read file '{}': {}",
-                filename, e
-            ))
-        })?,
-        ElfInput::Bytes(bytes) => bytes.to_vec(),
-    };
+impl ElfInput<'_> {
+    /// The file's contents, or the bytes
+    pub fn read(self) -> Result<Vec<u8>> {
+        match self {
+            ElfInput::File(filename) => std::fs::read(filename).map_err(|e| {
+                RiscletError::io(format!(
+                    "failed to read file '{}': {}",
+                    filename, e
+                ))
+            }),
+            ElfInput::Bytes(bytes) => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+/// Load an ELF file from either a filesystem path or a byte slice, adding
+/// a warning to `warnings` for each part of it that is skipped
+///
+/// Memory comes from the PT_LOAD program headers, so executables from other
+/// toolchains load the way their program headers say. Sections are only
+/// read for symbols and line tables: without a symbol table, the entry
+/// point and call targets get made-up names, and non-allocated sections
+/// risclet has no use for are skipped.
+pub fn load_elf(
+    input: ElfInput,
+    warnings: &mut Vec<String>,
+) -> Result<Machine> {
+    let raw = input.read()?;
 
     // Validate minimum size for ELF header
     if raw.len() < 52 {
@@ -105,7 +127,8 @@ pub fn load_elf(input: ElfInput) -> Result<Machine> {
             header.e_phentsize
         )));
     }
-    if header.e_shentsize != 40 {
+    // Stripped executables can have no section headers at all
+    if header.e_shnum > 0 && header.e_shentsize != 40 {
         return Err(RiscletError::elf(format!(
             "unexpected section header entry size: {} (expected 40)",
             header.e_shentsize
@@ -117,8 +140,32 @@ pub fn load_elf(input: ElfInput) -> Result<Machine> {
         ));
     }
 
+    // Load section header string table
+    let shstrtab = load_section_header_string_table(&raw, &header)?;
+
+    // Read the section headers
+    let mut sections = Vec::new();
+    for i in 0..header.e_shnum as usize {
+        let offset =
+            header.e_shoff as usize + (i * header.e_shentsize as usize);
+        if offset + header.e_shentsize as usize > raw.len() {
+            return Err(RiscletError::elf(format!(
+                "section header {} out of bounds: offset {} size {}",
+                i, offset, header.e_shentsize
+            )));
+        }
+
+        let sh_data = &raw[offset..offset + header.e_shentsize as usize];
+        sections.push(ElfSectionHeader::decode(sh_data)?);
+    }
+    let allocated: Vec<(u32, u32)> = sections
+        .iter()
+        .filter(|sh| sh.sh_flags & SHF_ALLOC != 0 && sh.sh_size > 0)
+        .map(|sh| (sh.sh_addr, sh.sh_addr.saturating_add(sh.sh_size)))
+        .collect();
+
     // Load program segments (PT_LOAD only)
-    let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut segments = Vec::new();
     for i in 0..header.e_phnum as usize {
         let offset =
             header.e_phoff as usize + (i * header.e_phentsize as usize);
@@ -132,8 +179,8 @@ pub fn load_elf(input: ElfInput) -> Result<Machine> {
         let ph_data = &raw[offset..offset + header.e_phentsize as usize];
         let ph = ElfProgramHeader::decode(ph_data)?;
 
-        // Only load PT_LOAD segments
-        if ph.p_type != PT_LOAD {
+        // Only load PT_LOAD segments that take up memory
+        if ph.p_type != PT_LOAD || ph.p_memsz == 0 {
             continue;
         }
 
@@ -148,128 +195,208 @@ pub fn load_elf(input: ElfInput) -> Result<Machine> {
                 raw.len()
             )));
         }
-
-        let segment_data = raw[ph.p_offset as usize..seg_end].to_vec();
-        chunks.push((ph.p_vaddr, segment_data));
-    }
-
-    // Load section header string table
-    let shstrtab = load_section_header_string_table(&raw, &header)?;
-
-    // Load section header entries and build segments
-    let mut segments = Vec::new();
-    let mut strtab: Option<Vec<u8>> = None;
-    let mut symtab: Option<Vec<u8>> = None;
-
-    for i in 0..header.e_shnum as usize {
-        let offset =
-            header.e_shoff as usize + (i * header.e_shentsize as usize);
-        if offset + header.e_shentsize as usize > raw.len() {
+        if ph.p_filesz > ph.p_memsz {
             return Err(RiscletError::elf(format!(
-                "section header {} out of bounds: offset {} size {}",
-                i, offset, header.e_shentsize
+                "program segment {} has more file contents than memory ({} > {})",
+                i, ph.p_filesz, ph.p_memsz
             )));
         }
-
-        let sh_data = &raw[offset..offset + header.e_shentsize as usize];
-        let sh = ElfSectionHeader::decode(sh_data)?;
-
-        // Get section name
-        let section_name = shstrtab.get_string(sh.sh_name as usize).ok();
-
-        // Check for unsupported section types
-        if is_unsupported_section_type(sh.sh_type) {
+        let Some(mem_end) = ph.p_vaddr.checked_add(ph.p_memsz) else {
             return Err(RiscletError::elf(format!(
-                "ELF file contains unsupported section type: {:#x}",
-                sh.sh_type
+                "program segment {} runs past the end of memory ({:#x} + {:#x})",
+                i, ph.p_vaddr, ph.p_memsz
             )));
+        };
+
+        // Segments usually start with the ELF headers, mapped in with the
+        // code. Where there are section headers, only the sections in the
+        // segment are loaded; where there are none, the code segment is
+        // loaded from the entry point so the headers aren't taken for code.
+        let executable = ph.p_flags & PF_X != 0;
+        let whole =
+            if executable && (ph.p_vaddr..mem_end).contains(&header.e_entry) {
+                (header.e_entry, mem_end)
+            } else {
+                (ph.p_vaddr, mem_end)
+            };
+        let (start, end) = allocated
+            .iter()
+            .filter(|&&(start, end)| start >= ph.p_vaddr && end <= mem_end)
+            .fold(None, |range, &(start, end)| match range {
+                None => Some((start, end)),
+                Some((lo, hi)) => Some((start.min(lo), end.max(hi))),
+            })
+            .unwrap_or(whole);
+
+        // The part past the file contents is a zero-filled segment of its
+        // own, the way .bss follows .data
+        let writeable = ph.p_flags & PF_W != 0;
+        let file_end = (ph.p_vaddr + ph.p_filesz).clamp(start, end);
+        if file_end > start {
+            let from = ph.p_offset as usize + (start - ph.p_vaddr) as usize;
+            let to = ph.p_offset as usize + (file_end - ph.p_vaddr) as usize;
+            segments.push(Segment::new(
+                start,
+                file_end,
+                writeable,
+                executable,
+                raw[from..to].to_vec(),
+            ));
         }
-
-        // Load allocatable sections (PROGBITS or NOBITS with SHF_ALLOC)
-        if (sh.sh_type == 1 || sh.sh_type == 8) && (sh.sh_flags & 0x2) != 0 {
-            // Find initialization data from program segments
-            let mut init = Vec::new();
-            for (p_vaddr, seg_data) in &chunks {
-                if *p_vaddr <= sh.sh_addr
-                    && sh.sh_addr < p_vaddr + seg_data.len() as u32
-                {
-                    let start_idx = (sh.sh_addr - p_vaddr) as usize;
-                    let end_idx =
-                        (start_idx + sh.sh_size as usize).min(seg_data.len());
-                    init = seg_data[start_idx..end_idx].to_vec();
-                    break;
-                }
-            }
-
+        if end > file_end {
             segments.push(Segment::new(
-                sh.sh_addr,
-                sh.sh_addr + sh.sh_size,
-                (sh.sh_flags & 0x1) != 0, // writable
-                (sh.sh_flags & 0x4) != 0, // executable
-                init,
+                file_end,
+                end,
+                writeable,
+                executable,
+                Vec::new(),
             ));
         }
-        // Load string table
-        else if matches!(section_name.as_deref(), Some(".strtab"))
-            && sh.sh_type == SHT_STRTAB
-        {
-            if sh.sh_offset as usize + sh.sh_size as usize > raw.len() {
-                return Err(RiscletError::elf(format!(
-                    ".strtab section extends beyond ELF file: offset {} + size {} > {}",
-                    sh.sh_offset,
-                    sh.sh_size,
-                    raw.len()
-                )));
+    }
+    if segments.is_empty() {
+        return Err(RiscletError::elf(
+            "ELF file has no PT_LOAD segments to load".to_string(),
+        ));
+    }
+
+    let mut symbol_table = None;
+    let mut code = Vec::new();
+    let mut debug_line: &[u8] = &[];
+    let mut debug_line_str: &[u8] = &[];
+    for sh in &sections {
+        // Get section name
+        let section_name = shstrtab.get_string(sh.sh_name as usize).ok();
+
+        // Allocated sections are already loaded by the program headers
+        if sh.sh_flags & SHF_ALLOC != 0 {
+            if sh.sh_flags & SHF_EXECINSTR != 0 && sh.sh_type != SHT_NOBITS {
+                code.push((sh.sh_addr, sh.sh_addr.saturating_add(sh.sh_size)));
             }
-            strtab = Some(
-                raw[sh.sh_offset as usize
-                    ..(sh.sh_offset as usize + sh.sh_size as usize)]
-                    .to_vec(),
-            );
         }
-        // Load symbol table
-        else if matches!(section_name.as_deref(), Some(".symtab"))
-            && sh.sh_type == SHT_SYMTAB
+        // Load the symbol table, with the string table it links to
+        else if sh.sh_type == SHT_SYMTAB {
+            let strtab =
+                sections.get(sh.sh_link as usize).ok_or_else(|| {
+                    RiscletError::elf(format!(
+                        "symbol table links to missing string table {}",
+                        sh.sh_link
+                    ))
+                })?;
+            symbol_table = Some((
+                section_contents(&raw, strtab, ".strtab")?,
+                section_contents(&raw, sh, ".symtab")?,
+            ));
+        }
+        // Load line tables (optional, so out-of-bounds ones are ignored)
+        else if let Some(name @ (".debug_line" | ".debug_line_str")) =
+            section_name.as_deref()
+            && let Some(contents) = raw.get(
+                sh.sh_offset as usize
+                    ..sh.sh_offset as usize + sh.sh_size as usize,
+            )
         {
-            if sh.sh_offset as usize + sh.sh_size as usize > raw.len() {
-                return Err(RiscletError::elf(format!(
-                    ".symtab section extends beyond ELF file: offset {} + size {} > {}",
-                    sh.sh_offset,
-                    sh.sh_size,
-                    raw.len()
-                )));
+            if name == ".debug_line" {
+                debug_line = contents;
+            } else {
+                debug_line_str = contents;
             }
-            symtab = Some(
-                raw[sh.sh_offset as usize
-                    ..(sh.sh_offset as usize + sh.sh_size as usize)]
-                    .to_vec(),
-            );
+        } else if !is_known_section_type(sh.sh_type) {
+            warnings.push(format!(
+                "skipping section '{}' of unknown type {:#x}",
+                section_name.as_deref().unwrap_or(""),
+                sh.sh_type
+            ));
         }
     }
 
-    let strtab = strtab.ok_or_else(|| {
-        RiscletError::elf(
-            "ELF file does not contain .strtab section".to_string(),
-        )
-    })?;
-    let symtab = symtab.ok_or_else(|| {
-        RiscletError::elf(
-            "ELF file does not contain .symtab section".to_string(),
-        )
-    })?;
-
     // Parse symbol table
-    let (address_symbols, other_symbols, global_pointer) =
-        parse_symbol_table(&strtab, &symtab)?;
+    let (address_symbols, other_symbols, global_pointer) = match symbol_table {
+        Some((strtab, symtab)) => parse_symbol_table(strtab, symtab)?,
+        None => (HashMap::new(), HashMap::new(), 0),
+    };
 
     // Create machine
-    Ok(Machine::new(
+    let mut machine = Machine::new(
         segments,
         header.e_entry,
         global_pointer,
         address_symbols,
         other_symbols,
-    ))
+    );
+    if symbol_table.is_none() {
+        if code.is_empty() {
+            code.push((machine.text_start(), machine.text_end()));
+        }
+        add_synthetic_labels(&mut machine, &code);
+    }
+    machine.line_table = read_line_table(debug_line, debug_line_str);
+    Ok(machine)
+}
+
+/// A section header, with its name
+pub struct NamedSection {
+    pub name: String,
+    pub header: ElfSectionHeader,
+}
+
+/// A symbol table entry, with its name
+pub struct NamedSymbol {
+    pub name: String,
+    pub symbol: ElfSymbol,
+}
+
+/// Read the sections and symbols of an ELF file as they are, for listings
+/// that show them the way the file has them (load_elf has already checked
+/// the header)
+pub fn read_sections_and_symbols(
+    raw: &[u8],
+) -> Result<(Vec<NamedSection>, Vec<NamedSymbol>)> {
+    let header = ElfHeader::decode(raw)?;
+    let mut headers = Vec::new();
+    for i in 0..header.e_shnum as usize {
+        let offset = header.e_shoff as usize + i * header.e_shentsize as usize;
+        let sh_data = raw.get(offset..offset + 40).ok_or_else(|| {
+            RiscletError::elf(format!("section header {} out of bounds", i))
+        })?;
+        headers.push(ElfSectionHeader::decode(sh_data)?);
+    }
+    let contents = |sh: &ElfSectionHeader| -> Result<&[u8]> {
+        let start = sh.sh_offset as usize;
+        raw.get(start..start + sh.sh_size as usize).ok_or_else(|| {
+            RiscletError::elf(format!(
+                "section extends beyond ELF file: offset {} + size {} > {}",
+                sh.sh_offset,
+                sh.sh_size,
+                raw.len()
+            ))
+        })
+    };
+
+    let shstrtab = match headers.get(header.e_shstrndx as usize) {
+        Some(sh) if header.e_shstrndx != 0 => contents(sh)?,
+        _ => &[],
+    };
+    let mut symbols = Vec::new();
+    for sh in headers.iter().filter(|sh| sh.sh_type == SHT_SYMTAB) {
+        let strtab = match headers.get(sh.sh_link as usize) {
+            Some(strtab) => contents(strtab)?,
+            None => &[],
+        };
+        for entry in contents(sh)?.chunks_exact(SYMBOL_ENTRY_SIZE) {
+            let symbol = ElfSymbol::decode(entry)?;
+            let name = get_symbol_name(strtab, symbol.st_name as usize)?;
+            symbols.push(NamedSymbol { name, symbol });
+        }
+    }
+    let sections = headers
+        .into_iter()
+        .map(|header| {
+            let name = get_symbol_name(shstrtab, header.sh_name as usize)
+                .unwrap_or_default();
+            NamedSection { name, header }
+        })
+        .collect();
+    Ok((sections, symbols))
 }
 
 /// Load the section header string table
@@ -328,9 +455,77 @@ fn load_section_header_string_table(
     Ok(strtab)
 }
 
-/// Check if a section type is unsupported
-fn is_unsupported_section_type(sh_type: u32) -> bool {
-    matches!(sh_type, 0x4 | 0x5 | 0x6 | 0x9 | 0xb | 0xe | 0xf | 0x10 | 0x11)
+/// The contents of a section, which must be within the file
+fn section_contents<'a>(
+    raw: &'a [u8],
+    sh: &ElfSectionHeader,
+    name: &str,
+) -> Result<&'a [u8]> {
+    let start = sh.sh_offset as usize;
+    raw.get(start..start + sh.sh_size as usize).ok_or_else(|| {
+        RiscletError::elf(format!(
+            "{} section extends beyond ELF file: offset {} + size {} > {}",
+            name,
+            sh.sh_offset,
+            sh.sh_size,
+            raw.len()
+        ))
+    })
+}
+
+/// Check if a non-allocated section is of a type risclet knows, whether or
+/// not it reads it
+fn is_known_section_type(sh_type: u32) -> bool {
+    matches!(
+        sh_type,
+        SHT_NULL
+            | SHT_PROGBITS
+            | SHT_SYMTAB
+            | SHT_STRTAB
+            | SHT_NOTE
+            | SHT_NOBITS
+            | SHT_RISCV_ATTRIBUTES
+    )
+}
+
+/// Name the entry point `_start` and each call target `func_<address>`,
+/// for executables without a symbol table, so disassemblies and traces
+/// have somewhere to hang their local labels
+fn add_synthetic_labels(m: &mut Machine, code: &[(u32, u32)]) {
+    m.address_symbols.insert(m.entry_point(), "_start".to_string());
+    for &(start, end) in code {
+        let mut pc = start;
+        let mut previous = None;
+        while pc < end {
+            let Ok((inst, length)) = m.load_instruction(pc) else {
+                break;
+            };
+            let op = Op::new(inst);
+            // jal ra and c.jal, or auipc ra followed by jalr ra, ..(ra) as
+            // for a call too far for jal
+            let target = match (previous, &op) {
+                (_, Op::Jal { rd: RA, offset }) => {
+                    Some((pc as i32).wrapping_add(*offset))
+                }
+                (
+                    Some((auipc_pc, Op::Auipc { rd: RA, imm })),
+                    Op::Jalr { rd: RA, rs1: RA, offset },
+                ) => Some(
+                    (auipc_pc as i32).wrapping_add(imm).wrapping_add(*offset),
+                ),
+                _ => None,
+            };
+            if let Some(target) = target.map(|target| target as u32)
+                && m.load_instruction(target).is_ok()
+            {
+                m.address_symbols
+                    .entry(target)
+                    .or_insert_with(|| format!("func_{:x}", target));
+            }
+            previous = Some((pc, op));
+            pc += length;
+        }
+    }
 }
 
 /// Type alias for symbol table data: (address_symbols, other_symbols, global_pointer)
@@ -0,0 +1,150 @@
+// elf_loader_tests.rs
+//
+// Tests for loading executables the way other toolchains leave them:
+// stripped, with sections risclet does not know, or with segment flags
+// that differ from the section flags
+
+use crate::assembler::assemble;
+use crate::config::Config;
+use crate::elf::{ElfHeader, ElfProgramHeader, PF_R, PF_W, PF_X, PT_LOAD};
+use crate::elf_loader::read_sections_and_symbols;
+use crate::execution::Machine;
+
+const PROGRAM: &str = "
+        .globl _start
+_start: jal   helper
+        li    a7, 93
+        ecall
+helper: la    a0, value
+        lw    a0, 0(a0)
+        ret
+        .data
+value:  .4byte 7
+";
+
+fn executable() -> Vec<u8> {
+    assemble_with(Config::assemble_default(), PROGRAM)
+}
+
+fn assemble_with(mut config: Config, source: &str) -> Vec<u8> {
+    let files = vec![("prog.s".to_string(), source.to_string())];
+    assemble(&mut config, files).unwrap()
+}
+
+/// Drop the section headers altogether, as strip can
+fn strip(elf: &mut [u8]) {
+    elf[32..36].copy_from_slice(&0u32.to_le_bytes()); // e_shoff
+    elf[46..52].copy_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+}
+
+fn load(elf: &[u8]) -> (Machine, Vec<String>) {
+    let mut warnings = Vec::new();
+    let m = Machine::from_elf(elf, &mut warnings).unwrap();
+    (m, warnings)
+}
+
+fn address_of(m: &Machine, name: &str) -> u32 {
+    m.address_symbols
+        .iter()
+        .find(|(_, symbol)| *symbol == name)
+        .map(|(&address, _)| address)
+        .unwrap()
+}
+
+#[test]
+fn test_stripped_executables_get_names_for_entry_and_calls() {
+    let mut elf = executable();
+    let (original, _) = load(&elf);
+    let helper = address_of(&original, "helper");
+
+    strip(&mut elf);
+    let (mut m, warnings) = load(&elf);
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(m.address_symbols[&m.entry_point()], "_start");
+    assert_eq!(m.address_symbols[&helper], format!("func_{:x}", helper));
+    assert_eq!(m.address_symbols.len(), 2);
+
+    // The code is loaded from the entry point, leaving out the headers
+    // mapped in front of it
+    assert_eq!(m.text_start(), m.entry_point());
+    assert_eq!(m.text_end(), original.text_end());
+    assert_eq!(
+        m.load_instruction(helper).unwrap(),
+        original.load_instruction(helper).unwrap()
+    );
+    let value = address_of(&original, "value");
+    assert_eq!(m.load(value, 4).unwrap(), [7, 0, 0, 0]);
+}
+
+#[test]
+fn test_stripped_executables_get_names_for_far_and_compressed_calls() {
+    let source = "
+        .globl _start
+_start: call  far
+        c.jal near
+        li    a7, 93
+        ecall
+near:   ret
+far:    ret
+";
+    let mut config = Config::assemble_default();
+    config.relax.pseudo = false;
+    let mut elf = assemble_with(config, source);
+    let (original, _) = load(&elf);
+    let near = address_of(&original, "near");
+    let far = address_of(&original, "far");
+    assert_eq!(
+        original.load_instruction(original.entry_point() + 8).unwrap().1,
+        2,
+        "c.jal"
+    );
+
+    strip(&mut elf);
+    let (m, _) = load(&elf);
+    assert_eq!(m.address_symbols[&near], format!("func_{:x}", near));
+    assert_eq!(m.address_symbols[&far], format!("func_{:x}", far));
+    assert_eq!(m.address_symbols.len(), 3);
+}
+
+#[test]
+fn test_unknown_sections_are_skipped_with_a_warning() {
+    let mut elf = executable();
+    let header = ElfHeader::decode(&elf).unwrap();
+    let (sections, _) = read_sections_and_symbols(&elf).unwrap();
+    let index = sections
+        .iter()
+        .position(|section| section.name == ".riscv.attributes")
+        .unwrap();
+    let offset = header.e_shoff as usize + index * 40;
+    elf[offset + 4..offset + 8].copy_from_slice(&0x6fff_fff7u32.to_le_bytes());
+
+    let (m, warnings) = load(&elf);
+    assert_eq!(
+        warnings,
+        ["skipping section '.riscv.attributes' of unknown type 0x6ffffff7"]
+    );
+    assert!(m.address_symbols.values().any(|name| name == "helper"));
+}
+
+#[test]
+fn test_memory_has_the_program_header_permissions() {
+    let mut elf = executable();
+    let (mut m, _) = load(&elf);
+    let helper = address_of(&m, "helper");
+    let value = address_of(&m, "value");
+    assert!(m.store(helper, &[0; 4]).is_err());
+    assert!(m.store(value, &[0; 4]).is_ok());
+
+    // Make the code segment writable, whatever its sections say
+    let header = ElfHeader::decode(&elf).unwrap();
+    for i in 0..header.e_phnum as usize {
+        let offset = header.e_phoff as usize + i * 32;
+        let mut ph = ElfProgramHeader::decode(&elf[offset..]).unwrap();
+        if ph.p_type == PT_LOAD && ph.p_flags & PF_X != 0 {
+            ph.p_flags = PF_R | PF_W | PF_X;
+            elf[offset..offset + 32].copy_from_slice(&ph.encode());
+        }
+    }
+    let (mut m, _) = load(&elf);
+    assert!(m.store(helper, &[0; 4]).is_ok());
+}
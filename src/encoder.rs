@@ -11,14 +11,17 @@
 
 use crate::ast::{
     AtomicOp, BTypeOp, CompressedOp, CompressedOperands, Directive, Expression,
-    ITypeOp, Instruction, Line, LineContent, LinePointer, LoadStoreOp,
-    Location, MemoryOrdering, PseudoOp, RTypeOp, Register, Segment, Source,
-    SpecialOp, UTypeOp,
+    ExtendOp, ITypeOp, Instruction, Line, LineContent, LinePointer,
+    LoadStoreOp, Location, MemoryOrdering, PseudoOp, RTypeOp, Register,
+    Segment, Source, SpecialOp, UTypeOp,
 };
-use crate::config::Config;
+use crate::config::Relax;
 use crate::error::{Result, RiscletError};
-use crate::expressions::{EvaluatedValue, SymbolValues, eval_expr};
+use crate::expressions::{
+    EvaluatedValue, SymbolValues, eval_common_block, eval_expr,
+};
 use crate::layout::{Layout, LineLayout, LineSizes};
+use crate::object_builder::resolve_base;
 use crate::symbols::SymbolLinks;
 
 // ============================================================================
@@ -33,8 +36,14 @@ pub struct EncodedPass {
 }
 
 /// Encode all lines using a read-only layout snapshot.
+///
+/// Each line is encoded with the relaxation settings recorded for it in the
+/// layout, so `.option` regions are honoured line by line. A line that
+/// fails to encode is reported and filled with zeros of its current size, so
+/// the rest of the pass still runs and every bad line is reported at once.
+/// Errors about a line point at its last operand, which is where every
+/// instruction keeps its immediate, offset or target.
 pub fn encode(
-    config: &Config,
     source: &Source,
     symbol_links: &SymbolLinks,
     symbol_values: &SymbolValues,
@@ -43,28 +52,36 @@ pub fn encode(
     let mut line_sizes = LineSizes::new();
     let mut text_bytes = Vec::new();
     let mut data_bytes = Vec::new();
+    let mut errors = Vec::new();
 
     for file_index in 0..source.files.len() {
         for line_index in 0..source.files[file_index].lines.len() {
             let pointer = LinePointer { file_index, line_index };
-            let &LineLayout { segment, .. } = layout.get(pointer);
+            let &LineLayout { segment, relax, size, .. } = layout.get(pointer);
             let current_address = layout.get_line_address(pointer);
-            let data_start = layout.data_start;
 
             let line = &source.files[file_index].lines[line_index];
 
             let (bytes, actual_size) = match segment {
                 Segment::Text | Segment::Data => {
                     let bytes = encode_line(
-                        config,
+                        &relax,
                         source,
                         symbol_links,
                         symbol_values,
+                        layout,
                         line,
                         pointer,
                         current_address,
-                        data_start,
-                    )?;
+                        size,
+                    )
+                    .unwrap_or_else(|e| {
+                        errors.push(e.or_pointing_at(
+                            &line.location,
+                            line.last_operand_location(),
+                        ));
+                        vec![0; size as usize]
+                    });
                     let size = bytes.len() as u32;
                     (Some(bytes), size)
                 }
@@ -76,7 +93,14 @@ pub fn encode(
                         line,
                         pointer,
                         current_address,
-                    )?;
+                    )
+                    .unwrap_or_else(|e| {
+                        errors.push(e.or_pointing_at(
+                            &line.location,
+                            line.last_operand_location(),
+                        ));
+                        size
+                    });
                     (None, size)
                 }
             };
@@ -93,46 +117,78 @@ pub fn encode(
         }
     }
 
+    if !errors.is_empty() {
+        return Err(RiscletError::from_errors(errors));
+    }
     Ok(EncodedPass { line_sizes, text_bytes, data_bytes })
 }
 
 /// Encode a single line
+///
+/// `size` is the line's size in the layout being encoded, which is what the
+/// previous pass produced for it.
 fn encode_line(
-    config: &Config,
+    relax: &Relax,
     source: &Source,
     symbol_links: &SymbolLinks,
     symbol_values: &SymbolValues,
+    layout: &Layout,
     line: &Line,
     pointer: LinePointer,
     current_address: u32,
-    data_start: u32,
+    size: u32,
 ) -> Result<Vec<u8>> {
     match &line.content {
         LineContent::Label(_) => Ok(Vec::new()),
         LineContent::Instruction(inst) => encode_instruction(
-            config,
+            relax,
             source,
             symbol_links,
             symbol_values,
+            layout,
             line,
             pointer,
             inst,
             current_address,
-            data_start,
-        ),
-        LineContent::Directive(dir) => encode_directive(
-            dir,
-            line,
-            current_address,
-            source,
-            symbol_values,
-            symbol_links,
-            pointer,
+            size,
         ),
+        LineContent::Directive(dir) => {
+            let bytes = encode_directive(
+                dir,
+                line,
+                current_address,
+                source,
+                symbol_values,
+                symbol_links,
+                pointer,
+            )?;
+            // Code can run into alignment padding, so as in GNU as it is
+            // filled with nops
+            if matches!(dir, Directive::Balign(_))
+                && layout.get(pointer).segment == Segment::Text
+            {
+                Ok(nop_padding(bytes.len()))
+            } else {
+                Ok(bytes)
+            }
+        }
     }
 }
 
-/// Encode BSS segment line (must be space directive only)
+/// `size` bytes of padding that run through as nops: zero bytes up to a
+/// 2-byte boundary, then a c.nop up to a 4-byte boundary, then nops
+fn nop_padding(size: usize) -> Vec<u8> {
+    let mut bytes = vec![0; size % 2];
+    if size % 4 >= 2 {
+        bytes.extend_from_slice(&0x0001u16.to_le_bytes());
+    }
+    for _ in 0..size / 4 {
+        bytes.extend_from_slice(&0x0000_0013u32.to_le_bytes());
+    }
+    bytes
+}
+
+/// Encode BSS segment line (only .space, .balign, common blocks and labels)
 fn encode_bss_line(
     source: &Source,
     symbol_links: &SymbolLinks,
@@ -163,12 +219,41 @@ fn encode_bss_line(
             }
             Ok(size as u32)
         }
+        LineContent::Directive(Directive::Comm(..) | Directive::Lcomm(..)) => {
+            let (padding, size) = eval_common_block(
+                current_address,
+                symbol_links,
+                symbol_values,
+                source,
+                pointer,
+            )?;
+            Ok(padding + size)
+        }
+        LineContent::Directive(
+            dir @ (Directive::Balign(_) | Directive::Size(..)),
+        ) => {
+            let padding = encode_directive(
+                dir,
+                line,
+                current_address,
+                source,
+                symbol_values,
+                symbol_links,
+                pointer,
+            )?;
+            Ok(padding.len() as u32)
+        }
         // Segment directives themselves don't produce bytes in BSS
         LineContent::Directive(Directive::Text)
         | LineContent::Directive(Directive::Data)
         | LineContent::Directive(Directive::Bss)
         | LineContent::Directive(Directive::Global(_))
-        | LineContent::Directive(Directive::Equ(_, _)) => Ok(0),
+        | LineContent::Directive(Directive::Weak(_))
+        | LineContent::Directive(Directive::Local(_))
+        | LineContent::Directive(Directive::Equ(_, _))
+        | LineContent::Directive(Directive::Equiv(_, _))
+        | LineContent::Directive(Directive::Option(_))
+        | LineContent::Directive(Directive::Type(..)) => Ok(0),
         LineContent::Directive(dir) => {
             let dir_name = match dir {
                 Directive::Byte(_) => ".byte",
@@ -176,21 +261,20 @@ fn encode_bss_line(
                 Directive::FourByte(_) => ".4byte",
                 Directive::String(_) => ".string",
                 Directive::Asciz(_) => ".asciz",
-                Directive::Balign(_) => ".balign",
                 _ => "directive",
             };
             Err(RiscletError::from_context(
                 format!(
-                    "{} cannot be used in .bss segment (only .space and labels are allowed)",
+                    "{} cannot be used in .bss segment (only .space, .balign and labels are allowed)",
                     dir_name
                 ),
-                line.location.clone(),
+                line.head_location(),
             ))
         }
         LineContent::Instruction(_) => Err(RiscletError::from_context(
-            "Instructions cannot be used in .bss segment (only .space and labels are allowed)"
+            "Instructions cannot be used in .bss segment (only .space, .balign and labels are allowed)"
                 .to_string(),
-            line.location.clone(),
+            line.head_location(),
         )),
     }
 }
@@ -200,19 +284,25 @@ fn encode_bss_line(
 // ============================================================================
 
 fn encode_instruction(
-    config: &Config,
+    relax: &Relax,
     source: &Source,
     symbol_links: &SymbolLinks,
     symbol_values: &SymbolValues,
+    layout: &Layout,
     line: &Line,
     pointer: LinePointer,
     inst: &Instruction,
     current_address: u32,
-    data_start: u32,
+    size: u32,
 ) -> Result<Vec<u8>> {
+    // A branch or jump that an earlier pass expanded stays expanded, so that
+    // sizes only grow and the relaxation loop converges
+    let expanded = size > 4;
+    let data_start = layout.data_start;
+
     match inst {
         Instruction::RType(op, rd, rs1, rs2) => {
-            encode_r_type_family(config, op, *rd, *rs1, *rs2)
+            encode_r_type_family(relax, op, *rd, *rs1, *rs2)
         }
         Instruction::IType(op, rd, rs1, imm) => {
             let val = eval_line_expr(
@@ -225,7 +315,7 @@ fn encode_instruction(
             )?;
             let imm_val =
                 require_integer(val, "I-type immediate", &line.location)?;
-            encode_i_type_family(config, &line.location, op, *rd, *rs1, imm_val)
+            encode_i_type_family(relax, &line.location, op, *rd, *rs1, imm_val)
         }
         Instruction::LoadStore(op, rd_or_rs, offset, rs1) => {
             let val = eval_line_expr(
@@ -244,7 +334,7 @@ fn encode_instruction(
                 *rs1,
                 offset_val,
                 &line.location,
-                config,
+                relax,
             )
         }
         Instruction::BType(op, rs1, rs2, target) => {
@@ -259,8 +349,42 @@ fn encode_instruction(
             let target_addr =
                 require_address(target_val, "Branch target", &line.location)?;
             let current_pc = current_address as i64;
-            let offset = target_addr as i64 - current_pc;
-            encode_branch_family(op, *rs1, *rs2, offset, &line.location, config)
+            let offset = if targets_other_file(
+                target,
+                layout,
+                source,
+                symbol_links,
+                pointer,
+            ) {
+                0
+            } else {
+                target_addr as i64 - current_pc
+            };
+            if relax.branches && (expanded || !fits_signed(offset, 13)) {
+                return encode_far_branch(
+                    op,
+                    *rs1,
+                    *rs2,
+                    offset,
+                    &line.location,
+                    relax,
+                );
+            }
+            // Only the command line turns expansion off outside objects,
+            // where relocated branches must keep their size
+            if !fits_signed(offset, 13)
+                && offset % 2 == 0
+                && !layout.relocatable
+            {
+                return Err(RiscletError::from_context(
+                    format!(
+                        "{}; use --relax-branches to have it branch over a jump instead",
+                        branch_out_of_range(offset)
+                    ),
+                    line.location.clone(),
+                ));
+            }
+            encode_branch_family(op, *rs1, *rs2, offset, &line.location, relax)
         }
         Instruction::UType(op, rd, imm) => {
             let val = eval_line_expr(
@@ -287,8 +411,24 @@ fn encode_instruction(
             let target_addr =
                 require_address(target_val, "Jump target", &line.location)?;
             let current_pc = current_address as i64;
-            let offset = target_addr as i64 - current_pc;
-            encode_jal_family(*rd, offset, &line.location, config)
+            let offset = if targets_other_file(
+                target,
+                layout,
+                source,
+                symbol_links,
+                pointer,
+            ) {
+                0
+            } else {
+                target_addr as i64 - current_pc
+            };
+            if relax.branches
+                && *rd != Register::X0
+                && (expanded || !fits_signed(offset, 21))
+            {
+                return encode_far_jal(*rd, offset, &line.location, relax);
+            }
+            encode_jal_family(*rd, offset, &line.location, relax)
         }
         Instruction::Pseudo(pseudo_op) => encode_pseudo(
             pseudo_op,
@@ -299,7 +439,7 @@ fn encode_instruction(
             symbol_links,
             pointer,
             data_start,
-            config,
+            relax,
         ),
         Instruction::Atomic(op, rd, rs1, rs2, ordering) => {
             encode_atomic(op, *rd, *rs1, *rs2, ordering)
@@ -313,6 +453,7 @@ fn encode_instruction(
             source,
             symbol_values,
             symbol_links,
+            layout,
             pointer,
         ),
     }
@@ -323,14 +464,14 @@ fn encode_instruction(
 // ============================================================================
 
 fn encode_r_type_family(
-    config: &Config,
+    relax: &Relax,
     op: &RTypeOp,
     rd: Register,
     rs1: Register,
     rs2: Register,
 ) -> Result<Vec<u8>> {
     // Try compressed encoding if enabled
-    if config.relax.compressed {
+    if relax.compressed {
         // c.add rd, rs2 (rd is also rs1, rs2 != x0, rd != x0)
         if matches!(op, RTypeOp::Add)
             && rd == rs1
@@ -396,7 +537,7 @@ fn encode_r_type_family(
 // ============================================================================
 
 fn encode_i_type_family(
-    config: &Config,
+    relax: &Relax,
     location: &Location,
     op: &ITypeOp,
     rd: Register,
@@ -405,11 +546,11 @@ fn encode_i_type_family(
 ) -> Result<Vec<u8>> {
     // Special handling for JALR (it can become c.jr or c.jalr)
     if matches!(op, ITypeOp::Jalr) {
-        return encode_jalr_family(config, location, rd, rs1, imm);
+        return encode_jalr_family(relax, location, rd, rs1, imm);
     }
 
     // Try compressed encoding if enabled
-    if config.relax.compressed {
+    if relax.compressed {
         // c.addi rd, imm (rd == rs1, rd != x0, imm fits in 6-bit signed)
         if matches!(op, ITypeOp::Addi)
             && rd == rs1
@@ -515,14 +656,14 @@ fn encode_i_type_family(
 // ============================================================================
 
 fn encode_jalr_family(
-    config: &Config,
+    relax: &Relax,
     location: &Location,
     rd: Register,
     rs1: Register,
     offset: i64,
 ) -> Result<Vec<u8>> {
     // Try compressed encoding if enabled
-    if config.relax.compressed && offset == 0 {
+    if relax.compressed && offset == 0 {
         // c.jr rs1 (rd == x0, rs1 != x0, offset == 0)
         if rd == Register::X0 && rs1 != Register::X0 {
             return Ok(encode_c_jr(rs1).to_le_bytes().to_vec());
@@ -549,7 +690,7 @@ fn encode_load_store_family(
     rs1: Register,
     offset: i64,
     location: &Location,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     let _is_load = matches!(
         op,
@@ -561,7 +702,7 @@ fn encode_load_store_family(
     );
 
     // Try compressed encoding if enabled
-    if config.relax.compressed {
+    if relax.compressed {
         // c.lw rd, offset(rs1) (compressed regs, offset 4-byte aligned, 0-124)
         if matches!(op, LoadStoreOp::Lw)
             && is_compressed_reg(rd_or_rs)
@@ -625,11 +766,10 @@ fn encode_branch_family(
     rs2: Register,
     offset: i64,
     location: &Location,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     // Try compressed encoding if enabled
-    if config.relax.compressed && is_compressed_reg(rs1) && rs2 == Register::X0
-    {
+    if relax.compressed && is_compressed_reg(rs1) && rs2 == Register::X0 {
         // c.beqz rs1, offset (rs2 == x0, compressed reg rs1, offset in ±256, even)
         if matches!(op, BTypeOp::Beq)
             && (-256..256).contains(&offset)
@@ -665,6 +805,45 @@ fn encode_branch_family(
     Ok(inst.to_le_bytes().to_vec())
 }
 
+/// Encode a conditional branch whose target is beyond its ±4 KiB reach as
+/// the inverted branch over a `jal x0` to the target
+fn encode_far_branch(
+    op: &BTypeOp,
+    rs1: Register,
+    rs2: Register,
+    offset: i64,
+    location: &Location,
+    relax: &Relax,
+) -> Result<Vec<u8>> {
+    // The jal follows the branch, 4 bytes further from the target
+    let jump_offset = offset - 4;
+    if !fits_signed(jump_offset, 21) {
+        return Err(RiscletError::from_context(
+            format!(
+                "Branch target is {} bytes away, out of range even for a branch over a jump (±1 MiB)",
+                offset
+            ),
+            location.clone(),
+        ));
+    }
+
+    let inverted = match op {
+        BTypeOp::Beq => BTypeOp::Bne,
+        BTypeOp::Bne => BTypeOp::Beq,
+        BTypeOp::Blt => BTypeOp::Bge,
+        BTypeOp::Bge => BTypeOp::Blt,
+        BTypeOp::Bltu => BTypeOp::Bgeu,
+        BTypeOp::Bgeu => BTypeOp::Bltu,
+    };
+    // Keep the skipping branch full size so the jal is always 4 bytes on
+    let full_size = Relax { compressed: false, ..*relax };
+    let mut bytes =
+        encode_branch_family(&inverted, rs1, rs2, 8, location, &full_size)?;
+    let jal = encode_j_type(0b1101111, Register::X0, jump_offset, location)?;
+    bytes.extend_from_slice(&jal.to_le_bytes());
+    Ok(bytes)
+}
+
 // ============================================================================
 // JAL Instruction Family
 // ============================================================================
@@ -673,10 +852,10 @@ fn encode_jal_family(
     rd: Register,
     offset: i64,
     location: &Location,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     // Try compressed encoding if enabled
-    if config.relax.compressed && fits_signed(offset, 12) && offset % 2 == 0 {
+    if relax.compressed && fits_signed(offset, 12) && offset % 2 == 0 {
         // c.j offset (rd == x0)
         if rd == Register::X0 {
             return Ok(encode_c_j(offset as i32).to_le_bytes().to_vec());
@@ -693,6 +872,23 @@ fn encode_jal_family(
     Ok(inst.to_le_bytes().to_vec())
 }
 
+/// Encode `jal rd, target` whose target is beyond its ±1 MiB reach as
+/// `auipc rd` + `jalr rd, rd`, which reach any address
+///
+/// The sequence needs rd as a temporary, so `j` (rd = x0) is not expanded.
+fn encode_far_jal(
+    rd: Register,
+    offset: i64,
+    location: &Location,
+    relax: &Relax,
+) -> Result<Vec<u8>> {
+    let (hi, lo) = split_offset_hi_lo(offset);
+    let auipc = encode_u_type(0b0010111, rd, (hi & 0xFFFFF) as u32, location)?;
+    let mut bytes = auipc.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&encode_jalr_family(relax, location, rd, rd, lo)?);
+    Ok(bytes)
+}
+
 // ============================================================================
 // U-Type Instruction Family
 // ============================================================================
@@ -738,7 +934,7 @@ fn encode_pseudo(
     symbol_links: &SymbolLinks,
     pointer: LinePointer,
     data_start: u32,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     match op {
         PseudoOp::Li(rd, imm) => encode_li(
@@ -750,7 +946,7 @@ fn encode_pseudo(
             symbol_values,
             symbol_links,
             pointer,
-            config,
+            relax,
         ),
         PseudoOp::La(rd, addr_expr) => encode_la(
             *rd,
@@ -762,19 +958,10 @@ fn encode_pseudo(
             symbol_links,
             pointer,
             data_start,
-            config,
-        ),
-        PseudoOp::Call(target) => encode_call(
-            target,
-            line,
-            current_address,
-            source,
-            symbol_values,
-            symbol_links,
-            pointer,
-            config,
+            relax,
         ),
-        PseudoOp::Tail(target) => encode_tail(
+        PseudoOp::Call(rd, target) => encode_call(
+            *rd,
             target,
             line,
             current_address,
@@ -782,8 +969,21 @@ fn encode_pseudo(
             symbol_values,
             symbol_links,
             pointer,
-            config,
+            relax,
         ),
+        PseudoOp::Tail(target, temp) | PseudoOp::Jump(target, temp) => {
+            encode_tail(
+                target,
+                *temp,
+                line,
+                current_address,
+                source,
+                symbol_values,
+                symbol_links,
+                pointer,
+                relax,
+            )
+        }
         PseudoOp::LoadGlobal(op, rd, addr) => encode_load_global_pseudo(
             op,
             *rd,
@@ -809,9 +1009,47 @@ fn encode_pseudo(
                 pointer,
             )
         }
+        PseudoOp::Extend(op, rd, rs) => {
+            encode_extend(*op, *rd, *rs, &line.location, relax)
+        }
+        PseudoOp::Sle(op, rd, rs1, rs2) => {
+            // slt rd, rs2, rs1; xori rd, rd, 1
+            let mut bytes = encode_r_type_family(relax, op, *rd, *rs2, *rs1)?;
+            bytes.extend(encode_i_type_family(
+                relax,
+                &line.location,
+                &ITypeOp::Xori,
+                *rd,
+                *rd,
+                1,
+            )?);
+            Ok(bytes)
+        }
     }
 }
 
+/// Encode `sext.b`, `sext.h` or `zext.h rd, rs` as a pair of shifts
+fn encode_extend(
+    op: ExtendOp,
+    rd: Register,
+    rs: Register,
+    location: &Location,
+    relax: &Relax,
+) -> Result<Vec<u8>> {
+    let shift = op.shift();
+    let mut bytes =
+        encode_i_type_family(relax, location, &ITypeOp::Slli, rd, rs, shift)?;
+    bytes.extend(encode_i_type_family(
+        relax,
+        location,
+        &op.right_shift(),
+        rd,
+        rd,
+        shift,
+    )?);
+    Ok(bytes)
+}
+
 /// Encode `li rd, imm` pseudo-instruction
 fn encode_li(
     rd: Register,
@@ -822,7 +1060,7 @@ fn encode_li(
     symbol_values: &SymbolValues,
     symbol_links: &SymbolLinks,
     pointer: LinePointer,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     let refs = symbol_links.get_line_refs(pointer);
     let val = eval_expr(
@@ -838,7 +1076,7 @@ fn encode_li(
     // If immediate fits in 12-bit signed, use addi rd, x0, imm
     if fits_signed(imm, 12) {
         return encode_i_type_family(
-            config,
+            relax,
             &line.location,
             &ITypeOp::Addi,
             rd,
@@ -859,7 +1097,7 @@ fn encode_li(
     // addi rd, rd, lo
     if lo != 0 {
         let addi_bytes = encode_i_type_family(
-            config,
+            relax,
             &line.location,
             &ITypeOp::Addi,
             rd,
@@ -883,7 +1121,7 @@ fn encode_la(
     symbol_links: &SymbolLinks,
     pointer: LinePointer,
     data_start: u32,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     let refs = symbol_links.get_line_refs(pointer);
     let target_val = eval_expr(
@@ -901,7 +1139,7 @@ fn encode_la(
     let is_gp_init = rd == Register::X3;
 
     // Try GP-relative encoding if enabled and not initializing GP
-    if !is_gp_init && config.relax.effective_gp() {
+    if !is_gp_init && relax.effective_gp() {
         // GP value is always data_start + 2048
         let gp_addr = data_start as i64 + 2048;
         let gp_offset = target_addr as i64 - gp_addr;
@@ -909,7 +1147,7 @@ fn encode_la(
         // If within ±2 KiB of GP, use addi rd, gp, offset
         if fits_signed(gp_offset, 12) {
             return encode_i_type_family(
-                config,
+                relax,
                 &line.location,
                 &ITypeOp::Addi,
                 rd,
@@ -931,7 +1169,7 @@ fn encode_la(
 
     // addi rd, rd, lo
     let addi_bytes = encode_i_type_family(
-        config,
+        relax,
         &line.location,
         &ITypeOp::Addi,
         rd,
@@ -943,8 +1181,9 @@ fn encode_la(
     Ok(bytes)
 }
 
-/// Encode `call target` pseudo-instruction
+/// Encode `call [rd,] target` pseudo-instruction
 fn encode_call(
+    rd: Register,
     target_expr: &Expression,
     line: &Line,
     current_address: u32,
@@ -952,7 +1191,7 @@ fn encode_call(
     symbol_values: &SymbolValues,
     symbol_links: &SymbolLinks,
     pointer: LinePointer,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     let refs = symbol_links.get_line_refs(pointer);
     let target_val = eval_expr(
@@ -969,47 +1208,38 @@ fn encode_call(
     let offset = target_addr as i64 - current_pc;
 
     // Try relaxed encoding if enabled and within range
-    if config.relax.pseudo && fits_signed(offset, 21) && offset % 2 == 0 {
-        // jal ra, offset (may become c.jal if compression enabled)
-        return encode_jal_family(Register::X1, offset, &line.location, config);
+    if relax.pseudo && fits_signed(offset, 21) && offset % 2 == 0 {
+        // jal rd, offset (may become c.jal if compression enabled)
+        return encode_jal_family(rd, offset, &line.location, relax);
     }
 
     // Fall back to auipc + jalr
     let mut bytes = Vec::new();
     let (hi, lo) = split_offset_hi_lo(offset);
 
-    // auipc ra, hi
-    let auipc_inst = encode_u_type(
-        0b0010111,
-        Register::X1,
-        (hi & 0xFFFFF) as u32,
-        &line.location,
-    )?;
+    // auipc rd, hi
+    let auipc_inst =
+        encode_u_type(0b0010111, rd, (hi & 0xFFFFF) as u32, &line.location)?;
     bytes.extend_from_slice(&auipc_inst.to_le_bytes());
 
-    // jalr ra, ra, lo
-    let jalr_bytes = encode_jalr_family(
-        config,
-        &line.location,
-        Register::X1,
-        Register::X1,
-        lo,
-    )?;
+    // jalr rd, rd, lo
+    let jalr_bytes = encode_jalr_family(relax, &line.location, rd, rd, lo)?;
     bytes.extend_from_slice(&jalr_bytes);
 
     Ok(bytes)
 }
 
-/// Encode `tail target` pseudo-instruction
+/// Encode `tail target [, temp]` or `jump target, temp` pseudo-instruction
 fn encode_tail(
     target_expr: &Expression,
+    temp: Register,
     line: &Line,
     current_address: u32,
     source: &Source,
     symbol_values: &SymbolValues,
     symbol_links: &SymbolLinks,
     pointer: LinePointer,
-    config: &Config,
+    relax: &Relax,
 ) -> Result<Vec<u8>> {
     let refs = symbol_links.get_line_refs(pointer);
     let target_val = eval_expr(
@@ -1026,32 +1256,23 @@ fn encode_tail(
     let offset = target_addr as i64 - current_pc;
 
     // Try relaxed encoding if enabled and within range
-    if config.relax.pseudo && fits_signed(offset, 21) && offset % 2 == 0 {
+    if relax.pseudo && fits_signed(offset, 21) && offset % 2 == 0 {
         // jal x0, offset (may become c.j if compression enabled)
-        return encode_jal_family(Register::X0, offset, &line.location, config);
+        return encode_jal_family(Register::X0, offset, &line.location, relax);
     }
 
-    // Fall back to auipc + jalr using t1
+    // Fall back to auipc + jalr using the temporary
     let mut bytes = Vec::new();
     let (hi, lo) = split_offset_hi_lo(offset);
 
-    // auipc t1, hi
-    let auipc_inst = encode_u_type(
-        0b0010111,
-        Register::X6,
-        (hi & 0xFFFFF) as u32,
-        &line.location,
-    )?;
+    // auipc temp, hi
+    let auipc_inst =
+        encode_u_type(0b0010111, temp, (hi & 0xFFFFF) as u32, &line.location)?;
     bytes.extend_from_slice(&auipc_inst.to_le_bytes());
 
-    // jalr x0, t1, lo
-    let jalr_bytes = encode_jalr_family(
-        config,
-        &line.location,
-        Register::X0,
-        Register::X6,
-        lo,
-    )?;
+    // jalr x0, temp, lo
+    let jalr_bytes =
+        encode_jalr_family(relax, &line.location, Register::X0, temp, lo)?;
     bytes.extend_from_slice(&jalr_bytes);
 
     Ok(bytes)
@@ -1152,10 +1373,11 @@ fn encode_compressed_explicit(
     source: &Source,
     symbol_values: &SymbolValues,
     symbol_links: &SymbolLinks,
+    layout: &Layout,
     pointer: LinePointer,
 ) -> Result<Vec<u8>> {
     // Evaluate any expressions in operands first
-    let evaluated_operands = eval_compressed_operands(
+    let mut evaluated_operands = eval_compressed_operands(
         operands,
         current_address,
         source,
@@ -1163,8 +1385,18 @@ fn encode_compressed_explicit(
         symbol_links,
         pointer,
     )?;
+    if let (
+        CompressedOperands::CBBranch { offset: target, .. }
+        | CompressedOperands::CJOpnd { offset: target },
+        EvaluatedCompressedOperands::CBBranch { offset, .. }
+        | EvaluatedCompressedOperands::CJOpnd { offset },
+    ) = (operands, &mut evaluated_operands)
+        && targets_other_file(target, layout, source, symbol_links, pointer)
+    {
+        *offset = 0;
+    }
 
-    let inst = encode_compressed_inst(op, &evaluated_operands, &line.location)?;
+    let inst = encode_compressed_inst(op, &evaluated_operands, line)?;
     Ok(inst.to_le_bytes().to_vec())
 }
 
@@ -1280,7 +1512,15 @@ fn encode_directive(
         | Directive::Data
         | Directive::Bss
         | Directive::Global(_)
-        | Directive::Equ(_, _) => Ok(Vec::new()),
+        | Directive::Weak(_)
+        | Directive::Local(_)
+        | Directive::Equ(_, _)
+        | Directive::Equiv(_, _)
+        | Directive::Option(_)
+        | Directive::Type(..) => Ok(Vec::new()),
+
+        // Layout always places common blocks in .bss (see encode_bss_line)
+        Directive::Comm(..) | Directive::Lcomm(..) => Ok(Vec::new()),
 
         Directive::Byte(exprs) => {
             let mut bytes = Vec::new();
@@ -1394,6 +1634,30 @@ fn encode_directive(
             let padding = (alignment - (abs_addr % alignment)) % alignment;
             Ok(vec![0; padding as usize])
         }
+
+        // Produces nothing, but the size has to make sense for the symbol
+        // map (--map)
+        Directive::Size(name, expr) => {
+            let val = eval_line_expr(
+                expr,
+                current_address,
+                source,
+                symbol_values,
+                symbol_links,
+                pointer,
+            )?;
+            let size = require_integer(val, ".size directive", &line.location)?;
+            if size < 0 {
+                return Err(RiscletError::from_context(
+                    format!(
+                        "Size of '{}' must be non-negative, got {}",
+                        name, size
+                    ),
+                    line.location.clone(),
+                ));
+            }
+            Ok(Vec::new())
+        }
     }
 }
 
@@ -1858,11 +2122,14 @@ fn encode_c_jalr(rs1: Register) -> u16 {
 // Compressed Instruction Full Encoder (for explicit c.* instructions)
 // ============================================================================
 
+/// Errors about an immediate are left at the line, for the caller to point
+/// at the operand that holds it
 fn encode_compressed_inst(
     op: &CompressedOp,
     operands: &EvaluatedCompressedOperands,
-    location: &Location,
+    line: &Line,
 ) -> Result<u16> {
+    let location = &line.location;
     match (op, operands) {
         (CompressedOp::CNop, EvaluatedCompressedOperands::None) => {
             Ok(0b000_0_00000_00000_01)
@@ -1920,7 +2187,7 @@ fn encode_compressed_inst(
             if *rd == Register::X2 {
                 return Err(RiscletError::from_context(
                     "c.lui cannot use sp (x2) as destination".to_string(),
-                    location.clone(),
+                    line.operand_location(0),
                 ));
             }
             if !fits_signed(*imm as i64, 6) {
@@ -2153,7 +2420,7 @@ fn encode_compressed_inst(
         ) => Ok(encode_c_jalr(*rs1)),
         _ => Err(RiscletError::from_context(
             format!("Invalid compressed instruction operands for {:?}", op),
-            location.clone(),
+            line.head_location(),
         )),
     }
 }
@@ -2339,7 +2606,7 @@ fn eval_compressed_operands(
 // Helper Functions
 // ============================================================================
 
-fn reg_to_u32(reg: Register) -> u32 {
+pub fn reg_to_u32(reg: Register) -> u32 {
     match reg {
         Register::X0 => 0,
         Register::X1 => 1,
@@ -2400,12 +2667,30 @@ fn fits_signed(value: i64, bits: u32) -> bool {
     value >= min && value <= max
 }
 
+/// Split a PC-relative offset into auipc and addi parts. Addresses wrap at
+/// 32 bits, so any offset reaches its target.
 fn split_offset_hi_lo(offset: i64) -> (i64, i64) {
     let lo = ((offset as i32) << 20) >> 20;
-    let hi = ((offset as i32) - lo) >> 12;
+    let hi = (offset as i32).wrapping_sub(lo) >> 12;
     (hi as i64, lo as i64)
 }
 
+/// Whether a branch or jump in a relocatable object goes to a symbol
+/// defined in another file. The linker fills in its offset, so it is
+/// encoded as 0, as GNU as does.
+fn targets_other_file(
+    target: &Expression,
+    layout: &Layout,
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    pointer: LinePointer,
+) -> bool {
+    let refs = symbol_links.get_line_refs(pointer);
+    layout.relocatable
+        && resolve_base(target, refs, source, symbol_links)
+            .is_some_and(|def| def.pointer.file_index != 0)
+}
+
 fn eval_line_expr(
     expr: &Expression,
     current_address: u32,
@@ -2495,16 +2780,20 @@ fn check_b_imm(offset: i64, location: &Location) -> Result<()> {
     }
     if !fits_signed(offset, 13) {
         return Err(RiscletError::from_context(
-            format!(
-                "Branch offset must be in range -4096 to 4094 (even 13-bit signed), got {}",
-                offset
-            ),
+            branch_out_of_range(offset),
             location.clone(),
         ));
     }
     Ok(())
 }
 
+fn branch_out_of_range(offset: i64) -> String {
+    format!(
+        "Branch target is {} bytes away, out of range for a conditional branch (-4096 to 4094)",
+        offset
+    )
+}
+
 fn check_j_imm(offset: i64, location: &Location) -> Result<()> {
     if offset % 2 != 0 {
         return Err(RiscletError::from_context(
@@ -2518,7 +2807,7 @@ fn check_j_imm(offset: i64, location: &Location) -> Result<()> {
     if !fits_signed(offset, 21) {
         return Err(RiscletError::from_context(
             format!(
-                "Jump offset must be in range -1048576 to 1048575 (even 21-bit signed), got {}",
+                "Jump target is {} bytes away, out of range for a jump (-1048576 to 1048574); use call or tail, which reach any address",
                 offset
             ),
             location.clone(),
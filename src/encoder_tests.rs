@@ -14,38 +14,28 @@ use crate::tokenizer::tokenize;
 // Default relaxation settings for most tests (compression disabled for predictable sizes)
 fn make_default_config() -> Config {
     Config {
-        mode: crate::config::Mode::Assemble,
-        verbose: false,
-        max_steps: 100_000_000,
-        executable: "a.out".to_string(),
-        check_abi: false,
-        hex_mode: false,
-        show_addresses: false,
-        verbose_instructions: false,
         input_files: vec!["test.s".to_string()],
-        output_file: "a.out".to_string(),
-        text_start: 0x10000,
-        dump: crate::dump::DumpConfig::new(),
-        relax: Relax { gp: Some(true), pseudo: true, compressed: false },
+        relax: Relax {
+            gp: Some(true),
+            pseudo: true,
+            compressed: false,
+            branches: true,
+        },
+        ..Config::assemble_default()
     }
 }
 
 // Relaxation with compression enabled (for compression-specific tests)
 fn make_config_with_compression() -> Config {
     Config {
-        mode: crate::config::Mode::Assemble,
-        verbose: false,
-        max_steps: 100_000_000,
-        executable: "a.out".to_string(),
-        check_abi: false,
-        hex_mode: false,
-        show_addresses: false,
-        verbose_instructions: false,
         input_files: vec!["test.s".to_string()],
-        output_file: "a.out".to_string(),
-        text_start: 0x10000,
-        dump: crate::dump::DumpConfig::new(),
-        relax: Relax { gp: Some(true), pseudo: true, compressed: true },
+        relax: Relax {
+            gp: Some(true),
+            pseudo: true,
+            compressed: true,
+            branches: true,
+        },
+        ..Config::assemble_default()
     }
 }
 
@@ -85,6 +75,7 @@ fn assemble(
         files: vec![SourceFile {
             file: "test.s".to_string(),
             lines: all_lines,
+            warning_flags: Vec::new(),
         }],
     };
 
@@ -467,6 +458,23 @@ auipc x15, 0x100
     assert_instructions_match(source, expected);
 }
 
+#[test]
+fn test_hi_lo_operators() {
+    // %hi rounds up when %lo is negative, so the pair adds back up
+    let source = r#"
+.text
+lui a0, %hi(0x12345fff)
+addi a0, a0, %lo(0x12345fff)
+"#;
+
+    let expected = &[
+        0x37, 0x65, 0x34, 0x12, // lui a0,0x12346
+        0x13, 0x05, 0xf5, 0xff, // addi a0,a0,-1
+    ];
+
+    assert_instructions_match(source, expected);
+}
+
 // ============================================================================
 // Special Instruction Tests
 // ============================================================================
@@ -715,6 +723,234 @@ nop
     assert_instructions_match(source, expected);
 }
 
+/// Assert that two sources assemble to the same text segment
+fn assert_same_text(source: &str, expanded: &str, config: &Config) {
+    let (text, _, _) = assemble(source, config).expect("source assembles");
+    let (expected, _, _) =
+        assemble(expanded, config).expect("expansion assembles");
+    assert_eq!(
+        bytes_to_hex(&text),
+        bytes_to_hex(&expected),
+        "{} should assemble like {}",
+        source.trim(),
+        expanded.trim()
+    );
+}
+
+#[test]
+fn test_extension_and_comparison_pseudos() {
+    let source = r#"
+sext.b a0, a1
+sext.h a2, a3
+zext.b a4, a5
+zext.h s0, s1
+sgt t0, t1, t2
+sgtu t3, t4, t5
+sle a0, a1, a2
+sleu a3, a4, a5
+"#;
+    let expanded = r#"
+slli a0, a1, 24
+srai a0, a0, 24
+slli a2, a3, 16
+srai a2, a2, 16
+andi a4, a5, 0xff
+slli s0, s1, 16
+srli s0, s0, 16
+slt t0, t2, t1
+sltu t3, t5, t4
+slt a0, a2, a1
+xori a0, a0, 1
+sltu a3, a5, a4
+xori a3, a3, 1
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+    assert_same_text(source, expanded, &make_config_with_compression());
+}
+
+#[test]
+fn test_jump_register_forms() {
+    let source = r#"
+jr t0
+jr t0, 8
+jr 8(t0)
+jr (t0)
+jalr t1
+jalr t1, 12
+jalr 12(t1)
+jalr (t1)
+jal target
+target:
+"#;
+    let expanded = r#"
+jalr zero, t0, 0
+jalr zero, t0, 8
+jalr zero, t0, 8
+jalr zero, t0, 0
+jalr ra, t1, 0
+jalr ra, t1, 12
+jalr ra, t1, 12
+jalr ra, t1, 0
+jal ra, target
+target:
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+}
+
+#[test]
+fn test_lla_and_lga_are_la() {
+    let source = r#"
+lla a0, target
+lga a1, target
+target:
+"#;
+    let expanded = r#"
+la a0, target
+la a1, target
+target:
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+}
+
+#[test]
+fn test_call_and_jump_with_registers() {
+    let mut config = make_default_config();
+    config.relax.pseudo = false;
+    let source = r#"
+call t0, target
+tail target, t2
+jump target, t3
+target:
+"#;
+    let (text, _, _) = assemble(source, &config).expect("should assemble");
+    let words: Vec<u32> = text
+        .chunks(4)
+        .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+        .collect();
+    let rd = |word: u32| (word >> 7) & 0x1f;
+    let rs1 = |word: u32| (word >> 15) & 0x1f;
+
+    // auipc t0 + jalr t0, t0
+    assert_eq!((words[0] & 0x7f, rd(words[0])), (0x17, 5));
+    assert_eq!((words[1] & 0x7f, rd(words[1]), rs1(words[1])), (0x67, 5, 5));
+    // auipc t2 + jalr zero, t2
+    assert_eq!((words[2] & 0x7f, rd(words[2])), (0x17, 7));
+    assert_eq!((words[3] & 0x7f, rd(words[3]), rs1(words[3])), (0x67, 0, 7));
+    // auipc t3 + jalr zero, t3
+    assert_eq!((words[4] & 0x7f, rd(words[4])), (0x17, 28));
+    assert_eq!((words[5] & 0x7f, rd(words[5]), rs1(words[5])), (0x67, 0, 28));
+
+    // Relaxed, they become jal with the link register or zero
+    let source = r#"
+call t0, target
+jump target, t3
+target:
+"#;
+    let expanded = r#"
+jal t0, target
+jal zero, target
+target:
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+}
+
+#[test]
+fn test_far_branch_jumps_over_inverted_branch() {
+    let source = r#"
+beq a0, a1, far
+.space 5000
+far:
+nop
+"#;
+    let expanded = r#"
+bne a0, a1, skip
+j far
+skip:
+.space 5000
+far:
+nop
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+
+    // The skipping branch stays full size under compression
+    let (text, _, _) = assemble(
+        "bnez s0, far\n.space 5000\nfar:\nret\n",
+        &make_config_with_compression(),
+    )
+    .expect("Assembly should succeed");
+    assert_eq!(text.len(), 8 + 5000 + 2);
+    assert_eq!(&text[0..4], &[0x63, 0x04, 0x04, 0x00], "beq s0, zero, 8");
+}
+
+#[test]
+fn test_branch_expansion_cascades() {
+    // `first` reaches `target` only until the second branch expands
+    let source = r#"
+first:
+blt a0, a1, target
+bgeu a0, a1, far
+.space 4084
+target:
+nop
+.space 5000
+far:
+nop
+"#;
+    let expanded = r#"
+bge a0, a1, 1f
+j target
+1:
+bltu a0, a1, 2f
+j far
+2:
+.space 4084
+target:
+nop
+.space 5000
+far:
+nop
+"#;
+    assert_same_text(source, expanded, &make_default_config());
+}
+
+#[test]
+fn test_far_jal_becomes_auipc_jalr() {
+    let source = "jal ra, far\n.space 0x100000\nfar:\nret\n";
+    let expanded = "call far\n.space 0x100000\nfar:\nret\n";
+    assert_same_text(source, expanded, &make_default_config());
+
+    // j has no register to build the address in
+    let err =
+        assemble("j far\n.space 0x100000\nfar:\nret\n", &make_default_config())
+            .unwrap_err();
+    assert!(err.contains("call or tail"), "{}", err);
+}
+
+#[test]
+fn test_no_relax_branches_reports_range() {
+    let mut config = make_default_config();
+    config.relax.branches = false;
+    let err = assemble("beq a0, a1, far\n.space 5000\nfar:\nnop\n", &config)
+        .unwrap_err();
+    assert!(err.contains("5004 bytes away"), "{}", err);
+    assert!(err.contains("--relax-branches"), "{}", err);
+
+    // Branches in range are unaffected
+    let (text, _, _) = assemble("beq a0, a1, near\nnear:\nnop\n", &config)
+        .expect("Assembly should succeed");
+    assert_eq!(text.len(), 8);
+}
+
+#[test]
+fn test_norelax_still_expands_out_of_range_branches() {
+    // As with GNU as, .option norelax only stops relaxations a linker could
+    // undo; a branch that cannot reach its target is still rewritten
+    let source = ".option norelax\nbeq a0, a1, far\n.space 5000\nfar:\nnop\n";
+    let (text, _, _) = assemble(source, &make_default_config())
+        .expect("Assembly should succeed");
+    assert_eq!(text.len(), 8 + 5000 + 4);
+}
+
 // ============================================================================
 // Data Directive Tests
 // ============================================================================
@@ -1178,7 +1414,9 @@ target:
 nop
 "#;
 
-    let result = assemble(source, &make_default_config());
+    let mut config = make_default_config();
+    config.relax.branches = false;
+    let result = assemble(source, &config);
     assert!(result.is_err(), "Expected error for branch offset out of range");
     let err_msg = result.unwrap_err();
     assert!(
@@ -1334,7 +1572,7 @@ fn test_jal_offset_out_of_range() {
     // JAL (not call pseudo) offset is 21-bit signed (±1 MiB range = ±1048576 bytes)
     // Using .space to create a distance greater than 1 MiB
     // Note: call pseudo-instruction never fails because it relaxes to auipc+jalr,
-    // but raw jal instruction has a fixed range
+    // but raw jal instruction has a fixed range without --relax-branches
     let source = r#"
 .text
 jal x1, target
@@ -1343,7 +1581,9 @@ target:
     nop
 "#;
 
-    let result = assemble(source, &make_default_config());
+    let mut config = make_default_config();
+    config.relax.branches = false;
+    let result = assemble(source, &config);
     assert!(result.is_err(), "Expected error for jal offset out of range");
     let err_msg = result.unwrap_err();
     assert!(
@@ -1563,8 +1803,8 @@ target:
 
     let expected = &[
         0xef, 0x00, 0xc0, 0x00, // jal ra,12 <target>
-        0x00, 0x00, 0x00, 0x00, // alignment padding
-        0x00, 0x00, 0x00, 0x00, // alignment padding
+        0x13, 0x00, 0x00, 0x00, // alignment padding (nop)
+        0x13, 0x00, 0x00, 0x00, // alignment padding (nop)
         0x13, 0x00, 0x00, 0x00, // nop
     ];
 
@@ -2350,3 +2590,282 @@ _start:
 
     assert_instructions_match(source, expected);
 }
+
+// ============================================================================
+// .option Directive Tests
+// ============================================================================
+
+#[test]
+fn test_option_norvc_region_keeps_full_width() {
+    // A fixed-width jump table inside an otherwise compressed program
+    let source = r#"
+.global _start
+_start:
+addi a0, a0, 1
+.option push
+.option norvc
+table:
+j _start
+j _start
+.option pop
+addi a0, a0, 1
+"#;
+
+    let (text, _, _) = assemble(source, &make_config_with_compression())
+        .expect("Assembly should succeed");
+
+    // c.addi (2) + 2 x jal (4 each) + c.addi (2)
+    assert_eq!(text.len(), 12, "Only the .option norvc region is full width");
+    assert_eq!(&text[0..2], &[0x05, 0x05], "c.addi a0, 1");
+    assert_eq!(&text[10..12], &[0x05, 0x05], "c.addi a0, 1 after pop");
+}
+
+#[test]
+fn test_option_rvc_enables_compression_locally() {
+    let source = r#"
+.global _start
+_start:
+addi a0, a0, 1
+.option rvc
+addi a0, a0, 1
+"#;
+
+    let (text, _, _) = assemble(source, &make_default_config())
+        .expect("Assembly should succeed");
+
+    assert_eq!(text, vec![0x13, 0x05, 0x15, 0x00, 0x05, 0x05]);
+}
+
+#[test]
+fn test_option_norelax_keeps_call_expanded() {
+    let source = r#"
+.global _start
+_start:
+.option norelax
+call target
+.option relax
+call target
+target:
+ret
+"#;
+
+    let (text, _, _) = assemble(source, &make_default_config())
+        .expect("Assembly should succeed");
+
+    // auipc + jalr (8) + jal (4) + ret (4)
+    assert_eq!(text.len(), 16);
+}
+
+#[test]
+fn test_option_pop_without_push_is_error() {
+    let source = r#"
+.global _start
+_start:
+.option pop
+nop
+"#;
+
+    let err = assemble(source, &make_default_config())
+        .expect_err("Unbalanced .option pop should fail");
+    assert!(err.contains(".option pop without a matching .option push"));
+}
+
+#[test]
+fn test_option_pop_without_push_does_not_hide_other_errors() {
+    // Each unmatched pop is reported, along with the errors after it
+    let source = r#"
+.global _start
+_start:
+.option pop
+addi a0, a0, 5000
+.option pop
+nop
+"#;
+
+    let err = assemble(source, &make_default_config())
+        .expect_err("Unbalanced .option pop should fail");
+    assert!(err.contains("3 errors"), "{}", err);
+    assert!(err.contains("test.s:4"));
+    assert!(err.contains("test.s:5"));
+    assert!(err.contains("test.s:6"));
+}
+
+#[test]
+fn test_option_applies_only_to_following_lines() {
+    // Settings set by .option only affect the lines that follow
+    let source = r#"
+.global _start
+_start:
+addi a0, a0, 1
+.option norvc
+addi a0, a0, 1
+"#;
+
+    let (text, _, _) = assemble(source, &make_config_with_compression())
+        .expect("Assembly should succeed");
+
+    assert_eq!(text, vec![0x05, 0x05, 0x13, 0x05, 0x15, 0x00]);
+}
+
+// ============================================================================
+// Symbol Directive Tests (.set, .equiv, .weak, .comm, .lcomm)
+// ============================================================================
+
+#[test]
+fn test_set_and_equiv_values() {
+    let source = r#"
+.data
+.set count, 1
+.set count, count + 1
+.equiv limit, count * 10
+.byte count, limit
+"#;
+    assert_data_match(source, &[2, 20]);
+}
+
+#[test]
+fn test_weak_undefined_resolves_to_zero() {
+    let source = r#"
+.data
+.weak missing
+.4byte missing
+"#;
+    assert_data_match(source, &[0, 0, 0, 0]);
+}
+
+#[test]
+fn test_comm_allocates_aligned_bss() {
+    let source = r#"
+.data
+.byte 1
+.comm buf, 8
+.lcomm word, 4, 4
+.4byte word - buf
+"#;
+
+    let (text, data, bss_size) = assemble(source, &make_default_config())
+        .expect("Assembly should succeed");
+
+    // bss starts five bytes past a page boundary, so buf (aligned to 8)
+    // needs 3 bytes of padding and word (aligned to 4) follows it directly
+    assert_eq!(text.len(), 0, "Expected no text segment output");
+    assert_eq!(data, vec![1, 8, 0, 0, 0]);
+    assert_eq!(bss_size, 3 + 8 + 4);
+}
+
+#[test]
+fn test_comm_rejects_bad_alignment() {
+    let source = r#"
+.comm buf, 8, 3
+"#;
+
+    let err = assemble(source, &make_default_config()).unwrap_err();
+    assert!(err.contains("power of two"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn test_comm_in_two_files_takes_the_largest_size_and_alignment() {
+    let file1 = r#"
+.globl _start
+_start: ret
+.comm buf, 10, 8
+.lcomm lbuf, 4
+"#;
+    let file2 = r#"
+.comm buf, 64
+.comm other, 4
+"#;
+    let mut config = Config::assemble_default();
+    let files = vec![
+        ("file1.s".to_string(), file1.to_string()),
+        ("file2.s".to_string(), file2.to_string()),
+    ];
+    let elf = crate::assembler::assemble(&mut config, files)
+        .expect("Assembly should succeed");
+    let m = crate::execution::Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+    let address = |name: &str| {
+        m.address_symbols
+            .iter()
+            .find(|(_, symbol)| *symbol == name)
+            .map(|(&address, _)| address)
+            .unwrap()
+    };
+
+    // buf is 64 bytes aligned to 16, and file2's block merged into it
+    // takes no space of its own
+    assert_eq!(address("buf") % 16, 0);
+    assert_eq!(address("lbuf"), address("buf") + 64);
+    assert_eq!(address("other"), address("lbuf") + 4);
+}
+
+// ============================================================================
+// Comparison, Logical and Conditional Operators
+// ============================================================================
+
+#[test]
+fn test_comparison_and_logical_operators() {
+    let source = r#"
+.data
+.byte 1 < 2, 2 < 1, 3 == 3, 3 != 3, -1 < 0, 2 >= 2, 2 <= 1, 5 > 4
+.byte 1 && 2, 1 && 0, 0 || 3, 0 || 0, !0, !7
+.byte 1 ? 10 : 20, 0 ? 10 : 20, 0 ? 1 / 0 : 3, 0 && 1 / 0, 1 || 1 / 0
+"#;
+    // Comparisons give -1 for true, as in GNU as; logical operators give 1
+    assert_data_match(
+        source,
+        &[
+            0xff, 0x00, 0xff, 0x00, 0xff, 0xff, 0x00, 0xff, //
+            0x01, 0x00, 0x01, 0x00, 0x01, 0x00, //
+            0x0a, 0x14, 0x03, 0x00, 0x01,
+        ],
+    );
+}
+
+#[test]
+fn test_address_comparisons_in_one_segment() {
+    let source = r#"
+.data
+table:  .byte 1, 2, 3
+end:    .byte end > table, table == table, (end - table == 3) ? 7 : 8
+.equ    LARGE, (end - table) / 4 > 0
+.byte   LARGE
+"#;
+    assert_data_match(source, &[1, 2, 3, 0xff, 0xff, 7, 0]);
+}
+
+#[test]
+fn test_address_comparison_across_segments_error() {
+    let source = r#"
+.text
+start:  nop
+.data
+value:  .byte start < value
+"#;
+    let err_msg = assemble(source, &make_default_config()).unwrap_err();
+    assert!(
+        err_msg.contains("Cannot compare addresses in different segments"),
+        "got: {}",
+        err_msg
+    );
+}
+
+#[test]
+fn test_address_number_comparison_error() {
+    let source = r#"
+.data
+value:  .byte value == 0
+"#;
+    let err_msg = assemble(source, &make_default_config()).unwrap_err();
+    assert!(
+        err_msg.contains("Cannot compare an address with a number"),
+        "got: {}",
+        err_msg
+    );
+
+    let source = r#"
+.data
+value:  .byte value && 1
+"#;
+    let err_msg = assemble(source, &make_default_config()).unwrap_err();
+    assert!(err_msg.contains("logical AND"), "got: {}", err_msg);
+}
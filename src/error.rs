@@ -8,9 +8,12 @@ use std::fs;
 use std::io::{self, BufRead};
 use std::result::Result as StdResult;
 
-use crate::ast::{LinePointer, Location, Source};
+use crate::ast::{LinePointer, Location, Source, Span};
 use crate::symbols::BUILTIN_FILE_NAME;
 
+/// The most errors reported from one assembly; the rest are only counted
+pub const MAX_REPORTED_ERRORS: usize = 20;
+
 /// An error type for the RISC-V assembler and simulator.
 /// Assembly errors carry source location for error reporting with context.
 /// Runtime errors do not have source locations.
@@ -38,6 +41,8 @@ pub enum RiscletError {
     Internal(String),
     /// Program exit via syscall (control flow, not an error)
     Exit(i32),
+    /// Several errors found in one run, sorted by location
+    Multiple(Vec<RiscletError>),
 }
 
 impl RiscletError {
@@ -58,6 +63,63 @@ impl RiscletError {
         RiscletError::Assembly { location, message }
     }
 
+    /// Combine the errors found in one run
+    ///
+    /// Nested lists are flattened, and only the first error on each source
+    /// line is kept, since later ones are usually knock-on effects of it.
+    /// A single error is returned as itself.
+    pub fn from_errors(errors: Vec<RiscletError>) -> Self {
+        let mut flat = Vec::new();
+        for error in errors {
+            match error {
+                RiscletError::Multiple(inner) => flat.extend(inner),
+                error => flat.push(error),
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        flat.retain(|error| match error.location() {
+            Some(location) => {
+                seen.insert((location.file.clone(), location.line))
+            }
+            None => true,
+        });
+
+        // Errors without a location go last
+        flat.sort_by(|a, b| match (a.location(), b.location()) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+
+        if flat.len() == 1 {
+            flat.remove(0)
+        } else {
+            RiscletError::Multiple(flat)
+        }
+    }
+
+    /// The source location of an assembly error
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            RiscletError::Assembly { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Point an assembly error about `line` at the given location, unless it
+    /// already points at specific columns
+    pub fn or_pointing_at(self, line: &Location, narrowed: Location) -> Self {
+        match self {
+            RiscletError::Assembly { location, message }
+                if location.span.is_none() && location.same_line(line) =>
+            {
+                RiscletError::Assembly { location: narrowed, message }
+            }
+            error => error,
+        }
+    }
+
     // File I/O error constructors
     pub fn io(message: String) -> Self {
         RiscletError::Io(message)
@@ -110,6 +172,11 @@ impl RiscletError {
             RiscletError::Ui(msg) => msg.clone(),
             RiscletError::Internal(msg) => msg.clone(),
             RiscletError::Exit(code) => format!("exit({})", code),
+            RiscletError::Multiple(errors) => errors
+                .iter()
+                .map(|e| e.message())
+                .collect::<Vec<_>>()
+                .join("\n"),
         }
     }
 
@@ -117,47 +184,87 @@ impl RiscletError {
     pub fn with_source_context(&self) -> String {
         match self {
             RiscletError::Assembly { location, message } => {
-                // Special handling for builtin file - don't try to read it
-                if location.file == BUILTIN_FILE_NAME {
-                    return format!("Error at {}: {}", location, message);
-                }
-
-                let file = fs::File::open(&location.file);
-                if let Ok(file) = file {
-                    let reader = io::BufReader::new(file);
-                    let lines: Vec<String> = reader
-                        .lines()
-                        .collect::<StdResult<_, _>>()
-                        .unwrap_or_default();
-                    let line_num = location.line;
-                    let start = line_num.saturating_sub(3);
-                    let end = (line_num + 3).min(lines.len());
-                    let mut context = String::new();
-                    for (i, line) in
-                        lines.iter().enumerate().take(end).skip(start)
-                    {
-                        let marker =
-                            if i + 1 == line_num { ">>> " } else { "    " };
-                        context.push_str(&format!(
-                            "{}{:4}: {}\n",
-                            marker,
-                            i + 1,
-                            line
-                        ));
+                format_with_context("Error", location, message)
+            }
+            RiscletError::Multiple(errors) => {
+                let mut report = String::new();
+                for error in errors.iter().take(MAX_REPORTED_ERRORS) {
+                    report.push_str(&error.with_source_context());
+                    if !report.ends_with('\n') {
+                        report.push('\n');
                     }
-                    format!("Error at {}: {}\n{}", location, message, context)
+                    report.push('\n');
+                }
+                if errors.len() > MAX_REPORTED_ERRORS {
+                    report.push_str(&format!(
+                        "{} errors (only the first {} are shown)",
+                        errors.len(),
+                        MAX_REPORTED_ERRORS
+                    ));
                 } else {
-                    format!(
-                        "Error at {}: {} (could not read source file)",
-                        location, message
-                    )
+                    report.push_str(&format!("{} errors", errors.len()));
                 }
+                report
             }
             _ => self.message(),
         }
     }
 }
 
+/// Format an assembly diagnostic ("Error", "Warning") with the source lines
+/// around its location
+pub fn format_with_context(
+    heading: &str,
+    location: &Location,
+    message: &str,
+) -> String {
+    // Special handling for builtin file - don't try to read it
+    if location.file == BUILTIN_FILE_NAME {
+        return format!("{} at {}: {}", heading, location, message);
+    }
+
+    let file = fs::File::open(&location.file);
+    if let Ok(file) = file {
+        let reader = io::BufReader::new(file);
+        let lines: Vec<String> =
+            reader.lines().collect::<StdResult<_, _>>().unwrap_or_default();
+        let line_num = location.line;
+        let start = line_num.saturating_sub(3);
+        let end = (line_num + 3).min(lines.len());
+        let mut context = String::new();
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            let marker = if i + 1 == line_num { ">>> " } else { "    " };
+            context.push_str(&format!("{}{:4}: {}\n", marker, i + 1, line));
+            if i + 1 == line_num
+                && let Some(span) = location.span
+            {
+                context.push_str(&underline(line, span));
+            }
+        }
+        format!("{} at {}: {}\n{}", heading, location, message, context)
+    } else {
+        format!(
+            "{} at {}: {} (could not read source file)",
+            heading, location, message
+        )
+    }
+}
+
+/// A line of carets under the given columns of a source line
+///
+/// Tabs before the span are kept so the carets line up however wide the
+/// terminal shows them.
+fn underline(line: &str, span: Span) -> String {
+    let indent: String = line
+        .chars()
+        .chain(std::iter::repeat(' '))
+        .take(span.start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = span.end.saturating_sub(span.start).max(1);
+    format!("{:10}{}{}\n", "", indent, "^".repeat(width))
+}
+
 impl From<io::Error> for RiscletError {
     fn from(e: io::Error) -> Self {
         RiscletError::Io(format!("IO error: {}", e))
@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 #[allow(unused_imports)]
 use std::io::{self, Read, Write};
 use std::rc::Rc;
@@ -7,9 +8,13 @@ use crossterm::tty::IsTty;
 
 use crate::checkabi::CheckABI;
 use crate::config::{Config, Mode};
+use crate::dwarf::LineRange;
+use crate::elf_loader::{ElfInput, load_elf};
 use crate::error::{Result, RiscletError};
 use crate::memory::{CpuState, MemoryLayout, MemoryManager, Segment};
-use crate::riscv::{Field, Op, fields_to_string};
+use crate::riscv::{
+    Field, Op, address_and_label, fields_to_disassembly, fields_to_string,
+};
 use crate::trace::{Effects, ExecutionTrace, MemoryValue, RegisterValue};
 
 pub struct Machine {
@@ -20,6 +25,8 @@ pub struct Machine {
     pub global_pointer: u32,
     pub address_symbols: HashMap<u32, String>,
     pub other_symbols: HashMap<String, u32>,
+    /// Source lines for code addresses, from the executable's debug info
+    pub(crate) line_table: Vec<LineRange>,
     most_recent_memory: u32,
     most_recent_data: (u32, usize),
     most_recent_stack: (u32, usize),
@@ -33,6 +40,16 @@ pub struct Machine {
     stdout_buffer: Vec<u8>,
 }
 
+// Memory and the execution trace are left out; they are too big to print
+impl fmt::Debug for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("pc", &self.pc())
+            .field("global_pointer", &self.global_pointer)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Machine {
     fn default_recent_memory(
         layout: MemoryLayout,
@@ -47,7 +64,7 @@ impl Machine {
         (most_recent_memory, most_recent_data, most_recent_stack)
     }
 
-    pub fn new(
+    pub(crate) fn new(
         segments: Vec<Segment>,
         pc_start: u32,
         global_pointer: u32,
@@ -72,6 +89,7 @@ impl Machine {
             global_pointer,
             address_symbols,
             other_symbols,
+            line_table: Vec::new(),
             most_recent_memory,
             most_recent_data,
             most_recent_stack,
@@ -86,10 +104,18 @@ impl Machine {
         }
     }
 
-    pub fn builder() -> MachineBuilder {
-        MachineBuilder::new()
+    /// Load an ELF executable, ready to run from its entry point
+    ///
+    /// Parts of the file that cannot be used, such as sections of unknown
+    /// types, are skipped with a warning added to `warnings`.
+    pub fn from_elf(
+        bytes: &[u8],
+        warnings: &mut Vec<String>,
+    ) -> Result<Machine> {
+        load_elf(ElfInput::Bytes(bytes), warnings)
     }
 
+    #[cfg(test)]
     pub fn for_testing() -> Self {
         MachineBuilder::new().with_flat_memory(1024 * 1024).build()
     }
@@ -107,6 +133,18 @@ impl Machine {
         self.reservation_set = None;
     }
 
+    /// Move the stack to end at `stack_end` and reset the machine so the
+    /// stack pointer starts there
+    pub fn set_stack(
+        &mut self,
+        stack_end: u32,
+        size: Option<u32>,
+    ) -> Result<()> {
+        self.memory.set_stack(stack_end, size)?;
+        self.reset();
+        Ok(())
+    }
+
     pub fn load(&mut self, addr: u32, size: u32) -> Result<Vec<u8>> {
         let raw = self.memory.load_raw(addr, size)?;
         if let Some(effects) = &mut self.current_effect {
@@ -267,6 +305,26 @@ impl Machine {
         effects
     }
 
+    /// Decode and execute the instruction at the pc
+    ///
+    /// Fails if there is no instruction there. A program that exits does so
+    /// through an `ecall` whose effects are marked `terminate`, with
+    /// `RiscletError::Exit` as their message.
+    pub fn step(&mut self) -> Result<Effects> {
+        let address = self.pc();
+        let (raw, length) = self.load_instruction(address)?;
+        let instruction = Rc::new(Instruction {
+            address,
+            op: Op::new(raw),
+            length,
+            pseudo_index: 0,
+            verbose_fields: Vec::new(),
+            pseudo_fields: Vec::new(),
+            source: None,
+        });
+        Ok(self.execute_and_collect_effects(&instruction))
+    }
+
     pub fn set_most_recent_memory(
         &mut self,
         sequence: &[Effects],
@@ -399,6 +457,12 @@ impl Machine {
         self.memory.layout.data_end
     }
 
+    /// The segments loaded from the executable, leaving out the stack
+    pub(crate) fn loaded_segments(&self) -> &[Segment] {
+        let segments = &self.memory.segments;
+        &segments[..segments.len() - 1]
+    }
+
     pub fn stack_start(&self) -> u32 {
         self.memory.layout.stack_start
     }
@@ -508,6 +572,8 @@ impl Machine {
     }
 }
 
+#[cfg(test)]
+#[derive(Debug)]
 pub struct MachineBuilder {
     segments: Vec<Segment>,
     pc_start: u32,
@@ -516,6 +582,7 @@ pub struct MachineBuilder {
     other_symbols: HashMap<String, u32>,
 }
 
+#[cfg(test)]
 impl MachineBuilder {
     pub fn new() -> Self {
         Self {
@@ -527,34 +594,6 @@ impl MachineBuilder {
         }
     }
 
-    pub fn with_segments(mut self, segments: Vec<Segment>) -> Self {
-        self.segments = segments;
-        self
-    }
-
-    pub fn with_entry_point(mut self, pc_start: u32) -> Self {
-        self.pc_start = pc_start;
-        self
-    }
-
-    pub fn with_global_pointer(mut self, gp: u32) -> Self {
-        self.global_pointer = gp;
-        self
-    }
-
-    pub fn with_address_symbols(
-        mut self,
-        symbols: HashMap<u32, String>,
-    ) -> Self {
-        self.address_symbols = symbols;
-        self
-    }
-
-    pub fn with_other_symbols(mut self, symbols: HashMap<String, u32>) -> Self {
-        self.other_symbols = symbols;
-        self
-    }
-
     pub fn with_flat_memory(mut self, size: u32) -> Self {
         self.segments =
             vec![Segment::new(0x1000, 0x1000 + size, true, true, Vec::new())];
@@ -573,12 +612,14 @@ impl MachineBuilder {
     }
 }
 
+#[cfg(test)]
 impl Default for MachineBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[derive(Debug)]
 pub struct Instruction {
     pub address: u32,
     pub op: Op,
@@ -586,12 +627,73 @@ pub struct Instruction {
     pub pseudo_index: usize,
     pub verbose_fields: Vec<Field>,
     pub pseudo_fields: Vec<Field>,
+    /// The line of source that produced this instruction, if known
+    pub source: Option<SourceLine>,
+}
+
+/// A line of assembly source, for showing in place of the disassembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+    /// The text of the line, without its label, or None if the file could
+    /// not be read
+    pub text: Option<String>,
+}
+
+/// An instruction as a line of disassembly, with the line of source that
+/// produced it in place of the mnemonic and operands when
+/// `config.show_source` is set and the source is known
+pub fn instruction_to_string(
+    config: &Config,
+    instruction: &Instruction,
+    fields: &[Field],
+    gp: u32,
+    arrow: Option<&str>,
+    symbols: &HashMap<u32, String>,
+) -> String {
+    let is_compressed = instruction.length == 2;
+    let Some(source) =
+        instruction.source.as_ref().filter(|_| config.show_source)
+    else {
+        return fields_to_string(
+            config,
+            fields,
+            instruction.address,
+            gp,
+            is_compressed,
+            arrow,
+            symbols,
+        );
+    };
+
+    let prefix = address_and_label(config, instruction.address, arrow, symbols);
+    let location = format!("{}:{}", source.file, source.line);
+    let text = match &source.text {
+        Some(text) => text.clone(),
+        None => fields_to_disassembly(
+            config,
+            fields,
+            instruction.address,
+            gp,
+            is_compressed,
+            symbols,
+        ),
+    };
+    format!("{prefix}{location:<12} {text:<35}")
 }
 
 pub fn add_local_labels(m: &mut Machine, instructions: &[Instruction]) {
     let mut branch_targets: HashSet<u32> = HashSet::new();
     for inst in instructions {
-        if let Some(target) = inst.op.branch_target(inst.address) {
+        // Calls get labels too, as a call to a .L label has no symbol
+        let target = match inst.op {
+            Op::Jal { offset, .. } => {
+                Some((inst.address as i32).wrapping_add(offset) as u32)
+            }
+            ref op => op.branch_target(inst.address),
+        };
+        if let Some(target) = target {
             branch_targets.insert(target);
         }
     }
@@ -642,12 +744,11 @@ fn disassembly_for_instruction(
     } else {
         &instructions[addresses[&instruction.address]].pseudo_fields
     };
-    fields_to_string(
+    instruction_to_string(
         config,
+        instruction,
         fields,
-        instruction.address,
         global_pointer,
-        instruction.length == 2,
         None,
         address_symbols,
     )
@@ -714,15 +815,7 @@ pub fn trace(
     addresses: &HashMap<u32, usize>,
     config: &Config,
 ) -> Vec<Effects> {
-    let mut abi = CheckABI::new(
-        m.get_reg(2) as u32,
-        m.text_start(),
-        m.text_end(),
-        m.data_start(),
-        m.data_end(),
-        m.stack_start(),
-        m.stack_end(),
-    );
+    let mut abi = CheckABI::for_machine(m);
     let mut sequence: Vec<Effects> = Vec::new();
     let mut i = 0;
     let mut prev_pseudo_index: Option<usize> = None;
@@ -11,6 +11,7 @@ use crate::ast::{
 use crate::error::{Result, RiscletError};
 use crate::layout::Layout;
 use crate::symbols::{SymbolDefinition, SymbolLinks, SymbolReference};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -37,12 +38,24 @@ impl fmt::Display for EvaluatedValue {
 /// in the program (both labels and .equ definitions).
 pub struct SymbolValues {
     values: HashMap<SymbolDefinition, EvaluatedValue>,
+    /// The address range of each segment, including the address just past
+    /// its end, for checking that compared addresses share a segment
+    segments: Vec<(u32, u32)>,
 }
 
 impl SymbolValues {
     /// Create an empty SymbolValues
     pub fn new() -> Self {
-        SymbolValues { values: HashMap::new() }
+        SymbolValues { values: HashMap::new(), segments: Vec::new() }
+    }
+
+    /// Whether two addresses are in the same segment (always true when the
+    /// segments are not known)
+    pub fn in_same_segment(&self, a: u32, b: u32) -> bool {
+        self.segments.is_empty()
+            || self.segments.iter().any(|&(start, end)| {
+                (start..=end).contains(&a) && (start..=end).contains(&b)
+            })
     }
 
     /// Look up a symbol value by definition
@@ -67,7 +80,7 @@ impl Default for SymbolValues {
     }
 }
 
-/// Evaluate all symbols (labels and .equ definitions) in the program
+/// Evaluate all symbols (labels, .equ definitions and .comm blocks) in the program
 ///
 /// This function computes all symbol values upfront, once per relaxation iteration.
 /// It replaces the per-line evaluation approach by processing all symbols globally
@@ -81,22 +94,32 @@ pub fn eval_symbol_values(
 ) -> Result<SymbolValues> {
     // Start with empty symbol values
     let mut symbol_values = SymbolValues::new();
+    symbol_values.segments = vec![
+        (layout.text_start, layout.text_start + layout.text_size),
+        (layout.data_start, layout.data_start + layout.data_size),
+        (layout.bss_start, layout.bss_start + layout.bss_size),
+    ];
 
     // Iterate all files and lines, evaluating labels and .equ definitions
     for (file_index, file) in source.files.iter().enumerate() {
         for (line_index, line) in file.lines.iter().enumerate() {
             let pointer = LinePointer { file_index, line_index };
 
-            // Only process lines that define symbols (labels and .equ)
-            let symbol_name = match &line.content {
-                LineContent::Label(name) => Some(name.clone()),
-                LineContent::Directive(Directive::Equ(name, _)) => {
-                    Some(name.clone())
-                }
-                _ => None,
+            // Only process lines that define symbols (labels, .equ, .comm,
+            // and .weak for symbols that may never be defined)
+            let symbol_names = match &line.content {
+                LineContent::Label(name)
+                | LineContent::Directive(
+                    Directive::Equ(name, _)
+                    | Directive::Equiv(name, _)
+                    | Directive::Comm(name, _, _)
+                    | Directive::Lcomm(name, _, _),
+                ) => vec![name.clone()],
+                LineContent::Directive(Directive::Weak(names)) => names.clone(),
+                _ => Vec::new(),
             };
 
-            if let Some(name) = symbol_name {
+            for name in symbol_names {
                 // Create symbol definition for this definition
                 let sym_def = SymbolDefinition { symbol: name, pointer };
 
@@ -156,30 +179,64 @@ fn eval_symbol(
             let addr = layout.get_line_address(key.pointer);
             EvaluatedValue::Address(addr)
         }
-        LineContent::Directive(Directive::Equ(_, expr)) => {
+        LineContent::Directive(
+            Directive::Equ(_, expr) | Directive::Equiv(_, expr),
+        ) => {
             // Recursive case: evaluate dependencies first
-            cycle_stack.push(key.clone());
-
-            // Get all symbol references from this .equ line
-            let sym_refs = symbol_links.get_line_refs(key.pointer);
-            for sym_ref in sym_refs {
-                eval_symbol(
-                    &sym_ref.definition,
-                    source,
-                    symbol_links,
-                    layout,
-                    symbol_values,
-                    cycle_stack,
-                )?;
-            }
-
-            cycle_stack.pop();
+            eval_dependencies(
+                key,
+                source,
+                symbol_links,
+                layout,
+                symbol_values,
+                cycle_stack,
+            )?;
 
             // Now evaluate the expression (all dependencies resolved)
             let address = layout.get_line_address(key.pointer);
             let refs = symbol_links.get_line_refs(key.pointer);
             eval_expr(expr, address, refs, symbol_values, source, key.pointer)?
         }
+        LineContent::Directive(
+            Directive::Comm(name, ..) | Directive::Lcomm(name, ..),
+        ) => {
+            // The symbol is at the start of the block, after alignment
+            // padding, which can depend on the blocks merged into it
+            eval_dependencies(
+                key,
+                source,
+                symbol_links,
+                layout,
+                symbol_values,
+                cycle_stack,
+            )?;
+            for definition in &symbol_links.overridden_symbols {
+                if definition.symbol == *name {
+                    eval_dependencies(
+                        definition,
+                        source,
+                        symbol_links,
+                        layout,
+                        symbol_values,
+                        cycle_stack,
+                    )?;
+                }
+            }
+
+            let address = layout.get_line_address(key.pointer);
+            let (padding, _) = eval_common_block(
+                address,
+                symbol_links,
+                symbol_values,
+                source,
+                key.pointer,
+            )?;
+            EvaluatedValue::Address(address + padding)
+        }
+        LineContent::Directive(Directive::Weak(_)) => {
+            // Base case: a weak symbol that is never defined resolves to 0
+            EvaluatedValue::Address(0)
+        }
         _ => {
             return Err(RiscletError::from_context(
                 format!(
@@ -196,6 +253,145 @@ fn eval_symbol(
     Ok(())
 }
 
+/// Evaluate every symbol referenced by the line defining `key`
+fn eval_dependencies(
+    key: &SymbolDefinition,
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    layout: &Layout,
+    symbol_values: &mut SymbolValues,
+    cycle_stack: &mut Vec<SymbolDefinition>,
+) -> Result<()> {
+    cycle_stack.push(key.clone());
+
+    let sym_refs = symbol_links.get_line_refs(key.pointer);
+    for sym_ref in sym_refs {
+        eval_symbol(
+            &sym_ref.definition,
+            source,
+            symbol_links,
+            layout,
+            symbol_values,
+            cycle_stack,
+        )?;
+    }
+
+    cycle_stack.pop();
+    Ok(())
+}
+
+/// Evaluate the layout of the .comm or .lcomm block at `pointer`, starting
+/// at `address`
+///
+/// Returns the padding needed to align the block, followed by its size.
+/// Without an explicit alignment, the block is aligned to the largest power
+/// of two that does not exceed its size, up to 16 bytes. As with GNU ld, a
+/// common block that other files' .comm blocks of the same name were merged
+/// into takes the largest size and alignment of them all, and the blocks
+/// merged into it take no space.
+pub fn eval_common_block(
+    address: u32,
+    symbol_links: &SymbolLinks,
+    symbol_values: &SymbolValues,
+    source: &Source,
+    pointer: LinePointer,
+) -> Result<(u32, u32)> {
+    let (name, common) = match &source.get_line(pointer)?.content {
+        LineContent::Directive(Directive::Comm(name, ..)) => (name, true),
+        LineContent::Directive(Directive::Lcomm(name, ..)) => (name, false),
+        _ => return Ok((0, 0)),
+    };
+    let overridden = &symbol_links.overridden_symbols;
+    let definition = SymbolDefinition { symbol: name.clone(), pointer };
+    if common && overridden.contains(&definition) {
+        return Ok((0, 0));
+    }
+
+    let (mut size, mut alignment) = eval_common_size(
+        address,
+        symbol_links,
+        symbol_values,
+        source,
+        pointer,
+    )?;
+    if common {
+        for definition in overridden.iter().filter(|d| d.symbol == *name) {
+            if let LineContent::Directive(Directive::Comm(..)) =
+                &source.get_line(definition.pointer)?.content
+            {
+                let (other_size, other_alignment) = eval_common_size(
+                    address,
+                    symbol_links,
+                    symbol_values,
+                    source,
+                    definition.pointer,
+                )?;
+                size = size.max(other_size);
+                alignment = alignment.max(other_alignment);
+            }
+        }
+    }
+
+    let padding = (alignment - address % alignment) % alignment;
+    Ok((padding, size))
+}
+
+/// The size and alignment a single .comm or .lcomm line asks for
+fn eval_common_size(
+    address: u32,
+    symbol_links: &SymbolLinks,
+    symbol_values: &SymbolValues,
+    source: &Source,
+    pointer: LinePointer,
+) -> Result<(u32, u32)> {
+    let line = source.get_line(pointer)?;
+    let location = &line.location;
+    let LineContent::Directive(
+        Directive::Comm(_, size, align) | Directive::Lcomm(_, size, align),
+    ) = &line.content
+    else {
+        return Ok((0, 1));
+    };
+    let refs = symbol_links.get_line_refs(pointer);
+
+    let size_val =
+        eval_expr(size, address, refs, symbol_values, source, pointer)?;
+    let size = require_integer(size_val, ".comm size", location)?;
+    if size < 0 {
+        return Err(RiscletError::from_context(
+            format!(".comm size must be non-negative, got {}", size),
+            location.clone(),
+        ));
+    }
+    let size = size as u32;
+
+    let alignment = match align {
+        Some(expr) => {
+            let val =
+                eval_expr(expr, address, refs, symbol_values, source, pointer)?;
+            let alignment = require_integer(val, ".comm alignment", location)?;
+            if alignment <= 0 || (alignment as u32).count_ones() != 1 {
+                return Err(RiscletError::from_context(
+                    format!(
+                        ".comm alignment must be a positive power of two, got {}",
+                        alignment
+                    ),
+                    location.clone(),
+                ));
+            }
+            alignment as u32
+        }
+        None => default_common_alignment(size),
+    };
+    Ok((size, alignment))
+}
+
+/// The alignment of a .comm or .lcomm block without an explicit alignment:
+/// the largest power of two that does not exceed its size, up to 16 bytes
+pub fn default_common_alignment(size: u32) -> u32 {
+    if size == 0 { 1 } else { (1 << size.ilog2()).min(16) }
+}
+
 /// Evaluate an expression with explicit context
 ///
 /// This is the new expression evaluator that works with pre-computed symbol values
@@ -450,6 +646,90 @@ pub fn eval_expr(
             Ok(EvaluatedValue::Integer(result))
         }
 
+        Expression::EqualOp { lhs, rhs }
+        | Expression::NotEqualOp { lhs, rhs }
+        | Expression::LessOp { lhs, rhs }
+        | Expression::LessEqualOp { lhs, rhs }
+        | Expression::GreaterOp { lhs, rhs }
+        | Expression::GreaterEqualOp { lhs, rhs } => {
+            let lhs_val =
+                eval_expr(lhs, address, refs, symbol_values, source, pointer)?;
+            let rhs_val =
+                eval_expr(rhs, address, refs, symbol_values, source, pointer)?;
+            let ordering =
+                compare(lhs_val, rhs_val, symbol_values, expr, location)?;
+            let result = match expr {
+                Expression::EqualOp { .. } => ordering.is_eq(),
+                Expression::NotEqualOp { .. } => ordering.is_ne(),
+                Expression::LessOp { .. } => ordering.is_lt(),
+                Expression::LessEqualOp { .. } => ordering.is_le(),
+                Expression::GreaterOp { .. } => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            };
+            // True is all bits set, as in GNU as
+            Ok(EvaluatedValue::Integer(-(result as i32)))
+        }
+
+        Expression::LogicalAndOp { lhs, rhs } => {
+            let lhs_val =
+                eval_expr(lhs, address, refs, symbol_values, source, pointer)?;
+            let result =
+                require_integer(lhs_val, "logical AND", location)? != 0 && {
+                    let rhs_val = eval_expr(
+                        rhs,
+                        address,
+                        refs,
+                        symbol_values,
+                        source,
+                        pointer,
+                    )?;
+                    require_integer(rhs_val, "logical AND", location)? != 0
+                };
+            Ok(EvaluatedValue::Integer(result as i32))
+        }
+
+        Expression::LogicalOrOp { lhs, rhs } => {
+            let lhs_val =
+                eval_expr(lhs, address, refs, symbol_values, source, pointer)?;
+            let result = require_integer(lhs_val, "logical OR", location)? != 0
+                || {
+                    let rhs_val = eval_expr(
+                        rhs,
+                        address,
+                        refs,
+                        symbol_values,
+                        source,
+                        pointer,
+                    )?;
+                    require_integer(rhs_val, "logical OR", location)? != 0
+                };
+            Ok(EvaluatedValue::Integer(result as i32))
+        }
+
+        Expression::ConditionalOp { condition, then_expr, else_expr } => {
+            let val = eval_expr(
+                condition,
+                address,
+                refs,
+                symbol_values,
+                source,
+                pointer,
+            )?;
+            let chosen = if require_integer(val, "condition", location)? != 0 {
+                then_expr
+            } else {
+                else_expr
+            };
+            eval_expr(chosen, address, refs, symbol_values, source, pointer)
+        }
+
+        Expression::LogicalNotOp { expr } => {
+            let val =
+                eval_expr(expr, address, refs, symbol_values, source, pointer)?;
+            let int = require_integer(val, "logical NOT", location)?;
+            Ok(EvaluatedValue::Integer((int == 0) as i32))
+        }
+
         Expression::NegateOp { expr } => {
             let val =
                 eval_expr(expr, address, refs, symbol_values, source, pointer)?;
@@ -469,9 +749,41 @@ pub fn eval_expr(
             let int = require_integer(val, "bitwise NOT", location)?;
             Ok(EvaluatedValue::Integer(!int))
         }
+
+        // %hi and %lo accept either type and always produce integers
+        Expression::HiOp { expr } => {
+            let val =
+                eval_expr(expr, address, refs, symbol_values, source, pointer)?;
+            Ok(EvaluatedValue::Integer(hi_part(value_bits(val))))
+        }
+
+        Expression::LoOp { expr } => {
+            let val =
+                eval_expr(expr, address, refs, symbol_values, source, pointer)?;
+            Ok(EvaluatedValue::Integer(lo_part(value_bits(val))))
+        }
     }
 }
 
+/// The raw 32-bit pattern of a value of either type
+fn value_bits(value: EvaluatedValue) -> u32 {
+    match value {
+        EvaluatedValue::Integer(i) => i as u32,
+        EvaluatedValue::Address(a) => a,
+    }
+}
+
+/// The `%hi` part of a value: bits 31:12, rounded up when bit 11 is set so
+/// that `(hi << 12) + lo` reconstructs the value
+pub fn hi_part(value: u32) -> i32 {
+    (value.wrapping_add(0x800) >> 12) as i32
+}
+
+/// The `%lo` part of a value: bits 11:0, sign-extended
+pub fn lo_part(value: u32) -> i32 {
+    ((value << 20) as i32) >> 20
+}
+
 /// Perform addition with type checking and overflow detection
 pub fn checked_add(
     lhs: EvaluatedValue,
@@ -578,6 +890,48 @@ pub fn checked_sub(
     }
 }
 
+/// Order two values for a comparison operator
+///
+/// Numbers compare as signed values. Addresses compare with each other, but
+/// only within one segment, since the order of segments is up to the
+/// layout. Comparing an address with a number is an error.
+fn compare(
+    lhs: EvaluatedValue,
+    rhs: EvaluatedValue,
+    symbol_values: &SymbolValues,
+    expr: &Expression,
+    location: &Location,
+) -> Result<Ordering> {
+    match (lhs, rhs) {
+        (EvaluatedValue::Integer(lhs_i), EvaluatedValue::Integer(rhs_i)) => {
+            Ok(lhs_i.cmp(&rhs_i))
+        }
+        (EvaluatedValue::Address(lhs_a), EvaluatedValue::Address(rhs_a)) => {
+            if symbol_values.in_same_segment(lhs_a, rhs_a) {
+                Ok(lhs_a.cmp(&rhs_a))
+            } else {
+                Err(RiscletError::from_context(
+                    format!(
+                        "Cannot compare addresses in different segments: {} \
+                         ({} and {}; only addresses in the same segment have \
+                         a fixed order)",
+                        expr, lhs, rhs
+                    ),
+                    location.clone(),
+                ))
+            }
+        }
+        _ => Err(RiscletError::from_context(
+            format!(
+                "Cannot compare an address with a number: {} ({} and {}; \
+                 compare two numbers or two addresses)",
+                expr, lhs, rhs
+            ),
+            location.clone(),
+        )),
+    }
+}
+
 /// Check that a value is an Integer type, return error if not
 fn require_integer(
     value: EvaluatedValue,
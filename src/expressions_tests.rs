@@ -1,6 +1,7 @@
 // expressions_tests.rs
 
 use crate::ast::*;
+use crate::config::Relax;
 use crate::error::Result;
 use crate::expressions::*;
 use crate::layout::{Layout, LineLayout};
@@ -15,9 +16,11 @@ mod tests {
             files: vec![SourceFile {
                 file: "test.s".to_string(),
                 lines: vec![Line {
-                    location: Location { file: "test.s".to_string(), line: 1 },
+                    location: Location::new("test.s", 1),
                     content: LineContent::Label("test".to_string()),
+                    spans: LineSpans::default(),
                 }],
+                warning_flags: Vec::new(),
             }],
         }
     }
@@ -31,7 +34,17 @@ mod tests {
         let mut layout = Layout::new_empty();
         layout.set(
             LinePointer { file_index: 0, line_index: 0 },
-            LineLayout { segment, offset, size },
+            LineLayout {
+                segment,
+                offset,
+                size,
+                relax: Relax {
+                    gp: None,
+                    pseudo: true,
+                    compressed: false,
+                    branches: true,
+                },
+            },
         );
         layout
     }
@@ -177,12 +190,8 @@ mod tests {
         let addr1 = EvaluatedValue::Address(0x10000u32 + 16);
         let addr2 = EvaluatedValue::Address(0x10000u32);
 
-        let result = checked_sub(
-            addr1,
-            addr2,
-            &Location { file: "test".to_string(), line: 1 },
-        )
-        .unwrap();
+        let result =
+            checked_sub(addr1, addr2, &Location::new("test", 1)).unwrap();
 
         match result {
             EvaluatedValue::Integer(i) => assert_eq!(i, 16),
@@ -195,11 +204,7 @@ mod tests {
         let addr1 = EvaluatedValue::Address(0x10000u32);
         let addr2 = EvaluatedValue::Address(0x10008u32);
 
-        let result = checked_add(
-            addr1,
-            addr2,
-            &Location { file: "test".to_string(), line: 1 },
-        );
+        let result = checked_add(addr1, addr2, &Location::new("test", 1));
 
         assert!(result.is_err());
         let err_msg = format!("{}", result.unwrap_err());
@@ -211,11 +216,7 @@ mod tests {
         let int_val = EvaluatedValue::Integer(8);
         let addr_val = EvaluatedValue::Address(0x10000u32);
 
-        let result = checked_sub(
-            int_val,
-            addr_val,
-            &Location { file: "test".to_string(), line: 1 },
-        );
+        let result = checked_sub(int_val, addr_val, &Location::new("test", 1));
 
         assert!(result.is_err());
         let err_msg = format!("{}", result.unwrap_err());
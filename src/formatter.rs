@@ -0,0 +1,229 @@
+// formatter.rs
+//
+// Source formatter for the `fmt` subcommand
+//
+// Lays out each line in the columns the AST Display implementations use:
+// labels in the first column, mnemonics and directives at column 16 padded
+// to 7 characters, then the operands separated by ", ". Trailing comments
+// start at column 40. Register names are rewritten as configured; every
+// other token keeps its spelling, so numbers stay in the base they were
+// written in and pseudo-instructions are not expanded.
+//
+// Only files that parse are formatted, and formatting never changes what a
+// line parses to.
+
+use crate::assembler::parse_source_recovering;
+use crate::ast::{Register, Span, Token};
+use crate::config::Config;
+use crate::encoder::reg_to_u32;
+use crate::error::{Result, RiscletError};
+use crate::tokenizer::tokenize_with_comment;
+
+/// The column instructions and directives start in
+const INSTRUCTION_COLUMN: usize = 16;
+
+/// The width mnemonics are padded to before their operands
+const MNEMONIC_WIDTH: usize = 7;
+
+/// The column trailing comments start in
+const COMMENT_COLUMN: usize = 40;
+
+/// How the formatter writes register names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterNames {
+    /// ABI names, such as `a0` and `sp`
+    Abi,
+    /// Numeric names, such as `x10` and `x2`
+    Numeric,
+}
+
+impl RegisterNames {
+    fn name(self, register: Register) -> String {
+        match self {
+            RegisterNames::Abi => register.to_string(),
+            RegisterNames::Numeric => format!("x{}", reg_to_u32(register)),
+        }
+    }
+}
+
+/// Format each input file in place, or with `--check` only list the files
+/// that are not formatted
+///
+/// Nothing is written unless every file parses. Returns whether all files
+/// were already formatted.
+pub fn format_files(config: &Config) -> Result<bool> {
+    let mut formatted = Vec::new();
+    let mut errors = Vec::new();
+    for file_path in &config.input_files {
+        let content = std::fs::read_to_string(file_path).map_err(|e| {
+            RiscletError::io(format!(
+                "could not read file '{}': {}",
+                file_path, e
+            ))
+        })?;
+        match format_source(file_path, &content, config.register_names) {
+            Ok(text) if text != content => formatted.push((file_path, text)),
+            Ok(_) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(RiscletError::from_errors(errors));
+    }
+
+    for (file_path, text) in &formatted {
+        if config.check_format {
+            println!("{}", file_path);
+        } else {
+            std::fs::write(file_path, text).map_err(|e| {
+                RiscletError::io(format!(
+                    "could not write file '{}': {}",
+                    file_path, e
+                ))
+            })?;
+        }
+    }
+    Ok(formatted.is_empty())
+}
+
+/// Format the text of one source file
+///
+/// Fails with the assembler's errors if the source does not parse. Blank
+/// lines are kept, except at the end of the file.
+pub fn format_source(
+    file_name: &str,
+    source: &str,
+    registers: RegisterNames,
+) -> Result<String> {
+    let (_, errors) = parse_source_recovering(file_name, source);
+    if !errors.is_empty() {
+        return Err(RiscletError::from_errors(errors));
+    }
+
+    let mut lines: Vec<String> =
+        source.lines().map(|line| format_line(line, registers)).collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Format one line that is known to parse
+fn format_line(line: &str, registers: RegisterNames) -> String {
+    let Ok((tokens, spans, comment)) = tokenize_with_comment(line) else {
+        return line.trim_end().to_string();
+    };
+    let chars: Vec<char> = line.chars().collect();
+    let text = |span: Span| span_text(&chars, span);
+
+    // Labels, including numeric ones, in the first column
+    let mut code = String::new();
+    let mut at = 0;
+    while matches!(
+        tokens.get(at),
+        Some(Token::Identifier(_) | Token::Integer(_))
+    ) && tokens.get(at + 1) == Some(&Token::Colon)
+    {
+        if !code.is_empty() {
+            code.push(' ');
+        }
+        code.push_str(&text(spans[at]));
+        code.push(':');
+        at += 2;
+    }
+
+    if at < tokens.len() {
+        let padding =
+            INSTRUCTION_COLUMN.saturating_sub(code.chars().count()).max(1);
+        code.push_str(&" ".repeat(padding));
+        let mnemonic = text(spans[at]);
+        let operands = format_operands(
+            &tokens[at + 1..],
+            &spans[at + 1..],
+            &chars,
+            registers,
+        );
+        if operands.is_empty() {
+            code.push_str(&mnemonic);
+        } else {
+            code.push_str(&format!(
+                "{:<width$} {}",
+                mnemonic,
+                operands,
+                width = MNEMONIC_WIDTH
+            ));
+        }
+    }
+
+    let Some(comment) = comment else {
+        return code;
+    };
+    if code.is_empty() {
+        // Comments on lines of their own stay in the first column if they
+        // started there, and are indented with the code otherwise
+        let indent = if line.starts_with('#') { 0 } else { INSTRUCTION_COLUMN };
+        return format!("{}{}", " ".repeat(indent), comment);
+    }
+    let padding = COMMENT_COLUMN.saturating_sub(code.chars().count()).max(1);
+    format!("{}{}{}", code, " ".repeat(padding), comment)
+}
+
+/// Format the operands of an instruction or directive
+///
+/// Commas are followed by a space and binary operators surrounded by one;
+/// unary operators, parentheses and `%hi`/`%lo` are written without spaces.
+fn format_operands(
+    tokens: &[Token],
+    spans: &[Span],
+    chars: &[char],
+    registers: RegisterNames,
+) -> String {
+    let ends_operand = |token: &Token| {
+        matches!(
+            token,
+            Token::Identifier(_)
+                | Token::Register(_)
+                | Token::Integer(_)
+                | Token::StringLiteral(_)
+                | Token::Dot
+                | Token::CloseParen
+        )
+    };
+
+    let mut operands = String::new();
+    for (index, token) in tokens.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|p| &tokens[p]);
+        let text = span_text(chars, spans[index]);
+        match token {
+            Token::Comma => operands.push_str(", "),
+            Token::Operator(_) | Token::Colon
+                if previous.is_some_and(ends_operand) =>
+            {
+                operands.push_str(&format!(" {} ", text));
+            }
+            _ => {
+                // Keep the space between adjacent operands such as `1 f`,
+                // which are not valid, but not in `1f`
+                if previous.is_some_and(ends_operand)
+                    && ends_operand(token)
+                    && *token != Token::CloseParen
+                    && spans[index].start > spans[index - 1].end
+                {
+                    operands.push(' ');
+                }
+                match token {
+                    Token::Register(register) => {
+                        operands.push_str(&registers.name(*register))
+                    }
+                    _ => operands.push_str(&text),
+                }
+            }
+        }
+    }
+    operands
+}
+
+/// The source text a token was read from
+fn span_text(chars: &[char], span: Span) -> String {
+    chars[span.start..span.end].iter().collect()
+}
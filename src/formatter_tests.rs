@@ -0,0 +1,94 @@
+// formatter_tests.rs
+//
+// Tests for the source formatter behind `risclet fmt`
+
+use crate::assembler::parse_source_from_string;
+use crate::ast::LineContent;
+use crate::formatter::{RegisterNames, format_source};
+
+const MESSY: &str = "# Count down from 16
+        .globl _start
+.text
+_start: li x10,0x10   # start
+loop:addi a0,a0,-1 # count down
+   bnez a0,loop
+ beqz a0, 1f
+1:
+      lw a0,4 ( sp )
+  lui a1,%hi(msg+4)
+  li a2, (1<<4)|~2 - -3
+  li a3, 1 ? 2 : 3
+a_very_long_label_name: nop
+  amoadd.w.aqrl a0, a1, (a2)
+    # indented comment
+done:  # all counted
+  ecall
+.data
+msg: .string \"a # b\",  \"c\"
+  .4byte 'x', msg-.
+
+
+";
+
+const FORMATTED: &str = "# Count down from 16
+                .globl  _start
+                .text
+_start:         li      a0, 0x10        # start
+loop:           addi    a0, a0, -1      # count down
+                bnez    a0, loop
+                beqz    a0, 1f
+1:
+                lw      a0, 4(sp)
+                lui     a1, %hi(msg + 4)
+                li      a2, (1 << 4) | ~2 - -3
+                li      a3, 1 ? 2 : 3
+a_very_long_label_name: nop
+                amoadd.w.aqrl a0, a1, (a2)
+                # indented comment
+done:                                   # all counted
+                ecall
+                .data
+msg:            .string \"a # b\", \"c\"
+                .4byte  'x', msg - .
+";
+
+fn format(source: &str) -> String {
+    format_source("test.s", source, RegisterNames::Abi).unwrap()
+}
+
+/// What each line of a source parses to, without locations
+fn contents(source: &str) -> Vec<LineContent> {
+    let file = parse_source_from_string("test.s", source).unwrap();
+    file.lines.into_iter().map(|line| line.content).collect()
+}
+
+#[test]
+fn test_format_aligns_columns() {
+    assert_eq!(format(MESSY), FORMATTED);
+}
+
+#[test]
+fn test_format_is_idempotent() {
+    assert_eq!(format(FORMATTED), FORMATTED);
+}
+
+#[test]
+fn test_format_keeps_meaning() {
+    assert_eq!(contents(&format(MESSY)), contents(MESSY));
+}
+
+#[test]
+fn test_format_numeric_register_names() {
+    let formatted =
+        format_source("test.s", "  lw fp, -4(sp)\n", RegisterNames::Numeric)
+            .unwrap();
+    assert_eq!(formatted, "                lw      x8, -4(x2)\n");
+    assert_eq!(format(&formatted), "                lw      s0, -4(sp)\n");
+}
+
+#[test]
+fn test_format_rejects_source_with_errors() {
+    let error = format_source("test.s", "  addx a0, a1\n", RegisterNames::Abi)
+        .unwrap_err();
+    assert!(error.to_string().contains("Unknown instruction 'addx'"));
+}
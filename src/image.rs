@@ -0,0 +1,507 @@
+// image.rs
+//
+// Memory images for FPGA soft cores and HDL testbenches
+// (--format=bin|ihex|memh)
+//
+// An image holds only the bytes a program puts in memory: its text and the
+// initial contents of .data, taken from the ElfBuilder's segments. Raw
+// binaries fill any gap between them with zeros; Intel HEX records carry
+// their own addresses; Verilog `$readmemh` files start each run of words
+// with an `@` word address, counted from the image's base. With --split,
+// text and data go to separate images for Harvard-style memories.
+//
+// Images can be loaded back into the simulator. They say nothing about
+// .bss, so memory after the image is zero-filled RAM up to the next
+// 64 KiB boundary. Instruction words typed in hex load as a raw image, and
+// a symbol file of `address name` lines can name addresses in any image.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::config::Config;
+use crate::error::{Result, RiscletError};
+use crate::execution::Machine;
+use crate::memory::Segment;
+
+/// Data bytes per Intel HEX record
+const IHEX_RECORD_BYTES: usize = 16;
+
+/// Words per line of a memh image
+const MEMH_WORDS_PER_LINE: usize = 4;
+
+/// The RAM after a loaded image ends on a multiple of this
+const IMAGE_RAM_ALIGNMENT: u32 = 0x10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raw bytes from the lowest address to the highest
+    Bin,
+    /// Intel HEX records
+    Ihex,
+    /// Hex words for Verilog's `$readmemh`
+    Memh,
+}
+
+impl ImageFormat {
+    /// The format named by --format (None for "elf")
+    pub fn parse(name: &str) -> std::result::Result<Option<Self>, String> {
+        match name {
+            "elf" => Ok(None),
+            "bin" => Ok(Some(ImageFormat::Bin)),
+            "ihex" => Ok(Some(ImageFormat::Ihex)),
+            "memh" => Ok(Some(ImageFormat::Memh)),
+            _ => Err(format!(
+                "Error: --format must be elf, bin, ihex or memh, not '{}'",
+                name
+            )),
+        }
+    }
+
+    /// The format of an image file, from its extension
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension {
+            "bin" => Some(ImageFormat::Bin),
+            "hex" | "ihex" => Some(ImageFormat::Ihex),
+            "memh" | "mem" => Some(ImageFormat::Memh),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Bin => "bin",
+            ImageFormat::Ihex => "hex",
+            ImageFormat::Memh => "memh",
+        }
+    }
+}
+
+/// The contents of one loaded segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSegment {
+    /// ".text" or ".data"
+    pub name: &'static str,
+    /// Where the segment runs
+    pub address: u32,
+    /// Where its contents are stored, which differs from `address` for a
+    /// .data that a `--layout` file puts in RAM but loads from flash
+    pub load_address: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Parse a --memh-width value in bits, giving the width in bytes
+pub fn parse_memh_width(value: &str) -> std::result::Result<u32, String> {
+    match value {
+        "8" => Ok(1),
+        "16" => Ok(2),
+        "32" => Ok(4),
+        "64" => Ok(8),
+        _ => Err(format!(
+            "Error: --memh-width must be 8, 16, 32 or 64, not '{}'",
+            value
+        )),
+    }
+}
+
+/// The file a split image is written to: "prog.hex" and ".text" give
+/// "prog.text.hex"
+pub fn split_file_name(output_file: &str, segment: &str) -> String {
+    match output_file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{}{}.{}", stem, segment, extension)
+        }
+        _ => format!("{}{}", output_file, segment),
+    }
+}
+
+/// Write the configured image (or images, with --split) of the segments
+pub fn write_images(
+    config: &Config,
+    format: ImageFormat,
+    segments: &[ImageSegment],
+    entry_point: u32,
+) -> Result<()> {
+    let write = |file_name: &str, bytes: Vec<u8>| {
+        std::fs::write(file_name, bytes).map_err(|e| {
+            RiscletError::io(format!(
+                "could not write image file '{}': {}",
+                file_name, e
+            ))
+        })
+    };
+
+    if !config.split_image {
+        // One memory holds everything, as it is stored
+        let chunks: Vec<(u32, &[u8])> = segments
+            .iter()
+            .map(|s| (s.load_address, s.bytes.as_slice()))
+            .collect();
+        let image =
+            encode_image(format, &chunks, Some(entry_point), config.memh_width);
+        return write(&config.output_file, image);
+    }
+
+    // Each memory holds its segment where it runs
+    for segment in segments {
+        if segment.bytes.is_empty() {
+            continue;
+        }
+        let entry = (segment.name == ".text").then_some(entry_point);
+        let chunks = [(segment.address, segment.bytes.as_slice())];
+        let image = encode_image(format, &chunks, entry, config.memh_width);
+        write(&split_file_name(&config.output_file, segment.name), image)?;
+    }
+    Ok(())
+}
+
+/// Encode chunks of memory, given as (address, bytes), as an image. The
+/// entry point is recorded by Intel HEX; memh words are `width` bytes.
+pub fn encode_image(
+    format: ImageFormat,
+    chunks: &[(u32, &[u8])],
+    entry_point: Option<u32>,
+    width: u32,
+) -> Vec<u8> {
+    let mut chunks: Vec<(u32, &[u8])> =
+        chunks.iter().copied().filter(|(_, bytes)| !bytes.is_empty()).collect();
+    chunks.sort_by_key(|&(address, _)| address);
+    match format {
+        ImageFormat::Bin => encode_bin(&chunks),
+        ImageFormat::Ihex => encode_ihex(&chunks, entry_point).into_bytes(),
+        ImageFormat::Memh => encode_memh(&chunks, width).into_bytes(),
+    }
+}
+
+fn encode_bin(chunks: &[(u32, &[u8])]) -> Vec<u8> {
+    let Some(&(base, _)) = chunks.first() else {
+        return Vec::new();
+    };
+    let mut image = Vec::new();
+    for &(address, bytes) in chunks {
+        let offset = (address - base) as usize;
+        if image.len() < offset + bytes.len() {
+            image.resize(offset + bytes.len(), 0);
+        }
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    image
+}
+
+fn encode_ihex(chunks: &[(u32, &[u8])], entry_point: Option<u32>) -> String {
+    let mut out = String::new();
+    let mut upper: Option<u16> = None;
+    for &(address, bytes) in chunks {
+        let mut address = address;
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            // Records may not cross a 64 KiB boundary
+            let high = (address >> 16) as u16;
+            if upper != Some(high) {
+                ihex_record(&mut out, 0, 0x04, &high.to_be_bytes());
+                upper = Some(high);
+            }
+            let to_boundary = 0x10000 - (address & 0xffff) as usize;
+            let length = rest.len().min(IHEX_RECORD_BYTES).min(to_boundary);
+            ihex_record(&mut out, address as u16, 0x00, &rest[..length]);
+            address = address.wrapping_add(length as u32);
+            rest = &rest[length..];
+        }
+    }
+    if let Some(entry_point) = entry_point {
+        ihex_record(&mut out, 0, 0x05, &entry_point.to_be_bytes());
+    }
+    ihex_record(&mut out, 0, 0x01, &[]);
+    out
+}
+
+fn ihex_record(out: &mut String, address: u16, kind: u8, data: &[u8]) {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+    let sum = record.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    record.push(sum.wrapping_neg());
+    out.push(':');
+    for byte in record {
+        let _ = write!(out, "{:02X}", byte);
+    }
+    out.push('\n');
+}
+
+fn encode_memh(chunks: &[(u32, &[u8])], width: u32) -> String {
+    let mut out = String::new();
+    let Some(&(first, _)) = chunks.first() else {
+        return out;
+    };
+    let base = first - first % width;
+    let _ = writeln!(
+        out,
+        "// base address 0x{:08x}, {}-bit words",
+        base,
+        width * 8
+    );
+    for &(address, bytes) in chunks {
+        // Start on a word boundary, padding the first word with zeros
+        let lead = (address % width) as usize;
+        let mut padded = vec![0; lead];
+        padded.extend_from_slice(bytes);
+        let _ = writeln!(out, "@{:x}", (address - lead as u32 - base) / width);
+        for line in padded.chunks(width as usize * MEMH_WORDS_PER_LINE) {
+            let words: Vec<String> = line
+                .chunks(width as usize)
+                .map(|word| {
+                    // Words are little-endian, so the last byte comes first
+                    let mut digits = String::new();
+                    for i in (0..width as usize).rev() {
+                        let _ = write!(
+                            digits,
+                            "{:02x}",
+                            word.get(i).copied().unwrap_or(0)
+                        );
+                    }
+                    digits
+                })
+                .collect();
+            let _ = writeln!(out, "{}", words.join(" "));
+        }
+    }
+    out
+}
+
+// ============================================================================
+// Loading images
+// ============================================================================
+
+/// Load an image into a machine. Raw and memh images are placed at `base`
+/// and start running there; Intel HEX gives its own addresses and entry
+/// point (or starts at its lowest address).
+pub fn load_image(
+    bytes: &[u8],
+    format: ImageFormat,
+    base: u32,
+    width: u32,
+) -> Result<Machine> {
+    let (chunks, entry) = match format {
+        ImageFormat::Bin => (vec![(base, bytes.to_vec())], None),
+        ImageFormat::Ihex => decode_ihex(image_text(bytes)?)?,
+        ImageFormat::Memh => {
+            (decode_memh(image_text(bytes)?, base, width)?, None)
+        }
+    };
+    let chunks = merge_chunks(chunks);
+    let Some(&(first, _)) = chunks.first() else {
+        return Err(RiscletError::elf("image is empty".to_string()));
+    };
+    let entry =
+        entry.unwrap_or(if format == ImageFormat::Ihex { first } else { base });
+
+    // The chunk the program starts in is its code; the rest is data
+    let mut segments = Vec::new();
+    let mut end = 0;
+    for (address, bytes) in chunks {
+        let chunk_end = address + bytes.len() as u32;
+        let executable = address <= entry && entry < chunk_end;
+        segments
+            .push(Segment::new(address, chunk_end, true, executable, bytes));
+        end = chunk_end;
+    }
+    let ram_end = (end / IMAGE_RAM_ALIGNMENT + 1) * IMAGE_RAM_ALIGNMENT;
+    segments.push(Segment::new(end, ram_end, true, false, Vec::new()));
+
+    Ok(Machine::new(segments, entry, 0, HashMap::new(), HashMap::new()))
+}
+
+fn image_text(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes)
+        .map_err(|_| RiscletError::elf("image is not a text file".to_string()))
+}
+
+/// Sort chunks by address and join the ones that touch
+fn merge_chunks(mut chunks: Vec<(u32, Vec<u8>)>) -> Vec<(u32, Vec<u8>)> {
+    chunks.retain(|(_, bytes)| !bytes.is_empty());
+    chunks.sort_by_key(|&(address, _)| address);
+    let mut merged: Vec<(u32, Vec<u8>)> = Vec::new();
+    for (address, bytes) in chunks {
+        if let Some((start, existing)) = merged.last_mut() {
+            let end = *start + existing.len() as u32;
+            if address <= end {
+                let offset = (address - *start) as usize;
+                if existing.len() < offset + bytes.len() {
+                    existing.resize(offset + bytes.len(), 0);
+                }
+                existing[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                continue;
+            }
+        }
+        merged.push((address, bytes));
+    }
+    merged
+}
+
+/// The chunks of an Intel HEX file and its start address, if it has one
+#[allow(clippy::type_complexity)]
+fn decode_ihex(text: &str) -> Result<(Vec<(u32, Vec<u8>)>, Option<u32>)> {
+    let mut chunks = Vec::new();
+    let mut entry = None;
+    let mut upper: u32 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| {
+            RiscletError::elf(format!("Intel HEX line {}: {}", i + 1, message))
+        };
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record does not start with ':'"))?;
+        let record = parse_hex_bytes(digits)
+            .ok_or_else(|| error("record is not made of hex digits"))?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(error("record length does not match its byte count"));
+        }
+        if record.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return Err(error("checksum does not match"));
+        }
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..record.len() - 1];
+        let value =
+            || data.iter().fold(0u32, |value, &b| (value << 8) | b as u32);
+        match record[3] {
+            0x00 => chunks.push((upper + offset, data.to_vec())),
+            0x01 => break,
+            0x02 => upper = value() << 4,
+            0x03 => {}
+            0x04 => upper = value() << 16,
+            0x05 => entry = Some(value()),
+            kind => {
+                return Err(error(&format!(
+                    "unknown record type {:02x}",
+                    kind
+                )));
+            }
+        }
+    }
+    Ok((chunks, entry))
+}
+
+/// The chunks of a `$readmemh` file of `width`-byte words placed at `base`
+fn decode_memh(
+    text: &str,
+    base: u32,
+    width: u32,
+) -> Result<Vec<(u32, Vec<u8>)>> {
+    let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut index: u32 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or("");
+        for token in line.split_whitespace() {
+            let error = || {
+                RiscletError::elf(format!(
+                    "memh line {}: '{}' is not a hex word or @address",
+                    i + 1,
+                    token
+                ))
+            };
+            if let Some(address) = token.strip_prefix('@') {
+                index =
+                    u32::from_str_radix(address, 16).map_err(|_| error())?;
+                continue;
+            }
+            let word = u64::from_str_radix(&token.replace('_', ""), 16)
+                .map_err(|_| error())?;
+            if width < 8 && word >> (width * 8) != 0 {
+                return Err(error());
+            }
+            let bytes = &word.to_le_bytes()[..width as usize];
+            let address = base.wrapping_add(index.wrapping_mul(width));
+            match chunks.last_mut() {
+                Some((start, data))
+                    if *start + data.len() as u32 == address =>
+                {
+                    data.extend_from_slice(bytes)
+                }
+                _ => chunks.push((address, bytes.to_vec())),
+            }
+            index += 1;
+        }
+    }
+    Ok(chunks)
+}
+
+/// The bytes of instruction words written in hex, as copied from a
+/// waveform viewer or a memory dump (disassemble --hex-words). Words may
+/// have a `0x` prefix and be separated by spaces, commas or newlines, and
+/// `#` or `//` starts a comment. A word of up to four digits whose low two
+/// bits are not both set is a compressed instruction, and takes two bytes;
+/// every other word takes four.
+pub fn decode_hex_words(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or("");
+        let line = line.split('#').next().unwrap_or("");
+        for token in line.split([' ', '\t', ',']).filter(|t| !t.is_empty()) {
+            let digits = token.strip_prefix("0x").unwrap_or(token);
+            let digits = digits.replace('_', "");
+            let word = u32::from_str_radix(&digits, 16)
+                .ok()
+                .filter(|_| digits.len() <= 8)
+                .ok_or_else(|| {
+                    RiscletError::elf(format!(
+                        "line {}: '{}' is not a hex word",
+                        i + 1,
+                        token
+                    ))
+                })?;
+            if digits.len() <= 4 && word & 3 != 3 {
+                bytes.extend_from_slice(&(word as u16).to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+    }
+    if bytes.is_empty() {
+        return Err(RiscletError::elf(
+            "no hex words to disassemble".to_string(),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// The names in a symbol file: one `address name` per line, with the
+/// address in hex. Blank lines and lines starting with `#` are skipped, and
+/// nm's type letter between the address and the name is allowed, so
+/// `nm` output works as it is.
+pub fn parse_symbol_file(text: &str) -> Result<HashMap<u32, String>> {
+    let mut symbols = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (address, name) = match fields[..] {
+            [] => continue,
+            [first, ..] if first.starts_with('#') => continue,
+            [address, name] | [address, _, name] => (address, name),
+            _ => ("", ""),
+        };
+        let digits = address.strip_prefix("0x").unwrap_or(address);
+        let address = u32::from_str_radix(digits, 16).map_err(|_| {
+            RiscletError::elf(format!(
+                "symbol file line {}: expected 'address name', not '{}'",
+                i + 1,
+                line.trim()
+            ))
+        })?;
+        symbols.insert(address, name.to_string());
+    }
+    Ok(symbols)
+}
+
+fn parse_hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,205 @@
+// image_tests.rs
+//
+// Tests for memory images (--format=bin|ihex|memh)
+
+use crate::assembler::assemble;
+use crate::config::Config;
+use crate::image::{
+    ImageFormat, decode_hex_words, encode_image, load_image, parse_memh_width,
+    parse_symbol_file, split_file_name,
+};
+use crate::test_helpers::{TempFile, run_to_exit};
+
+const PROGRAM: &str = "
+        .text
+        .globl _start
+_start: la   t0, numbers
+        lw   a0, 0(t0)
+        lw   t1, 4(t0)
+        add  a0, a0, t1
+        li   a7, 93
+        ecall
+        .data
+numbers: .4byte 30, 12
+";
+
+/// Assemble PROGRAM to an image file
+fn assemble_image(format: ImageFormat, output: &TempFile) {
+    let mut config = Config::assemble_default();
+    config.image_format = Some(format);
+    config.output_file = output.0.clone();
+    assemble(&mut config, vec![("prog.s".to_string(), PROGRAM.to_string())])
+        .unwrap();
+}
+
+#[test]
+fn test_bin_fills_gaps_with_zeros() {
+    let image = encode_image(
+        ImageFormat::Bin,
+        &[(0x108, &[5, 6]), (0x100, &[1, 2, 3])],
+        None,
+        4,
+    );
+    assert_eq!(image, vec![1, 2, 3, 0, 0, 0, 0, 0, 5, 6]);
+}
+
+#[test]
+fn test_ihex_records() {
+    let image = encode_image(
+        ImageFormat::Ihex,
+        &[(0x10000, &[0x13, 0, 0, 0])],
+        Some(0x10000),
+        4,
+    );
+    let text = String::from_utf8(image).unwrap();
+    assert_eq!(
+        text,
+        ":020000040001F9\n\
+         :0400000013000000E9\n\
+         :0400000500010000F6\n\
+         :00000001FF\n"
+    );
+}
+
+#[test]
+fn test_ihex_records_do_not_cross_64k() {
+    let bytes = [0xaa; 8];
+    let image = encode_image(ImageFormat::Ihex, &[(0xfffc, &bytes)], None, 4);
+    let text = String::from_utf8(image).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines[0], ":020000040000FA");
+    assert!(lines[1].starts_with(":04FFFC00"));
+    assert_eq!(lines[2], ":020000040001F9");
+    assert!(lines[3].starts_with(":04000000"));
+}
+
+#[test]
+fn test_memh_words_and_widths() {
+    let bytes = [0x13, 0x05, 0xa0, 0x02, 0x73, 0x00, 0x00, 0x00];
+    let image = encode_image(ImageFormat::Memh, &[(0x1000, &bytes)], None, 4);
+    assert_eq!(
+        String::from_utf8(image).unwrap(),
+        "// base address 0x00001000, 32-bit words\n@0\n02a00513 00000073\n"
+    );
+
+    let image =
+        encode_image(ImageFormat::Memh, &[(0x1000, &bytes[..4])], None, 1);
+    assert!(String::from_utf8(image).unwrap().ends_with("@0\n13 05 a0 02\n"));
+
+    // A second chunk gets its own word address from the base
+    let image = encode_image(
+        ImageFormat::Memh,
+        &[(0x1000, &bytes[..4]), (0x1010, &bytes[4..])],
+        None,
+        2,
+    );
+    let text = String::from_utf8(image).unwrap();
+    assert!(text.contains("@0\n0513 02a0\n@8\n0073 0000\n"), "{}", text);
+
+    assert_eq!(parse_memh_width("64"), Ok(8));
+    assert!(parse_memh_width("24").is_err());
+}
+
+#[test]
+fn test_split_file_names() {
+    assert_eq!(split_file_name("prog.hex", ".text"), "prog.text.hex");
+    assert_eq!(split_file_name("out/a.memh", ".data"), "out/a.data.memh");
+    assert_eq!(split_file_name("image", ".text"), "image.text");
+}
+
+#[test]
+fn test_formats_from_file_names() {
+    assert_eq!(ImageFormat::from_file_name("a.bin"), Some(ImageFormat::Bin));
+    assert_eq!(ImageFormat::from_file_name("a.hex"), Some(ImageFormat::Ihex));
+    assert_eq!(ImageFormat::from_file_name("a.memh"), Some(ImageFormat::Memh));
+    assert_eq!(ImageFormat::from_file_name("a.out"), None);
+    assert_eq!(ImageFormat::parse("elf"), Ok(None));
+    assert!(ImageFormat::parse("srec").is_err());
+}
+
+#[test]
+fn test_images_run_after_loading() {
+    for format in [ImageFormat::Bin, ImageFormat::Ihex, ImageFormat::Memh] {
+        let output = TempFile::new(&format!("run.{}", format.extension()));
+        assemble_image(format, &output);
+        let mut machine =
+            load_image(&output.read(), format, 0x10000, 4).unwrap();
+        assert_eq!(machine.pc(), 0x10000, "{:?}", format);
+        assert_eq!(run_to_exit(&mut machine), 42, "{:?}", format);
+    }
+}
+
+#[test]
+fn test_image_text_starts_exactly_at_text_start() {
+    let output = TempFile::new("start.hex");
+    let mut config = Config::assemble_default();
+    config.image_format = Some(ImageFormat::Ihex);
+    config.output_file = output.0.clone();
+    config.text_start = 0;
+    assemble(&mut config, vec![("prog.s".to_string(), PROGRAM.to_string())])
+        .unwrap();
+    let text = String::from_utf8(output.read()).unwrap();
+    assert!(text.starts_with(":020000040000FA\n:10000000"), "{}", text);
+    assert!(text.contains(":0400000500000000F7\n"), "{}", text);
+}
+
+#[test]
+fn test_split_images() {
+    let output = TempFile::new("split.bin");
+    let text = TempFile(split_file_name(&output.0, ".text"));
+    let data = TempFile(split_file_name(&output.0, ".data"));
+    let mut config = Config::assemble_default();
+    config.image_format = Some(ImageFormat::Bin);
+    config.split_image = true;
+    config.output_file = output.0.clone();
+    assemble(&mut config, vec![("prog.s".to_string(), PROGRAM.to_string())])
+        .unwrap();
+    assert_eq!(data.read(), vec![30, 0, 0, 0, 12, 0, 0, 0]);
+    assert_eq!(text.read().len() % 2, 0);
+    assert!(!std::path::Path::new(&output.0).exists());
+}
+
+#[test]
+fn test_load_rejects_bad_images() {
+    let bad_checksum = b":0400000013000000E8\n:00000001FF\n";
+    assert!(load_image(bad_checksum, ImageFormat::Ihex, 0, 4).is_err());
+    assert!(load_image(b"@0\nxyz\n", ImageFormat::Memh, 0, 4).is_err());
+    assert!(load_image(b"@0\n123456789\n", ImageFormat::Memh, 0, 4).is_err());
+    assert!(load_image(b"", ImageFormat::Bin, 0, 4).is_err());
+}
+
+#[test]
+fn test_hex_words() {
+    let bytes = decode_hex_words("00a50533 0x4501, 8082 // ret\n13 # nop\n")
+        .expect("words decode");
+    assert_eq!(
+        bytes,
+        [
+            0x33, 0x05, 0xa5, 0x00, // add a0, a0, a0
+            0x01, 0x45, // c.li a0, 0
+            0x82, 0x80, // c.jr ra
+            0x13, 0x00, 0x00, 0x00, // a short word that is not compressed
+        ]
+    );
+
+    let m = load_image(&bytes, ImageFormat::Bin, 0x8000_0000, 4).unwrap();
+    assert_eq!(m.load_instruction(0x8000_0004).unwrap(), (0x4501, 2));
+
+    assert!(decode_hex_words("00a50533 nop").is_err());
+    assert!(decode_hex_words("123456789").is_err());
+    assert!(decode_hex_words("// nothing\n").is_err());
+}
+
+#[test]
+fn test_symbol_files() {
+    let symbols = parse_symbol_file(
+        "# from the FPGA build\n0x80000000 _start\n\n80000010 T loop\n",
+    )
+    .expect("symbols parse");
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[&0x8000_0000], "_start");
+    assert_eq!(symbols[&0x8000_0010], "loop");
+
+    assert!(parse_symbol_file("_start 0x80000000\n").is_err());
+    assert!(parse_symbol_file("80000000\n").is_err());
+}
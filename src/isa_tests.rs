@@ -7,6 +7,7 @@ mod riscv_isa_tests {
     fn run_test_binary(binary: &[u8]) -> Result<i32, String> {
         let mut machine = crate::elf_loader::load_elf(
             crate::elf_loader::ElfInput::Bytes(binary),
+            &mut Vec::new(),
         )?;
 
         for _step in 0..MAX_STEPS {
@@ -28,6 +29,7 @@ mod riscv_isa_tests {
                     pseudo_index: 0,
                     verbose_fields: Vec::new(),
                     pseudo_fields: Vec::new(),
+                    source: None,
                 },
             ));
 
@@ -5,9 +5,13 @@
 // creating a clear data flow: Parsing → AST, Linking → SymbolLinks, Layout Computation → Layout.
 
 use crate::ast::{
-    Directive, Instruction, LineContent, LinePointer, PseudoOp, Segment, Source,
+    Directive, Instruction, LineContent, LinePointer, OptionArg, PseudoOp,
+    Segment, Source,
 };
+use crate::config::Relax;
 use crate::elf::compute_header_size;
+use crate::error::RiscletError;
+use crate::memory_map::MemoryMap;
 use crate::symbols::{BUILTIN_FILE_NAME, SPECIAL_GLOBAL_POINTER};
 use std::collections::HashMap;
 
@@ -37,18 +41,111 @@ impl Default for LineSizes {
     }
 }
 
+/// The relaxation settings in effect for every source line.
+///
+/// These start from the command-line settings and are adjusted region by
+/// region with `.option` directives. Unlike sizes, they are fixed before
+/// the relaxation loop starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineRelax {
+    by_line: HashMap<LinePointer, Relax>,
+}
+
+impl LineRelax {
+    /// Compute the settings for every line, starting each file from `base`.
+    ///
+    /// `.option push` saves the current settings and `.option pop` restores
+    /// them. `rvc`/`norvc` control compression; `relax`/`norelax` control
+    /// the call/tail and GP-relative relaxations (GP relaxation is only
+    /// re-enabled if `base` allows it). Out-of-range branches are still
+    /// expanded under `norelax`, as GNU as does: that is a fix-up of code
+    /// that would not assemble, not a relaxation a linker could undo. A
+    /// directive's own line records the settings after it takes effect.
+    ///
+    /// An `.option pop` with nothing to restore is reported and otherwise
+    /// ignored, so the settings for the rest of the file are still known.
+    pub fn compute(source: &Source, base: Relax) -> (Self, Vec<RiscletError>) {
+        let mut by_line = HashMap::new();
+        let mut errors = Vec::new();
+
+        for (file_index, file) in source.files.iter().enumerate() {
+            let mut current = base;
+            let mut stack = Vec::new();
+
+            for (line_index, line) in file.lines.iter().enumerate() {
+                if let LineContent::Directive(Directive::Option(arg)) =
+                    &line.content
+                {
+                    match arg {
+                        OptionArg::Push => stack.push(current),
+                        OptionArg::Pop => match stack.pop() {
+                            Some(saved) => current = saved,
+                            None => errors.push(RiscletError::from_context(
+                                ".option pop without a matching .option push"
+                                    .to_string(),
+                                line.location.clone(),
+                            )),
+                        },
+                        OptionArg::Rvc => current.compressed = true,
+                        OptionArg::NoRvc => current.compressed = false,
+                        OptionArg::Relax => {
+                            current.pseudo = true;
+                            current.gp = base.gp;
+                        }
+                        OptionArg::NoRelax => {
+                            current.pseudo = false;
+                            current.gp = Some(false);
+                        }
+                    }
+                }
+                by_line.insert(LinePointer { file_index, line_index }, current);
+            }
+        }
+
+        (Self { by_line }, errors)
+    }
+
+    pub fn get(&self, pointer: LinePointer) -> Relax {
+        *self.by_line.get(&pointer).unwrap()
+    }
+
+    /// Override the settings for a single line
+    pub fn set(&mut self, pointer: LinePointer, relax: Relax) {
+        self.by_line.insert(pointer, relax);
+    }
+}
+
 /// Information about a line's position and size in the binary
 ///
 /// This is stored in the Layout structure and represents where a particular
 /// source line ends up in the assembled binary.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LineLayout {
-    /// Which segment this line belongs to (text, data, or bss)
+    /// Which segment this line belongs to (text, data, or bss; .comm and
+    /// .lcomm blocks are always in bss)
     pub segment: Segment,
     /// Offset within the segment (in bytes)
     pub offset: u32,
     /// Size in bytes for this layout snapshot
     pub size: u32,
+    /// Relaxation settings in effect for this line (see `.option`)
+    pub relax: Relax,
+}
+
+/// Where a layout places its segments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// An executable whose text starts after the ELF headers at this
+    /// nominal address
+    Executable(u32),
+    /// An executable whose segments go in the memory regions of a
+    /// `--layout` file
+    Regions(MemoryMap),
+    /// A relocatable object, with each segment at its own offset from 0
+    Relocatable,
+    /// A memory image (--format) whose text starts exactly at this address,
+    /// with no ELF headers in front of it
+    Image(u32),
 }
 
 /// Complete layout information for the assembled program
@@ -74,6 +171,18 @@ pub struct Layout {
     pub text_start: u32,
     pub data_start: u32,
     pub bss_start: u32,
+
+    /// Where the initial contents of .data are stored (its LMA); the same
+    /// as data_start unless a `--layout` file says otherwise
+    pub data_load_start: u32,
+
+    /// Whether the text segment also maps the ELF and program headers
+    /// (true for `-t` placement, where text_start follows them)
+    pub headers_mapped: bool,
+
+    /// Whether this is a relocatable object's layout (`-c`), where branches
+    /// keep their size for the relocations that describe them
+    pub relocatable: bool,
 }
 
 impl Layout {
@@ -88,6 +197,9 @@ impl Layout {
             text_start: 0,
             data_start: 0,
             bss_start: 0,
+            data_load_start: 0,
+            headers_mapped: false,
+            relocatable: false,
         }
     }
 
@@ -124,6 +236,63 @@ impl Layout {
 
         // BSS starts immediately after data
         self.bss_start = self.data_start + self.data_size;
+
+        self.data_load_start = self.data_start;
+        self.headers_mapped = true;
+    }
+
+    /// Set segment start addresses for a memory image, where nothing but
+    /// the segments is loaded: text starts exactly at `text_start`, and
+    /// data and .bss follow as they do after `-t`
+    pub fn set_image_segment_addresses(&mut self, text_start: u32) {
+        self.text_start = text_start;
+        let text_end = self.text_start + self.text_size;
+        self.data_start = (text_end + 4095) & !(4096 - 1);
+        self.bss_start = self.data_start + self.data_size;
+        self.data_load_start = self.data_start;
+        self.headers_mapped = false;
+    }
+
+    /// Set segment start addresses from the regions of a memory map
+    ///
+    /// Text starts exactly at its region's origin; the ELF headers are not
+    /// part of any loaded segment.
+    pub fn set_region_segment_addresses(&mut self, memory_map: &MemoryMap) {
+        let addresses =
+            memory_map.place(self.text_size, self.data_size, self.bss_size);
+        self.text_start = addresses.text_start;
+        self.data_start = addresses.data_start;
+        self.data_load_start = addresses.data_load_start;
+        self.bss_start = addresses.bss_start;
+        self.headers_mapped = false;
+
+        // A .bss that doesn't directly follow .data needs its own segment
+        if self.has_separate_bss_segment() {
+            self.header_size = compute_header_size(4);
+        }
+    }
+
+    /// Whether .bss is loaded by its own program header rather than as the
+    /// zero-filled tail of the .data segment
+    pub fn has_separate_bss_segment(&self) -> bool {
+        self.data_size > 0
+            && self.bss_size > 0
+            && self.bss_start != self.data_start + self.data_size
+    }
+
+    /// Set segment start addresses for a relocatable object
+    ///
+    /// Every segment starts on its own 4K boundary, counting from 0, so that
+    /// offsets within a section keep the alignment the source asked for and
+    /// an address at the end of one section is never the start of another.
+    pub fn set_relocatable_segment_addresses(&mut self) {
+        self.text_start = 0;
+        self.data_start = (self.text_size + 4096) & !(4096 - 1);
+        self.bss_start =
+            (self.data_start + self.data_size + 4096) & !(4096 - 1);
+        self.data_load_start = self.data_start;
+        self.headers_mapped = false;
+        self.relocatable = true;
     }
 
     /// Compute the concrete address of a line in the final binary
@@ -145,7 +314,8 @@ impl Layout {
     pub fn from_sizes(
         source: &Source,
         line_sizes: &LineSizes,
-        nominal_text_start: u32,
+        line_relax: &LineRelax,
+        placement: &Placement,
     ) -> Self {
         let mut layout = Layout::new_empty();
 
@@ -158,13 +328,27 @@ impl Layout {
                         segment: Segment::Text,
                         offset: 0,
                         size: line_sizes.get(pointer),
+                        relax: line_relax.get(pointer),
                     },
                 );
             }
         }
 
         layout.update_addresses(source);
-        layout.set_segment_addresses(nominal_text_start);
+        match placement {
+            Placement::Executable(nominal_text_start) => {
+                layout.set_segment_addresses(*nominal_text_start)
+            }
+            Placement::Regions(memory_map) => {
+                layout.set_region_segment_addresses(memory_map)
+            }
+            Placement::Relocatable => {
+                layout.set_relocatable_segment_addresses()
+            }
+            Placement::Image(text_start) => {
+                layout.set_image_segment_addresses(*text_start)
+            }
+        }
 
         layout
     }
@@ -213,7 +397,7 @@ impl Layout {
                         }
                     }
 
-                    let size = self.get(pointer).size;
+                    let &LineLayout { size, relax, .. } = self.get(pointer);
 
                     // Special handling: __global_pointer$ label is at offset 2048 in data segment
                     let offset = if let LineContent::Label(name) = &line.content
@@ -225,7 +409,12 @@ impl Layout {
 
                     self.set(
                         pointer,
-                        LineLayout { segment: current_segment, offset, size },
+                        LineLayout {
+                            segment: current_segment,
+                            offset,
+                            size,
+                            relax,
+                        },
                     );
                 }
                 continue;
@@ -249,22 +438,27 @@ impl Layout {
                     }
                 }
 
-                // Compute offset based on current segment
-                let offset = match current_segment {
+                // .comm and .lcomm blocks always go in .bss
+                let segment = match &line.content {
+                    LineContent::Directive(
+                        Directive::Comm(..) | Directive::Lcomm(..),
+                    ) => Segment::Bss,
+                    _ => current_segment,
+                };
+
+                // Compute offset based on the line's segment
+                let offset = match segment {
                     Segment::Text => text_offset,
                     Segment::Data => data_offset,
                     Segment::Bss => bss_offset,
                 };
-                let size = self.get(pointer).size;
+                let &LineLayout { size, relax, .. } = self.get(pointer);
 
                 // Update layout with computed offset and segment
-                self.set(
-                    pointer,
-                    LineLayout { segment: current_segment, offset, size },
-                );
+                self.set(pointer, LineLayout { segment, offset, size, relax });
 
                 // Advance offset in the appropriate segment
-                match current_segment {
+                match segment {
                     Segment::Text => text_offset += size,
                     Segment::Data => data_offset += size,
                     Segment::Bss => bss_offset += size,
@@ -332,10 +526,13 @@ fn guess_line_size(content: &LineContent) -> u32 {
             Instruction::Pseudo(pseudo) => match pseudo {
                 PseudoOp::Li(_, _) => 8,                // lui + addi
                 PseudoOp::La(_, _) => 8,                // auipc + addi
-                PseudoOp::Call(_) => 8,                 // auipc + jalr
-                PseudoOp::Tail(_) => 8,                 // auipc + jalr
+                PseudoOp::Call(_, _) => 8,              // auipc + jalr
+                PseudoOp::Tail(_, _) => 8,              // auipc + jalr
+                PseudoOp::Jump(_, _) => 8,              // auipc + jalr
                 PseudoOp::LoadGlobal(_, _, _) => 8,     // auipc + load
                 PseudoOp::StoreGlobal(_, _, _, _) => 8, // auipc + store
+                PseudoOp::Extend(_, _, _) => 8,         // slli + srai/srli
+                PseudoOp::Sle(_, _, _, _) => 8,         // slt + xori
             },
             // All other instructions start at 4 bytes (may relax to 2 with auto-relaxation)
             _ => 4,
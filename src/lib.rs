@@ -0,0 +1,113 @@
+// Risclet: A RISC-V simulator and assembler
+//
+// The library behind the `risclet` binary. Programs that embed risclet
+// should use what is exported here: `assemble` with `Options` to build an
+// `Executable`, then `Machine::step` to run it one instruction at a time,
+// with `CheckABI` to check each step against the calling convention (see
+// the api module). The modules themselves are private and may change.
+
+// Shared modules
+mod elf;
+mod error;
+
+// Simulator modules
+mod checkabi;
+mod decoder;
+mod disassembly;
+mod elf_loader;
+mod execution;
+mod isa_tests;
+mod memory;
+mod riscv;
+mod simulator;
+mod test_utils;
+mod trace;
+mod ui;
+
+// Assembler modules
+mod assembler;
+mod ast;
+mod config;
+mod dump;
+mod dwarf;
+mod elf_builder;
+mod encoder;
+mod expressions;
+mod formatter;
+mod image;
+mod json;
+mod layout;
+mod lint;
+mod listing;
+mod lsp;
+mod map;
+mod memory_map;
+mod objdump;
+mod object_builder;
+mod object_loader;
+mod parser;
+mod suggest;
+mod symbols;
+mod tokenizer;
+mod watch;
+
+// Test modules
+#[cfg(test)]
+mod api_tests;
+#[cfg(test)]
+mod checkabi_tests;
+#[cfg(test)]
+mod diagnostics_tests;
+#[cfg(test)]
+mod disassembly_tests;
+#[cfg(test)]
+mod dwarf_tests;
+#[cfg(test)]
+mod elf_loader_tests;
+#[cfg(test)]
+mod encoder_tests;
+#[cfg(test)]
+mod expressions_tests;
+#[cfg(test)]
+mod formatter_tests;
+#[cfg(test)]
+mod image_tests;
+#[cfg(test)]
+mod lint_tests;
+#[cfg(test)]
+mod listing_tests;
+#[cfg(test)]
+mod lsp_tests;
+#[cfg(test)]
+mod map_tests;
+#[cfg(test)]
+mod memory_map_tests;
+#[cfg(test)]
+mod objdump_tests;
+#[cfg(test)]
+mod object_tests;
+#[cfg(test)]
+mod parser_tests;
+#[cfg(test)]
+mod riscv_tests;
+#[cfg(test)]
+mod symbols_tests;
+#[cfg(test)]
+mod test_helpers;
+#[cfg(test)]
+mod tokenizer_tests;
+#[cfg(test)]
+mod watch_tests;
+
+// Embedding API
+mod api;
+#[doc(hidden)]
+pub mod cli;
+
+pub use crate::api::{Diagnostic, Executable, Options, Severity, assemble};
+pub use crate::checkabi::CheckABI;
+pub use crate::error::{Result, RiscletError};
+pub use crate::execution::{Instruction, Machine};
+pub use crate::lint::Lint;
+pub use crate::riscv::Op;
+pub use crate::trace::{Effects, MemoryValue, RegisterValue, SyscallInfo};
@@ -0,0 +1,671 @@
+// lint.rs
+//
+// Warnings for suspicious but legal code
+//
+// The lint pass runs on the parsed and linked source, after all errors have
+// been ruled out. Each lint has a stable ID used in the report and in the
+// `-W<id>` / `-Wno-<id>` flags.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::{
+    AtomicOp, CompressedOp, CompressedOperands, Directive, Expression, ITypeOp,
+    Instruction, JTypeOp, Line, LineContent, LinePointer, Location, PseudoOp,
+    Register, Source, SourceFile,
+};
+use crate::error::format_with_context;
+use crate::object_builder::is_common_block_symbol;
+use crate::suggest::did_you_mean;
+use crate::symbols::SymbolLinks;
+
+/// A kind of warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A label or `.equ` symbol that nothing refers to
+    UnusedSymbol,
+    /// Instructions after `j`, `ret` or `tail` with no label to reach them
+    UnreachableCode,
+    /// An instruction whose result is written to x0 and discarded
+    WriteX0,
+    /// A `t` register read after a `call`, which may have changed it
+    TempAcrossCall,
+    /// A `_start` label that is not declared `.global`
+    MissingGlobalStart,
+    /// Data directives in `.text`
+    DataInText,
+    /// A number above 0x7fffffff in an `li` expression, where it is negative
+    LiWrap,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 7] = [
+        Lint::UnusedSymbol,
+        Lint::UnreachableCode,
+        Lint::WriteX0,
+        Lint::TempAcrossCall,
+        Lint::MissingGlobalStart,
+        Lint::DataInText,
+        Lint::LiWrap,
+    ];
+
+    /// The stable name used in reports and flags
+    pub fn id(self) -> &'static str {
+        match self {
+            Lint::UnusedSymbol => "unused-symbol",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::WriteX0 => "write-x0",
+            Lint::TempAcrossCall => "temp-across-call",
+            Lint::MissingGlobalStart => "missing-global-start",
+            Lint::DataInText => "data-in-text",
+            Lint::LiWrap => "li-wrap",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.id() == id)
+    }
+}
+
+/// Which lints are enabled (all of them by default)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    disabled: HashSet<Lint>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        LintConfig { disabled: HashSet::new() }
+    }
+
+    pub fn is_enabled(&self, lint: Lint) -> bool {
+        !self.disabled.contains(&lint)
+    }
+
+    pub fn set(&mut self, lint: Lint, enabled: bool) {
+        if enabled {
+            self.disabled.remove(&lint);
+        } else {
+            self.disabled.insert(lint);
+        }
+    }
+
+    /// Apply a warning flag: `-Wall`, `-w` (no warnings), `-W<id>` or
+    /// `-Wno-<id>`. Returns false if the argument is not a warning flag.
+    pub fn parse_flag(&mut self, arg: &str) -> Result<bool, String> {
+        if arg == "-w" {
+            self.disabled = Lint::ALL.into_iter().collect();
+            return Ok(true);
+        }
+        let Some(name) = arg.strip_prefix("-W") else {
+            return Ok(false);
+        };
+        if name == "all" {
+            self.disabled.clear();
+            return Ok(true);
+        }
+        let (id, enabled) = match name.strip_prefix("no-") {
+            Some(id) => (id, false),
+            None => (name, true),
+        };
+        match Lint::from_id(id) {
+            Some(lint) => {
+                self.set(lint, enabled);
+                Ok(true)
+            }
+            None => Err(format!(
+                "Error: unknown warning '{}'{}",
+                id,
+                did_you_mean(id, Lint::ALL.into_iter().map(Lint::id))
+            )),
+        }
+    }
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig::new()
+    }
+}
+
+/// One warning, at the source location it is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = format!("{} [-W{}]", self.message, self.lint.id());
+        write!(
+            f,
+            "{}",
+            format_with_context("Warning", &self.location, &message)
+        )
+    }
+}
+
+/// Print warnings to stderr
+pub fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("{}\n", warning.to_string().trim_end());
+    }
+}
+
+/// Run every enabled lint over the source files (not the linked objects or
+/// the assembler's own symbol files), sorted by location
+///
+/// A file's own `# risclet:` warning flags apply after the command line's,
+/// and flags that are not valid there are ignored.
+pub fn lint(
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    config: &LintConfig,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for (file_index, file) in source.files.iter().enumerate() {
+        if !is_assembly_source(file) {
+            continue;
+        }
+        let mut file_warnings = Vec::new();
+        check_unused_symbols(
+            source,
+            symbol_links,
+            file_index,
+            &mut file_warnings,
+        );
+        check_start_is_global(file, symbol_links, &mut file_warnings);
+        check_control_flow(file, &mut file_warnings);
+        for line in &file.lines {
+            if let LineContent::Instruction(instruction) = &line.content {
+                check_write_x0(line, instruction, &mut file_warnings);
+                check_li_wrap(line, instruction, &mut file_warnings);
+            }
+        }
+
+        let mut file_config = config.clone();
+        for flag in &file.warning_flags {
+            let _ = file_config.parse_flag(flag);
+        }
+        file_warnings.retain(|warning| file_config.is_enabled(warning.lint));
+        warnings.extend(file_warnings);
+    }
+
+    warnings.sort_by(|a, b| a.location.cmp(&b.location));
+    warnings
+}
+
+fn is_assembly_source(file: &SourceFile) -> bool {
+    !file.file.starts_with('<') && !file.file.ends_with(".o")
+}
+
+/// The location of the operand that reads a register
+///
+/// Only the destination comes before the sources, so the last operand that
+/// names the register is the one read.
+fn read_location(line: &Line, register: Register) -> Location {
+    match line.spans.registers.iter().rfind(|(r, _)| *r == register) {
+        Some(&(_, index)) => line.operand_location(index),
+        None => line.head_location(),
+    }
+}
+
+// ============================================================================
+// Symbols
+// ============================================================================
+
+fn check_unused_symbols(
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    file_index: usize,
+    warnings: &mut Vec<Warning>,
+) {
+    let used: HashSet<LinePointer> = symbol_links
+        .line_refs
+        .iter()
+        .flatten()
+        .flatten()
+        .map(|reference| reference.definition.pointer)
+        .collect();
+    // Weak definitions that another file overrides are still meant for
+    // other files, so they count as global too
+    let globals: HashSet<LinePointer> = symbol_links
+        .global_symbols
+        .iter()
+        .map(|global| global.definition_pointer)
+        .chain(symbol_links.overridden_symbols.iter().map(|def| def.pointer))
+        .collect();
+
+    let file = &source.files[file_index];
+    let exported: HashSet<&String> = file
+        .lines
+        .iter()
+        .filter_map(|line| match &line.content {
+            LineContent::Directive(
+                Directive::Global(names) | Directive::Weak(names),
+            ) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    for definition in &symbol_links.local_symbols_by_file[file_index] {
+        let pointer = definition.pointer;
+        if definition.symbol == "_start"
+            || is_common_block_symbol(&definition.symbol)
+            || used.contains(&pointer)
+            || globals.contains(&pointer)
+            || exported.contains(&definition.symbol)
+        {
+            continue;
+        }
+        let line = &file.lines[pointer.line_index];
+        let (kind, location) = match &line.content {
+            LineContent::Label(_) => ("Label", line.head_location()),
+            LineContent::Directive(
+                Directive::Equ(..) | Directive::Equiv(..),
+            ) => ("Symbol", line.symbol_location(&definition.symbol)),
+            _ => continue,
+        };
+        warnings.push(Warning {
+            lint: Lint::UnusedSymbol,
+            location,
+            message: format!(
+                "{} '{}' is defined but never used",
+                kind, definition.symbol
+            ),
+        });
+    }
+}
+
+fn check_start_is_global(
+    file: &SourceFile,
+    symbol_links: &SymbolLinks,
+    warnings: &mut Vec<Warning>,
+) {
+    if symbol_links.global_symbols.iter().any(|g| g.symbol == "_start") {
+        return;
+    }
+    for line in &file.lines {
+        if matches!(&line.content, LineContent::Label(name) if name == "_start")
+        {
+            warnings.push(Warning {
+                lint: Lint::MissingGlobalStart,
+                location: line.head_location(),
+                message: "'_start' is not declared with '.global _start', so \
+                          it is not the program's entry point"
+                    .to_string(),
+            });
+        }
+    }
+}
+
+// ============================================================================
+// Control flow
+// ============================================================================
+
+/// Which segment the lines being checked are in
+#[derive(Clone, Copy, PartialEq)]
+enum Segment {
+    Text,
+    Other,
+}
+
+/// Check straight-line runs of code in `.text`: data mixed in with the
+/// instructions, code after an unconditional jump, and `t` registers read
+/// after a call
+///
+/// A label ends a run, since it can be reached from anywhere.
+fn check_control_flow(file: &SourceFile, warnings: &mut Vec<Warning>) {
+    let mut segment = Segment::Text;
+    let mut in_data = false;
+    // The unconditional jump that ended the current run of code
+    let mut jumped: Option<&'static str> = None;
+    // The temporaries a call has overwritten, and the call's line
+    let mut clobbered: Vec<Register> = Vec::new();
+    let mut call_line = 0;
+
+    for line in &file.lines {
+        match &line.content {
+            LineContent::Label(_) => {
+                jumped = None;
+                clobbered.clear();
+            }
+            LineContent::Directive(directive) => match directive {
+                Directive::Text | Directive::Data | Directive::Bss => {
+                    segment = if *directive == Directive::Text {
+                        Segment::Text
+                    } else {
+                        Segment::Other
+                    };
+                    in_data = false;
+                    jumped = None;
+                    clobbered.clear();
+                }
+                Directive::String(_)
+                | Directive::Asciz(_)
+                | Directive::Byte(_)
+                | Directive::TwoByte(_)
+                | Directive::FourByte(_)
+                    if segment == Segment::Text =>
+                {
+                    if !in_data {
+                        warnings.push(Warning {
+                            lint: Lint::DataInText,
+                            location: line.head_location(),
+                            message: "Data in the .text section, where it \
+                                      could be executed as code (missing \
+                                      '.data'?)"
+                                .to_string(),
+                        });
+                    }
+                    in_data = true;
+                    jumped = None;
+                    clobbered.clear();
+                }
+                _ => {}
+            },
+            LineContent::Instruction(instruction) => {
+                in_data = false;
+                if let Some(jump) = jumped.take() {
+                    warnings.push(Warning {
+                        lint: Lint::UnreachableCode,
+                        location: line.head_location(),
+                        message: format!(
+                            "Unreachable code: nothing jumps here after \
+                             the '{}' above (missing label?)",
+                            jump
+                        ),
+                    });
+                }
+
+                let (reads, writes) = register_uses(instruction);
+                for register in reads {
+                    if let Some(index) =
+                        clobbered.iter().position(|&r| r == register)
+                    {
+                        clobbered.remove(index);
+                        warnings.push(Warning {
+                            lint: Lint::TempAcrossCall,
+                            location: read_location(line, register),
+                            message: format!(
+                                "'{}' is read after the call on line {}, \
+                                 which does not preserve it (use an 's' \
+                                 register)",
+                                register, call_line
+                            ),
+                        });
+                    }
+                }
+                clobbered.retain(|register| !writes.contains(register));
+
+                if is_call(instruction) {
+                    clobbered = TEMPORARIES.to_vec();
+                    call_line = line.location.line;
+                }
+                jumped = unconditional_jump(instruction);
+                if jumped.is_some() {
+                    clobbered.clear();
+                }
+            }
+        }
+    }
+}
+
+/// The registers a callee may change without restoring them
+const TEMPORARIES: [Register; 7] = [
+    Register::X5,
+    Register::X6,
+    Register::X7,
+    Register::X28,
+    Register::X29,
+    Register::X30,
+    Register::X31,
+];
+
+fn is_call(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Pseudo(PseudoOp::Call(Register::X1, _))
+            | Instruction::JType(JTypeOp::Jal, Register::X1, _)
+            | Instruction::IType(ITypeOp::Jalr, Register::X1, _, _)
+            | Instruction::Compressed(
+                CompressedOp::CJalr | CompressedOp::CJalComp,
+                _
+            )
+    )
+}
+
+/// The mnemonic of an instruction that never falls through to the next one
+fn unconditional_jump(instruction: &Instruction) -> Option<&'static str> {
+    match instruction {
+        Instruction::JType(JTypeOp::Jal, Register::X0, _) => Some("j"),
+        Instruction::IType(ITypeOp::Jalr, Register::X0, Register::X1, _) => {
+            Some("ret")
+        }
+        Instruction::IType(ITypeOp::Jalr, Register::X0, _, _) => Some("jr"),
+        Instruction::Pseudo(PseudoOp::Tail(_, _)) => Some("tail"),
+        Instruction::Pseudo(PseudoOp::Jump(_, _)) => Some("jump"),
+        Instruction::Compressed(CompressedOp::CJComp, _) => Some("c.j"),
+        Instruction::Compressed(CompressedOp::CJr, _) => Some("c.jr"),
+        _ => None,
+    }
+}
+
+/// The registers an instruction reads and writes
+fn register_uses(instruction: &Instruction) -> (Vec<Register>, Vec<Register>) {
+    match instruction {
+        Instruction::RType(_, rd, rs1, rs2) => (vec![*rs1, *rs2], vec![*rd]),
+        Instruction::IType(_, rd, rs1, _) => (vec![*rs1], vec![*rd]),
+        Instruction::BType(_, rs1, rs2, _) => (vec![*rs1, *rs2], vec![]),
+        Instruction::UType(_, rd, _) | Instruction::JType(_, rd, _) => {
+            (vec![], vec![*rd])
+        }
+        Instruction::LoadStore(op, register, _, base) => {
+            if op.is_store() {
+                (vec![*register, *base], vec![])
+            } else {
+                (vec![*base], vec![*register])
+            }
+        }
+        Instruction::Atomic(_, rd, rs1, rs2, _) => {
+            (vec![*rs1, *rs2], vec![*rd])
+        }
+        Instruction::Special(_) => (vec![], vec![]),
+        Instruction::Compressed(op, operands) => {
+            compressed_register_uses(*op, operands)
+        }
+        Instruction::Pseudo(pseudo) => match pseudo {
+            PseudoOp::Li(rd, _)
+            | PseudoOp::La(rd, _)
+            | PseudoOp::LoadGlobal(_, rd, _) => (vec![], vec![*rd]),
+            PseudoOp::StoreGlobal(_, rs, _, temp) => (vec![*rs], vec![*temp]),
+            PseudoOp::Call(rd, _) => (vec![], vec![*rd]),
+            PseudoOp::Tail(_, _) | PseudoOp::Jump(_, _) => (vec![], vec![]),
+            PseudoOp::Extend(_, rd, rs) => (vec![*rs], vec![*rd]),
+            PseudoOp::Sle(_, rd, rs1, rs2) => (vec![*rs1, *rs2], vec![*rd]),
+        },
+    }
+}
+
+fn compressed_register_uses(
+    op: CompressedOp,
+    operands: &CompressedOperands,
+) -> (Vec<Register>, Vec<Register>) {
+    match operands {
+        CompressedOperands::CR { rd, rs2 } => {
+            if op == CompressedOp::CMv {
+                (vec![*rs2], vec![*rd])
+            } else {
+                (vec![*rd, *rs2], vec![*rd])
+            }
+        }
+        CompressedOperands::CRSingle { rs1 } => (vec![*rs1], vec![]),
+        CompressedOperands::CI { rd, .. } => {
+            if matches!(op, CompressedOp::CLi | CompressedOp::CLui) {
+                (vec![], vec![*rd])
+            } else {
+                (vec![*rd], vec![*rd])
+            }
+        }
+        CompressedOperands::CIStackLoad { rd, .. } => {
+            (vec![Register::X2], vec![*rd])
+        }
+        CompressedOperands::CSSStackStore { rs2, .. } => {
+            (vec![*rs2, Register::X2], vec![])
+        }
+        CompressedOperands::CIW { rd_prime, .. } => {
+            (vec![Register::X2], vec![*rd_prime])
+        }
+        CompressedOperands::CL { rd_prime, rs1_prime, .. } => {
+            (vec![*rs1_prime], vec![*rd_prime])
+        }
+        CompressedOperands::CS { rs2_prime, rs1_prime, .. } => {
+            (vec![*rs2_prime, *rs1_prime], vec![])
+        }
+        CompressedOperands::CA { rd_prime, rs2_prime } => {
+            (vec![*rd_prime, *rs2_prime], vec![*rd_prime])
+        }
+        CompressedOperands::CBImm { rd_prime, .. } => {
+            (vec![*rd_prime], vec![*rd_prime])
+        }
+        CompressedOperands::CBBranch { rs1_prime, .. } => {
+            (vec![*rs1_prime], vec![])
+        }
+        CompressedOperands::CJOpnd { .. } | CompressedOperands::None => {
+            (vec![], vec![])
+        }
+    }
+}
+
+// ============================================================================
+// Single instructions
+// ============================================================================
+
+/// Warn about a result written to x0, except in the idioms that use x0 on
+/// purpose: `nop`, `j`/`jr`/`ret` (jumps that do not link), and atomic
+/// memory operations that discard the old value
+fn check_write_x0(
+    line: &Line,
+    instruction: &Instruction,
+    warnings: &mut Vec<Warning>,
+) {
+    let idiom = match instruction {
+        Instruction::JType(JTypeOp::Jal, ..)
+        | Instruction::IType(ITypeOp::Jalr, ..)
+        | Instruction::Compressed(..) => true,
+        Instruction::IType(ITypeOp::Addi, Register::X0, Register::X0, imm) => {
+            **imm == Expression::Literal(0)
+        }
+        Instruction::Atomic(op, ..) => *op != AtomicOp::LrW,
+        _ => false,
+    };
+    let (_, writes) = register_uses(instruction);
+    if !idiom && writes.contains(&Register::X0) {
+        warnings.push(Warning {
+            lint: Lint::WriteX0,
+            location: line.operand_location(0),
+            message: "The result is written to x0 (zero), which discards it"
+                .to_string(),
+        });
+    }
+}
+
+/// Warn when a number above 0x7fffffff changes the value of an `li`
+/// expression by being treated as negative
+///
+/// Numbers are 32 bits, so 0x80000000 is -0x80000000. That is harmless when
+/// the number is loaded as it is or combined bitwise, but not when it is
+/// negated, divided, shifted right or compared with `<` and friends.
+fn check_li_wrap(
+    line: &Line,
+    instruction: &Instruction,
+    warnings: &mut Vec<Warning>,
+) {
+    let Instruction::Pseudo(PseudoOp::Li(_, expr)) = instruction else {
+        return;
+    };
+    if let Some(value) = sign_sensitive_wrapped_literal(expr, false) {
+        warnings.push(Warning {
+            lint: Lint::LiWrap,
+            location: line.operand_location(1),
+            message: format!(
+                "{:#x} does not fit in a signed 32-bit number, so it is {} \
+                 in this expression",
+                value as u32, value
+            ),
+        });
+    }
+}
+
+/// A literal that wrapped to a negative number, used where its sign
+/// matters
+fn sign_sensitive_wrapped_literal(
+    expr: &Expression,
+    sign_matters: bool,
+) -> Option<i32> {
+    match expr {
+        Expression::Literal(value) => {
+            (sign_matters && *value < 0).then_some(*value)
+        }
+        Expression::Parenthesized(expr) => {
+            sign_sensitive_wrapped_literal(expr, sign_matters)
+        }
+        Expression::NegateOp { expr } => {
+            sign_sensitive_wrapped_literal(expr, true)
+        }
+        Expression::DivideOp { lhs, rhs }
+        | Expression::ModuloOp { lhs, rhs } => {
+            sign_sensitive_wrapped_literal(lhs, true)
+                .or_else(|| sign_sensitive_wrapped_literal(rhs, true))
+        }
+        Expression::RightShiftOp { lhs, rhs } => {
+            sign_sensitive_wrapped_literal(lhs, true)
+                .or_else(|| sign_sensitive_wrapped_literal(rhs, false))
+        }
+        Expression::LessOp { lhs, rhs }
+        | Expression::LessEqualOp { lhs, rhs }
+        | Expression::GreaterOp { lhs, rhs }
+        | Expression::GreaterEqualOp { lhs, rhs } => {
+            sign_sensitive_wrapped_literal(lhs, true)
+                .or_else(|| sign_sensitive_wrapped_literal(rhs, true))
+        }
+        Expression::PlusOp { lhs, rhs }
+        | Expression::MinusOp { lhs, rhs }
+        | Expression::MultiplyOp { lhs, rhs }
+        | Expression::LeftShiftOp { lhs, rhs }
+        | Expression::BitwiseOrOp { lhs, rhs }
+        | Expression::BitwiseAndOp { lhs, rhs }
+        | Expression::BitwiseXorOp { lhs, rhs } => {
+            sign_sensitive_wrapped_literal(lhs, sign_matters)
+                .or_else(|| sign_sensitive_wrapped_literal(rhs, sign_matters))
+        }
+        // Only whether these are zero matters, or bit patterns are compared
+        Expression::EqualOp { lhs, rhs }
+        | Expression::NotEqualOp { lhs, rhs }
+        | Expression::LogicalAndOp { lhs, rhs }
+        | Expression::LogicalOrOp { lhs, rhs } => {
+            sign_sensitive_wrapped_literal(lhs, false)
+                .or_else(|| sign_sensitive_wrapped_literal(rhs, false))
+        }
+        Expression::ConditionalOp { condition, then_expr, else_expr } => {
+            sign_sensitive_wrapped_literal(condition, false)
+                .or_else(|| {
+                    sign_sensitive_wrapped_literal(then_expr, sign_matters)
+                })
+                .or_else(|| {
+                    sign_sensitive_wrapped_literal(else_expr, sign_matters)
+                })
+        }
+        Expression::BitwiseNotOp { expr }
+        | Expression::LogicalNotOp { expr }
+        | Expression::HiOp { expr }
+        | Expression::LoOp { expr } => {
+            sign_sensitive_wrapped_literal(expr, false)
+        }
+        Expression::Identifier(_)
+        | Expression::CurrentAddress
+        | Expression::NumericLabelRef(_) => None,
+    }
+}
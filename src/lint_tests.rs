@@ -0,0 +1,301 @@
+// lint_tests.rs
+//
+// Tests for assembler warnings (-W<id> / -Wno-<id>)
+
+use crate::assembler::parse_source_from_string;
+use crate::ast::{Source, Span};
+use crate::lint::{Lint, LintConfig, Warning, lint};
+use crate::symbols::{create_builtin_symbols_file, link_symbols};
+
+/// The warnings for one file
+fn lint_file(code: &str, config: &LintConfig) -> Vec<Warning> {
+    let file = parse_source_from_string("lint.s", code).unwrap();
+    let source = Source { files: vec![file, create_builtin_symbols_file()] };
+    let symbol_links = link_symbols(&source).unwrap();
+    lint(&source, &symbol_links, config)
+}
+
+/// The (lint, line) of each warning for one file
+fn warnings_with(code: &str, config: &LintConfig) -> Vec<(Lint, usize)> {
+    lint_file(code, config)
+        .into_iter()
+        .map(|warning| (warning.lint, warning.location.line))
+        .collect()
+}
+
+fn warnings(code: &str) -> Vec<(Lint, usize)> {
+    warnings_with(code, &LintConfig::new())
+}
+
+const START: &str = "        .text\n        .globl _start\n_start:\n";
+
+#[test]
+fn test_clean_program_has_no_warnings() {
+    let code = format!(
+        "{}        la   a0, message
+        li   s0, 3
+loop:   call print
+        addi s0, s0, -1
+        bnez s0, loop
+        li   a7, 93
+        ecall
+print:  mv   t0, a0
+        ret
+        .data
+message: .string \"hi\"
+",
+        START
+    );
+    assert_eq!(warnings(&code), []);
+}
+
+#[test]
+fn test_unused_symbols() {
+    let code = format!(
+        "{}        j    used
+unused: nop
+used:   li   a0, SIZE
+        .equ SIZE, 4
+        .equ SPARE, 8
+1:      ret
+",
+        START
+    );
+    assert_eq!(
+        warnings(&code),
+        [(Lint::UnusedSymbol, 5), (Lint::UnusedSymbol, 8)]
+    );
+}
+
+#[test]
+fn test_overridden_weak_symbol_is_not_unused() {
+    let weak = parse_source_from_string("w1.s", ".weak dflt\ndflt: ret\n");
+    let strong = parse_source_from_string("w2.s", ".global dflt\ndflt: ret\n");
+    let source = Source {
+        files: vec![
+            weak.unwrap(),
+            strong.unwrap(),
+            create_builtin_symbols_file(),
+        ],
+    };
+    let symbol_links = link_symbols(&source).unwrap();
+    let warnings = lint(&source, &symbol_links, &LintConfig::new());
+    assert!(
+        warnings.iter().all(|warning| warning.lint != Lint::UnusedSymbol),
+        "{:?}",
+        warnings
+    );
+}
+
+#[test]
+fn test_unreachable_code() {
+    let code = format!(
+        "{}        j    next
+        addi a0, a0, 1
+        addi a0, a0, 2
+next:   tail next
+        .balign 4
+        ret
+        .data
+after:  .4byte after
+        .text
+        ret
+",
+        START
+    );
+    assert_eq!(
+        warnings(&code),
+        [(Lint::UnreachableCode, 5), (Lint::UnreachableCode, 9)]
+    );
+}
+
+#[test]
+fn test_unreachable_code_names_the_jump() {
+    let code = format!("{}        jump _start, t2\n        nop\n", START);
+    let warnings = lint_file(&code, &LintConfig::new());
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].message.contains("the 'jump' above"));
+}
+
+#[test]
+fn test_write_x0() {
+    let code = format!(
+        "{}        add  zero, a0, a1
+        li   x0, 5
+        lw   zero, 0(a0)
+        nop
+        mv   x0, a0
+        jal  x0, 1f
+1:      amoswap.w zero, a1, (a0)
+        ret
+",
+        START
+    );
+    assert_eq!(
+        warnings(&code),
+        [
+            (Lint::WriteX0, 4),
+            (Lint::WriteX0, 5),
+            (Lint::WriteX0, 6),
+            (Lint::WriteX0, 8)
+        ]
+    );
+}
+
+#[test]
+fn test_temp_across_call() {
+    let code = format!(
+        "{}        li   t0, 1
+        li   t1, 2
+        call helper
+        li   t1, 3
+        add  a0, t0, t1
+        add  a0, a0, t0
+        call helper
+1:      mv   a0, t2
+helper: ret
+",
+        START
+    );
+    // t0 is reported once; t1 was set again after the call, and the label
+    // before the last read could be reached from elsewhere
+    assert_eq!(warnings(&code), [(Lint::TempAcrossCall, 8)]);
+
+    // The warning points at the operand that reads t0
+    let warnings = lint_file(&code, &LintConfig::new());
+    assert_eq!(warnings[0].location.span, Some(Span { start: 17, end: 19 }));
+}
+
+#[test]
+fn test_missing_global_start() {
+    assert_eq!(
+        warnings("_start: li a7, 93\n ecall\n"),
+        [(Lint::MissingGlobalStart, 1)]
+    );
+}
+
+#[test]
+fn test_data_in_text() {
+    let code = format!(
+        "{}        ret
+table:  .4byte _start
+        .byte 1, 2
+        .string \"x\"
+        .data
+        .byte 3
+",
+        START
+    );
+    // One warning for each run of data
+    assert_eq!(
+        warnings(&code),
+        [(Lint::UnusedSymbol, 5), (Lint::DataInText, 5)]
+    );
+}
+
+#[test]
+fn test_li_wrap() {
+    let code = format!(
+        "{}        li   a0, 0x80000000
+        li   a0, 0xffffffff & ~0xf
+        li   a0, -0x80000001
+        li   a0, 0x80000000 / 16
+        li   a0, (0xf0000000 >> 4) + 1
+        li   a0, 0x7fffffff / 16
+        li   a0, 0x80000000 > 0 ? 1 : 2
+        li   a0, 0x80000000 == 0x80000000
+        ret
+",
+        START
+    );
+    assert_eq!(
+        warnings(&code),
+        [
+            (Lint::LiWrap, 6),
+            (Lint::LiWrap, 7),
+            (Lint::LiWrap, 8),
+            (Lint::LiWrap, 10)
+        ]
+    );
+}
+
+#[test]
+fn test_warning_flags() {
+    let code =
+        format!("{}        add  zero, a0, a1\n        ret\nspare:\n", START);
+    let mut config = LintConfig::new();
+    assert_eq!(config.parse_flag("-Wno-write-x0"), Ok(true));
+    assert_eq!(warnings_with(&code, &config), [(Lint::UnusedSymbol, 6)]);
+
+    assert_eq!(config.parse_flag("-w"), Ok(true));
+    assert_eq!(warnings_with(&code, &config), []);
+    assert_eq!(config.parse_flag("-Wunused-symbol"), Ok(true));
+    assert_eq!(warnings_with(&code, &config), [(Lint::UnusedSymbol, 6)]);
+    assert_eq!(config.parse_flag("-Wall"), Ok(true));
+    assert_eq!(warnings_with(&code, &config).len(), 2);
+
+    assert_eq!(config.parse_flag("--relax"), Ok(false));
+    assert_eq!(
+        config.parse_flag("-Wno-write-zero"),
+        Err("Error: unknown warning 'write-zero' (did you mean 'write-x0'?)"
+            .to_string())
+    );
+}
+
+#[test]
+fn test_warning_flags_in_a_comment() {
+    let code = format!(
+        "# risclet: -Wno-write-x0 -Wno-write-zero\n{}{}",
+        START,
+        "        add  zero, a0, a1
+        ret
+spare:  # risclet: -Wno-unused-symbol
+"
+    );
+    assert_eq!(warnings(&code), []);
+    let code = code.replace("risclet: -Wno-unused-symbol", "unused");
+    assert_eq!(warnings(&code), [(Lint::UnusedSymbol, 7)]);
+
+    // The command line's flags apply first
+    let mut config = LintConfig::new();
+    assert_eq!(config.parse_flag("-w"), Ok(true));
+    let code = code.replace("-Wno-write-x0", "-Wwrite-x0");
+    assert_eq!(warnings_with(&code, &config), [(Lint::WriteX0, 5)]);
+}
+
+#[test]
+fn test_lint_ids_are_stable() {
+    let ids: Vec<&str> = Lint::ALL.iter().map(|lint| lint.id()).collect();
+    assert_eq!(
+        ids,
+        [
+            "unused-symbol",
+            "unreachable-code",
+            "write-x0",
+            "temp-across-call",
+            "missing-global-start",
+            "data-in-text",
+            "li-wrap"
+        ]
+    );
+    for lint in Lint::ALL {
+        assert_eq!(Lint::from_id(lint.id()), Some(lint));
+    }
+}
+
+#[test]
+fn test_warning_report() {
+    let file = parse_source_from_string("lint.s", "_start: ret\n").unwrap();
+    let source = Source { files: vec![file] };
+    let symbol_links = link_symbols(&source).unwrap();
+    let warning = &lint(&source, &symbol_links, &LintConfig::new())[0];
+    assert!(
+        warning.to_string().starts_with(
+            "Warning at [lint.s:1:1]: '_start' is not declared with \
+             '.global _start'"
+        ),
+        "{}",
+        warning
+    );
+    assert!(warning.to_string().contains("[-Wmissing-global-start]"));
+}
@@ -0,0 +1,489 @@
+// listing.rs
+//
+// Assembler listing output (-a / --listing=FILE)
+//
+// Like GNU `as -al`, a listing shows each source line exactly as written,
+// with the segment and address it was assembled at and the bytes it produced.
+// Pseudo-instructions are followed by the real instructions they expanded to.
+// A cross-reference table at the end shows where each symbol is defined and
+// every line that uses it.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ast::{
+    Instruction, LineContent, LinePointer, Location, Segment, Source,
+};
+use crate::dump::format_address;
+use crate::error::{Result, RiscletError};
+use crate::expressions::{EvaluatedValue, SymbolValues};
+use crate::layout::{Layout, LineLayout};
+use crate::riscv::Op;
+use crate::symbols::{
+    BUILTIN_FILE_NAME, SPECIAL_GLOBAL_POINTER, SymbolDefinition, SymbolLinks,
+    is_synthetic_file, is_undefined_weak,
+};
+
+/// Data bytes shown per listing row
+const DATA_BYTES_PER_ROW: usize = 4;
+
+/// Rows of data shown for one line before the rest is summarized
+const MAX_DATA_ROWS: usize = 8;
+
+/// Everything the listing needs from a finished assembly
+pub struct ListingInput<'a> {
+    pub source: &'a Source,
+    pub symbol_links: &'a SymbolLinks,
+    pub symbol_values: &'a SymbolValues,
+    pub layout: &'a Layout,
+    pub text_bytes: &'a [u8],
+    pub data_bytes: &'a [u8],
+}
+
+/// Write a listing to `file_name`, or to stdout if it is "-"
+///
+/// Source text is read back from each input file; files with no readable
+/// text (such as linked objects) are listed from the AST instead.
+pub fn write_listing(file_name: &str, input: &ListingInput) -> Result<()> {
+    let texts: Vec<Option<String>> = input
+        .source
+        .files
+        .iter()
+        .map(|file| {
+            if file.file.ends_with(".o") {
+                None
+            } else {
+                std::fs::read_to_string(&file.file).ok()
+            }
+        })
+        .collect();
+    let listing = format_listing(input, &texts);
+
+    if file_name == "-" {
+        print!("{}", listing);
+        return Ok(());
+    }
+    std::fs::write(file_name, listing).map_err(|e| {
+        RiscletError::io(format!(
+            "could not write listing file '{}': {}",
+            file_name, e
+        ))
+    })
+}
+
+/// Format a listing, given the original text of each source file (indexed
+/// like `source.files`)
+pub fn format_listing(
+    input: &ListingInput,
+    texts: &[Option<String>],
+) -> String {
+    let labels = address_labels(input);
+    let gp = global_pointer(input);
+    let mut out = String::new();
+
+    for (file_index, file) in input.source.files.iter().enumerate() {
+        if is_synthetic_file(&file.file) {
+            continue;
+        }
+        let _ = writeln!(out, "File: {}", file.file);
+        let _ = writeln!(out, "{}", "=".repeat(79));
+        let _ = writeln!(
+            out,
+            "{:>5}  {:<10}  {:<13}  Source",
+            "Line", "Address", "Code"
+        );
+
+        let mut rows = ListingRows { out: &mut out, labels: &labels, gp };
+        let mut line_index = 0;
+        match texts.get(file_index).and_then(|t| t.as_ref()) {
+            Some(text) => {
+                for (i, raw) in text.lines().enumerate() {
+                    let line_number = i + 1;
+                    let start = line_index;
+                    while line_index < file.lines.len()
+                        && file.lines[line_index].location.line == line_number
+                    {
+                        line_index += 1;
+                    }
+                    let pointers = (start..line_index)
+                        .map(|line_index| LinePointer {
+                            file_index,
+                            line_index,
+                        })
+                        .collect::<Vec<_>>();
+                    rows.source_line(
+                        input,
+                        line_number,
+                        raw.trim_end(),
+                        &pointers,
+                    );
+                }
+            }
+            None => {
+                for (i, line) in file.lines.iter().enumerate() {
+                    let pointer = LinePointer { file_index, line_index: i };
+                    let text = match &line.content {
+                        LineContent::Label(name) => format!("{}:", name),
+                        content => format!("        {}", content),
+                    };
+                    rows.source_line(
+                        input,
+                        line.location.line,
+                        &text,
+                        &[pointer],
+                    );
+                }
+            }
+        }
+        out.push('\n');
+    }
+
+    write_cross_reference(&mut out, input);
+    out
+}
+
+/// Writes the rows for each source line of one file
+struct ListingRows<'a> {
+    out: &'a mut String,
+    labels: &'a HashMap<u32, String>,
+    gp: u32,
+}
+
+impl ListingRows<'_> {
+    fn row(
+        &mut self,
+        line: Option<usize>,
+        address: &str,
+        code: &str,
+        text: &str,
+    ) {
+        let line = line.map(|n| n.to_string()).unwrap_or_default();
+        let row =
+            format!("{:>5}  {:<10}  {:<13}  {}", line, address, code, text);
+        let _ = writeln!(self.out, "{}", row.trim_end());
+    }
+
+    /// List one line of source and everything assembled from it
+    fn source_line(
+        &mut self,
+        input: &ListingInput,
+        line_number: usize,
+        text: &str,
+        pointers: &[LinePointer],
+    ) {
+        // The first line with an address gives the row its address; the
+        // first that produced bytes gives it its code
+        let Some(&first) = pointers.first() else {
+            self.row(Some(line_number), "", "", text);
+            return;
+        };
+        let producing = pointers
+            .iter()
+            .copied()
+            .find(|&pointer| input.layout.get(pointer).size > 0);
+        let shown = producing.unwrap_or(first);
+        let address = line_address(input, shown);
+
+        let Some(pointer) = producing else {
+            self.row(Some(line_number), &address, "", text);
+            return;
+        };
+        let line = input.source.get_line(pointer).ok();
+        let bytes = line_bytes(input, pointer);
+        match line.map(|line| &line.content) {
+            Some(LineContent::Instruction(Instruction::Pseudo(_))) => {
+                self.row(Some(line_number), &address, "", text);
+                self.instructions(input, pointer, &bytes, true);
+            }
+            Some(LineContent::Instruction(_)) => {
+                let (code, length) = instruction_code(&bytes);
+                self.row(Some(line_number), &address, &code, text);
+                if length < bytes.len() {
+                    self.instructions(input, pointer, &bytes[length..], false);
+                }
+            }
+            _ => self.data(input, pointer, line_number, text, &bytes),
+        }
+    }
+
+    /// List the instructions in `bytes`, one row each, decoded
+    fn instructions(
+        &mut self,
+        input: &ListingInput,
+        pointer: LinePointer,
+        bytes: &[u8],
+        from_start: bool,
+    ) {
+        let LineLayout { segment, .. } = *input.layout.get(pointer);
+        let line_address = input.layout.get_line_address(pointer);
+        let mut offset = if from_start {
+            0
+        } else {
+            input.layout.get(pointer).size as usize - bytes.len()
+        };
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (code, length) = instruction_code(rest);
+            let pc = line_address + offset as u32;
+            let decoded = if length == 2 {
+                i16::from_le_bytes([rest[0], rest[1]]) as i32
+            } else {
+                i32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]])
+            };
+            let text = format!("    > {}", self.disassemble(decoded, pc));
+            self.row(None, &format_address(pc, 8, segment), &code, &text);
+            offset += length;
+            rest = &rest[length..];
+        }
+    }
+
+    fn disassemble(&self, raw: i32, pc: u32) -> String {
+        let fields = Op::new(raw).to_fields();
+        let operands = fields[1..]
+            .iter()
+            .map(|field| {
+                field.to_string(pc, self.gp, false, false, self.labels)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let opcode =
+            fields[0].to_string(pc, self.gp, false, false, self.labels);
+        format!("{:<8}{}", opcode, operands).trim_end().to_string()
+    }
+
+    /// List the bytes produced by a data directive
+    fn data(
+        &mut self,
+        input: &ListingInput,
+        pointer: LinePointer,
+        line_number: usize,
+        text: &str,
+        bytes: &[u8],
+    ) {
+        let LineLayout { segment, .. } = *input.layout.get(pointer);
+        let address = input.layout.get_line_address(pointer);
+        let mut chunks = bytes.chunks(DATA_BYTES_PER_ROW);
+        let first = chunks.next().unwrap_or(&[]);
+        self.row(
+            Some(line_number),
+            &format_address(address, 8, segment),
+            &hex_bytes(first),
+            text,
+        );
+        for (i, chunk) in chunks.enumerate() {
+            if i + 1 == MAX_DATA_ROWS {
+                let shown = MAX_DATA_ROWS * DATA_BYTES_PER_ROW;
+                let text = format!("    ({} more bytes)", bytes.len() - shown);
+                self.row(None, "", "", &text);
+                break;
+            }
+            let offset = ((i + 1) * DATA_BYTES_PER_ROW) as u32;
+            self.row(
+                None,
+                &format_address(address + offset, 8, segment),
+                &hex_bytes(chunk),
+                "",
+            );
+        }
+    }
+}
+
+/// The formatted address of a line
+fn line_address(input: &ListingInput, pointer: LinePointer) -> String {
+    let segment = input.layout.get(pointer).segment;
+    format_address(input.layout.get_line_address(pointer), 8, segment)
+}
+
+/// The bytes a line assembled to (none for .bss)
+fn line_bytes(input: &ListingInput, pointer: LinePointer) -> Vec<u8> {
+    let &LineLayout { segment, offset, size, .. } = input.layout.get(pointer);
+    let bytes = match segment {
+        Segment::Text => input.text_bytes,
+        Segment::Data => input.data_bytes,
+        Segment::Bss => return Vec::new(),
+    };
+    let start = offset as usize;
+    bytes.get(start..start + size as usize).unwrap_or(&[]).to_vec()
+}
+
+/// The code column for the first instruction in `bytes`, and its length.
+/// Instructions are shown as words, with compressed ones marked "c".
+fn instruction_code(bytes: &[u8]) -> (String, usize) {
+    if bytes.len() >= 2 && bytes[0] & 0b11 != 0b11 {
+        let half = u16::from_le_bytes([bytes[0], bytes[1]]);
+        (format!("{:04x} c", half), 2)
+    } else if bytes.len() >= 4 {
+        let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        (format!("{:08x}", word), 4)
+    } else {
+        (hex_bytes(bytes), bytes.len())
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Named labels by address, for showing branch and jump targets
+fn address_labels(input: &ListingInput) -> HashMap<u32, String> {
+    let mut labels = HashMap::new();
+    for definitions in &input.symbol_links.local_symbols_by_file {
+        for definition in definitions {
+            if let Some(EvaluatedValue::Address(address)) =
+                input.symbol_values.get(definition)
+            {
+                labels.entry(address).or_insert(definition.symbol.clone());
+            }
+        }
+    }
+    labels
+}
+
+fn global_pointer(input: &ListingInput) -> u32 {
+    input
+        .symbol_links
+        .global_symbols
+        .iter()
+        .find(|global| global.symbol == SPECIAL_GLOBAL_POINTER)
+        .and_then(|global| {
+            input.symbol_values.get(&SymbolDefinition {
+                symbol: global.symbol.clone(),
+                pointer: global.definition_pointer,
+            })
+        })
+        .map_or(0, |value| match value {
+            EvaluatedValue::Address(address) => address,
+            EvaluatedValue::Integer(value) => value as u32,
+        })
+}
+
+// ============================================================================
+// Symbol cross-reference
+// ============================================================================
+
+/// One row of the cross-reference table
+struct CrossReference {
+    definition: SymbolDefinition,
+    scope: String,
+    uses: Vec<Location>,
+}
+
+fn write_cross_reference(out: &mut String, input: &ListingInput) {
+    let source = input.source;
+    let links = input.symbol_links;
+    let mut rows: Vec<CrossReference> = Vec::new();
+    let mut index: HashMap<SymbolDefinition, usize> = HashMap::new();
+    let mut add = |rows: &mut Vec<CrossReference>,
+                   definition: &SymbolDefinition,
+                   scope: &str| {
+        *index.entry(definition.clone()).or_insert_with(|| {
+            rows.push(CrossReference {
+                definition: definition.clone(),
+                scope: scope.to_string(),
+                uses: Vec::new(),
+            });
+            rows.len() - 1
+        })
+    };
+
+    for global in &links.global_symbols {
+        let definition = SymbolDefinition {
+            symbol: global.symbol.clone(),
+            pointer: global.definition_pointer,
+        };
+        let scope = if is_undefined_weak(source, global) {
+            "weak, undefined".to_string()
+        } else {
+            global.binding.to_string()
+        };
+        add(&mut rows, &definition, &scope);
+    }
+    for definitions in &links.local_symbols_by_file {
+        for definition in definitions {
+            add(&mut rows, definition, "local");
+        }
+    }
+    for (file_index, file) in links.line_refs.iter().enumerate() {
+        for (line_index, refs) in file.iter().enumerate() {
+            let pointer = LinePointer { file_index, line_index };
+            let Ok(line) = source.get_line(pointer) else {
+                continue;
+            };
+            for reference in refs {
+                let definition = &reference.definition;
+                if definition.symbol.starts_with(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+                let row = add(&mut rows, definition, "local");
+                let uses = &mut rows[row].uses;
+                if uses.last() != Some(&line.location) {
+                    uses.push(line.location.clone());
+                }
+            }
+        }
+    }
+
+    // The global pointer is only interesting if something uses it
+    rows.retain(|row| {
+        let file = &source.files[row.definition.pointer.file_index];
+        file.file != BUILTIN_FILE_NAME || !row.uses.is_empty()
+    });
+    rows.sort_by(|a, b| {
+        a.definition.symbol.cmp(&b.definition.symbol).then(
+            a.definition
+                .pointer
+                .file_index
+                .cmp(&b.definition.pointer.file_index),
+        )
+    });
+
+    let _ = writeln!(out, "Symbols:");
+    let _ = writeln!(out, "{}", "=".repeat(79));
+    let width = rows
+        .iter()
+        .map(|row| row.definition.symbol.len())
+        .chain(std::iter::once(6))
+        .max()
+        .unwrap_or(6);
+    let _ = writeln!(
+        out,
+        "{:<width$}  {:<10}  {:<16}  {:<20}  Used at",
+        "Symbol",
+        "Value",
+        "Scope",
+        "Defined at",
+        width = width
+    );
+    for row in &rows {
+        let value = match input.symbol_values.get(&row.definition) {
+            Some(EvaluatedValue::Address(address)) => {
+                format!("0x{:08x}", address)
+            }
+            Some(EvaluatedValue::Integer(value)) => value.to_string(),
+            None => "0".to_string(),
+        };
+        let defined = match source.get_line(row.definition.pointer) {
+            Ok(line) if !is_synthetic_file(&line.location.file) => {
+                format_location(&line.location)
+            }
+            Ok(line) => line.location.file.clone(),
+            Err(_) => String::new(),
+        };
+        let uses =
+            row.uses.iter().map(format_location).collect::<Vec<_>>().join(", ");
+        let text = format!(
+            "{:<width$}  {:<10}  {:<16}  {:<20}  {}",
+            row.definition.symbol,
+            value,
+            row.scope,
+            defined,
+            uses,
+            width = width
+        );
+        let _ = writeln!(out, "{}", text.trim_end());
+    }
+}
+
+fn format_location(location: &Location) -> String {
+    format!("{}:{}", location.file, location.line)
+}
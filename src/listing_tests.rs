@@ -0,0 +1,156 @@
+// listing_tests.rs
+//
+// Tests for assembler listings (-a / --listing=FILE)
+
+use crate::assembler::{parse_source_from_string, relaxation_loop};
+use crate::ast::Source;
+use crate::config::Config;
+use crate::layout::approximate_line_sizes;
+use crate::listing::{ListingInput, format_listing};
+use crate::symbols::{create_builtin_symbols_file, link_symbols};
+
+const PROGRAM: &str = "# Sum a small array
+        .text
+        .globl _start
+_start: la   t0, numbers      # address of the array
+        li   a0, 0
+loop:   lw   t1, 0(t0)
+        add  a0, a0, t1
+        addi t0, t0, 4
+        bnez t1, loop
+        li   a7, 93
+        ecall
+
+        .data
+numbers: .4byte 1, 2, 0
+text:   .string \"0123456789abcdef0123456789abcdef0123456789\"
+";
+
+/// Assemble one file and return its listing
+fn listing(code: &str, compressed: bool) -> String {
+    let mut config = Config::assemble_default();
+    config.relax.gp = Some(false);
+    config.relax.compressed = compressed;
+
+    let mut source = Source {
+        files: vec![parse_source_from_string("sum.s", code).unwrap()],
+    };
+    source.files.push(create_builtin_symbols_file());
+    let symbol_links = link_symbols(&source).unwrap();
+    let relaxed = relaxation_loop(
+        &config,
+        &source,
+        &symbol_links,
+        approximate_line_sizes(&source),
+    )
+    .unwrap();
+
+    format_listing(
+        &ListingInput {
+            source: &source,
+            symbol_links: &symbol_links,
+            symbol_values: &relaxed.symbol_values,
+            layout: &relaxed.layout,
+            text_bytes: &relaxed.text_bytes,
+            data_bytes: &relaxed.data_bytes,
+        },
+        &[Some(code.to_string()), None],
+    )
+}
+
+fn rows_for_line(listing: &str, line: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut in_line = false;
+    for row in listing.lines() {
+        let number = row.get(..5).and_then(|n| n.trim().parse::<usize>().ok());
+        match number {
+            Some(n) => in_line = n == line,
+            None if row.starts_with("     ") => {}
+            None => in_line = false,
+        }
+        if in_line {
+            rows.push(row.to_string());
+        }
+    }
+    rows
+}
+
+#[test]
+fn test_listing_keeps_source_text_and_comments() {
+    let listing = listing(PROGRAM, false);
+    assert!(listing.starts_with("File: sum.s\n"));
+    let comment = &rows_for_line(&listing, 1)[0];
+    assert!(comment.ends_with("# Sum a small array"), "{}", comment);
+    let la = &rows_for_line(&listing, 4)[0];
+    assert!(
+        la.ends_with("_start: la   t0, numbers      # address of the array"),
+        "{}",
+        la
+    );
+    assert!(la.contains("00010094.t"), "{}", la);
+    assert!(!listing.contains("<builtin>"));
+}
+
+#[test]
+fn test_listing_expands_pseudo_instructions() {
+    let listing = listing(PROGRAM, false);
+    let la = rows_for_line(&listing, 4);
+    assert_eq!(la.len(), 3, "{:?}", la);
+    assert!(la[1].contains("00010094.t  00001297"), "{}", la[1]);
+    assert!(la[1].ends_with("> auipc   t0, 4096"), "{}", la[1]);
+    assert!(la[2].contains("00010098.t"), "{}", la[2]);
+    assert!(la[2].contains("> addi    t0, t0, "), "{}", la[2]);
+
+    // A real instruction shows its encoding on the source row
+    let lw = rows_for_line(&listing, 6);
+    assert_eq!(lw.len(), 1);
+    assert!(lw[0].contains("0002a303       loop:   lw"), "{}", lw[0]);
+}
+
+#[test]
+fn test_listing_marks_compressed_instructions() {
+    let listing = listing(PROGRAM, true);
+    let add = &rows_for_line(&listing, 7)[0];
+    assert!(add.contains("  951a c  "), "{}", add);
+    let li = rows_for_line(&listing, 5);
+    assert!(li[1].contains("4501 c"), "{}", li[1]);
+    assert!(li[1].ends_with("> addi    a0, zero, 0"), "{}", li[1]);
+}
+
+#[test]
+fn test_listing_data_rows() {
+    let listing = listing(PROGRAM, false);
+    let numbers = rows_for_line(&listing, 14);
+    assert_eq!(numbers.len(), 3, "{:?}", numbers);
+    assert!(numbers[0].contains("00011000.d  01 00 00 00"), "{}", numbers[0]);
+    assert!(numbers[2].contains("00011008.d  00 00 00 00"), "{}", numbers[2]);
+
+    // Long data is cut off after a few rows
+    let text = rows_for_line(&listing, 15);
+    assert_eq!(text.len(), 9, "{:?}", text);
+    assert!(text[8].ends_with("(10 more bytes)"), "{}", text[8]);
+}
+
+#[test]
+fn test_listing_cross_reference() {
+    let listing = listing(PROGRAM, false);
+    let symbols = listing.split("Symbols:\n").nth(1).unwrap();
+    let row = |name: &str| {
+        symbols
+            .lines()
+            .find(|row| row.starts_with(&format!("{} ", name)))
+            .unwrap_or_else(|| panic!("no row for {}", name))
+            .split_whitespace()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(row("_start"), ["_start", "0x00010094", "global", "sum.s:4"]);
+    assert_eq!(
+        row("numbers"),
+        ["numbers", "0x00011000", "local", "sum.s:14", "sum.s:4"]
+    );
+    assert_eq!(
+        row("loop"),
+        ["loop", "0x000100a0", "local", "sum.s:6", "sum.s:9"]
+    );
+    assert!(!symbols.contains("__global_pointer$"));
+}
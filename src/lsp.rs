@@ -0,0 +1,962 @@
+// lsp.rs
+//
+// Language server for RISC-V assembly (`risclet lsp`)
+//
+// Speaks the Language Server Protocol over stdin and stdout. A document is
+// assembled on its own, since a directory often holds several stand-alone
+// programs, unless the client names the files of a multi-file program in
+// its initialization options:
+//
+//     "initializationOptions": {"programs": [["main.s", "lib.s"]]}
+//
+// Paths are relative to the workspace root. A document in one of those
+// programs is assembled with the program's other files; open documents are
+// read from the editor and the rest from disk. Each request assembles the
+// program again (without writing anything) and answers from its parsed
+// lines, symbol links, symbol values and code.
+//
+// Columns are counted in characters, which are the protocol's UTF-16 code
+// units for ASCII source.
+
+use crate::assembler::{
+    apply_gp_auto_detection, parse_source_recovering, relaxation_loop,
+};
+use crate::ast::{Line, LinePointer, Segment, Source, Token};
+use crate::config::Config;
+use crate::error::{Result, RiscletError};
+use crate::expressions::{EvaluatedValue, SymbolValues, eval_symbol_values};
+use crate::json::{self, Json};
+use crate::layout::{Layout, LineRelax, Placement, approximate_line_sizes};
+use crate::lint::{LintConfig, Warning, lint};
+use crate::parser::MNEMONICS;
+use crate::riscv::{Op, fields_to_disassembly};
+use crate::symbols::{
+    SymbolDefinition, SymbolLinks, create_builtin_symbols_file,
+    create_external_symbols_file, link_symbols_recovering,
+};
+use crate::tokenizer::{REGISTER_NAMES, tokenize_with_spans};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The largest message body read from a client, far more than any source
+/// file needs
+const MAX_MESSAGE_LENGTH: usize = 64 << 20;
+
+// Diagnostic severities
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+
+// Completion item kinds
+const KIND_VARIABLE: u32 = 6;
+const KIND_KEYWORD: u32 = 14;
+const KIND_CONSTANT: u32 = 21;
+
+/// Serve one client on stdin and stdout until it exits
+///
+/// Returns the process exit code: 0 if the client asked the server to shut
+/// down before exiting, 1 otherwise.
+pub fn run_language_server() -> Result<i32> {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut input)? {
+        let replies = match message {
+            Message::Body(body) => match json::parse(&body) {
+                Ok(message) => server.handle(&message),
+                Err(e) => vec![error_response(&Json::Null, PARSE_ERROR, e)],
+            },
+            Message::TooLong(length) => vec![error_response(
+                &Json::Null,
+                INVALID_REQUEST,
+                format!(
+                    "message of {} bytes is longer than the limit of {} bytes",
+                    length, MAX_MESSAGE_LENGTH
+                ),
+            )],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+    Ok(1)
+}
+
+/// One message read from the client
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Body(String),
+    /// A message longer than `MAX_MESSAGE_LENGTH`, skipped unread
+    TooLong(usize),
+}
+
+/// Read one message, or `None` at the end of the input
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<Message>> {
+    let io_error = |e: std::io::Error| RiscletError::io(e.to_string());
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).map_err(io_error)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| {
+        RiscletError::io("message without a Content-Length header".to_string())
+    })?;
+    // A bad length could ask for more memory than there is
+    if length > MAX_MESSAGE_LENGTH {
+        std::io::copy(
+            &mut input.by_ref().take(length as u64),
+            &mut std::io::sink(),
+        )
+        .map_err(io_error)?;
+        return Ok(Some(Message::TooLong(length)));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(io_error)?;
+    Ok(Some(Message::Body(String::from_utf8_lossy(&body).into_owned())))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|e| RiscletError::io(e.to_string()))
+}
+
+fn response(id: &Json, result: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: &Json, code: i32, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code as f64)),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+/// A range within one line
+fn range(line: usize, start: usize, end: usize) -> Json {
+    let position = |character: usize| {
+        Json::object([
+            ("line", (line as u32).into()),
+            ("character", (character as u32).into()),
+        ])
+    };
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+/// The name a file is assembled under: its path for `file:` URIs, and the
+/// URI itself otherwise
+fn uri_to_name(uri: &str) -> String {
+    match uri.strip_prefix("file://") {
+        Some(path) => percent_decode(path),
+        None => uri.to_string(),
+    }
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.display().to_string().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        let escape = text
+            .get(at + 1..at + 3)
+            .filter(|_| bytes[at] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                at += 3;
+            }
+            None => {
+                decoded.push(bytes[at]);
+                at += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+/// The state of one session with a client
+pub struct Server {
+    /// The text of each open document, by URI
+    documents: BTreeMap<String, String>,
+    /// The names of the files of each multi-file program the client set up
+    programs: Vec<Vec<String>>,
+    shutdown: bool,
+    /// Set once the client has sent `exit`
+    pub exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server {
+            documents: BTreeMap::new(),
+            programs: Vec::new(),
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Handle one message from the client, returning the messages to send
+    /// back
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or("");
+        let params = message.get("params");
+        let uri = params.get("textDocument").get("uri").as_str();
+        let id = message.get("id");
+        if *id == Json::Null {
+            return self.handle_notification(method, params);
+        }
+
+        let result = match (method, uri) {
+            ("initialize", _) => {
+                self.programs = configured_programs(params);
+                capabilities()
+            }
+            ("shutdown", _) => {
+                self.shutdown = true;
+                Json::Null
+            }
+            ("textDocument/definition", Some(uri)) => {
+                let (line, character) = position(params);
+                self.analyze(uri).definition(uri, line, character)
+            }
+            ("textDocument/references", Some(uri)) => {
+                let (line, character) = position(params);
+                let declaration = params
+                    .get("context")
+                    .get("includeDeclaration")
+                    .as_bool()
+                    .unwrap_or(true);
+                self.analyze(uri).references(uri, line, character, declaration)
+            }
+            ("textDocument/hover", Some(uri)) => {
+                let (line, character) = position(params);
+                self.analyze(uri).hover(uri, line, character)
+            }
+            ("textDocument/completion", Some(uri)) => {
+                let (line, character) = position(params);
+                self.analyze(uri).completion(uri, line, character)
+            }
+            _ => {
+                let message = format!("Method not found: {}", method);
+                return vec![error_response(id, METHOD_NOT_FOUND, message)];
+            }
+        };
+        vec![response(id, result)]
+    }
+
+    fn handle_notification(
+        &mut self,
+        method: &str,
+        params: &Json,
+    ) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.get("uri").as_str() else {
+            if method == "exit" {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+            }
+            return Vec::new();
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let text = document.get("text").as_str().unwrap_or("");
+                self.documents.insert(uri.to_string(), text.to_string());
+            }
+            "textDocument/didChange" => {
+                // Full synchronization: the last change is the whole text
+                let changes = params.get("contentChanges").as_array();
+                if let Some(text) =
+                    changes.last().and_then(|c| c.get("text").as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+            }
+            "textDocument/didSave" => {}
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                let params = Json::object([
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(Vec::new())),
+                ]);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    params,
+                )];
+            }
+            _ => return Vec::new(),
+        }
+        self.analyze(uri).diagnostics(uri)
+    }
+
+    /// The files of the program a document belongs to, sorted by name
+    fn program_files(&self, uri: &str) -> Vec<ProgramFile> {
+        let name = uri_to_name(uri);
+        let names = self
+            .programs
+            .iter()
+            .find(|program| program.contains(&name))
+            .cloned()
+            .unwrap_or_else(|| vec![name.clone()]);
+
+        let mut files = BTreeMap::new();
+        for file_name in names {
+            let open = self
+                .documents
+                .iter()
+                .find(|(open_uri, _)| uri_to_name(open_uri) == file_name);
+            let file = match open {
+                Some((open_uri, text)) => ProgramFile {
+                    uri: open_uri.clone(),
+                    name: file_name.clone(),
+                    text: text.clone(),
+                },
+                None => match std::fs::read_to_string(&file_name) {
+                    Ok(text) => ProgramFile {
+                        uri: path_to_uri(Path::new(&file_name)),
+                        name: file_name.clone(),
+                        text,
+                    },
+                    Err(_) => continue,
+                },
+            };
+            files.insert(file_name, file);
+        }
+        files.into_values().collect()
+    }
+
+    /// Assemble the program a document belongs to
+    fn analyze(&self, uri: &str) -> Analysis {
+        let files = self.program_files(uri);
+        let mut source = Source { files: Vec::new() };
+        let mut errors = Vec::new();
+        for file in &files {
+            let (parsed, file_errors) =
+                parse_source_recovering(&file.name, &file.text);
+            source.files.push(parsed);
+            errors.extend(file_errors);
+        }
+        source.files.push(create_builtin_symbols_file());
+
+        // As the assembler does, undefined symbols are declared weak so the
+        // rest of the program can still be evaluated
+        let (mut symbol_links, link_errors, undefined) =
+            link_symbols_recovering(&source);
+        errors.extend(link_errors);
+        if !undefined.is_empty() {
+            source.files.push(create_external_symbols_file(&undefined));
+            symbol_links = link_symbols_recovering(&source).0;
+        }
+        let warnings = if errors.is_empty() {
+            lint(&source, &symbol_links, &LintConfig::new())
+        } else {
+            Vec::new()
+        };
+
+        let mut config = Config::assemble_default();
+        apply_gp_auto_detection(&mut config, &source);
+        let sizes = approximate_line_sizes(&source);
+        let (layout, symbol_values, text_bytes) = match relaxation_loop(
+            &config,
+            &source,
+            &symbol_links,
+            sizes.clone(),
+        ) {
+            Ok(assembly) => (
+                Some(assembly.layout),
+                Some(assembly.symbol_values),
+                Some(assembly.text_bytes),
+            ),
+            Err(e) => {
+                errors.push(e);
+                // A line that fails to encode (an immediate out of range, say)
+                // should not hide every symbol, so estimate the addresses from
+                // the approximate line sizes
+                let line_relax = LineRelax::compute(&source, config.relax).0;
+                let layout = Layout::from_sizes(
+                    &source,
+                    &sizes,
+                    &line_relax,
+                    &Placement::Executable(config.text_start),
+                );
+                let symbol_values =
+                    eval_symbol_values(&source, &symbol_links, &layout).ok();
+                (Some(layout), symbol_values, None)
+            }
+        };
+
+        let texts = files
+            .iter()
+            .map(|file| file.text.lines().map(String::from).collect())
+            .collect();
+        Analysis {
+            files,
+            texts,
+            source,
+            symbol_links,
+            layout,
+            symbol_values,
+            text_bytes,
+            errors,
+            warnings,
+            config,
+        }
+    }
+}
+
+/// The programs named in the initialization options, each as the names
+/// its files are assembled under
+fn configured_programs(params: &Json) -> Vec<Vec<String>> {
+    let root = params
+        .get("rootUri")
+        .as_str()
+        .map(uri_to_name)
+        .or_else(|| params.get("rootPath").as_str().map(String::from));
+    let options = params.get("initializationOptions");
+    options
+        .get("programs")
+        .as_array()
+        .iter()
+        .map(|program| {
+            program
+                .as_array()
+                .iter()
+                .filter_map(Json::as_str)
+                .map(|file| match &root {
+                    Some(root) => Path::new(root).join(file),
+                    None => Path::new(file).to_path_buf(),
+                })
+                .map(|path| path.display().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+/// The line and character of a request's position
+fn position(params: &Json) -> (usize, usize) {
+    let position = params.get("position");
+    let line = position.get("line").as_u32().unwrap_or(0);
+    let character = position.get("character").as_u32().unwrap_or(0);
+    (line as usize, character as usize)
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("textDocumentSync", 1.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("completionProvider", Json::object([])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "risclet".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+// ============================================================================
+// Analysis
+// ============================================================================
+
+/// A source file of the program being analyzed
+struct ProgramFile {
+    uri: String,
+    /// The name it is assembled under
+    name: String,
+    text: String,
+}
+
+/// A program assembled for the editor
+struct Analysis {
+    /// The program's source files, in the order of `source.files`
+    files: Vec<ProgramFile>,
+    /// The lines of each source file
+    texts: Vec<Vec<String>>,
+    source: Source,
+    symbol_links: SymbolLinks,
+    /// The layout and symbol values, estimated from approximate line sizes
+    /// if the program did not assemble
+    layout: Option<Layout>,
+    symbol_values: Option<SymbolValues>,
+    /// The encoded .text segment, if the program assembled
+    text_bytes: Option<Vec<u8>>,
+    errors: Vec<RiscletError>,
+    warnings: Vec<Warning>,
+    config: Config,
+}
+
+/// An identifier in a source line and the columns it covers
+struct Word {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+impl Analysis {
+    fn file_index(&self, uri: &str) -> Option<usize> {
+        let name = uri_to_name(uri);
+        self.files.iter().position(|file| file.name == name)
+    }
+
+    /// A `publishDiagnostics` notification for each file of the program
+    fn diagnostics(&self, uri: &str) -> Vec<Json> {
+        let mut by_file: Vec<Vec<Json>> = vec![Vec::new(); self.files.len()];
+        let mut add = |location: Option<&crate::ast::Location>, diagnostic| {
+            // Errors about no line in particular go on the first line of
+            // the document that changed
+            let (file_index, line, span) = match location {
+                Some(location) => {
+                    let Some(index) = self
+                        .files
+                        .iter()
+                        .position(|file| file.name == location.file)
+                    else {
+                        return;
+                    };
+                    let span = location.span.map(|s| (s.start, s.end));
+                    (index, location.line.saturating_sub(1), span)
+                }
+                None => match self.file_index(uri) {
+                    Some(index) => (index, 0, None),
+                    None => return,
+                },
+            };
+            let (start, end) = span.unwrap_or_else(|| {
+                let text = self.texts[file_index].get(line);
+                (0, text.map_or(0, |text| text.chars().count()))
+            });
+            let (severity, code, message) = diagnostic;
+            let mut members = vec![
+                ("range".to_string(), range(line, start, end)),
+                ("severity".to_string(), Json::from(severity)),
+                ("source".to_string(), "risclet".into()),
+                ("message".to_string(), Json::String(message)),
+            ];
+            if let Some(code) = code {
+                members.push(("code".to_string(), Json::from(code)));
+            }
+            by_file[file_index].push(Json::Object(members));
+        };
+
+        let errors = match RiscletError::from_errors(self.errors.clone()) {
+            RiscletError::Multiple(errors) => errors,
+            error => vec![error],
+        };
+        for error in &errors {
+            let message = match error {
+                RiscletError::Assembly { message, .. } => message.clone(),
+                error => error.to_string(),
+            };
+            add(error.location(), (SEVERITY_ERROR, None, message));
+        }
+        for warning in &self.warnings {
+            add(
+                Some(&warning.location),
+                (
+                    SEVERITY_WARNING,
+                    Some(warning.lint.id()),
+                    warning.message.clone(),
+                ),
+            );
+        }
+
+        self.files
+            .iter()
+            .zip(by_file)
+            .map(|(file, diagnostics)| {
+                notification(
+                    "textDocument/publishDiagnostics",
+                    Json::object([
+                        ("uri", file.uri.as_str().into()),
+                        ("diagnostics", Json::Array(diagnostics)),
+                    ]),
+                )
+            })
+            .collect()
+    }
+
+    /// The identifiers on a source line (0-based)
+    fn words(&self, file_index: usize, line: usize) -> Vec<Word> {
+        let Some(text) = self.texts[file_index].get(line) else {
+            return Vec::new();
+        };
+        let Ok((tokens, spans)) = tokenize_with_spans(text) else {
+            return Vec::new();
+        };
+        tokens
+            .into_iter()
+            .zip(spans)
+            .filter_map(|(token, span)| match token {
+                Token::Identifier(name) => {
+                    Some(Word { name, start: span.start, end: span.end })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The parsed lines that come from a source line (0-based)
+    fn lines_at(
+        &self,
+        file_index: usize,
+        line: usize,
+    ) -> impl Iterator<Item = (LinePointer, &Line)> {
+        self.source.files[file_index]
+            .lines
+            .iter()
+            .enumerate()
+            .filter(move |(_, l)| l.location.line == line + 1)
+            .map(move |(line_index, l)| {
+                (LinePointer { file_index, line_index }, l)
+            })
+    }
+
+    fn location_line(&self, pointer: LinePointer) -> usize {
+        self.source.files[pointer.file_index].lines[pointer.line_index]
+            .location
+            .line
+            - 1
+    }
+
+    /// The symbol named at a position and the definition it refers to
+    fn symbol_at(
+        &self,
+        uri: &str,
+        line: usize,
+        character: usize,
+    ) -> Option<(String, SymbolDefinition)> {
+        let file_index = self.file_index(uri)?;
+        let word = self
+            .words(file_index, line)
+            .into_iter()
+            .find(|w| w.start <= character && character <= w.end)?;
+        let name = word.name;
+        let here: Vec<LinePointer> =
+            self.lines_at(file_index, line).map(|(p, _)| p).collect();
+
+        // A use of the symbol
+        for &pointer in &here {
+            if let Some(reference) = self
+                .symbol_links
+                .get_line_refs(pointer)
+                .iter()
+                .find(|r| r.outgoing_name == name)
+            {
+                return Some((name, reference.definition.clone()));
+            }
+        }
+        // Its definition, or its .global declaration
+        let local = self.symbol_links.local_symbols_by_file[file_index]
+            .iter()
+            .find(|d| d.symbol == name && here.contains(&d.pointer))
+            .cloned();
+        let global = || {
+            self.symbol_links.global_symbols.iter().find_map(|g| {
+                (g.symbol == name
+                    && (here.contains(&g.definition_pointer)
+                        || here.contains(&g.declaration_pointer)))
+                .then(|| SymbolDefinition {
+                    symbol: g.symbol.clone(),
+                    pointer: g.definition_pointer,
+                })
+            })
+        };
+        let definition = local.or_else(global)?;
+        Some((name, definition))
+    }
+
+    /// The places a name appears on the line a pointer refers to
+    fn occurrences(&self, pointer: LinePointer, name: &str) -> Vec<Json> {
+        if pointer.file_index >= self.files.len() {
+            return Vec::new();
+        }
+        let line = self.location_line(pointer);
+        self.words(pointer.file_index, line)
+            .into_iter()
+            .filter(|w| w.name == name)
+            .map(|w| {
+                Json::object([
+                    ("uri", self.files[pointer.file_index].uri.as_str().into()),
+                    ("range", range(line, w.start, w.end)),
+                ])
+            })
+            .collect()
+    }
+
+    fn definition(&self, uri: &str, line: usize, character: usize) -> Json {
+        self.symbol_at(uri, line, character)
+            .and_then(|(_, definition)| {
+                let symbol = &definition.symbol;
+                self.occurrences(definition.pointer, symbol).into_iter().next()
+            })
+            .unwrap_or(Json::Null)
+    }
+
+    fn references(
+        &self,
+        uri: &str,
+        line: usize,
+        character: usize,
+        include_declaration: bool,
+    ) -> Json {
+        let Some((_, definition)) = self.symbol_at(uri, line, character) else {
+            return Json::Null;
+        };
+        let mut pointers = Vec::new();
+        if include_declaration {
+            pointers.push((definition.pointer, definition.symbol.clone()));
+            for global in &self.symbol_links.global_symbols {
+                if global.symbol == definition.symbol
+                    && global.definition_pointer == definition.pointer
+                {
+                    pointers.push((
+                        global.declaration_pointer,
+                        global.symbol.clone(),
+                    ));
+                }
+            }
+        }
+        for (file_index, file) in self.symbol_links.line_refs.iter().enumerate()
+        {
+            for (line_index, refs) in file.iter().enumerate() {
+                for reference in refs {
+                    if reference.definition == definition {
+                        let pointer = LinePointer { file_index, line_index };
+                        pointers
+                            .push((pointer, reference.outgoing_name.clone()));
+                    }
+                }
+            }
+        }
+
+        // Several parsed lines can share a source line
+        let mut seen = BTreeSet::new();
+        let locations = pointers
+            .iter()
+            .flat_map(|(pointer, name)| self.occurrences(*pointer, name))
+            .filter(|location| seen.insert(location.to_string()))
+            .collect();
+        Json::Array(locations)
+    }
+
+    fn hover(&self, uri: &str, line: usize, character: usize) -> Json {
+        let text = match self.symbol_at(uri, line, character) {
+            Some((name, definition)) => {
+                self.describe_symbol(&name, &definition)
+            }
+            None => self.describe_code(uri, line),
+        };
+        match text {
+            Some(text) => Json::object([(
+                "contents",
+                Json::object([
+                    ("kind", "markdown".into()),
+                    ("value", text.into()),
+                ]),
+            )]),
+            None => Json::Null,
+        }
+    }
+
+    /// The value of a symbol, or its address
+    fn describe_symbol(
+        &self,
+        name: &str,
+        definition: &SymbolDefinition,
+    ) -> Option<String> {
+        let value = self.symbol_values.as_ref()?.get(definition)?;
+        Some(match value {
+            EvaluatedValue::Integer(v) => {
+                format!("`{}` = {} (0x{:x})", name, v, v)
+            }
+            EvaluatedValue::Address(address) => {
+                // Symbols the assembler defines have no line in the program
+                let segment = if definition.pointer.file_index
+                    < self.files.len()
+                {
+                    match self.layout.as_ref()?.get(definition.pointer).segment
+                    {
+                        Segment::Text => " in .text",
+                        Segment::Data => " in .data",
+                        Segment::Bss => " in .bss",
+                    }
+                } else {
+                    ""
+                };
+                format!("`{}` at address 0x{:08x}{}", name, address, segment)
+            }
+        })
+    }
+
+    /// The address and encoding of the instructions on a source line
+    fn describe_code(&self, uri: &str, line: usize) -> Option<String> {
+        let layout = self.layout.as_ref()?;
+        let text_bytes = self.text_bytes.as_ref()?;
+        let file_index = self.file_index(uri)?;
+        let mut words = Vec::new();
+        for (pointer, _) in self.lines_at(file_index, line) {
+            let line_layout = layout.get(pointer);
+            if line_layout.segment != Segment::Text || line_layout.size == 0 {
+                continue;
+            }
+            let address = layout.get_line_address(pointer);
+            let start = line_layout.offset as usize;
+            let bytes =
+                text_bytes.get(start..start + line_layout.size as usize)?;
+            let mut at = 0;
+            while at + 2 <= bytes.len() {
+                let length = if bytes[at] & 0x3 == 0x3 { 4 } else { 2 };
+                let Some(word) = bytes.get(at..at + length) else {
+                    break;
+                };
+                let mut raw = [0; 4];
+                raw[..length].copy_from_slice(word);
+                words.push((
+                    address + at as u32,
+                    u32::from_le_bytes(raw),
+                    length,
+                ));
+                at += length;
+            }
+        }
+        if words.is_empty() {
+            return None;
+        }
+
+        let mut text = String::from("```\n");
+        for (address, raw, length) in words {
+            let op = Op::new(raw as i32);
+            let disassembly = fields_to_disassembly(
+                &self.config,
+                &op.to_fields(),
+                address,
+                0,
+                length == 2,
+                &HashMap::new(),
+            );
+            let hex = if length == 2 {
+                format!("    {:04x}", raw)
+            } else {
+                format!("{:08x}", raw)
+            };
+            text.push_str(&format!(
+                "{:08x}: {}  {}\n",
+                address,
+                hex,
+                disassembly.trim_end()
+            ));
+        }
+        text.push_str("```");
+        Some(text)
+    }
+
+    /// Mnemonics where one goes, and registers and symbols in operands
+    fn completion(&self, uri: &str, line: usize, character: usize) -> Json {
+        let file_index = self.file_index(uri);
+        let prefix: String = file_index
+            .and_then(|index| self.texts[index].get(line))
+            .map(|text| text.chars().take(character).collect())
+            .unwrap_or_default();
+        let mut tokens = tokenize_with_spans(&prefix)
+            .map(|(tokens, _)| tokens)
+            .unwrap_or_default();
+        while tokens.len() >= 2 && tokens[1] == Token::Colon {
+            tokens.drain(..2);
+        }
+        let typing_mnemonic = tokens.is_empty()
+            || (tokens.len() == 1 && !prefix.ends_with(char::is_whitespace));
+
+        let item = |label: &str, kind: u32| {
+            Json::object([("label", label.into()), ("kind", kind.into())])
+        };
+        let mut items = Vec::new();
+        if typing_mnemonic {
+            items.extend(MNEMONICS.iter().map(|m| item(m, KIND_KEYWORD)));
+        } else {
+            items.extend(REGISTER_NAMES.iter().map(|r| item(r, KIND_VARIABLE)));
+            let mut symbols = BTreeSet::new();
+            if let Some(index) = file_index {
+                symbols.extend(
+                    self.symbol_links.local_symbols_by_file[index]
+                        .iter()
+                        .map(|d| d.symbol.as_str()),
+                );
+            }
+            symbols.extend(self.symbol_links.global_symbols.iter().filter_map(
+                |g| {
+                    (g.definition_pointer.file_index < self.files.len())
+                        .then_some(g.symbol.as_str())
+                },
+            ));
+            items.extend(
+                symbols
+                    .into_iter()
+                    .filter(|s| !s.chars().all(|c| c.is_ascii_digit()))
+                    .map(|s| item(s, KIND_CONSTANT)),
+            );
+        }
+        Json::Array(items)
+    }
+}
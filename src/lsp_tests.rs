@@ -0,0 +1,304 @@
+// lsp_tests.rs
+//
+// Tests for the language server behind `risclet lsp`
+
+use crate::json::{self, Json};
+use crate::lsp::{Message, Server, read_message};
+
+/// A document in a directory that does not exist, so no other files join
+/// its program
+const URI: &str = "file:///nonexistent-risclet-lsp-test/prog.s";
+
+const PROGRAM: &str = "        .equ N, 10
+        .globl _start
+_start:
+        li a0, N
+        call helper
+        li a7, 93
+        ecall
+helper:
+        addi a0, a0, 1
+        ret
+";
+
+fn message(text: &str) -> Json {
+    json::parse(text).unwrap()
+}
+
+fn open(server: &mut Server, text: &str) -> Vec<Json> {
+    open_at(server, URI, text)
+}
+
+fn open_at(server: &mut Server, uri: &str, text: &str) -> Vec<Json> {
+    let document = Json::object([
+        ("uri", uri.into()),
+        ("version", 1.into()),
+        ("text", text.into()),
+    ]);
+    server.handle(&Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/didOpen".into()),
+        ("params", Json::object([("textDocument", document)])),
+    ]))
+}
+
+/// The result of a request at a position in the document
+fn request(
+    server: &mut Server,
+    method: &str,
+    line: u32,
+    character: u32,
+) -> Json {
+    let replies = server.handle(&message(&format!(
+        r#"{{"jsonrpc": "2.0", "id": 7, "method": "textDocument/{}",
+            "params": {{"textDocument": {{"uri": "{}"}},
+                        "position": {{"line": {}, "character": {}}},
+                        "context": {{"includeDeclaration": true}}}}}}"#,
+        method, URI, line, character
+    )));
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].get("id").as_u32(), Some(7));
+    replies[0].get("result").clone()
+}
+
+/// The line and start and end characters of a location's range
+fn range_of(location: &Json) -> (u32, u32, u32) {
+    let range = location.get("range");
+    let start = range.get("start");
+    (
+        start.get("line").as_u32().unwrap(),
+        start.get("character").as_u32().unwrap(),
+        range.get("end").get("character").as_u32().unwrap(),
+    )
+}
+
+fn labels(items: &Json) -> Vec<&str> {
+    items
+        .as_array()
+        .iter()
+        .map(|item| item.get("label").as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_json_round_trip() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"x\"\\\né😀"}"#;
+    let value = message(text);
+    assert_eq!(value.get("a").as_array()[0].as_u32(), Some(1));
+    assert_eq!(value.get("b").as_str(), Some("x\"\\\né\u{1f600}"));
+    assert_eq!(json::parse(&value.to_string()).unwrap(), value);
+    assert!(json::parse("{\"a\": }").is_err());
+    assert!(json::parse("[1] 2").is_err());
+}
+
+#[test]
+fn test_overlong_messages_are_skipped() {
+    let input = "Content-Length: 99999999999\r\n\r\n{}";
+    let mut input = std::io::Cursor::new(input);
+    assert_eq!(
+        read_message(&mut input).unwrap(),
+        Some(Message::TooLong(99_999_999_999))
+    );
+    assert_eq!(read_message(&mut input).unwrap(), None);
+
+    let input = "Content-Length: 2\r\n\r\n{}";
+    let mut input = std::io::Cursor::new(input);
+    assert_eq!(
+        read_message(&mut input).unwrap(),
+        Some(Message::Body("{}".to_string()))
+    );
+}
+
+#[test]
+fn test_initialize_and_shutdown() {
+    let mut server = Server::new();
+    let replies = server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}"#,
+    ));
+    let capabilities = replies[0].get("result").get("capabilities");
+    assert_eq!(capabilities.get("hoverProvider").as_bool(), Some(true));
+    assert_eq!(capabilities.get("textDocumentSync").as_u32(), Some(1));
+
+    let replies = server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "workspace/symbol"}"#,
+    ));
+    assert_eq!(replies[0].get("error").get("code"), &Json::Number(-32601.0));
+
+    server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "shutdown"}"#,
+    ));
+    assert_eq!(server.exit_code, None);
+    server.handle(&message(r#"{"jsonrpc": "2.0", "method": "exit"}"#));
+    assert_eq!(server.exit_code, Some(0));
+}
+
+#[test]
+fn test_diagnostics_follow_changes() {
+    let mut server = Server::new();
+    let replies = open(&mut server, "        li a0, 1\n        bogus a0\n");
+    assert_eq!(replies.len(), 1);
+    let params = replies[0].get("params");
+    assert_eq!(params.get("uri").as_str(), Some(URI));
+    let diagnostics = params.get("diagnostics").as_array();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(range_of(&diagnostics[0]), (1, 8, 13));
+    assert_eq!(diagnostics[0].get("severity").as_u32(), Some(1));
+    assert!(diagnostics[0].get("message").as_str().unwrap().contains("bogus"));
+
+    // Fixing the error clears it
+    let replies = server.handle(&Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/didChange".into()),
+        (
+            "params",
+            Json::object([
+                ("textDocument", Json::object([("uri", URI.into())])),
+                (
+                    "contentChanges",
+                    Json::Array(vec![Json::object([("text", PROGRAM.into())])]),
+                ),
+            ]),
+        ),
+    ]));
+    let diagnostics = replies[0].get("params").get("diagnostics");
+    assert_eq!(diagnostics.as_array(), &[]);
+}
+
+/// The diagnostics published after opening a document
+fn diagnostics_at(server: &mut Server, uri: &str, text: &str) -> Vec<Json> {
+    let replies = open_at(server, uri, text);
+    let params = replies
+        .iter()
+        .map(|reply| reply.get("params"))
+        .find(|params| params.get("uri").as_str() == Some(uri))
+        .unwrap();
+    params.get("diagnostics").as_array().to_vec()
+}
+
+#[test]
+fn test_programs_in_one_directory_stay_apart() {
+    let mut server = Server::new();
+    let other = "file:///nonexistent-risclet-lsp-test/other.s";
+    assert_eq!(diagnostics_at(&mut server, URI, PROGRAM), []);
+    assert_eq!(diagnostics_at(&mut server, other, PROGRAM), []);
+}
+
+#[test]
+fn test_configured_programs_link_their_files() {
+    let mut server = Server::new();
+    server.handle(&message(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+            "rootUri": "file:///nonexistent-risclet-lsp-test",
+            "initializationOptions": {"programs": [["main.s", "lib.s"]]}}}"#,
+    ));
+    let main = "        .globl _start
+_start:
+        call helper
+        li a7, 93
+        ecall
+";
+    let lib = "        .globl helper
+helper:
+        ret
+";
+    let directory = "file:///nonexistent-risclet-lsp-test";
+    let lib_uri = format!("{}/lib.s", directory);
+    assert_eq!(diagnostics_at(&mut server, &lib_uri, lib), []);
+    let main_uri = format!("{}/main.s", directory);
+    assert_eq!(diagnostics_at(&mut server, &main_uri, main), []);
+
+    // A file outside the program does not see its symbols
+    let stray_uri = format!("{}/stray.s", directory);
+    let diagnostics = diagnostics_at(&mut server, &stray_uri, main);
+    assert_eq!(diagnostics.len(), 1);
+    let message = diagnostics[0].get("message").as_str().unwrap();
+    assert!(message.contains("helper"), "{}", message);
+}
+
+#[test]
+fn test_lint_warnings_are_diagnostics() {
+    let mut server = Server::new();
+    let replies =
+        open(&mut server, "_start:\n        li a0, 1\n        ecall\n");
+    let diagnostics = replies[0].get("params").get("diagnostics").as_array();
+    assert!(!diagnostics.is_empty());
+    assert!(diagnostics.iter().all(|d| d.get("severity").as_u32() == Some(2)));
+    assert!(diagnostics.iter().all(|d| d.get("code").as_str().is_some()));
+}
+
+#[test]
+fn test_definition_and_references() {
+    let mut server = Server::new();
+    open(&mut server, PROGRAM);
+
+    // `helper` in `call helper` goes to its label
+    let definition = request(&mut server, "definition", 4, 14);
+    assert_eq!(definition.get("uri").as_str(), Some(URI));
+    assert_eq!(range_of(&definition), (7, 0, 6));
+
+    // `N` is defined by .equ and used once
+    let references = request(&mut server, "references", 3, 16);
+    let ranges: Vec<_> = references.as_array().iter().map(range_of).collect();
+    assert_eq!(ranges, vec![(0, 13, 14), (3, 15, 16)]);
+
+    // A global's references include its .globl line
+    let references = request(&mut server, "references", 2, 3);
+    let ranges: Vec<_> = references.as_array().iter().map(range_of).collect();
+    assert_eq!(ranges, vec![(2, 0, 6), (1, 15, 21)]);
+
+    // Nothing to find away from a symbol
+    assert_eq!(request(&mut server, "definition", 5, 8), Json::Null);
+}
+
+#[test]
+fn test_hover_shows_values_and_encodings() {
+    let mut server = Server::new();
+    open(&mut server, PROGRAM);
+    let hover = |server: &mut Server, line, character| {
+        let result = request(server, "hover", line, character);
+        result.get("contents").get("value").as_str().unwrap().to_string()
+    };
+
+    assert_eq!(hover(&mut server, 3, 16), "`N` = 10 (0xa)");
+    assert!(hover(&mut server, 7, 2).contains("at address 0x"));
+
+    // li a0, 10 is one addi
+    let encoding = hover(&mut server, 3, 9);
+    assert!(encoding.contains("00a00513"), "{}", encoding);
+    assert!(encoding.contains("addi"), "{}", encoding);
+}
+
+#[test]
+fn test_hover_with_encoding_error() {
+    let mut server = Server::new();
+    open(&mut server, &PROGRAM.replace("addi a0, a0, 1", "addi a0, a0, 5000"));
+    let hover = |server: &mut Server, line, character| {
+        let result = request(server, "hover", line, character);
+        result.get("contents").get("value").as_str().unwrap().to_string()
+    };
+
+    // Symbols still have values, but no line has an encoding to show
+    assert_eq!(hover(&mut server, 3, 16), "`N` = 10 (0xa)");
+    assert!(hover(&mut server, 7, 2).contains("at address 0x"));
+    assert_eq!(request(&mut server, "hover", 3, 9), Json::Null);
+}
+
+#[test]
+fn test_completion() {
+    let mut server = Server::new();
+    open(&mut server, PROGRAM);
+
+    // In the mnemonic column of `addi a0, a0, 1`
+    let items = request(&mut server, "completion", 8, 10);
+    let mnemonics = labels(&items);
+    assert!(mnemonics.contains(&"addi"));
+    assert!(!mnemonics.contains(&"a0"));
+
+    // In its operands
+    let items = request(&mut server, "completion", 8, 21);
+    let operands = labels(&items);
+    assert!(operands.contains(&"a0"));
+    assert!(operands.contains(&"helper"));
+    assert!(operands.contains(&"N"));
+    assert!(!operands.contains(&"addi"));
+}
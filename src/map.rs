@@ -0,0 +1,456 @@
+// map.rs
+//
+// Symbol map output (--map FILE)
+//
+// Like the map GNU ld writes with -Map, this describes a linked executable:
+// where each segment went, which part of it each input file contributed, and
+// every symbol by address with its size. Sizes come from `.size` where the
+// source gives one, and otherwise run to the next symbol or the end of the
+// segment. A summary at the end shows what compression saved.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::ast::{
+    Directive, Instruction, LineContent, LinePointer, Segment, Source,
+};
+use crate::error::{Result, RiscletError};
+use crate::expressions::{EvaluatedValue, SymbolValues, eval_expr};
+use crate::layout::Layout;
+use crate::symbols::{
+    SymbolDefinition, SymbolLinks, is_assembler_local, is_synthetic_file,
+    is_undefined_weak,
+};
+
+/// Everything the map needs from a finished assembly
+pub struct MapInput<'a> {
+    pub source: &'a Source,
+    pub symbol_links: &'a SymbolLinks,
+    pub symbol_values: &'a SymbolValues,
+    pub layout: &'a Layout,
+    pub text_bytes: &'a [u8],
+    /// The executable being described
+    pub output_file: &'a str,
+    /// Whether `.L` labels are listed (--keep-locals)
+    pub keep_locals: bool,
+}
+
+/// Write a map to `file_name`
+pub fn write_map(file_name: &str, input: &MapInput) -> Result<()> {
+    std::fs::write(file_name, format_map(input)).map_err(|e| {
+        RiscletError::io(format!(
+            "could not write map file '{}': {}",
+            file_name, e
+        ))
+    })
+}
+
+/// Format a map of the executable
+pub fn format_map(input: &MapInput) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Map of {}", input.output_file);
+    out.push('\n');
+    write_segments(&mut out, input);
+    write_sections(&mut out, input);
+    write_symbols(&mut out, input);
+    write_compression(&mut out, input);
+    out
+}
+
+const SEGMENTS: [Segment; 3] = [Segment::Text, Segment::Data, Segment::Bss];
+
+fn segment_name(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Text => ".text",
+        Segment::Data => ".data",
+        Segment::Bss => ".bss",
+    }
+}
+
+/// A segment's start address and size
+fn segment_range(layout: &Layout, segment: Segment) -> (u32, u32) {
+    match segment {
+        Segment::Text => (layout.text_start, layout.text_size),
+        Segment::Data => (layout.data_start, layout.data_size),
+        Segment::Bss => (layout.bss_start, layout.bss_size),
+    }
+}
+
+fn heading(out: &mut String, title: &str) {
+    let _ = writeln!(out, "{}", title);
+    let _ = writeln!(out, "{}", "=".repeat(79));
+}
+
+/// One row of an address range table
+fn range_row(out: &mut String, name: &str, start: u32, size: u32, rest: &str) {
+    let row = format!(
+        "{:<8}  0x{:08x}  0x{:08x}  {:>8}  {}",
+        name,
+        start,
+        start.wrapping_add(size),
+        size,
+        rest
+    );
+    let _ = writeln!(out, "{}", row.trim_end());
+}
+
+// ============================================================================
+// Segments and sections
+// ============================================================================
+
+fn write_segments(out: &mut String, input: &MapInput) {
+    let layout = input.layout;
+    heading(out, "Segments:");
+    let _ = writeln!(
+        out,
+        "{:<8}  {:<10}  {:<10}  {:>8}",
+        "Segment", "Start", "End", "Size"
+    );
+    for segment in SEGMENTS {
+        let (start, size) = segment_range(layout, segment);
+        let note = if segment == Segment::Data
+            && layout.data_load_start != layout.data_start
+        {
+            format!("loaded from 0x{:08x}", layout.data_load_start)
+        } else {
+            String::new()
+        };
+        range_row(out, segment_name(segment), start, size, &note);
+    }
+    let _ = writeln!(
+        out,
+        "{:<8}  {:<10}  {:<10}  {:>8}",
+        "Total",
+        "",
+        "",
+        layout.text_size + layout.data_size + layout.bss_size
+    );
+    out.push('\n');
+}
+
+/// The part of each segment that each file contributed, in address order
+fn write_sections(out: &mut String, input: &MapInput) {
+    heading(out, "Sections:");
+    let _ = writeln!(
+        out,
+        "{:<8}  {:<10}  {:<10}  {:>8}  File",
+        "Section", "Start", "End", "Size"
+    );
+    for segment in SEGMENTS {
+        let (segment_start, _) = segment_range(input.layout, segment);
+        for (file_index, file) in input.source.files.iter().enumerate() {
+            if is_synthetic_file(&file.file) {
+                continue;
+            }
+            let mut range: Option<(u32, u32)> = None;
+            for line_index in 0..file.lines.len() {
+                let pointer = LinePointer { file_index, line_index };
+                let line = input.layout.get(pointer);
+                if line.segment != segment || line.size == 0 {
+                    continue;
+                }
+                let end = line.offset + line.size;
+                range = Some(match range {
+                    Some((start, old_end)) => {
+                        (start.min(line.offset), old_end.max(end))
+                    }
+                    None => (line.offset, end),
+                });
+            }
+            if let Some((start, end)) = range {
+                range_row(
+                    out,
+                    segment_name(segment),
+                    segment_start + start,
+                    end - start,
+                    &file.file,
+                );
+            }
+        }
+    }
+    out.push('\n');
+}
+
+// ============================================================================
+// Symbols
+// ============================================================================
+
+/// One symbol in the map
+struct MapSymbol {
+    definition: SymbolDefinition,
+    binding: String,
+    value: EvaluatedValue,
+    /// The size given by `.size`, if any
+    declared_size: Option<u32>,
+}
+
+fn write_symbols(out: &mut String, input: &MapInput) {
+    let symbols = collect_symbols(input);
+    let (mut addresses, constants): (Vec<_>, Vec<_>) = symbols
+        .into_iter()
+        .partition(|s| matches!(s.value, EvaluatedValue::Address(_)));
+    addresses.sort_by(|a, b| {
+        address_of(a)
+            .cmp(&address_of(b))
+            .then(a.definition.symbol.cmp(&b.definition.symbol))
+    });
+    let sizes = symbol_sizes(input, &addresses);
+
+    let width = addresses
+        .iter()
+        .chain(&constants)
+        .map(|s| s.definition.symbol.len())
+        .chain(std::iter::once(6))
+        .max()
+        .unwrap_or(6);
+
+    heading(out, "Symbols:");
+    let _ = writeln!(
+        out,
+        "{:<10}  {:>8}  {:<7}  {:<width$}  Defined at",
+        "Address",
+        "Size",
+        "Binding",
+        "Symbol",
+        width = width
+    );
+    for (symbol, size) in addresses.iter().zip(&sizes) {
+        let size = size.map(|size| size.to_string()).unwrap_or_default();
+        let row = format!(
+            "0x{:08x}  {:>8}  {:<7}  {:<width$}  {}",
+            address_of(symbol),
+            size,
+            symbol.binding,
+            symbol.definition.symbol,
+            defined_at(input.source, &symbol.definition),
+            width = width
+        );
+        let _ = writeln!(out, "{}", row.trim_end());
+    }
+    out.push('\n');
+
+    if constants.is_empty() {
+        return;
+    }
+    heading(out, "Constants:");
+    let _ = writeln!(
+        out,
+        "{:<10}  {:<7}  {:<width$}  Defined at",
+        "Value",
+        "Binding",
+        "Symbol",
+        width = width
+    );
+    for symbol in &constants {
+        let row = format!(
+            "{:<10}  {:<7}  {:<width$}  {}",
+            symbol.value.to_string(),
+            symbol.binding,
+            symbol.definition.symbol,
+            defined_at(input.source, &symbol.definition),
+            width = width
+        );
+        let _ = writeln!(out, "{}", row.trim_end());
+    }
+    out.push('\n');
+}
+
+fn address_of(symbol: &MapSymbol) -> u32 {
+    match symbol.value {
+        EvaluatedValue::Address(address) => address,
+        EvaluatedValue::Integer(value) => value as u32,
+    }
+}
+
+/// Every symbol that would go in the symbol table, with its binding
+fn collect_symbols(input: &MapInput) -> Vec<MapSymbol> {
+    let source = input.source;
+    let links = input.symbol_links;
+    let declared_sizes = declared_sizes(input);
+    let overridden: HashSet<&SymbolDefinition> =
+        links.overridden_symbols.iter().collect();
+    let mut seen: HashSet<SymbolDefinition> = HashSet::new();
+    let mut symbols = Vec::new();
+
+    let globals = links
+        .global_symbols
+        .iter()
+        .filter(|global| !is_undefined_weak(source, global))
+        .map(|global| {
+            let definition = SymbolDefinition {
+                symbol: global.symbol.clone(),
+                pointer: global.definition_pointer,
+            };
+            (definition, global.binding.to_string())
+        });
+    let locals = links
+        .local_symbols_by_file
+        .iter()
+        .flatten()
+        .filter(|definition| {
+            input.keep_locals || !is_assembler_local(&definition.symbol)
+        })
+        .map(|definition| (definition.clone(), "local".to_string()));
+
+    for (definition, binding) in globals.chain(locals) {
+        let file = &source.files[definition.pointer.file_index].file;
+        if is_synthetic_file(file)
+            || overridden.contains(&definition)
+            || !seen.insert(definition.clone())
+        {
+            continue;
+        }
+        let Some(value) = input.symbol_values.get(&definition) else {
+            continue;
+        };
+        let declared_size = declared_sizes
+            .get(&(definition.pointer.file_index, definition.symbol.as_str()))
+            .or_else(|| declared_sizes.get(&(usize::MAX, &definition.symbol)))
+            .copied();
+        symbols.push(MapSymbol { definition, binding, value, declared_size });
+    }
+    symbols
+}
+
+/// The sizes given by `.size`, by file index and symbol name, and also
+/// under `usize::MAX` for a size that may belong to a global defined in
+/// another file
+fn declared_sizes<'a>(input: &MapInput<'a>) -> HashMap<(usize, &'a str), u32> {
+    let mut sizes = HashMap::new();
+    for (file_index, file) in input.source.files.iter().enumerate() {
+        for (line_index, line) in file.lines.iter().enumerate() {
+            let LineContent::Directive(Directive::Size(name, expr)) =
+                &line.content
+            else {
+                continue;
+            };
+            let pointer = LinePointer { file_index, line_index };
+            let value = eval_expr(
+                expr,
+                input.layout.get_line_address(pointer),
+                input.symbol_links.get_line_refs(pointer),
+                input.symbol_values,
+                input.source,
+                pointer,
+            );
+            // The encoder has already rejected sizes that are not integers
+            if let Ok(EvaluatedValue::Integer(size)) = value
+                && size >= 0
+            {
+                sizes.insert((file_index, name.as_str()), size as u32);
+                sizes.entry((usize::MAX, name.as_str())).or_insert(size as u32);
+            }
+        }
+    }
+    sizes
+}
+
+/// The size of each symbol (sorted by address): as declared, or else up to
+/// the next symbol at a higher address in its segment, or the segment's end
+fn symbol_sizes(input: &MapInput, symbols: &[MapSymbol]) -> Vec<Option<u32>> {
+    let segments: Vec<Option<Segment>> =
+        symbols.iter().map(|s| symbol_segment(input, s)).collect();
+    symbols
+        .iter()
+        .enumerate()
+        .map(|(i, symbol)| {
+            if symbol.declared_size.is_some() {
+                return symbol.declared_size;
+            }
+            let segment = segments[i]?;
+            let address = address_of(symbol);
+            let (start, size) = segment_range(input.layout, segment);
+            let next = symbols[i + 1..]
+                .iter()
+                .zip(&segments[i + 1..])
+                .find(|(s, seg)| {
+                    **seg == Some(segment) && address_of(s) > address
+                })
+                .map_or(start + size, |(s, _)| address_of(s));
+            Some(next - address)
+        })
+        .collect()
+}
+
+/// The segment a symbol's address is in
+fn symbol_segment(input: &MapInput, symbol: &MapSymbol) -> Option<Segment> {
+    let line = input.source.get_line(symbol.definition.pointer).ok()?;
+    match &line.content {
+        LineContent::Label(_)
+        | LineContent::Directive(Directive::Comm(..) | Directive::Lcomm(..)) => {
+            Some(input.layout.get(symbol.definition.pointer).segment)
+        }
+        // An .equ may name an address anywhere; use the segment it is in
+        _ => {
+            let address = address_of(symbol);
+            SEGMENTS.into_iter().find(|&segment| {
+                let (start, size) = segment_range(input.layout, segment);
+                address >= start && address < start + size
+            })
+        }
+    }
+}
+
+fn defined_at(source: &Source, definition: &SymbolDefinition) -> String {
+    match source.get_line(definition.pointer) {
+        Ok(line) => format!("{}:{}", line.location.file, line.location.line),
+        Err(_) => String::new(),
+    }
+}
+
+// ============================================================================
+// Compression
+// ============================================================================
+
+/// How many instructions relaxation compressed, and the bytes it saved.
+/// Instructions written in their compressed form (c.addi and so on) are
+/// not counted, since no relaxation was involved. The size without
+/// compression is approximate, as `.balign` padding would change too.
+fn write_compression(out: &mut String, input: &MapInput) {
+    let mut instructions = 0;
+    let mut compressed = 0;
+    for (file_index, file) in input.source.files.iter().enumerate() {
+        for (line_index, line) in file.lines.iter().enumerate() {
+            if !matches!(line.content, LineContent::Instruction(_)) {
+                continue;
+            }
+            let pointer = LinePointer { file_index, line_index };
+            let layout = input.layout.get(pointer);
+            if layout.segment != Segment::Text {
+                continue;
+            }
+            let start = layout.offset as usize;
+            let end = start + layout.size as usize;
+            let mut rest = input.text_bytes.get(start..end).unwrap_or(&[]);
+            let explicit = matches!(
+                line.content,
+                LineContent::Instruction(Instruction::Compressed(..))
+            );
+            while rest.len() >= 2 {
+                let length = if rest[0] & 0b11 == 0b11 { 4 } else { 2 };
+                if !explicit {
+                    instructions += 1;
+                    if length == 2 {
+                        compressed += 1;
+                    }
+                }
+                rest = &rest[length.min(rest.len())..];
+            }
+        }
+    }
+
+    let saved = compressed * 2;
+    let text_size = input.layout.text_size;
+    heading(out, "Compression:");
+    let _ = writeln!(
+        out,
+        "{} of {} instructions compressed, saving {} bytes",
+        compressed, instructions, saved
+    );
+    let _ = writeln!(
+        out,
+        ".text is {} bytes, about {} without compression",
+        text_size,
+        text_size + saved
+    );
+}
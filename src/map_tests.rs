@@ -0,0 +1,180 @@
+// map_tests.rs
+//
+// Tests for symbol maps (--map FILE)
+
+use crate::assembler::{parse_source_from_string, relaxation_loop};
+use crate::ast::Source;
+use crate::config::Config;
+use crate::layout::approximate_line_sizes;
+use crate::map::{MapInput, format_map};
+use crate::symbols::{create_builtin_symbols_file, link_symbols};
+
+const MAIN: &str = "
+        .equ COUNT, 3
+        .text
+        .globl _start
+_start: la   a0, numbers
+        li   a1, COUNT
+        call sum
+        li   a7, 93
+        ecall
+        .size _start, 8
+
+sum:    li   t0, 0
+.Lloop: lw   t1, 0(a0)
+        add  t0, t0, t1
+        addi a0, a0, 4
+        addi a1, a1, -1
+        bnez a1, .Lloop
+        mv   a0, t0
+        ret
+
+        .data
+numbers: .4byte 1, 2, 3
+        .bss
+buffer: .space 40
+";
+
+const HELPER: &str = "
+        .text
+        .globl helper
+helper: ret
+        .data
+flag:   .byte 1
+";
+
+/// Assemble the files and return their map
+fn map(files: &[(&str, &str)], compressed: bool, keep_locals: bool) -> String {
+    let mut config = Config::assemble_default();
+    config.relax.gp = Some(false);
+    config.relax.compressed = compressed;
+
+    let mut source = Source {
+        files: files
+            .iter()
+            .map(|(name, code)| parse_source_from_string(name, code).unwrap())
+            .collect(),
+    };
+    source.files.push(create_builtin_symbols_file());
+    let symbol_links = link_symbols(&source).unwrap();
+    let relaxed = relaxation_loop(
+        &config,
+        &source,
+        &symbol_links,
+        approximate_line_sizes(&source),
+    )
+    .unwrap();
+
+    format_map(&MapInput {
+        source: &source,
+        symbol_links: &symbol_links,
+        symbol_values: &relaxed.symbol_values,
+        layout: &relaxed.layout,
+        text_bytes: &relaxed.text_bytes,
+        output_file: "a.out",
+        keep_locals,
+    })
+}
+
+/// The rows of the table under `heading`
+fn table<'a>(map: &'a str, heading: &str) -> Vec<&'a str> {
+    map.lines()
+        .skip_while(|line| *line != heading)
+        .skip(3)
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+/// The columns of the row whose last column is `last`
+fn row<'a>(rows: &[&'a str], last: &str) -> Vec<&'a str> {
+    rows.iter()
+        .map(|row| row.split_whitespace().collect::<Vec<_>>())
+        .find(|columns| columns.last() == Some(&last))
+        .unwrap_or_else(|| panic!("no row for {} in {:?}", last, rows))
+}
+
+#[test]
+fn test_map_lists_segments_and_each_files_sections() {
+    let map = map(&[("main.s", MAIN), ("helper.s", HELPER)], false, false);
+    assert!(map.starts_with("Map of a.out\n"));
+
+    let segments = table(&map, "Segments:");
+    let text: Vec<_> = segments[0].split_whitespace().collect();
+    assert_eq!(text[0], ".text");
+    let data: Vec<_> = segments[1].split_whitespace().collect();
+    assert_eq!(data[0], ".data");
+    assert_eq!(data[3], "13");
+    let bss: Vec<_> = segments[2].split_whitespace().collect();
+    assert_eq!(bss[3], "40");
+
+    let sections = table(&map, "Sections:");
+    let main_text = row(&sections, "main.s");
+    let helper_text = row(&sections, "helper.s");
+    assert_eq!(main_text[0], ".text");
+    assert_eq!(helper_text[0], ".text");
+    // The helper's code follows main's
+    assert_eq!(main_text[2], helper_text[1]);
+    assert_eq!(helper_text[3], "4");
+    assert!(sections.iter().any(|row| row.starts_with(".data")
+        && row.ends_with("helper.s")
+        && row.split_whitespace().nth(3) == Some("1")));
+}
+
+#[test]
+fn test_map_symbols_are_sorted_with_sizes_and_bindings() {
+    let map = map(&[("main.s", MAIN), ("helper.s", HELPER)], false, false);
+    let symbols = table(&map, "Symbols:");
+    let names: Vec<_> = symbols
+        .iter()
+        .map(|row| row.split_whitespace().nth(3).unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["_start", "sum", "helper", "numbers", "flag", "buffer"]
+    );
+
+    // .size wins over the distance to the next symbol
+    let start = row(&symbols, "main.s:5");
+    assert_eq!(start[1..4], ["8", "global", "_start"]);
+    // .Lloop is not listed, so it does not cut sum short
+    let sum = row(&symbols, "main.s:12");
+    assert_eq!(sum[1..4], ["32", "local", "sum"]);
+    let numbers = row(&symbols, "main.s:22");
+    assert_eq!(numbers[1..4], ["12", "local", "numbers"]);
+    // The last symbol in a segment runs to its end
+    let buffer = row(&symbols, "main.s:24");
+    assert_eq!(buffer[1], "40");
+    assert_eq!(row(&symbols, "helper.s:4")[1..3], ["4", "global"]);
+
+    let constants = table(&map, "Constants:");
+    assert_eq!(row(&constants, "main.s:2")[..3], ["3", "local", "COUNT"]);
+}
+
+#[test]
+fn test_map_keeps_local_labels_when_asked() {
+    let map = map(&[("main.s", MAIN)], false, true);
+    let symbols = table(&map, "Symbols:");
+    assert_eq!(row(&symbols, "main.s:13")[3], ".Lloop");
+    assert_eq!(row(&symbols, "main.s:12")[1], "4");
+}
+
+#[test]
+fn test_map_reports_compression_savings() {
+    let uncompressed = map(&[("main.s", MAIN)], false, false);
+    assert!(
+        uncompressed
+            .contains("0 of 14 instructions compressed, saving 0 bytes"),
+        "{}",
+        uncompressed
+    );
+
+    let compressed = map(&[("main.s", MAIN)], true, false);
+    let summary = compressed
+        .lines()
+        .find(|line| line.contains("instructions compressed"))
+        .unwrap();
+    let words: Vec<_> = summary.split_whitespace().collect();
+    let count: u32 = words[0].parse().unwrap();
+    assert!(count > 0);
+    assert_eq!(words[6], (count * 2).to_string());
+}
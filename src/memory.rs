@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::error::{Result, RiscletError};
 
 const STACK_SIZE: u32 = 8192;
@@ -11,6 +13,18 @@ pub struct Segment {
     executable: bool,
 }
 
+// The contents are left out; they are too big to print
+impl fmt::Debug for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Segment")
+            .field("start", &format_args!("{:#x}", self.start))
+            .field("end", &format_args!("{:#x}", self.end))
+            .field("writeable", &self.writeable)
+            .field("executable", &self.executable)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Segment {
     pub fn new(
         start: u32,
@@ -19,7 +33,7 @@ impl Segment {
         executable: bool,
         init: Vec<u8>,
     ) -> Self {
-        assert!(start > 0 && end > start);
+        assert!(end > start);
         assert!(init.len() <= (end - start) as usize);
         Self { start, end, mem: Vec::new(), init, writeable, executable }
     }
@@ -28,12 +42,21 @@ impl Segment {
         addr >= self.start && addr.saturating_add(size) <= self.end
     }
 
-    pub fn is_executable(&self) -> bool {
-        self.executable
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// The bytes the segment starts out with; the rest of it is zeros
+    pub fn init(&self) -> &[u8] {
+        &self.init
     }
 
-    pub fn is_writeable(&self) -> bool {
-        self.writeable
+    pub fn is_executable(&self) -> bool {
+        self.executable
     }
 
     pub fn reset(&mut self) {
@@ -74,7 +97,7 @@ impl MemoryLayout {
                     .end
                     .saturating_add(STACK_SIZE * 2)
                     .saturating_sub(1))
-                    & (STACK_SIZE - 1);
+                    & !(STACK_SIZE - 1);
             }
         }
 
@@ -127,6 +150,42 @@ impl MemoryManager {
         Self { segments, layout }
     }
 
+    /// Move the stack so it ends at `stack_end`, replacing the default
+    /// placement. The stack must not overlap any loaded segment.
+    pub fn set_stack(
+        &mut self,
+        stack_end: u32,
+        size: Option<u32>,
+    ) -> Result<()> {
+        let size = size.unwrap_or(STACK_SIZE);
+        let stack_start = stack_end.checked_sub(size).filter(|_| size > 0);
+        let Some(stack_start) = stack_start else {
+            return Err(RiscletError::memory_access_error(format!(
+                "a {} byte stack does not fit below 0x{:x}",
+                size, stack_end
+            )));
+        };
+
+        // The stack segment is always the last one
+        let loaded = self.segments.len() - 1;
+        for segment in &self.segments[..loaded] {
+            if segment.start < stack_end && stack_start < segment.end {
+                return Err(RiscletError::memory_access_error(format!(
+                    "stack (0x{:x}-0x{:x}) overlaps loaded memory at 0x{:x}-0x{:x}",
+                    stack_start,
+                    stack_end - 1,
+                    segment.start,
+                    segment.end - 1
+                )));
+            }
+        }
+        self.segments[loaded] =
+            Segment::new(stack_start, stack_end, true, false, Vec::new());
+        self.layout.stack_start = stack_start;
+        self.layout.stack_end = stack_end;
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         for segment in &mut self.segments {
             segment.reset();
@@ -0,0 +1,459 @@
+// memory_map.rs
+//
+// Memory regions and section placement from a `--layout` file
+//
+// By default the text segment starts at the `-t` address and data follows on
+// the next page. A layout file instead names memory regions and says which
+// region each section runs from (its VMA) and, for `.data`, which region its
+// initial contents are stored in (its LMA):
+//
+//     [regions.flash]
+//     origin = 0x00000000
+//     length = 0x10000
+//
+//     [regions.ram]
+//     origin = 0x80000000
+//     length = 0x4000
+//
+//     [sections.text]
+//     region = "flash"
+//
+//     [sections.data]
+//     region = "ram"
+//     load_region = "flash"
+//
+//     [sections.bss]
+//     region = "ram"
+//
+//     [stack]
+//     region = "ram"
+//     size = 0x1000
+//
+// The file format is the small subset of TOML shown above: table headers,
+// `key = value` pairs with integer or string values, and `#` comments.
+
+use std::collections::HashMap;
+
+/// A named range of memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub origin: u32,
+    pub length: u32,
+}
+
+impl Region {
+    /// One past the last address in the region (as u64, since a region may
+    /// end at the top of the address space)
+    pub fn end(&self) -> u64 {
+        self.origin as u64 + self.length as u64
+    }
+}
+
+/// Where a section runs from and where its initial contents are stored
+/// (indexes into `MemoryMap::regions`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionRule {
+    pub region: usize,
+    pub load_region: usize,
+}
+
+/// Where the simulator puts the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackRule {
+    pub region: usize,
+    pub size: Option<u32>,
+}
+
+/// Memory regions and the rules for placing each section in them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    /// The layout file the map was read from
+    pub file_name: String,
+    pub regions: Vec<Region>,
+    pub text: SectionRule,
+    pub data: SectionRule,
+    pub bss: SectionRule,
+    pub stack: StackRule,
+}
+
+/// Concrete segment addresses chosen by `MemoryMap::place`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentAddresses {
+    pub text_start: u32,
+    pub data_start: u32,
+    pub data_load_start: u32,
+    pub bss_start: u32,
+}
+
+const PAGE_SIZE: u32 = 0x1000;
+
+impl MemoryMap {
+    /// Read and parse a layout file
+    pub fn load(file_name: &str) -> Result<MemoryMap, String> {
+        let text = std::fs::read_to_string(file_name).map_err(|e| {
+            format!("Error: could not read layout file '{}': {}", file_name, e)
+        })?;
+        MemoryMap::parse(file_name, &text)
+    }
+
+    /// Parse the contents of a layout file
+    pub fn parse(file_name: &str, text: &str) -> Result<MemoryMap, String> {
+        let tables = parse_tables(file_name, text)?;
+        let error = |message: String| format!("{}: {}", file_name, message);
+
+        // Regions, in the order they were declared
+        let mut regions = Vec::new();
+        for (path, entries) in &tables {
+            let Some(name) = path.strip_prefix("regions.") else {
+                continue;
+            };
+            let origin = entries.integer(path, "origin").map_err(error)?;
+            let length = entries.integer(path, "length").map_err(error)?;
+            let region = Region { name: name.to_string(), origin, length };
+            if region.end() > 1 << 32 {
+                return Err(error(format!(
+                    "region '{}' extends past the end of memory",
+                    name
+                )));
+            }
+            regions.push(region);
+        }
+        for (i, a) in regions.iter().enumerate() {
+            for b in &regions[i + 1..] {
+                if (a.origin as u64) < b.end() && (b.origin as u64) < a.end() {
+                    return Err(error(format!(
+                        "regions '{}' and '{}' overlap",
+                        a.name, b.name
+                    )));
+                }
+            }
+        }
+
+        let find_region = |name: &str| {
+            regions
+                .iter()
+                .position(|r| r.name == name)
+                .ok_or_else(|| error(format!("unknown region '{}'", name)))
+        };
+        let section = |name: &str| -> Result<SectionRule, String> {
+            let path = format!("sections.{}", name);
+            let entries = tables
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, entries)| entries)
+                .ok_or_else(|| error(format!("missing [{}] table", path)))?;
+            let region =
+                find_region(&entries.string(&path, "region").map_err(error)?)?;
+            let load_region = match entries.get("load_region") {
+                Some(_) => find_region(
+                    &entries.string(&path, "load_region").map_err(error)?,
+                )?,
+                None => region,
+            };
+            Ok(SectionRule { region, load_region })
+        };
+        let text = section("text")?;
+        let data = section("data")?;
+        let bss = section("bss")?;
+        if bss.load_region != bss.region {
+            return Err(error(
+                "[sections.bss] cannot have a load_region".to_string(),
+            ));
+        }
+
+        let stack = match tables.iter().find(|(path, _)| path == "stack") {
+            Some((path, entries)) => StackRule {
+                region: match entries.get("region") {
+                    Some(_) => find_region(
+                        &entries.string(path, "region").map_err(error)?,
+                    )?,
+                    None => bss.region,
+                },
+                size: match entries.get("size") {
+                    Some(_) => {
+                        Some(entries.integer(path, "size").map_err(error)?)
+                    }
+                    None => None,
+                },
+            },
+            None => StackRule { region: bss.region, size: None },
+        };
+
+        // Reject tables and keys we don't understand, to catch typos
+        for (path, entries) in &tables {
+            let known: &[&str] = if path.starts_with("regions.") {
+                &["origin", "length"]
+            } else if path.starts_with("sections.") {
+                if !["sections.text", "sections.data", "sections.bss"]
+                    .contains(&path.as_str())
+                {
+                    return Err(error(format!(
+                        "unknown section [{}] (expected text, data or bss)",
+                        path
+                    )));
+                }
+                &["region", "load_region"]
+            } else if path == "stack" {
+                &["region", "size"]
+            } else {
+                return Err(error(format!("unknown table [{}]", path)));
+            };
+            for (key, line) in &entries.keys {
+                if !known.contains(&key.as_str()) {
+                    return Err(format!(
+                        "{}:{}: unknown key '{}' in [{}]",
+                        file_name, line, key, path
+                    ));
+                }
+            }
+        }
+
+        Ok(MemoryMap {
+            file_name: file_name.to_string(),
+            regions,
+            text,
+            data,
+            bss,
+            stack,
+        })
+    }
+
+    /// Choose segment addresses for the given segment sizes
+    ///
+    /// Sections that share a region are packed the way `-t` placement packs
+    /// them: data starts on the next page after text, and bss immediately
+    /// follows data. A `.data` load image stored in another region goes after
+    /// whatever already runs from that region, on a word boundary.
+    pub fn place(
+        &self,
+        text_size: u32,
+        data_size: u32,
+        bss_size: u32,
+    ) -> SegmentAddresses {
+        // The next free address in each region, once something is in it
+        let mut next: Vec<Option<u32>> = vec![None; self.regions.len()];
+        let mut start_in = |region: usize, align: u32, size: u32| {
+            let start = match next[region] {
+                Some(address) => address.wrapping_add(align - 1) & !(align - 1),
+                None => self.regions[region].origin,
+            };
+            next[region] = Some(start.wrapping_add(size));
+            start
+        };
+
+        let text_start = start_in(self.text.region, 1, text_size);
+        let data_start = start_in(self.data.region, PAGE_SIZE, data_size);
+        let bss_align =
+            if self.bss.region == self.data.region { 1 } else { PAGE_SIZE };
+        let bss_start = start_in(self.bss.region, bss_align, bss_size);
+        let data_load_start = if self.data.load_region == self.data.region {
+            data_start
+        } else {
+            start_in(self.data.load_region, 4, data_size)
+        };
+
+        SegmentAddresses { text_start, data_start, data_load_start, bss_start }
+    }
+
+    /// The address just past the top of the stack: the end of the stack
+    /// region, kept 16-byte aligned and below the top of the address space
+    pub fn stack_end(&self) -> u32 {
+        let end = self.regions[self.stack.region].end().min(u32::MAX as u64);
+        end as u32 & !0xf
+    }
+
+    /// Check that every section (and the `.data` load image) fits in its
+    /// region, and that nothing overlaps
+    pub fn check(
+        &self,
+        addresses: &SegmentAddresses,
+        text_size: u32,
+        data_size: u32,
+        bss_size: u32,
+    ) -> Result<(), String> {
+        let mut placed = vec![
+            (".text", addresses.text_start, text_size, self.text.region),
+            (".data", addresses.data_start, data_size, self.data.region),
+            (".bss", addresses.bss_start, bss_size, self.bss.region),
+        ];
+        if self.data.load_region != self.data.region {
+            placed.push((
+                ".data load image",
+                addresses.data_load_start,
+                data_size,
+                self.data.load_region,
+            ));
+        }
+        placed.retain(|&(_, _, size, _)| size > 0);
+
+        for &(name, start, size, region) in &placed {
+            let region = &self.regions[region];
+            let end = start as u64 + size as u64;
+            if end > region.end() {
+                return Err(format!(
+                    "{} ({} bytes at 0x{:x}) overflows region '{}' \
+                     (0x{:x}-0x{:x}) by {} bytes",
+                    name,
+                    size,
+                    start,
+                    region.name,
+                    region.origin,
+                    region.end() - 1,
+                    end - region.end()
+                ));
+            }
+        }
+        for (i, &(a, a_start, a_size, _)) in placed.iter().enumerate() {
+            for &(b, b_start, b_size, _) in &placed[i + 1..] {
+                if (a_start as u64) < b_start as u64 + b_size as u64
+                    && (b_start as u64) < a_start as u64 + a_size as u64
+                {
+                    return Err(format!("{} and {} overlap", a, b));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// TOML subset parser
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(u32),
+    String(String),
+}
+
+/// The key/value pairs of one table, with the line each key was set on
+#[derive(Default)]
+struct Entries {
+    values: HashMap<String, Value>,
+    keys: Vec<(String, usize)>,
+}
+
+impl Entries {
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    fn integer(&self, table: &str, key: &str) -> Result<u32, String> {
+        match self.values.get(key) {
+            Some(Value::Integer(value)) => Ok(*value),
+            Some(Value::String(_)) => {
+                Err(format!("'{}' in [{}] must be an integer", key, table))
+            }
+            None => Err(format!("missing '{}' in [{}]", key, table)),
+        }
+    }
+
+    fn string(&self, table: &str, key: &str) -> Result<String, String> {
+        match self.values.get(key) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(Value::Integer(_)) => {
+                Err(format!("'{}' in [{}] must be a string", key, table))
+            }
+            None => Err(format!("missing '{}' in [{}]", key, table)),
+        }
+    }
+}
+
+/// Split a layout file into its tables, in file order
+fn parse_tables(
+    file_name: &str,
+    text: &str,
+) -> Result<Vec<(String, Entries)>, String> {
+    let mut tables: Vec<(String, Entries)> = Vec::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: &str| {
+            format!("{}:{}: {}", file_name, line_number, message)
+        };
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let path = header
+                .strip_suffix(']')
+                .ok_or_else(|| error("expected ']' after table name"))?
+                .trim();
+            let valid = !path.is_empty()
+                && path.split('.').all(|part| {
+                    !part.is_empty()
+                        && part.chars().all(|c| {
+                            c.is_ascii_alphanumeric() || c == '_' || c == '-'
+                        })
+                });
+            if !valid {
+                return Err(error(&format!("invalid table name '{}'", path)));
+            }
+            if tables.iter().any(|(p, _)| p == path) {
+                return Err(error(&format!("duplicate table [{}]", path)));
+            }
+            tables.push((path.to_string(), Entries::default()));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected 'key = value' or '[table]'"))?;
+        let key = key.trim();
+        let (_, entries) = tables
+            .last_mut()
+            .ok_or_else(|| error("key outside of any [table]"))?;
+        if entries.values.contains_key(key) {
+            return Err(error(&format!("duplicate key '{}'", key)));
+        }
+        let value = parse_value(value.trim()).map_err(|e| error(&e))?;
+        entries.values.insert(key.to_string(), value);
+        entries.keys.push((key.to_string(), line_number));
+    }
+
+    Ok(tables)
+}
+
+/// Remove a trailing `#` comment, ignoring `#` inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let value = rest
+            .strip_suffix('"')
+            .filter(|s| !s.contains('"'))
+            .ok_or_else(|| format!("invalid string {}", text))?;
+        return Ok(Value::String(value.to_string()));
+    }
+
+    let digits = text.replace('_', "");
+    let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        u64::from_str_radix(binary, 2)
+    } else {
+        digits.parse::<u64>()
+    };
+    match parsed {
+        Ok(value) if value <= u32::MAX as u64 => {
+            Ok(Value::Integer(value as u32))
+        }
+        Ok(_) => Err(format!("{} does not fit in 32 bits", text)),
+        Err(_) => {
+            Err(format!("expected an integer or a string, found {}", text))
+        }
+    }
+}
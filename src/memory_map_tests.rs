@@ -0,0 +1,235 @@
+// Tests for `--layout` memory maps: parsing, segment placement, the program
+// headers written for them, and running the result in the simulator
+
+use crate::assembler::assemble;
+use crate::config::Config;
+use crate::elf::{ElfHeader, ElfProgramHeader, PT_LOAD};
+use crate::elf_loader::{ElfInput, load_elf};
+use crate::execution::Machine;
+use crate::memory_map::{MemoryMap, SegmentAddresses};
+use crate::test_helpers::run_to_ecall;
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+const FLASH_AND_RAM: &str = r#"
+# Code and the .data image in flash, everything else in RAM
+[regions.flash]
+origin = 0x00000000
+length = 0x10000
+
+[regions.ram]
+origin = 0x8000_0000
+length = 0x4000
+
+[sections.text]
+region = "flash"
+
+[sections.data]
+region = "ram"
+load_region = "flash"
+
+[sections.bss]
+region = "ram"
+
+[stack]
+region = "ram"
+size = 0x800
+"#;
+
+fn make_config(memory_map: &str) -> Config {
+    Config {
+        max_steps: 1_000_000,
+        memory_map: Some(parse(memory_map)),
+        ..Config::assemble_default()
+    }
+}
+
+fn parse(text: &str) -> MemoryMap {
+    MemoryMap::parse("layout.toml", text).unwrap()
+}
+
+fn parse_error(text: &str) -> String {
+    MemoryMap::parse("layout.toml", text).unwrap_err()
+}
+
+fn assemble_with_layout(
+    memory_map: &str,
+    code: &str,
+) -> Result<Vec<u8>, String> {
+    assemble(
+        &mut make_config(memory_map),
+        vec![("prog.s".to_string(), code.to_string())],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// All PT_LOAD program headers of an executable
+fn load_headers(elf: &[u8]) -> Vec<ElfProgramHeader> {
+    let header = ElfHeader::decode(elf).unwrap();
+    (0..header.e_phnum as usize)
+        .map(|i| {
+            let offset =
+                header.e_phoff as usize + i * header.e_phentsize as usize;
+            ElfProgramHeader::decode(&elf[offset..]).unwrap()
+        })
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .collect()
+}
+
+const PROGRAM: &str = r#"
+.text
+.globl _start
+_start:
+    la t0, counter
+    lw a0, 0(t0)
+    la t1, scratch
+    sw a0, 0(t1)
+    addi sp, sp, -16
+    sw a0, 12(sp)
+    lw a1, 12(sp)
+    add a0, a0, a1
+    li a7, 93
+    ecall
+.data
+counter: .4byte 21
+.bss
+scratch: .space 64
+"#;
+
+// ============================================================================
+// PARSING
+// ============================================================================
+
+#[test]
+fn test_parse_regions_and_sections() {
+    let map = parse(FLASH_AND_RAM);
+    assert_eq!(map.regions.len(), 2);
+    assert_eq!(map.regions[0].name, "flash");
+    assert_eq!(map.regions[1].origin, 0x8000_0000);
+    assert_eq!(map.regions[1].length, 0x4000);
+    assert_eq!((map.text.region, map.text.load_region), (0, 0));
+    assert_eq!((map.data.region, map.data.load_region), (1, 0));
+    assert_eq!((map.bss.region, map.bss.load_region), (1, 1));
+    assert_eq!(map.stack.region, 1);
+    assert_eq!(map.stack.size, Some(0x800));
+    assert_eq!(map.stack_end(), 0x8000_4000);
+}
+
+#[test]
+fn test_parse_errors() {
+    let unknown_region = FLASH_AND_RAM
+        .replace("load_region = \"flash\"", "load_region = \"rom\"");
+    assert!(parse_error(&unknown_region).contains("unknown region 'rom'"));
+
+    let typo = FLASH_AND_RAM.replace("length = 0x4000", "lenght = 0x4000");
+    assert!(parse_error(&typo).contains("missing 'length' in [regions.ram]"));
+
+    let extra =
+        format!("{}\n[sections.rodata]\nregion = \"flash\"\n", FLASH_AND_RAM);
+    assert!(parse_error(&extra).contains("unknown section [sections.rodata]"));
+
+    let overlap =
+        FLASH_AND_RAM.replace("origin = 0x8000_0000", "origin = 0x8000");
+    assert!(parse_error(&overlap).contains("overlap"));
+
+    let missing = FLASH_AND_RAM.replace("[sections.bss]", "[stack2]");
+    assert!(parse_error(&missing).contains("layout.toml:"));
+
+    assert!(parse_error("origin = 1").contains("layout.toml:1:"));
+}
+
+// ============================================================================
+// PLACEMENT
+// ============================================================================
+
+#[test]
+fn test_place_separate_regions() {
+    let map = parse(FLASH_AND_RAM);
+    let addresses = map.place(0x102, 0x10, 0x40);
+    assert_eq!(
+        addresses,
+        SegmentAddresses {
+            text_start: 0,
+            data_start: 0x8000_0000,
+            data_load_start: 0x104,
+            bss_start: 0x8000_0010,
+        }
+    );
+    assert!(map.check(&addresses, 0x102, 0x10, 0x40).is_ok());
+}
+
+#[test]
+fn test_check_region_overflow() {
+    let map = parse(FLASH_AND_RAM);
+    let addresses = map.place(0x100, 0x10, 0x8000);
+    let error = map.check(&addresses, 0x100, 0x10, 0x8000).unwrap_err();
+    assert!(error.contains(".bss"), "{}", error);
+    assert!(error.contains("overflows region 'ram'"), "{}", error);
+}
+
+// ============================================================================
+// EXECUTABLES
+// ============================================================================
+
+#[test]
+fn test_program_headers_separate_lma_and_vma() {
+    let elf = assemble_with_layout(FLASH_AND_RAM, PROGRAM).unwrap();
+    let headers = load_headers(&elf);
+    assert_eq!(headers.len(), 2);
+
+    let text = &headers[0];
+    assert_eq!((text.p_vaddr, text.p_paddr), (0, 0));
+    assert_eq!(text.p_offset % 0x1000, 0);
+
+    let data = &headers[1];
+    assert_eq!(data.p_vaddr, 0x8000_0000);
+    assert_eq!(data.p_paddr, text.p_filesz.next_multiple_of(4));
+    assert_eq!(data.p_filesz, 4);
+    assert_eq!(data.p_memsz, 4 + 64);
+    assert_eq!(data.p_offset % 0x1000, 0);
+
+    let header = ElfHeader::decode(&elf).unwrap();
+    assert_eq!(header.e_entry, 0);
+}
+
+#[test]
+fn test_bss_in_its_own_region() {
+    let layout = FLASH_AND_RAM.replace(
+        "[sections.bss]\nregion = \"ram\"",
+        "[sections.bss]\nregion = \"sram\"",
+    ) + "\n[regions.sram]\norigin = 0x2000_0000\nlength = 0x1000\n";
+    let elf = assemble_with_layout(&layout, PROGRAM).unwrap();
+    let headers = load_headers(&elf);
+    assert_eq!(headers.len(), 3);
+    let bss = &headers[2];
+    assert_eq!(bss.p_vaddr, 0x2000_0000);
+    assert_eq!((bss.p_filesz, bss.p_memsz), (0, 64));
+}
+
+#[test]
+fn test_overflow_is_an_error() {
+    let layout = FLASH_AND_RAM.replace("length = 0x10000", "length = 0x10");
+    let error = assemble_with_layout(&layout, PROGRAM).unwrap_err();
+    assert!(error.contains("overflows region 'flash'"), "{}", error);
+}
+
+#[test]
+fn test_run_with_layout() {
+    let elf = assemble_with_layout(FLASH_AND_RAM, PROGRAM).unwrap();
+    let map = parse(FLASH_AND_RAM);
+    let mut machine = Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+    machine.set_stack(map.stack_end(), map.stack.size).unwrap();
+    assert_eq!(run_to_ecall(&mut machine), 42);
+
+    let machine = {
+        let mut machine =
+            load_elf(ElfInput::Bytes(&elf), &mut Vec::new()).unwrap();
+        machine.set_stack(map.stack_end(), map.stack.size).unwrap();
+        machine
+    };
+    assert_eq!(machine.stack_end(), 0x8000_4000);
+    assert_eq!(machine.stack_start(), 0x8000_3800);
+    assert_eq!(machine.get_reg(2) as u32, 0x8000_4000);
+}
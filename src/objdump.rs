@@ -0,0 +1,533 @@
+// objdump.rs
+//
+// Disassembly in the layout of GNU objdump -d (disassemble --format=objdump),
+// so it can be diffed against objdump's output
+
+use std::fmt::Write;
+
+use crate::disassembly::ordering;
+use crate::elf::{
+    SHF_ALLOC, SHF_EXECINSTR, SHN_COMMON, SHN_UNDEF, STB_GLOBAL, STB_WEAK,
+    STT_FILE, STT_FUNC, STT_SECTION,
+};
+use crate::elf_loader::read_sections_and_symbols;
+use crate::error::Result;
+use crate::execution::Machine;
+use crate::riscv::{Field, GP, Op, R, RA, SP, ZERO};
+
+/// The thread pointer, which objdump treats as a base address of zero
+const TP: usize = 4;
+
+/// The encoding of `unimp` (`csrrw zero, cycle, zero`)
+const UNIMP: i32 = 0xc0001073_u32 as i32;
+
+/// objdump shows runs of at least this many zero bytes as `...`, or this
+/// many at the end of a symbol
+const SKIP_ZEROES: u32 = 8;
+const SKIP_ZEROES_AT_END: u32 = 3;
+
+/// An allocated section
+struct Section {
+    name: String,
+    start: u32,
+    end: u32,
+    executable: bool,
+}
+
+/// A symbol objdump can name an address after
+struct Symbol {
+    address: u32,
+    name: String,
+    /// Index into the sections, or None for an absolute symbol
+    section: Option<usize>,
+}
+
+/// Disassemble the executable sections of a machine as `objdump -d` does,
+/// with objdump's aliases, or none of them with `no_aliases` (as with
+/// `objdump -d -M no-aliases`). Sections and symbols come from the ELF
+/// file if there is one, and from the machine for a memory image.
+pub fn objdump(
+    m: &Machine,
+    file_name: &str,
+    elf: Option<&[u8]>,
+    no_aliases: bool,
+) -> Result<String> {
+    let (sections, symbols) = match elf {
+        Some(raw) => elf_sections_and_symbols(raw)?,
+        None => machine_sections_and_symbols(m),
+    };
+    let mut out = String::new();
+    let format = if elf.is_some() { "elf32-littleriscv" } else { "binary" };
+    writeln!(out, "\n{}:     file format {}\n", file_name, format).unwrap();
+
+    let mut printer = Printer {
+        symbols: &symbols,
+        section: None,
+        gp: m.global_pointer,
+        hi: [None; 32],
+        no_aliases,
+    };
+    for (index, section) in sections.iter().enumerate() {
+        if !section.executable {
+            continue;
+        }
+        writeln!(out, "\nDisassembly of section {}:", section.name).unwrap();
+        printer.section = Some(index);
+        printer.hi = [None; 32];
+
+        // objdump starts a block at the section and at each of its symbols
+        let in_section: Vec<&Symbol> = symbols
+            .iter()
+            .filter(|symbol| {
+                symbol.section == Some(index)
+                    && (section.start..section.end).contains(&symbol.address)
+            })
+            .collect();
+        let mut starts: Vec<u32> =
+            in_section.iter().map(|symbol| symbol.address).collect();
+        starts.insert(0, section.start);
+        starts.dedup();
+
+        let mut pc = section.start;
+        for (i, &start) in starts.iter().enumerate() {
+            let stop = starts.get(i + 1).copied().unwrap_or(section.end);
+            if pc >= stop {
+                continue;
+            }
+            let heading = match in_section.iter().find(|s| s.address == start) {
+                Some(symbol) => symbol.name.clone(),
+                None => label(&section.name, section.start, start),
+            };
+            writeln!(out, "\n{:08x} <{}>:", start, heading).unwrap();
+            pc = pc.max(start);
+            while pc < stop {
+                let zeros =
+                    (pc..stop).take_while(|&a| byte(m, a) == 0).count() as u32;
+                if zeros >= SKIP_ZEROES
+                    || (pc + zeros == stop && zeros >= SKIP_ZEROES_AT_END)
+                {
+                    writeln!(out, "\t...").unwrap();
+                    pc = if pc + zeros == stop {
+                        stop
+                    } else {
+                        pc + (zeros & !3)
+                    };
+                    continue;
+                }
+                let Ok((inst, length)) = m.load_instruction(pc) else {
+                    break;
+                };
+                let raw = if length == 2 {
+                    format!("{:04x}                ", inst & 0xffff)
+                } else {
+                    format!("{:08x}          ", inst)
+                };
+                let text =
+                    printer.instruction(&Op::new(inst), inst, length, pc);
+                writeln!(out, "{:>8x}:\t{}\t{}", pc, raw, text).unwrap();
+                pc += length;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The allocated sections of an ELF file, and the symbols objdump uses in
+/// the order it prefers them: by address, then functions, global, weak
+/// and local symbols, then by name
+fn elf_sections_and_symbols(raw: &[u8]) -> Result<(Vec<Section>, Vec<Symbol>)> {
+    let (named_sections, named_symbols) = read_sections_and_symbols(raw)?;
+    let mut indices = Vec::new();
+    let mut sections = Vec::new();
+    for (index, section) in named_sections.into_iter().enumerate() {
+        let header = section.header;
+        if header.sh_flags & SHF_ALLOC == 0 || header.sh_size == 0 {
+            continue;
+        }
+        indices.push(index);
+        sections.push(Section {
+            name: section.name,
+            start: header.sh_addr,
+            end: header.sh_addr + header.sh_size,
+            executable: header.sh_flags & SHF_EXECINSTR != 0,
+        });
+    }
+
+    let mut symbols: Vec<((u32, bool, u8), Symbol)> = named_symbols
+        .into_iter()
+        .filter(|named| {
+            let symbol_type = named.symbol.st_info & 0xf;
+            !named.name.is_empty()
+                && !is_mapping_symbol(&named.name)
+                && symbol_type != STT_FILE
+                && symbol_type != STT_SECTION
+                && named.symbol.st_shndx != SHN_UNDEF
+                && named.symbol.st_shndx != SHN_COMMON
+        })
+        .map(|named| {
+            let symbol = named.symbol;
+            let rank = match symbol.st_info >> 4 {
+                STB_GLOBAL => 0,
+                STB_WEAK => 1,
+                _ => 2,
+            };
+            let function = symbol.st_info & 0xf == STT_FUNC;
+            let section =
+                indices.iter().position(|&i| i == symbol.st_shndx as usize);
+            let key = (symbol.st_value, !function, rank);
+            (
+                key,
+                Symbol { address: symbol.st_value, name: named.name, section },
+            )
+        })
+        .collect();
+    symbols
+        .sort_by(|(a, x), (b, y)| a.cmp(b).then_with(|| x.name.cmp(&y.name)));
+    Ok((sections, symbols.into_iter().map(|(_, symbol)| symbol).collect()))
+}
+
+/// Sections for the machine's segments, and its symbols, for a memory
+/// image that has no section or symbol table of its own
+fn machine_sections_and_symbols(m: &Machine) -> (Vec<Section>, Vec<Symbol>) {
+    let mut sections: Vec<Section> = m
+        .loaded_segments()
+        .iter()
+        .map(|segment| Section {
+            name: if segment.is_executable() {
+                ".text".to_string()
+            } else {
+                ".data".to_string()
+            },
+            start: segment.start(),
+            end: segment.end(),
+            executable: segment.is_executable(),
+        })
+        .collect();
+    sections.sort_by_key(|section| section.start);
+    let mut symbols: Vec<Symbol> = m
+        .address_symbols
+        .iter()
+        .map(|(&address, name)| Symbol {
+            address,
+            name: name.clone(),
+            section: sections
+                .iter()
+                .position(|s| (s.start..s.end).contains(&address)),
+        })
+        .collect();
+    symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+    (sections, symbols)
+}
+
+/// The `$x` and `$d` symbols that mark code and data, which objdump never
+/// names an address after
+fn is_mapping_symbol(name: &str) -> bool {
+    name == "$x" || name == "$d" || name.starts_with("$xrv")
+}
+
+/// A name for `address` relative to something at `base`
+fn label(name: &str, base: u32, address: u32) -> String {
+    match address.cmp(&base) {
+        std::cmp::Ordering::Equal => name.to_string(),
+        std::cmp::Ordering::Greater => {
+            format!("{}+0x{:x}", name, address - base)
+        }
+        std::cmp::Ordering::Less => format!("{}-0x{:x}", name, base - address),
+    }
+}
+
+/// A byte of the loaded program, or zero outside it
+fn byte(m: &Machine, address: u32) -> u8 {
+    m.loaded_segments()
+        .iter()
+        .find(|segment| (segment.start()..segment.end()).contains(&address))
+        .and_then(|segment| {
+            segment.init().get((address - segment.start()) as usize).copied()
+        })
+        .unwrap_or(0)
+}
+
+/// What objdump needs to know to print one instruction after another
+struct Printer<'a> {
+    /// Symbols in the order objdump prefers them
+    symbols: &'a [Symbol],
+    /// The section being disassembled
+    section: Option<usize>,
+    gp: u32,
+    /// The address each register holds from a `lui` or `auipc`, until an
+    /// instruction uses it as a base
+    hi: [Option<u32>; 32],
+    no_aliases: bool,
+}
+
+impl Printer<'_> {
+    /// The mnemonic and operands of an instruction, with the address it
+    /// refers to as a comment where objdump works one out
+    fn instruction(
+        &mut self,
+        op: &Op,
+        inst: i32,
+        length: u32,
+        pc: u32,
+    ) -> String {
+        if inst == UNIMP || (length == 2 && inst & 0xffff == 0) {
+            return "unimp".to_string();
+        }
+        if let Op::Unimplemented { .. } = op {
+            return match length {
+                2 => format!(".2byte\t0x{:x}", inst & 0xffff),
+                _ => format!(".4byte\t0x{:x}", inst),
+            };
+        }
+
+        let fields = match (length, self.no_aliases) {
+            (2, true) => compressed_fields(op, inst),
+            (2, false) => compressed_alias_fields(op, inst),
+            (_, true) => op.to_fields(),
+            (_, false) => alias_fields(op),
+        };
+        let comment = self.referenced_address(op, &fields, length, pc);
+
+        let upper = matches!(op, Op::Lui { .. } | Op::Auipc { .. });
+        let shift =
+            matches!(op, Op::Slli { .. } | Op::Srli { .. } | Op::Srai { .. });
+        let ordering = ordering(op);
+        let operands: Vec<String> = fields[1..]
+            .iter()
+            .map(|field| match field {
+                Field::Reg(reg) => R[*reg].to_string(),
+                Field::Imm(imm) if upper => {
+                    format!("0x{:x}", (*imm as u32) >> 12)
+                }
+                Field::Imm(imm) if shift => format!("0x{:x}", imm),
+                Field::Indirect(_, reg) if ordering.is_some() => {
+                    format!("({})", R[*reg])
+                }
+                Field::Indirect(offset, reg) => {
+                    format!("{}({})", offset, R[*reg])
+                }
+                Field::PCRelAddr(offset) => {
+                    self.address(pc.wrapping_add(*offset as u32))
+                }
+                Field::FenceOrdering(pred, succ) => {
+                    format!("{},{}", pred, succ)
+                }
+                field => field.to_string(
+                    pc,
+                    self.gp,
+                    false,
+                    true,
+                    &Default::default(),
+                ),
+            })
+            .collect();
+
+        let Field::Opcode(mnemonic) = fields[0] else {
+            unreachable!("instruction fields start with the opcode");
+        };
+        let mut text = match ordering {
+            Some((true, true)) => format!("{}.aqrl", mnemonic),
+            Some((true, false)) => format!("{}.aq", mnemonic),
+            Some((false, true)) => format!("{}.rl", mnemonic),
+            _ => mnemonic.to_string(),
+        };
+        if !operands.is_empty() {
+            write!(text, "\t{}", operands.join(",")).unwrap();
+        }
+        if let Some(address) = comment {
+            write!(text, " # {}", self.address(address)).unwrap();
+        }
+        text
+    }
+
+    /// The address an `addi`, load, store or `jalr` works out from a
+    /// register set by `lui` or `auipc`, or from `gp`, `tp` or `zero`,
+    /// remembering the addresses that `lui` and `auipc` set
+    fn referenced_address(
+        &mut self,
+        op: &Op,
+        fields: &[Field],
+        length: u32,
+        pc: u32,
+    ) -> Option<u32> {
+        match *op {
+            Op::Lui { rd, imm } => self.hi[rd] = Some(imm as u32),
+            Op::Auipc { rd, imm } => {
+                self.hi[rd] = Some(pc.wrapping_add(imm as u32))
+            }
+            _ => {}
+        }
+        if length == 2 {
+            return None;
+        }
+        match *op {
+            Op::Addi { rs1, imm, .. }
+                if rs1 != ZERO
+                    && fields.iter().any(|f| matches!(f, Field::Imm(_))) =>
+            {
+                self.base_address(rs1, imm)
+            }
+            _ if ordering(op).is_some() => None,
+            _ => fields.iter().find_map(|field| match *field {
+                Field::Indirect(offset, reg) => self.base_address(reg, offset),
+                _ => None,
+            }),
+        }
+    }
+
+    fn base_address(&mut self, reg: usize, offset: i32) -> Option<u32> {
+        if let Some(hi) = self.hi[reg].take() {
+            Some(hi.wrapping_add(offset as u32))
+        } else if reg == GP && self.gp != 0 {
+            Some(self.gp.wrapping_add(offset as u32))
+        } else if reg == TP || reg == ZERO {
+            Some(offset as u32)
+        } else {
+            None
+        }
+    }
+
+    /// An address as objdump prints it: in hex, with the nearest symbol at
+    /// or before it (or the first symbol, if it comes before them all),
+    /// preferring symbols in the section being disassembled
+    fn address(&self, address: u32) -> String {
+        let Some(first) = self.symbols.first() else {
+            return format!("{:x}", address);
+        };
+        let index = self.symbols.partition_point(|s| s.address <= address);
+        let symbol = match index.checked_sub(1) {
+            Some(last) => {
+                let base = self.symbols[last].address;
+                let same = &self.symbols[self.symbols[..index]
+                    .partition_point(|s| s.address < base)
+                    ..index];
+                same.iter()
+                    .find(|s| s.section == self.section)
+                    .unwrap_or(&same[0])
+            }
+            None => first,
+        };
+        format!(
+            "{:x} <{}>",
+            address,
+            label(&symbol.name, symbol.address, address)
+        )
+    }
+}
+
+/// A base instruction under the alias objdump gives it
+fn alias_fields(op: &Op) -> Vec<Field> {
+    match *op {
+        // objdump has no `la` from `gp`, and only tests `rs2` for zero
+        Op::Addi { rs1: GP, imm, .. } if imm != 0 => op.to_fields(),
+        Op::Beq { rs1: ZERO, rs2, .. } | Op::Bne { rs1: ZERO, rs2, .. }
+            if rs2 != ZERO =>
+        {
+            op.to_fields()
+        }
+        Op::Jalr { rd, rs1, offset: 0 } if rd != ZERO && rd != RA => {
+            vec![Field::Opcode("jalr"), Field::Reg(rd), Field::Reg(rs1)]
+        }
+        _ => op.to_pseudo_fields(),
+    }
+}
+
+/// A compressed instruction under the alias objdump gives it, which is
+/// the base instruction's except for `c.mv`
+fn compressed_alias_fields(op: &Op, inst: i32) -> Vec<Field> {
+    match (inst & 3, (inst >> 13) & 7, op) {
+        (2, 4, &Op::Add { rd, rs1: ZERO, rs2 }) if rd != ZERO => {
+            vec![Field::Opcode("mv"), Field::Reg(rd), Field::Reg(rs2)]
+        }
+        _ => alias_fields(op),
+    }
+}
+
+/// A compressed instruction as the `c.` instruction it was, or as the base
+/// instruction for a hint
+fn compressed_fields(op: &Op, inst: i32) -> Vec<Field> {
+    let quadrant = inst & 3;
+    let funct3 = (inst >> 13) & 7;
+    let (mnemonic, operands) = match (quadrant, funct3, op) {
+        (0, 0, &Op::Addi { rd, imm, .. }) => (
+            "c.addi4spn",
+            vec![Field::Reg(rd), Field::Reg(SP), Field::Imm(imm)],
+        ),
+        (0, 2, &Op::Lw { rd, rs1, offset }) => {
+            ("c.lw", vec![Field::Reg(rd), Field::Indirect(offset, rs1)])
+        }
+        (0, 6, &Op::Sw { rs1, rs2, offset }) => {
+            ("c.sw", vec![Field::Reg(rs2), Field::Indirect(offset, rs1)])
+        }
+        (1, 0, &Op::Addi { rd: ZERO, imm: 0, .. }) => ("c.nop", vec![]),
+        (1, 0, &Op::Addi { rd, imm, .. }) if rd != ZERO && imm != 0 => {
+            ("c.addi", vec![Field::Reg(rd), Field::Imm(imm)])
+        }
+        (1, 1, &Op::Jal { offset, .. }) => {
+            ("c.jal", vec![Field::PCRelAddr(offset)])
+        }
+        (1, 2, &Op::Addi { rd, imm, .. }) if rd != ZERO => {
+            ("c.li", vec![Field::Reg(rd), Field::Imm(imm)])
+        }
+        (1, 3, &Op::Addi { imm, .. }) => {
+            ("c.addi16sp", vec![Field::Reg(SP), Field::Imm(imm)])
+        }
+        (1, 3, &Op::Lui { rd, imm }) if rd != ZERO => {
+            ("c.lui", vec![Field::Reg(rd), Field::Imm(imm)])
+        }
+        (1, 4, &Op::Srli { rd, shamt, .. }) => {
+            ("c.srli", vec![Field::Reg(rd), Field::Imm(shamt)])
+        }
+        (1, 4, &Op::Srai { rd, shamt, .. }) => {
+            ("c.srai", vec![Field::Reg(rd), Field::Imm(shamt)])
+        }
+        (1, 4, &Op::Andi { rd, imm, .. }) => {
+            ("c.andi", vec![Field::Reg(rd), Field::Imm(imm)])
+        }
+        (1, 4, &Op::Sub { rd, rs2, .. }) => {
+            ("c.sub", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (1, 4, &Op::Xor { rd, rs2, .. }) => {
+            ("c.xor", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (1, 4, &Op::Or { rd, rs2, .. }) => {
+            ("c.or", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (1, 4, &Op::And { rd, rs2, .. }) => {
+            ("c.and", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (1, 5, &Op::Jal { offset, .. }) => {
+            ("c.j", vec![Field::PCRelAddr(offset)])
+        }
+        (1, 6, &Op::Beq { rs1, offset, .. }) => {
+            ("c.beqz", vec![Field::Reg(rs1), Field::PCRelAddr(offset)])
+        }
+        (1, 7, &Op::Bne { rs1, offset, .. }) => {
+            ("c.bnez", vec![Field::Reg(rs1), Field::PCRelAddr(offset)])
+        }
+        (2, 0, &Op::Slli { rd, shamt, .. }) if rd != ZERO && shamt != 0 => {
+            ("c.slli", vec![Field::Reg(rd), Field::Imm(shamt)])
+        }
+        (2, 2, &Op::Lw { rd, offset, .. }) => {
+            ("c.lwsp", vec![Field::Reg(rd), Field::Indirect(offset, SP)])
+        }
+        (2, 4, &Op::Jalr { rd: ZERO, rs1, .. }) => {
+            ("c.jr", vec![Field::Reg(rs1)])
+        }
+        (2, 4, &Op::Jalr { rs1, .. }) => ("c.jalr", vec![Field::Reg(rs1)]),
+        (2, 4, &Op::Ebreak) => ("c.ebreak", vec![]),
+        (2, 4, &Op::Add { rd, rs1: ZERO, rs2 }) if rd != ZERO => {
+            ("c.mv", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (2, 4, &Op::Add { rd, rs2, .. }) if rd != ZERO => {
+            ("c.add", vec![Field::Reg(rd), Field::Reg(rs2)])
+        }
+        (2, 6, &Op::Sw { rs2, offset, .. }) => {
+            ("c.swsp", vec![Field::Reg(rs2), Field::Indirect(offset, SP)])
+        }
+        _ => return op.to_fields(),
+    };
+    let mut fields = vec![Field::Opcode(mnemonic)];
+    fields.extend(operands);
+    fields
+}
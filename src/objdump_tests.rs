@@ -0,0 +1,118 @@
+// objdump_tests.rs
+//
+// Tests for disassembly in objdump's layout (disassemble --format=objdump)
+
+use crate::assembler::assemble_collecting_warnings;
+use crate::config::{Config, parse_cli_args};
+use crate::execution::Machine;
+use crate::objdump::objdump;
+
+const PROGRAM: &str = "
+        .globl _start
+_start: la    a0, message
+        lw    a1, 4(a0)
+        c.li  a2, 5
+loop:   addi  a2, a2, -1
+        bnez  a2, loop
+        jal   done
+        lr.w.aq a3, (a0)
+        slli  a3, a3, 2
+        j     _start
+done:   li    a7, 93
+        ecall
+        .data
+message: .4byte 42, 43
+";
+
+fn assemble(source: &str) -> Vec<u8> {
+    let mut config = Config::assemble_default();
+    let files = vec![("prog.s".to_string(), source.to_string())];
+    assemble_collecting_warnings(&mut config, files, &mut Vec::new()).unwrap()
+}
+
+fn disassemble(source: &str, no_aliases: bool) -> String {
+    let executable = assemble(source);
+    let m = Machine::from_elf(&executable, &mut Vec::new()).unwrap();
+    objdump(&m, "a.out", Some(&executable), no_aliases).unwrap()
+}
+
+#[test]
+fn test_layout_matches_objdump() {
+    let expected = "
+a.out:     file format elf32-littleriscv
+
+
+Disassembly of section .text:
+
+00010094 <_start>:
+   10094:\t00001517          \tauipc\ta0,0x1
+   10098:\tf6c50513          \taddi\ta0,a0,-148 # 11000 <message>
+   1009c:\t00452583          \tlw\ta1,4(a0)
+   100a0:\t4615                \tli\ta2,5
+
+000100a2 <loop>:
+   100a2:\tfff60613          \taddi\ta2,a2,-1
+   100a6:\tfe061ee3          \tbnez\ta2,100a2 <loop>
+   100aa:\t010000ef          \tjal\t100ba <done>
+   100ae:\t140526af          \tlr.w.aq\ta3,(a0)
+   100b2:\t00269693          \tslli\ta3,a3,0x2
+   100b6:\tfdfff06f          \tj\t10094 <_start>
+
+000100ba <done>:
+   100ba:\t05d00893          \tli\ta7,93
+   100be:\t00000073          \tecall
+";
+    assert_eq!(disassemble(PROGRAM, false), expected);
+}
+
+#[test]
+fn test_no_aliases_with_verbose_instructions() {
+    let output = disassemble(PROGRAM, true);
+    assert!(output.contains("\tc.li\ta2,5\n"), "{}", output);
+    assert!(output.contains("\tbne\ta2,zero,100a2 <loop>\n"));
+    assert!(output.contains("\tjal\tra,100ba <done>\n"));
+    assert!(output.contains("\tjal\tzero,10094 <_start>\n"));
+    assert!(output.contains("\taddi\ta7,zero,93\n"));
+}
+
+#[test]
+fn test_addresses_outside_symbols_and_zero_runs() {
+    let program = "
+        .globl _start
+_start: lui   a0, 0x1
+        addi  a0, a0, 0x234
+        lw    a1, 8(zero)
+        .2byte 0
+        j     end
+        .space 16
+end:    ecall
+";
+    let output = disassemble(program, false);
+    // Below every symbol, objdump counts back from the first one
+    assert!(
+        output.contains("\taddi\ta0,a0,564 # 1234 <_start-0xee40>\n"),
+        "{}",
+        output
+    );
+    assert!(output.contains("\tlw\ta1,8(zero) # 8 <_start-0x1006c>\n"));
+    assert!(output.contains("0000                \tunimp\n"));
+    assert!(output.contains("\tj\t10096 <end>\n\t...\n\n00010096 <end>:\n"));
+}
+
+#[test]
+fn test_format_flag_in_disassemble_mode() {
+    let args = |list: &[&str]| -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    };
+    let config =
+        parse_cli_args(&args(&["disassemble", "--format=objdump", "a.out"]))
+            .unwrap();
+    assert!(config.objdump_output);
+
+    let Err(error) =
+        parse_cli_args(&args(&["disassemble", "--format=gnu", "a.out"]))
+    else {
+        panic!("--format=gnu is not a disassembly format");
+    };
+    assert!(error.contains("must be objdump"), "{}", error);
+}
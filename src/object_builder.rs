@@ -0,0 +1,1107 @@
+// Relocatable ELF object generation for RISC-V 32-bit little-endian
+//
+// This module turns a single assembled source file into an ELF ET_REL
+// object. Every operand that refers to an address is recorded in a
+// `.rela.text` or `.rela.data` section so that `risclet link` (see
+// object_loader) can resolve it and relax the code around it.
+//
+// Relocated instructions are always encoded in their full-size form
+// (auipc+jalr for call, auipc+addi for la, uncompressed branches) and
+// marked with R_RISCV_RELAX when the line's relaxation settings would have
+// allowed the assembler to shrink them. The linker then makes that decision
+// once final addresses are known.
+//
+// Reference: RISC-V ELF psABI, "Relocations"
+
+use crate::ast::{
+    CompressedOp, CompressedOperands, Directive, Expression, Instruction, Line,
+    LineContent, LinePointer, LineSpans, Location, PseudoOp, Segment, Source,
+    SourceFile, UTypeOp,
+};
+use crate::config::Relax;
+use crate::elf::{
+    ET_REL, ElfHeader, ElfRela, ElfSectionHeader, ElfSymbol, R_RISCV_32,
+    R_RISCV_ALIGN, R_RISCV_BRANCH, R_RISCV_CALL, R_RISCV_HI20, R_RISCV_JAL,
+    R_RISCV_LO12_I, R_RISCV_LO12_S, R_RISCV_PCREL_HI20, R_RISCV_PCREL_LO12_I,
+    R_RISCV_PCREL_LO12_S, R_RISCV_RELAX, R_RISCV_RVC_BRANCH, R_RISCV_RVC_JUMP,
+    RELA_ENTRY_SIZE, SHF_ALLOC, SHF_EXECINSTR, SHF_INFO_LINK, SHF_WRITE,
+    SHN_ABS, SHN_COMMON, SHN_UNDEF, SHT_NOBITS, SHT_PROGBITS, SHT_RELA,
+    SHT_RISCV_ATTRIBUTES, SHT_STRTAB, SHT_SYMTAB, STB_GLOBAL, STB_LOCAL,
+    STB_WEAK, STT_NOTYPE, StringTable, generate_riscv_attributes, make_st_info,
+};
+use crate::error::{Result, RiscletError};
+use crate::expressions::{
+    EvaluatedValue, SymbolValues, default_common_alignment, eval_expr,
+};
+use crate::layout::{Layout, LineRelax};
+use crate::symbols::{
+    EXTERNAL_FILE_NAME, SymbolBinding, SymbolDefinition, SymbolLinks,
+    SymbolReference, is_assembler_local, is_undefined_weak,
+};
+use std::collections::{HashMap, HashSet};
+
+// ============================================================================
+// Relocation Sites
+// ============================================================================
+
+/// A field in one line that the linker must fill in
+#[derive(Debug, Clone)]
+pub struct RelocationSite {
+    /// Offset of the field from the start of the line
+    pub offset: u32,
+    /// Relocation type (one of the R_RISCV_* constants)
+    pub typ: u8,
+    /// The address the field refers to
+    pub target: Expression,
+    /// Whether the linker may relax the instruction (adds R_RISCV_RELAX)
+    pub relax: bool,
+}
+
+/// The relocation sites of every line that has any
+pub type RelocationSites = HashMap<LinePointer, Vec<RelocationSite>>;
+
+/// A `.comm` block left for the linker to allocate
+#[derive(Debug, Clone)]
+pub struct CommonSymbol {
+    pub name: String,
+    pub size: u32,
+    pub alignment: u32,
+}
+
+/// Prefix for the hidden .equ symbols that hold a .comm block's size and
+/// alignment once the block itself is removed from the object's source
+const COMMON_SIZE_PREFIX: &str = "$comm.size.";
+const COMMON_ALIGN_PREFIX: &str = "$comm.align.";
+
+/// Whether a symbol is one of the hidden `.comm` symbols added by
+/// `extract_common_blocks`
+pub fn is_common_block_symbol(name: &str) -> bool {
+    name.starts_with(COMMON_SIZE_PREFIX)
+        || name.starts_with(COMMON_ALIGN_PREFIX)
+}
+
+/// Remove global `.comm` blocks from a file that is being assembled into an
+/// object, so they are allocated by the linker rather than in this object's
+/// bss. Each block is replaced by hidden `.equ` definitions of its size and
+/// alignment, read back by `common_symbols` after relaxation. Returns the
+/// names of the removed blocks.
+pub fn extract_common_blocks(file: &mut SourceFile) -> Vec<String> {
+    let local_names: HashSet<String> = file
+        .lines
+        .iter()
+        .filter_map(|line| match &line.content {
+            LineContent::Directive(Directive::Local(names)) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .cloned()
+        .collect();
+
+    let mut names = Vec::new();
+    let mut lines = Vec::with_capacity(file.lines.len());
+    for line in file.lines.drain(..) {
+        let LineContent::Directive(Directive::Comm(name, size, align)) =
+            &line.content
+        else {
+            lines.push(line);
+            continue;
+        };
+        if local_names.contains(name) {
+            lines.push(line);
+            continue;
+        }
+
+        let hidden = |prefix: &str, expr: Expression| Line {
+            location: line.location.clone(),
+            content: LineContent::Directive(Directive::Equ(
+                format!("{}{}", prefix, name),
+                expr,
+            )),
+            spans: LineSpans::default(),
+        };
+        lines.push(hidden(COMMON_SIZE_PREFIX, size.clone()));
+        if let Some(align) = align {
+            lines.push(hidden(COMMON_ALIGN_PREFIX, align.clone()));
+        }
+        names.push(name.clone());
+    }
+    file.lines = lines;
+    names
+}
+
+/// Read the sizes and alignments of the blocks removed by
+/// `extract_common_blocks`
+pub fn common_symbols(
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    symbol_values: &SymbolValues,
+    names: &[String],
+) -> Result<Vec<CommonSymbol>> {
+    let mut commons = Vec::new();
+    for name in names {
+        let lookup = |prefix: &str| -> Option<(i32, Location)> {
+            let symbol = format!("{}{}", prefix, name);
+            let def = symbol_links.local_symbols_by_file[0]
+                .iter()
+                .find(|def| def.symbol == symbol)?;
+            let location = source.get_line(def.pointer).ok()?.location.clone();
+            match symbol_values.get(def)? {
+                EvaluatedValue::Integer(v) => Some((v, location)),
+                EvaluatedValue::Address(a) => Some((a as i32, location)),
+            }
+        };
+
+        let Some((size, location)) = lookup(COMMON_SIZE_PREFIX) else {
+            return Err(RiscletError::internal(format!(
+                "size of common block '{}' was not evaluated",
+                name
+            )));
+        };
+        if size < 0 {
+            return Err(RiscletError::from_context(
+                format!(".comm size must be non-negative, got {}", size),
+                location,
+            ));
+        }
+        let alignment = match lookup(COMMON_ALIGN_PREFIX) {
+            Some((align, location)) => {
+                if align <= 0 || (align as u32).count_ones() != 1 {
+                    return Err(RiscletError::from_context(
+                        format!(
+                            ".comm alignment must be a positive power of two, got {}",
+                            align
+                        ),
+                        location,
+                    ));
+                }
+                align as u32
+            }
+            None => default_common_alignment(size as u32),
+        };
+
+        commons.push(CommonSymbol {
+            name: name.clone(),
+            size: size as u32,
+            alignment,
+        });
+    }
+    Ok(commons)
+}
+
+/// Find every operand that must be relocated
+///
+/// An operand is relocated when it evaluates to an address. The values in
+/// `symbol_values` only need the right types, not final addresses, so this
+/// runs once before relaxation on a rough layout. The relaxation settings
+/// in `line_relax` decide which sites are marked relaxable.
+pub fn find_relocation_sites(
+    source: &Source,
+    symbol_links: &SymbolLinks,
+    symbol_values: &SymbolValues,
+    layout: &Layout,
+    line_relax: &LineRelax,
+) -> Result<RelocationSites> {
+    let mut sites = RelocationSites::new();
+    let file = &source.files[0];
+
+    for (line_index, line) in file.lines.iter().enumerate() {
+        let pointer = LinePointer { file_index: 0, line_index };
+        let refs = symbol_links.get_line_refs(pointer);
+        let address = layout.get_line_address(pointer);
+        let relax = line_relax.get(pointer);
+
+        let is_address = |expr: &Expression| -> Result<bool> {
+            let value =
+                eval_expr(expr, address, refs, symbol_values, source, pointer)?;
+            Ok(matches!(value, EvaluatedValue::Address(_)))
+        };
+
+        let mut line_sites = Vec::new();
+        for candidate in candidate_sites(line, relax) {
+            if is_address(&candidate.target)? {
+                line_sites.push(candidate);
+            }
+        }
+
+        // A PC-relative pair refers back to the auipc at the start of the line
+        if let Some(hi) =
+            line_sites.iter().find(|site| site.typ == R_RISCV_PCREL_HI20)
+        {
+            let lo_type = match &line.content {
+                LineContent::Instruction(Instruction::Pseudo(
+                    PseudoOp::StoreGlobal(..),
+                )) => R_RISCV_PCREL_LO12_S,
+                _ => R_RISCV_PCREL_LO12_I,
+            };
+            let relax = hi.relax;
+            line_sites.push(RelocationSite {
+                offset: 4,
+                typ: lo_type,
+                target: Expression::CurrentAddress,
+                relax,
+            });
+        }
+
+        // Data smaller than a word cannot hold a relocated address
+        if let LineContent::Directive(
+            Directive::Byte(exprs) | Directive::TwoByte(exprs),
+        ) = &line.content
+        {
+            for expr in exprs {
+                if is_address(expr)? {
+                    return Err(RiscletError::from_context(
+                        "Only .4byte can hold an address in a relocatable object"
+                            .to_string(),
+                        line.location.clone(),
+                    ));
+                }
+            }
+        }
+
+        // Anything else that uses an undefined symbol cannot be resolved
+        if line_sites.is_empty()
+            && !matches!(
+                line.content,
+                LineContent::Directive(
+                    Directive::Equ(..)
+                        | Directive::Equiv(..)
+                        | Directive::Global(_)
+                        | Directive::Weak(_)
+                        | Directive::Local(_)
+                )
+            )
+            && let Some(external) =
+                refs.iter().find(|r| r.definition.pointer.file_index != 0)
+        {
+            return Err(RiscletError::from_context(
+                format!(
+                    "Symbol '{}' is not defined in this file and cannot be used here in a relocatable object",
+                    external.outgoing_name
+                ),
+                line.location.clone(),
+            ));
+        }
+
+        if !line_sites.is_empty() {
+            sites.insert(pointer, line_sites);
+        }
+    }
+
+    Ok(sites)
+}
+
+/// Reserve padding for the linker to align relaxable code
+///
+/// Once the linker relaxes code before a `.balign`, padding computed here
+/// would be wrong. As in GNU as, a power-of-two `.balign` in relaxable text
+/// is replaced by its worst-case padding (alignment - 2 bytes) with an
+/// R_RISCV_ALIGN relocation, and the linker aligns the code again.
+pub fn reserve_alignment_padding(
+    source: &mut Source,
+    symbol_links: &SymbolLinks,
+    symbol_values: &SymbolValues,
+    layout: &Layout,
+    line_relax: &LineRelax,
+    sites: &mut RelocationSites,
+) -> Result<()> {
+    for line_index in 0..source.files[0].lines.len() {
+        let pointer = LinePointer { file_index: 0, line_index };
+        let line = source.get_line(pointer)?;
+        let LineContent::Directive(Directive::Balign(expr)) = &line.content
+        else {
+            continue;
+        };
+        let relax = line_relax.get(pointer);
+        let relaxable =
+            relax.pseudo || relax.compressed || relax.gp != Some(false);
+        if layout.get(pointer).segment != Segment::Text || !relaxable {
+            continue;
+        }
+
+        let value = eval_expr(
+            expr,
+            layout.get_line_address(pointer),
+            symbol_links.get_line_refs(pointer),
+            symbol_values,
+            source,
+            pointer,
+        )?;
+        let EvaluatedValue::Integer(alignment) = value else {
+            continue;
+        };
+        if alignment <= 2 || (alignment as u32).count_ones() != 1 {
+            continue;
+        }
+
+        let reserved = alignment - 2;
+        source.files[0].lines[line_index].content = LineContent::Directive(
+            Directive::Space(Expression::Literal(reserved)),
+        );
+        sites.insert(
+            pointer,
+            vec![RelocationSite {
+                offset: 0,
+                typ: R_RISCV_ALIGN,
+                target: Expression::Literal(reserved),
+                relax: false,
+            }],
+        );
+    }
+    Ok(())
+}
+
+/// The settings for lines with relocation sites: nothing may change their
+/// size, since the relocations describe the full-size encoding
+pub const RELOCATED_LINE_RELAX: Relax = Relax {
+    gp: Some(false),
+    pseudo: false,
+    compressed: false,
+    branches: false,
+};
+
+/// The operands of a line that may need relocation, before type checking
+fn candidate_sites(line: &Line, relax: Relax) -> Vec<RelocationSite> {
+    let site = |offset, typ, target: &Expression, relax| RelocationSite {
+        offset,
+        typ,
+        target: target.clone(),
+        relax,
+    };
+    let gp_relax = relax.gp != Some(false);
+
+    match &line.content {
+        LineContent::Instruction(inst) => match inst {
+            Instruction::Pseudo(
+                PseudoOp::Call(_, target)
+                | PseudoOp::Tail(target, _)
+                | PseudoOp::Jump(target, _),
+            ) => vec![site(0, R_RISCV_CALL, target, relax.pseudo)],
+            Instruction::Pseudo(
+                PseudoOp::La(_, target)
+                | PseudoOp::LoadGlobal(_, _, target)
+                | PseudoOp::StoreGlobal(_, _, target, _),
+            ) => vec![site(0, R_RISCV_PCREL_HI20, target, gp_relax)],
+            Instruction::BType(_, _, _, target) => {
+                vec![site(0, R_RISCV_BRANCH, target, relax.compressed)]
+            }
+            Instruction::JType(_, _, target) => {
+                vec![site(0, R_RISCV_JAL, target, relax.compressed)]
+            }
+            Instruction::Compressed(
+                CompressedOp::CBeqz | CompressedOp::CBnez,
+                CompressedOperands::CBBranch { offset, .. },
+            ) => vec![site(0, R_RISCV_RVC_BRANCH, offset, false)],
+            Instruction::Compressed(
+                CompressedOp::CJComp | CompressedOp::CJalComp,
+                CompressedOperands::CJOpnd { offset },
+            ) => vec![site(0, R_RISCV_RVC_JUMP, offset, false)],
+            Instruction::UType(UTypeOp::Lui, _, imm) => match strip_parens(imm)
+            {
+                Expression::HiOp { expr } => {
+                    vec![site(0, R_RISCV_HI20, expr, relax.compressed)]
+                }
+                _ => Vec::new(),
+            },
+            Instruction::IType(_, _, _, imm) => match strip_parens(imm) {
+                Expression::LoOp { expr } => {
+                    vec![site(0, R_RISCV_LO12_I, expr, relax.compressed)]
+                }
+                _ => Vec::new(),
+            },
+            Instruction::LoadStore(op, _, offset, _) => {
+                match strip_parens(offset) {
+                    Expression::LoOp { expr } => {
+                        let typ = if op.is_store() {
+                            R_RISCV_LO12_S
+                        } else {
+                            R_RISCV_LO12_I
+                        };
+                        vec![site(0, typ, expr, relax.compressed)]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        },
+        LineContent::Directive(Directive::FourByte(exprs)) => exprs
+            .iter()
+            .enumerate()
+            .map(|(i, expr)| site(4 * i as u32, R_RISCV_32, expr, false))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn strip_parens(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Parenthesized(inner) => strip_parens(inner),
+        _ => expr,
+    }
+}
+
+/// Find the symbol an address expression is based on
+///
+/// The base is the one address-valued identifier in a sum or difference
+/// with integers, e.g. `buffer` in `buffer + 4*N`. An `.equ` alias is
+/// followed to the symbol it is based on. Returns None when the address is
+/// based on `.`.
+pub fn resolve_base(
+    expr: &Expression,
+    refs: &[SymbolReference],
+    source: &Source,
+    symbol_links: &SymbolLinks,
+) -> Option<SymbolDefinition> {
+    let name = match base_term(expr)? {
+        Expression::Identifier(name) => name.clone(),
+        Expression::NumericLabelRef(nlr) => nlr.to_string(),
+        _ => return None,
+    };
+    let def = refs.iter().find(|r| r.outgoing_name == name)?.definition.clone();
+
+    match &source.get_line(def.pointer).ok()?.content {
+        LineContent::Directive(
+            Directive::Equ(_, alias) | Directive::Equiv(_, alias),
+        ) => resolve_base(
+            alias,
+            symbol_links.get_line_refs(def.pointer),
+            source,
+            symbol_links,
+        ),
+        _ => Some(def),
+    }
+}
+
+/// The leftmost term of a sum that is added rather than subtracted
+///
+/// Integer terms never contain a label on their own, so the first
+/// identifier that is not subtracted is the base of an address.
+fn base_term(expr: &Expression) -> Option<&Expression> {
+    match expr {
+        Expression::Identifier(_)
+        | Expression::NumericLabelRef(_)
+        | Expression::CurrentAddress => Some(expr),
+        Expression::Parenthesized(inner) => base_term(inner),
+        Expression::PlusOp { lhs, rhs } => {
+            base_term(lhs).or_else(|| base_term(rhs))
+        }
+        Expression::MinusOp { lhs, .. } => base_term(lhs),
+        _ => None,
+    }
+}
+
+// ============================================================================
+// Object Builder
+// ============================================================================
+
+/// What a relocation refers to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RelocationTarget {
+    /// No symbol (for R_RISCV_RELAX)
+    None,
+    /// The start of one of this object's sections
+    Section(Segment),
+    /// A global, weak, common or undefined symbol
+    Named(String),
+}
+
+struct PendingRelocation {
+    segment: Segment,
+    offset: u32,
+    typ: u8,
+    target: RelocationTarget,
+    addend: i32,
+}
+
+pub struct ObjectBuilder<'a> {
+    source: &'a Source,
+    symbol_links: &'a SymbolLinks,
+    symbol_values: &'a SymbolValues,
+    layout: &'a Layout,
+    text_data: Vec<u8>,
+    data_data: Vec<u8>,
+    /// Write `.L` labels to the symbol table, too
+    keep_locals: bool,
+}
+
+impl<'a> ObjectBuilder<'a> {
+    pub fn new(
+        source: &'a Source,
+        symbol_links: &'a SymbolLinks,
+        symbol_values: &'a SymbolValues,
+        layout: &'a Layout,
+        text_data: Vec<u8>,
+        data_data: Vec<u8>,
+        keep_locals: bool,
+    ) -> Self {
+        Self {
+            source,
+            symbol_links,
+            symbol_values,
+            layout,
+            text_data,
+            data_data,
+            keep_locals,
+        }
+    }
+
+    /// Build the complete relocatable object
+    ///
+    /// Sections are laid out as `.text`, `.rela.text`, `.data`, `.rela.data`,
+    /// `.bss`, `.riscv.attributes`, `.symtab`, `.strtab` and `.shstrtab`.
+    /// Like GNU as, the three content sections are always present; the
+    /// relocation sections only when they have entries.
+    pub fn build(
+        self,
+        sites: &RelocationSites,
+        commons: &[CommonSymbol],
+    ) -> Result<Vec<u8>> {
+        let relocations = self.compute_relocations(sites)?;
+        let text_alignment = self.section_alignment(Segment::Text, 2)?;
+        let data_alignment = self.section_alignment(Segment::Data, 1)?;
+        let bss_alignment = self.section_alignment(Segment::Bss, 1)?;
+        let has_rela =
+            |segment| relocations.iter().any(|r| r.segment == segment);
+
+        // Assign section indices
+        let mut section_names = StringTable::new();
+        let mut sections: Vec<(ElfSectionHeader, Vec<u8>)> =
+            vec![(ElfSectionHeader::null(), Vec::new())];
+        let mut segment_index: HashMap<Segment, u16> = HashMap::new();
+        let mut rela_index: Vec<(Segment, usize)> = Vec::new();
+
+        let mut add_section = |name: &str,
+                               sh_type: u32,
+                               sh_flags: u32,
+                               sh_addralign: u32,
+                               sh_size: u32,
+                               data: Vec<u8>| {
+            sections.push((
+                ElfSectionHeader {
+                    sh_name: section_names.add(name),
+                    sh_type,
+                    sh_flags,
+                    sh_addr: 0,
+                    sh_offset: 0,
+                    sh_size,
+                    sh_link: 0,
+                    sh_info: 0,
+                    sh_addralign,
+                    sh_entsize: 0,
+                },
+                data,
+            ));
+            sections.len() - 1
+        };
+
+        let text_size = self.text_data.len() as u32;
+        let index = add_section(
+            ".text",
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            text_alignment,
+            text_size,
+            self.text_data.clone(),
+        );
+        segment_index.insert(Segment::Text, index as u16);
+        if has_rela(Segment::Text) {
+            let index = add_section(
+                ".rela.text",
+                SHT_RELA,
+                SHF_INFO_LINK,
+                4,
+                0,
+                vec![],
+            );
+            rela_index.push((Segment::Text, index));
+        }
+        let data_size = self.data_data.len() as u32;
+        let index = add_section(
+            ".data",
+            SHT_PROGBITS,
+            SHF_WRITE | SHF_ALLOC,
+            data_alignment,
+            data_size,
+            self.data_data.clone(),
+        );
+        segment_index.insert(Segment::Data, index as u16);
+        if has_rela(Segment::Data) {
+            let index = add_section(
+                ".rela.data",
+                SHT_RELA,
+                SHF_INFO_LINK,
+                4,
+                0,
+                vec![],
+            );
+            rela_index.push((Segment::Data, index));
+        }
+        let index = add_section(
+            ".bss",
+            SHT_NOBITS,
+            SHF_WRITE | SHF_ALLOC,
+            bss_alignment,
+            self.layout.bss_size,
+            vec![],
+        );
+        segment_index.insert(Segment::Bss, index as u16);
+        let attributes = generate_riscv_attributes();
+        let attributes_size = attributes.len() as u32;
+        add_section(
+            ".riscv.attributes",
+            SHT_RISCV_ATTRIBUTES,
+            0,
+            1,
+            attributes_size,
+            attributes,
+        );
+        let symtab_index =
+            add_section(".symtab", SHT_SYMTAB, 0, 4, 0, Vec::new());
+        let strtab_index =
+            add_section(".strtab", SHT_STRTAB, 0, 1, 0, Vec::new());
+        let shstrtab_index =
+            add_section(".shstrtab", SHT_STRTAB, 0, 1, 0, Vec::new());
+
+        // Symbol table, then the relocations that refer to it
+        let (symbols, symbol_names, symbol_index) =
+            self.build_symbol_table(&segment_index, commons)?;
+        let first_global = symbols
+            .iter()
+            .position(|sym| (sym.st_info >> 4) != STB_LOCAL)
+            .unwrap_or(symbols.len()) as u32;
+
+        for (segment, index) in &rela_index {
+            let mut data = Vec::new();
+            for reloc in relocations.iter().filter(|r| r.segment == *segment) {
+                let symbol = symbol_index[&reloc.target];
+                data.extend_from_slice(
+                    &ElfRela::new(
+                        reloc.offset,
+                        symbol,
+                        reloc.typ,
+                        reloc.addend,
+                    )
+                    .encode(),
+                );
+            }
+            let (header, contents) = &mut sections[*index];
+            header.sh_size = data.len() as u32;
+            header.sh_link = symtab_index as u32;
+            header.sh_info = segment_index[segment] as u32;
+            header.sh_entsize = RELA_ENTRY_SIZE as u32;
+            *contents = data;
+        }
+
+        let mut symtab = Vec::new();
+        for sym in &symbols {
+            symtab.extend_from_slice(&sym.encode());
+        }
+        sections[symtab_index].0.sh_size = symtab.len() as u32;
+        sections[symtab_index].0.sh_link = strtab_index as u32;
+        sections[symtab_index].0.sh_info = first_global;
+        sections[symtab_index].0.sh_entsize = 16;
+        sections[symtab_index].1 = symtab;
+        sections[strtab_index].0.sh_size = symbol_names.len() as u32;
+        sections[strtab_index].1 = symbol_names.data().to_vec();
+        sections[shstrtab_index].0.sh_size = section_names.len() as u32;
+        sections[shstrtab_index].1 = section_names.data().to_vec();
+
+        // File layout: header, section contents, section headers
+        let mut output = vec![0; 52];
+        for (header, data) in sections.iter_mut().skip(1) {
+            let align = header.sh_addralign.max(1) as usize;
+            output.resize(output.len().div_ceil(align) * align, 0);
+            header.sh_offset = output.len() as u32;
+            if header.sh_type != SHT_NOBITS {
+                output.extend_from_slice(data);
+            }
+        }
+        output.resize(output.len().div_ceil(4) * 4, 0);
+
+        let mut header = ElfHeader::new();
+        header.e_type = ET_REL;
+        header.e_phoff = 0;
+        header.e_phentsize = 0;
+        header.e_shoff = output.len() as u32;
+        header.e_shnum = sections.len() as u16;
+        header.e_shstrndx = shstrtab_index as u16;
+        for (section, _) in &sections {
+            output.extend_from_slice(&section.encode());
+        }
+        output[0..52].copy_from_slice(&header.encode());
+
+        Ok(output)
+    }
+
+    /// The alignment a section needs to keep its `.balign` and `.lcomm`
+    /// alignments when the linker places it
+    ///
+    /// Only the power-of-two part of an alignment can be preserved.
+    fn section_alignment(&self, segment: Segment, minimum: u32) -> Result<u32> {
+        let mut alignment = minimum;
+        for (line_index, line) in self.source.files[0].lines.iter().enumerate()
+        {
+            let pointer = LinePointer { file_index: 0, line_index };
+            if self.layout.get(pointer).segment != segment {
+                continue;
+            }
+            let (expr, default) = match &line.content {
+                LineContent::Directive(Directive::Balign(expr)) => {
+                    (Some(expr), 1)
+                }
+                LineContent::Directive(Directive::Lcomm(_, size, align)) => {
+                    let size = self.eval_integer(size, pointer)?;
+                    (align.as_ref(), default_common_alignment(size as u32))
+                }
+                _ => continue,
+            };
+            let value = match expr {
+                Some(expr) => self.eval_integer(expr, pointer)? as u32,
+                None => default,
+            };
+            if value > 0 {
+                alignment = alignment.max(1 << value.trailing_zeros());
+            }
+        }
+        Ok(alignment)
+    }
+
+    fn eval_integer(
+        &self,
+        expr: &Expression,
+        pointer: LinePointer,
+    ) -> Result<i32> {
+        let value = eval_expr(
+            expr,
+            self.layout.get_line_address(pointer),
+            self.symbol_links.get_line_refs(pointer),
+            self.symbol_values,
+            self.source,
+            pointer,
+        )?;
+        match value {
+            EvaluatedValue::Integer(value) => Ok(value),
+            EvaluatedValue::Address(_) => Err(RiscletError::internal(
+                "alignment evaluated to an address".to_string(),
+            )),
+        }
+    }
+
+    /// Evaluate every relocation site against the final layout
+    fn compute_relocations(
+        &self,
+        sites: &RelocationSites,
+    ) -> Result<Vec<PendingRelocation>> {
+        let mut pointers: Vec<&LinePointer> = sites.keys().collect();
+        pointers.sort_by_key(|p| p.line_index);
+
+        let mut relocations = Vec::new();
+        for &pointer in pointers {
+            let line_layout = self.layout.get(pointer);
+            let address = self.layout.get_line_address(pointer);
+            let refs = self.symbol_links.get_line_refs(pointer);
+            let location = &self.source.get_line(pointer)?.location;
+
+            for site in &sites[&pointer] {
+                if let (R_RISCV_ALIGN, Expression::Literal(reserved)) =
+                    (site.typ, &site.target)
+                {
+                    relocations.push(PendingRelocation {
+                        segment: line_layout.segment,
+                        offset: line_layout.offset,
+                        typ: R_RISCV_ALIGN,
+                        target: RelocationTarget::None,
+                        addend: *reserved,
+                    });
+                    continue;
+                }
+                let value = eval_expr(
+                    &site.target,
+                    address,
+                    refs,
+                    self.symbol_values,
+                    self.source,
+                    pointer,
+                )?;
+                let EvaluatedValue::Address(value) = value else {
+                    return Err(RiscletError::internal(
+                        "relocation target is no longer an address".to_string(),
+                    ));
+                };
+
+                let (target, addend) = self.relocation_target(
+                    &site.target,
+                    refs,
+                    value,
+                    location,
+                )?;
+                relocations.push(PendingRelocation {
+                    segment: line_layout.segment,
+                    offset: line_layout.offset + site.offset,
+                    typ: site.typ,
+                    target,
+                    addend,
+                });
+                if site.relax {
+                    relocations.push(PendingRelocation {
+                        segment: line_layout.segment,
+                        offset: line_layout.offset + site.offset,
+                        typ: R_RISCV_RELAX,
+                        target: RelocationTarget::None,
+                        addend: 0,
+                    });
+                }
+            }
+        }
+        Ok(relocations)
+    }
+
+    /// Express an address as a symbol plus addend
+    ///
+    /// Symbols visible to the linker are referred to by name so that a
+    /// definition in another object can take their place. Everything else
+    /// is an offset from the start of the section containing it.
+    fn relocation_target(
+        &self,
+        expr: &Expression,
+        refs: &[SymbolReference],
+        value: u32,
+        location: &Location,
+    ) -> Result<(RelocationTarget, i32)> {
+        let base = resolve_base(expr, refs, self.source, self.symbol_links);
+
+        if let Some(def) = &base
+            && self.is_named(def)
+        {
+            let base_value = match self.symbol_values.get(def) {
+                Some(EvaluatedValue::Address(a)) => a,
+                _ => 0,
+            };
+            return Ok((
+                RelocationTarget::Named(def.symbol.clone()),
+                value.wrapping_sub(base_value) as i32,
+            ));
+        }
+
+        // Prefer the section of the base symbol; an address just past the
+        // end of one section may also be the start of the next
+        let base_segment = base.as_ref().and_then(|def| {
+            match self.source.get_line(def.pointer).ok()?.content {
+                LineContent::Label(_)
+                | LineContent::Directive(
+                    Directive::Comm(..) | Directive::Lcomm(..),
+                ) => Some(self.layout.get(def.pointer).segment),
+                _ => None,
+            }
+        });
+        let segment = base_segment
+            .or_else(|| self.segment_containing(value))
+            .ok_or_else(|| {
+                RiscletError::from_context(
+                    format!(
+                        "Address 0x{:x} is outside every section and cannot be relocated",
+                        value
+                    ),
+                    location.clone(),
+                )
+            })?;
+        let start = self.segment_start(segment);
+        Ok((
+            RelocationTarget::Section(segment),
+            value.wrapping_sub(start) as i32,
+        ))
+    }
+
+    /// Whether relocations against a definition use its symbol name
+    fn is_named(&self, def: &SymbolDefinition) -> bool {
+        def.pointer.file_index != 0
+            || self.symbol_links.global_symbols.iter().any(|g| {
+                g.symbol == def.symbol && g.definition_pointer == def.pointer
+            })
+    }
+
+    fn segment_start(&self, segment: Segment) -> u32 {
+        match segment {
+            Segment::Text => self.layout.text_start,
+            Segment::Data => self.layout.data_start,
+            Segment::Bss => self.layout.bss_start,
+        }
+    }
+
+    fn segment_containing(&self, address: u32) -> Option<Segment> {
+        let layout = self.layout;
+        [
+            (Segment::Text, layout.text_start, layout.text_size),
+            (Segment::Data, layout.data_start, layout.data_size),
+            (Segment::Bss, layout.bss_start, layout.bss_size),
+        ]
+        .into_iter()
+        .find(|&(_, start, size)| address >= start && address <= start + size)
+        .map(|(segment, _, _)| segment)
+    }
+
+    /// Build the symbol table
+    ///
+    /// Symbol ordering:
+    /// 1. Null symbol (entry 0)
+    /// 2. Section symbols (.text, .data, .bss)
+    /// 3. FILE symbol
+    /// 4. Local labels and .lcomm blocks (`.L` labels only if kept)
+    /// 5. Global and weak symbols defined in this file, in source order
+    /// 6. Undefined and common symbols, in order of first use
+    #[allow(clippy::type_complexity)]
+    fn build_symbol_table(
+        &self,
+        segment_index: &HashMap<Segment, u16>,
+        commons: &[CommonSymbol],
+    ) -> Result<(Vec<ElfSymbol>, StringTable, HashMap<RelocationTarget, u32>)>
+    {
+        let mut symbols = vec![ElfSymbol::null()];
+        let mut names = StringTable::new();
+        let mut index = HashMap::from([(RelocationTarget::None, 0)]);
+
+        for segment in [Segment::Text, Segment::Data, Segment::Bss] {
+            if let Some(&section) = segment_index.get(&segment) {
+                index.insert(
+                    RelocationTarget::Section(segment),
+                    symbols.len() as u32,
+                );
+                symbols.push(ElfSymbol::section(section));
+            }
+        }
+
+        let file = &self.source.files[0];
+        let file_name = std::path::Path::new(&file.file)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&file.file);
+        symbols.push(ElfSymbol::file(names.add(file_name)));
+
+        // Local labels and .lcomm blocks
+        for (line_index, line) in file.lines.iter().enumerate() {
+            let pointer = LinePointer { file_index: 0, line_index };
+            let (LineContent::Label(name)
+            | LineContent::Directive(
+                Directive::Comm(name, _, _) | Directive::Lcomm(name, _, _),
+            )) = &line.content
+            else {
+                continue;
+            };
+            let def = SymbolDefinition { symbol: name.clone(), pointer };
+            if name.chars().all(|c| c.is_ascii_digit())
+                || (is_assembler_local(name) && !self.keep_locals)
+                || self.is_named(&def)
+            {
+                continue;
+            }
+            let (st_value, segment) = self.symbol_location(&def)?;
+            symbols.push(ElfSymbol {
+                st_name: names.add(name),
+                st_value,
+                st_size: 0,
+                st_info: make_st_info(STB_LOCAL, STT_NOTYPE),
+                st_other: 0,
+                st_shndx: segment_index[&segment],
+            });
+        }
+
+        // Globals defined (or declared weak) in this file
+        let mut globals: Vec<_> = self
+            .symbol_links
+            .global_symbols
+            .iter()
+            .filter(|g| g.definition_pointer.file_index == 0)
+            .collect();
+        globals.sort_by_key(|g| g.declaration_pointer.line_index);
+        for global in globals {
+            let binding = match global.binding {
+                SymbolBinding::Weak => STB_WEAK,
+                SymbolBinding::Global | SymbolBinding::Common => STB_GLOBAL,
+            };
+            let def = SymbolDefinition {
+                symbol: global.symbol.clone(),
+                pointer: global.definition_pointer,
+            };
+            let (st_value, st_shndx) = if is_undefined_weak(self.source, global)
+            {
+                (0, SHN_UNDEF)
+            } else {
+                match self.symbol_values.get(&def) {
+                    Some(EvaluatedValue::Integer(v)) => (v as u32, SHN_ABS),
+                    _ => {
+                        let (value, segment) = self.symbol_location(&def)?;
+                        (value, segment_index[&segment])
+                    }
+                }
+            };
+            index.insert(
+                RelocationTarget::Named(global.symbol.clone()),
+                symbols.len() as u32,
+            );
+            symbols.push(ElfSymbol {
+                st_name: names.add(&global.symbol),
+                st_value,
+                st_size: 0,
+                st_info: make_st_info(binding, STT_NOTYPE),
+                st_other: 0,
+                st_shndx,
+            });
+        }
+
+        // Common blocks, then symbols left for the linker
+        for common in commons {
+            index.insert(
+                RelocationTarget::Named(common.name.clone()),
+                symbols.len() as u32,
+            );
+            symbols.push(ElfSymbol {
+                st_name: names.add(&common.name),
+                st_value: common.alignment,
+                st_size: common.size,
+                st_info: make_st_info(STB_GLOBAL, STT_NOTYPE),
+                st_other: 0,
+                st_shndx: SHN_COMMON,
+            });
+        }
+        if let Some(external) =
+            self.source.files.iter().find(|f| f.file == EXTERNAL_FILE_NAME)
+        {
+            for line in &external.lines {
+                let LineContent::Directive(Directive::Weak(names_in_line)) =
+                    &line.content
+                else {
+                    continue;
+                };
+                for name in names_in_line {
+                    let target = RelocationTarget::Named(name.clone());
+                    if index.contains_key(&target) {
+                        continue;
+                    }
+                    index.insert(target, symbols.len() as u32);
+                    symbols.push(ElfSymbol {
+                        st_name: names.add(name),
+                        st_value: 0,
+                        st_size: 0,
+                        st_info: make_st_info(STB_GLOBAL, STT_NOTYPE),
+                        st_other: 0,
+                        st_shndx: SHN_UNDEF,
+                    });
+                }
+            }
+        }
+
+        Ok((symbols, names, index))
+    }
+
+    /// The section and section-relative value of a label or .lcomm block
+    fn symbol_location(
+        &self,
+        def: &SymbolDefinition,
+    ) -> Result<(u32, Segment)> {
+        let segment = self.layout.get(def.pointer).segment;
+        let address = match self.symbol_values.get(def) {
+            Some(EvaluatedValue::Address(a)) => a,
+            _ => self.layout.get_line_address(def.pointer),
+        };
+        Ok((address - self.segment_start(segment), segment))
+    }
+}
@@ -0,0 +1,965 @@
+// Relocatable ELF object input for `risclet link`
+//
+// A relocatable object (ET_REL), written by `risclet assemble -c` or by a
+// GNU assembler, is turned back into an assembler SourceFile so that it can
+// be linked by the ordinary assembly pipeline alongside other objects and
+// source files:
+//
+// - Symbols become labels, `.global`/`.weak` declarations, `.equ`
+//   definitions (absolute symbols) and `.comm` blocks (common symbols).
+// - Each relocated instruction becomes the instruction or pseudo-instruction
+//   it was assembled from, with a symbolic operand: R_RISCV_CALL becomes
+//   `call`/`tail`, an adjacent PCREL_HI20/PCREL_LO12 pair becomes `la` or a
+//   global load/store, HI20/LO12 become `%hi`/`%lo` operands, and so on.
+//   The two halves of any other PC-relative pair use `%hi`/`%lo` of the
+//   target's offset from the auipc.
+// - R_RISCV_ALIGN padding becomes `.balign`, and R_RISCV_ADD/SUB pairs
+//   become label differences.
+// - Everything else is copied verbatim as `.2byte`/`.4byte`/`.byte` data
+//   (or `.space` in bss). Every allocated section is kept, so `.rodata` and
+//   `.sdata` go in the data segment and `.sbss` in bss.
+//
+// Because relocated instructions are re-encoded, the linker relaxes them
+// exactly like the assembler would. Instructions without R_RISCV_RELAX are
+// wrapped in `.option norelax`/`.option norvc` so they keep their size.
+//
+// Reference: RISC-V ELF psABI, "Relocations"
+
+use crate::ast::{
+    BTypeOp, CompressedOp, CompressedOperands, Directive, Expression, ITypeOp,
+    Instruction, JTypeOp, Line, LineContent, LineSpans, LoadStoreOp, Location,
+    OptionArg, PseudoOp, Register, Segment, SourceFile, UTypeOp,
+};
+use crate::elf::{
+    EI_CLASS, EM_RISCV, ET_REL, ElfHeader, ElfRela, ElfSectionHeader,
+    ElfSymbol, R_RISCV_32, R_RISCV_ADD8, R_RISCV_ADD16, R_RISCV_ADD32,
+    R_RISCV_ALIGN, R_RISCV_BRANCH, R_RISCV_CALL, R_RISCV_CALL_PLT,
+    R_RISCV_HI20, R_RISCV_JAL, R_RISCV_LO12_I, R_RISCV_LO12_S,
+    R_RISCV_PCREL_HI20, R_RISCV_PCREL_LO12_I, R_RISCV_PCREL_LO12_S,
+    R_RISCV_RELAX, R_RISCV_RVC_BRANCH, R_RISCV_RVC_JUMP, R_RISCV_SUB8,
+    R_RISCV_SUB16, R_RISCV_SUB32, RELA_ENTRY_SIZE, SHF_ALLOC, SHF_EXECINSTR,
+    SHN_ABS, SHN_COMMON, SHN_UNDEF, SHT_NOBITS, SHT_PROGBITS, SHT_RELA,
+    SHT_SYMTAB, STB_GLOBAL, STB_LOCAL, STB_WEAK, STT_FILE, STT_SECTION,
+    SYMBOL_ENTRY_SIZE, StringTable,
+};
+use crate::error::{Result, RiscletError};
+use std::collections::{BTreeMap, HashMap};
+
+const SHT_REL: u32 = 9;
+
+#[rustfmt::skip]
+const REGISTERS: [Register; 32] = [
+    Register::X0,  Register::X1,  Register::X2,  Register::X3,
+    Register::X4,  Register::X5,  Register::X6,  Register::X7,
+    Register::X8,  Register::X9,  Register::X10, Register::X11,
+    Register::X12, Register::X13, Register::X14, Register::X15,
+    Register::X16, Register::X17, Register::X18, Register::X19,
+    Register::X20, Register::X21, Register::X22, Register::X23,
+    Register::X24, Register::X25, Register::X26, Register::X27,
+    Register::X28, Register::X29, Register::X30, Register::X31,
+];
+
+/// Load a relocatable object and turn it back into assembler source
+pub fn load_object(file_name: &str, bytes: &[u8]) -> Result<SourceFile> {
+    let object = ObjectFile::parse(file_name, bytes)?;
+    let mut lifter = Lifter::new(&object);
+    lifter.lift()?;
+    Ok(SourceFile {
+        file: file_name.to_string(),
+        lines: lifter.lines,
+        warning_flags: Vec::new(),
+    })
+}
+
+// ============================================================================
+// Object File Parsing
+// ============================================================================
+
+/// A section that is loaded into memory, with its relocations
+struct ContentSection {
+    index: usize,
+    name: String,
+    segment: Segment,
+    header: ElfSectionHeader,
+    relocations: Vec<ElfRela>,
+}
+
+struct ObjectFile<'a> {
+    file_name: &'a str,
+    bytes: &'a [u8],
+    sections: Vec<ContentSection>,
+    symbols: Vec<(String, ElfSymbol)>,
+}
+
+impl<'a> ObjectFile<'a> {
+    fn parse(file_name: &'a str, bytes: &'a [u8]) -> Result<Self> {
+        let error = |message: String| {
+            RiscletError::elf(format!("{}: {}", file_name, message))
+        };
+
+        let header = ElfHeader::decode(bytes)?;
+        if header.e_ident[4] != EI_CLASS
+            || header.e_machine != EM_RISCV
+            || header.e_type != ET_REL
+        {
+            return Err(error(
+                "not a 32-bit RISC-V relocatable object".to_string(),
+            ));
+        }
+
+        let mut headers = Vec::new();
+        for i in 0..header.e_shnum as usize {
+            let offset =
+                header.e_shoff as usize + i * header.e_shentsize as usize;
+            let data = bytes.get(offset..offset + 40).ok_or_else(|| {
+                error(format!("section header {} out of bounds", i))
+            })?;
+            headers.push(ElfSectionHeader::decode(data)?);
+        }
+
+        let section_data = |sh: &ElfSectionHeader| -> Result<&'a [u8]> {
+            let start = sh.sh_offset as usize;
+            bytes.get(start..start + sh.sh_size as usize).ok_or_else(|| {
+                error(format!(
+                    "section extends beyond end of file (offset {} + size {})",
+                    sh.sh_offset, sh.sh_size
+                ))
+            })
+        };
+
+        let section_names = match headers.get(header.e_shstrndx as usize) {
+            Some(sh) => StringTable::from_data(section_data(sh)?),
+            None => StringTable::new(),
+        };
+
+        // Symbols
+        let mut symbols = Vec::new();
+        if let Some(symtab) = headers.iter().find(|sh| sh.sh_type == SHT_SYMTAB)
+        {
+            let strtab_header =
+                headers.get(symtab.sh_link as usize).ok_or_else(|| {
+                    error("symbol table has no string table".to_string())
+                })?;
+            let names = StringTable::from_data(section_data(strtab_header)?);
+            for entry in section_data(symtab)?.chunks(SYMBOL_ENTRY_SIZE) {
+                let symbol = ElfSymbol::decode(entry)?;
+                let name = names.get_string(symbol.st_name as usize)?;
+                symbols.push((name, symbol));
+            }
+        }
+
+        // Sections loaded into memory
+        let mut sections = Vec::new();
+        for (index, sh) in headers.iter().enumerate() {
+            if !matches!(sh.sh_type, SHT_PROGBITS | SHT_NOBITS)
+                || sh.sh_flags & SHF_ALLOC == 0
+            {
+                continue;
+            }
+            let segment = if sh.sh_type == SHT_NOBITS {
+                Segment::Bss
+            } else if sh.sh_flags & SHF_EXECINSTR != 0 {
+                Segment::Text
+            } else {
+                Segment::Data
+            };
+            // Checked here so `contents` can slice it later
+            if sh.sh_type == SHT_PROGBITS {
+                section_data(sh)?;
+            }
+            sections.push(ContentSection {
+                index,
+                name: section_names.get_string(sh.sh_name as usize)?,
+                segment,
+                header: sh.clone(),
+                relocations: Vec::new(),
+            });
+        }
+
+        // Relocations, attached to the sections they apply to
+        for sh in &headers {
+            if sh.sh_type == SHT_REL {
+                return Err(error(
+                    "REL relocations are not supported (only RELA)".to_string(),
+                ));
+            }
+            if sh.sh_type != SHT_RELA {
+                continue;
+            }
+            let Some(section) = sections
+                .iter_mut()
+                .find(|section| section.index == sh.sh_info as usize)
+            else {
+                continue;
+            };
+            for entry in section_data(sh)?.chunks(RELA_ENTRY_SIZE) {
+                section.relocations.push(ElfRela::decode(entry)?);
+            }
+            section.relocations.sort_by_key(|r| r.r_offset);
+        }
+
+        Ok(Self { file_name, bytes, sections, symbols })
+    }
+
+    fn error(&self, message: String) -> RiscletError {
+        RiscletError::elf(format!("{}: {}", self.file_name, message))
+    }
+
+    fn contents(&self, section: &ContentSection) -> &'a [u8] {
+        if section.header.sh_type == SHT_NOBITS {
+            return &[];
+        }
+        let start = section.header.sh_offset as usize;
+        &self.bytes[start..start + section.header.sh_size as usize]
+    }
+
+    fn symbol(&self, index: u32) -> Result<&(String, ElfSymbol)> {
+        self.symbols.get(index as usize).ok_or_else(|| {
+            self.error(format!("relocation refers to missing symbol {}", index))
+        })
+    }
+
+    fn section(&self, index: u16) -> Option<&ContentSection> {
+        self.sections.iter().find(|s| s.index == index as usize)
+    }
+}
+
+// ============================================================================
+// Lifting Objects to Source
+// ============================================================================
+
+struct Lifter<'a> {
+    object: &'a ObjectFile<'a>,
+    lines: Vec<Line>,
+    /// Labels to emit in each section, by section index and offset
+    labels: HashMap<usize, BTreeMap<u32, Vec<String>>>,
+}
+
+impl<'a> Lifter<'a> {
+    fn new(object: &'a ObjectFile<'a>) -> Self {
+        Self { object, lines: Vec::new(), labels: HashMap::new() }
+    }
+
+    fn push(&mut self, content: LineContent) {
+        let location =
+            Location::new(self.object.file_name, self.lines.len() + 1);
+        self.lines.push(Line {
+            location,
+            content,
+            spans: LineSpans::default(),
+        });
+    }
+
+    fn directive(&mut self, directive: Directive) {
+        self.push(LineContent::Directive(directive));
+    }
+
+    fn lift(&mut self) -> Result<()> {
+        self.lift_symbols()?;
+
+        // Give every relocation target a label, so it moves with relaxation
+        for section in &self.object.sections {
+            for reloc in &section.relocations {
+                if !matches!(
+                    reloc.typ(),
+                    R_RISCV_RELAX
+                        | R_RISCV_ALIGN
+                        | R_RISCV_PCREL_LO12_I
+                        | R_RISCV_PCREL_LO12_S
+                ) {
+                    self.target_expression(reloc)?;
+                }
+            }
+        }
+
+        // An auipc that is not lifted together with its %pcrel_lo
+        // instruction needs a label for that instruction to refer to
+        for section in &self.object.sections {
+            for reloc in &section.relocations {
+                if reloc.typ() == R_RISCV_PCREL_HI20
+                    && self.pcrel_pseudo(section, reloc.r_offset)?.is_none()
+                    && self.label_at(section, reloc.r_offset).is_none()
+                {
+                    let label = format!(
+                        ".L{}.{:x}",
+                        section.name.trim_start_matches('.'),
+                        reloc.r_offset
+                    );
+                    self.add_label(section.index, reloc.r_offset, label);
+                }
+            }
+        }
+
+        for section in &self.object.sections {
+            self.lift_section(section)?;
+        }
+        // The segment carries over into the next input file, which expects
+        // to start in .text
+        self.directive(Directive::Text);
+        Ok(())
+    }
+
+    /// Emit declarations for every symbol and record label positions
+    fn lift_symbols(&mut self) -> Result<()> {
+        let object = self.object;
+        for (name, symbol) in object.symbols.iter().skip(1) {
+            let binding = symbol.st_info >> 4;
+            let typ = symbol.st_info & 0xf;
+            if name.is_empty()
+                || name.starts_with('$')
+                || matches!(typ, STT_SECTION | STT_FILE)
+            {
+                continue;
+            }
+
+            match binding {
+                STB_GLOBAL if symbol.st_shndx != SHN_UNDEF => {
+                    if symbol.st_shndx != SHN_COMMON {
+                        self.directive(Directive::Global(vec![name.clone()]));
+                    }
+                }
+                STB_WEAK => {
+                    self.directive(Directive::Weak(vec![name.clone()]));
+                }
+                STB_GLOBAL | STB_LOCAL => {}
+                _ => {
+                    return Err(object.error(format!(
+                        "symbol '{}' has unsupported binding {}",
+                        name, binding
+                    )));
+                }
+            }
+
+            match symbol.st_shndx {
+                SHN_UNDEF => {}
+                SHN_ABS => self.directive(Directive::Equ(
+                    name.clone(),
+                    Expression::Literal(symbol.st_value as i32),
+                )),
+                SHN_COMMON => self.directive(Directive::Comm(
+                    name.clone(),
+                    Expression::Literal(symbol.st_size as i32),
+                    Some(Expression::Literal(symbol.st_value as i32)),
+                )),
+                index => {
+                    if object.section(index).is_some() {
+                        self.add_label(
+                            index as usize,
+                            symbol.st_value,
+                            name.clone(),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_label(&mut self, section: usize, offset: u32, name: String) {
+        self.labels
+            .entry(section)
+            .or_default()
+            .entry(offset)
+            .or_default()
+            .push(name);
+    }
+
+    /// The expression a relocation refers to: a symbol plus addend
+    ///
+    /// A section symbol plus addend is replaced by a label at that offset,
+    /// using an existing symbol when there is one and a synthetic `.L` label
+    /// otherwise.
+    fn target_expression(&mut self, reloc: &ElfRela) -> Result<Expression> {
+        let object = self.object;
+        let (name, symbol) = object.symbol(reloc.symbol())?;
+        if reloc.symbol() == 0 {
+            return Err(object.error(format!(
+                "relocation at offset 0x{:x} has no symbol",
+                reloc.r_offset
+            )));
+        }
+        if symbol.st_info & 0xf != STT_SECTION {
+            return Ok(offset_expression(name.clone(), reloc.r_addend));
+        }
+
+        let section = object.section(symbol.st_shndx).ok_or_else(|| {
+            object.error(format!(
+                "relocation at offset 0x{:x} refers to an unsupported section",
+                reloc.r_offset
+            ))
+        })?;
+        let offset = symbol.st_value.wrapping_add(reloc.r_addend as u32);
+        let existing = self
+            .labels
+            .get(&section.index)
+            .and_then(|labels| labels.get(&offset))
+            .and_then(|names| names.first());
+        let label = match existing {
+            Some(label) => label.clone(),
+            None => {
+                let label = format!(
+                    ".L{}.{:x}",
+                    section.name.trim_start_matches('.'),
+                    offset
+                );
+                self.add_label(section.index, offset, label.clone());
+                label
+            }
+        };
+        Ok(Expression::Identifier(label))
+    }
+
+    fn lift_section(&mut self, section: &ContentSection) -> Result<()> {
+        let object = self.object;
+        let contents = object.contents(section);
+        let size = section.header.sh_size;
+        let labels =
+            self.labels.get(&section.index).cloned().unwrap_or_default();
+
+        self.directive(match section.segment {
+            Segment::Text => Directive::Text,
+            Segment::Data => Directive::Data,
+            Segment::Bss => Directive::Bss,
+        });
+        if section.header.sh_addralign > 1 {
+            self.directive(Directive::Balign(Expression::Literal(
+                section.header.sh_addralign as i32,
+            )));
+        }
+
+        let relocations = &section.relocations;
+        let mut next_reloc = 0;
+        let mut offset = 0;
+        loop {
+            for name in labels.get(&offset).into_iter().flatten() {
+                self.push(LineContent::Label(name.clone()));
+            }
+            if offset >= size {
+                break;
+            }
+
+            // Lines end at the next label or relocation
+            let next_label = labels
+                .range(offset + 1..)
+                .next()
+                .map_or(size, |(&label_offset, _)| label_offset);
+            let next_cut = relocations[next_reloc..]
+                .iter()
+                .map(|r| r.r_offset)
+                .find(|&r| r > offset)
+                .map_or(next_label, |r| r.min(next_label))
+                .min(size);
+
+            let here: Vec<&ElfRela> = relocations[next_reloc..]
+                .iter()
+                .take_while(|r| r.r_offset == offset)
+                .collect();
+            let relocated = if here.is_empty() {
+                0
+            } else {
+                self.lift_relocated(section, &here, offset)?
+            };
+            if next_label < offset + relocated {
+                return Err(object.error(format!(
+                    "label inside the relocated instruction at {}+0x{:x}",
+                    section.name, offset
+                )));
+            }
+            // Relocations that cover no bytes leave them to be copied
+            let consumed = if relocated == 0 {
+                self.lift_bytes(section.segment, contents, offset, next_cut)
+            } else {
+                relocated
+            };
+
+            offset += consumed;
+            while next_reloc < relocations.len()
+                && relocations[next_reloc].r_offset < offset
+            {
+                next_reloc += 1;
+            }
+        }
+
+        if let Some(reloc) = relocations.get(next_reloc) {
+            return Err(object.error(format!(
+                "relocation at {}+0x{:x} is outside the section",
+                section.name, reloc.r_offset
+            )));
+        }
+        if let Some((&label_offset, names)) = labels.range(size + 1..).next() {
+            return Err(object.error(format!(
+                "symbol '{}' at {}+0x{:x} is outside the section",
+                names[0], section.name, label_offset
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copy bytes without relocations up to `end`, returning the count
+    ///
+    /// Text is copied an instruction at a time, data as a run of bytes.
+    fn lift_bytes(
+        &mut self,
+        segment: Segment,
+        contents: &[u8],
+        offset: u32,
+        end: u32,
+    ) -> u32 {
+        let start = offset as usize;
+        match segment {
+            Segment::Bss => {
+                self.directive(Directive::Space(Expression::Literal(
+                    (end - offset) as i32,
+                )));
+                end - offset
+            }
+            Segment::Text
+                if contents[start] & 0b11 == 0b11 && end - offset >= 4 =>
+            {
+                let word = read_u32(contents, start);
+                self.directive(Directive::FourByte(vec![Expression::Literal(
+                    word as i32,
+                )]));
+                4
+            }
+            Segment::Text if end - offset >= 2 => {
+                let half =
+                    u16::from_le_bytes([contents[start], contents[start + 1]]);
+                self.directive(Directive::TwoByte(vec![Expression::Literal(
+                    half as i32,
+                )]));
+                2
+            }
+            Segment::Text | Segment::Data => {
+                let bytes = contents[start..end as usize]
+                    .iter()
+                    .map(|&b| Expression::Literal(b as i32))
+                    .collect();
+                self.directive(Directive::Byte(bytes));
+                end - offset
+            }
+        }
+    }
+
+    /// Turn a relocated instruction (or word) back into source, returning
+    /// the number of bytes it covers
+    fn lift_relocated(
+        &mut self,
+        section: &ContentSection,
+        here: &[&ElfRela],
+        offset: u32,
+    ) -> Result<u32> {
+        let object = self.object;
+        let contents = object.contents(section);
+        let relax = here.iter().any(|r| r.typ() == R_RISCV_RELAX);
+        let Some(reloc) = here.iter().find(|r| r.typ() != R_RISCV_RELAX) else {
+            // A lone R_RISCV_RELAX has nothing to relax
+            return Ok(0);
+        };
+
+        // The assembler reserved `addend` bytes of padding, enough for the
+        // next power of two alignment wherever relaxation leaves the code
+        if reloc.typ() == R_RISCV_ALIGN {
+            let reserved = reloc.r_addend.max(0) as u32;
+            let alignment = (reserved + 1).next_power_of_two();
+            self.directive(Directive::Balign(Expression::Literal(
+                alignment as i32,
+            )));
+            return Ok(reserved.min(section.header.sh_size - offset));
+        }
+
+        let unsupported = || {
+            object.error(format!(
+                "unsupported use of relocation type {} at {}+0x{:x}",
+                reloc.typ(),
+                section.name,
+                offset
+            ))
+        };
+        let available = section.header.sh_size - offset;
+        let needed = match reloc.typ() {
+            R_RISCV_CALL | R_RISCV_CALL_PLT | R_RISCV_PCREL_HI20 => 8,
+            R_RISCV_RVC_BRANCH | R_RISCV_RVC_JUMP => 2,
+            _ => 4,
+        };
+        if available < needed || section.header.sh_type == SHT_NOBITS {
+            return Err(unsupported());
+        }
+
+        let start = offset as usize;
+        let word = read_u32(contents, start);
+
+        // A label difference: R_RISCV_ADDn and R_RISCV_SUBn at one offset
+        if let Some((width, sub_type)) = match reloc.typ() {
+            R_RISCV_ADD8 => Some((1, R_RISCV_SUB8)),
+            R_RISCV_ADD16 => Some((2, R_RISCV_SUB16)),
+            R_RISCV_ADD32 => Some((4, R_RISCV_SUB32)),
+            _ => None,
+        } {
+            let sub = here
+                .iter()
+                .find(|r| r.typ() == sub_type)
+                .ok_or_else(unsupported)?;
+            let difference = Expression::MinusOp {
+                lhs: Box::new(self.target_expression(reloc)?),
+                rhs: Box::new(grouped(self.target_expression(sub)?)),
+            };
+            self.directive(match width {
+                1 => Directive::Byte(vec![difference]),
+                2 => Directive::TwoByte(vec![difference]),
+                _ => Directive::FourByte(vec![difference]),
+            });
+            return Ok(width);
+        }
+
+        if reloc.typ() == R_RISCV_32 {
+            let target = self.target_expression(reloc)?;
+            self.directive(Directive::FourByte(vec![target]));
+            return Ok(4);
+        }
+        if section.segment != Segment::Text {
+            return Err(unsupported());
+        }
+
+        // The %pcrel_lo of a non-adjacent pair uses the auipc's target and
+        // address; everything else uses the relocation's own target
+        let target = match reloc.typ() {
+            R_RISCV_PCREL_LO12_I | R_RISCV_PCREL_LO12_S => {
+                let hi_offset = self
+                    .pcrel_lo_target(section, reloc)
+                    .ok_or_else(unsupported)?;
+                let hi = section
+                    .relocations
+                    .iter()
+                    .find(|r| {
+                        r.r_offset == hi_offset && r.typ() == R_RISCV_PCREL_HI20
+                    })
+                    .ok_or_else(unsupported)?;
+                self.pcrel_offset(section, hi)?
+            }
+            _ => self.target_expression(reloc)?,
+        };
+
+        let (instruction, size) = match reloc.typ() {
+            R_RISCV_CALL | R_RISCV_CALL_PLT => {
+                let jalr = read_u32(contents, start + 4);
+                let auipc_rd = rd(word);
+                if opcode(word) != 0x17
+                    || opcode(jalr) != 0x67
+                    || rs1(jalr) != auipc_rd
+                {
+                    return Err(unsupported());
+                }
+                let pseudo = match (auipc_rd, rd(jalr)) {
+                    (Register::X0, _) => return Err(unsupported()),
+                    (temp, Register::X0) => {
+                        PseudoOp::Tail(Box::new(target), temp)
+                    }
+                    (link, jalr_rd) if jalr_rd == link => {
+                        PseudoOp::Call(link, Box::new(target))
+                    }
+                    _ => return Err(unsupported()),
+                };
+                (Instruction::Pseudo(pseudo), 8)
+            }
+            R_RISCV_PCREL_HI20 if opcode(word) == 0x17 => {
+                match self.pcrel_pseudo(section, offset)? {
+                    Some(pseudo) => (Instruction::Pseudo(pseudo), 8),
+                    None => {
+                        let imm = Expression::HiOp {
+                            expr: Box::new(self.pcrel_offset(section, reloc)?),
+                        };
+                        let instruction = Instruction::UType(
+                            UTypeOp::Auipc,
+                            rd(word),
+                            Box::new(imm),
+                        );
+                        (instruction, 4)
+                    }
+                }
+            }
+            R_RISCV_HI20 if opcode(word) == 0x37 => {
+                let imm = Expression::HiOp { expr: Box::new(target) };
+                (Instruction::UType(UTypeOp::Lui, rd(word), Box::new(imm)), 4)
+            }
+            R_RISCV_LO12_I | R_RISCV_PCREL_LO12_I => {
+                let imm = Box::new(Expression::LoOp { expr: Box::new(target) });
+                let instruction = match opcode(word) {
+                    0x13 => Instruction::IType(
+                        alu_immediate_op(word).ok_or_else(unsupported)?,
+                        rd(word),
+                        rs1(word),
+                        imm,
+                    ),
+                    0x67 if funct3(word) == 0 => Instruction::IType(
+                        ITypeOp::Jalr,
+                        rd(word),
+                        rs1(word),
+                        imm,
+                    ),
+                    0x03 => Instruction::LoadStore(
+                        load_op(word).ok_or_else(unsupported)?,
+                        rd(word),
+                        imm,
+                        rs1(word),
+                    ),
+                    _ => return Err(unsupported()),
+                };
+                (instruction, 4)
+            }
+            R_RISCV_LO12_S | R_RISCV_PCREL_LO12_S if opcode(word) == 0x23 => {
+                let imm = Box::new(Expression::LoOp { expr: Box::new(target) });
+                let op = store_op(word).ok_or_else(unsupported)?;
+                (Instruction::LoadStore(op, rs2(word), imm, rs1(word)), 4)
+            }
+            R_RISCV_BRANCH if opcode(word) == 0x63 => {
+                let op = branch_op(word).ok_or_else(unsupported)?;
+                let instruction = Instruction::BType(
+                    op,
+                    rs1(word),
+                    rs2(word),
+                    Box::new(target),
+                );
+                (instruction, 4)
+            }
+            R_RISCV_JAL if opcode(word) == 0x6f => (
+                Instruction::JType(JTypeOp::Jal, rd(word), Box::new(target)),
+                4,
+            ),
+            R_RISCV_RVC_BRANCH => {
+                let half = word & 0xffff;
+                let op = match (half & 0b11, half >> 13) {
+                    (0b01, 0b110) => CompressedOp::CBeqz,
+                    (0b01, 0b111) => CompressedOp::CBnez,
+                    _ => return Err(unsupported()),
+                };
+                let rs1_prime = REGISTERS[(8 + ((half >> 7) & 0b111)) as usize];
+                let operands = CompressedOperands::CBBranch {
+                    rs1_prime,
+                    offset: Box::new(target),
+                };
+                (Instruction::Compressed(op, operands), 2)
+            }
+            R_RISCV_RVC_JUMP => {
+                let half = word & 0xffff;
+                let op = match (half & 0b11, half >> 13) {
+                    (0b01, 0b101) => CompressedOp::CJComp,
+                    (0b01, 0b001) => CompressedOp::CJalComp,
+                    _ => return Err(unsupported()),
+                };
+                let operands =
+                    CompressedOperands::CJOpnd { offset: Box::new(target) };
+                (Instruction::Compressed(op, operands), 2)
+            }
+            _ => return Err(unsupported()),
+        };
+
+        // Without R_RISCV_RELAX the instruction must keep its size
+        let keep_size =
+            !relax && !matches!(instruction, Instruction::Compressed(..));
+        if keep_size {
+            self.directive(Directive::Option(OptionArg::Push));
+            self.directive(Directive::Option(OptionArg::NoRelax));
+            self.directive(Directive::Option(OptionArg::NoRvc));
+        }
+        self.push(LineContent::Instruction(instruction));
+        if keep_size {
+            self.directive(Directive::Option(OptionArg::Pop));
+        }
+        Ok(size)
+    }
+
+    /// The pseudo-instruction an auipc and the instruction after it came
+    /// from, if they form an adjacent PC-relative pair
+    ///
+    /// `la`, a global load and a global store are lifted whole, so that the
+    /// linker can relax them like the assembler would.
+    fn pcrel_pseudo(
+        &mut self,
+        section: &ContentSection,
+        offset: u32,
+    ) -> Result<Option<PseudoOp>> {
+        let contents = self.object.contents(section);
+        if offset + 8 > section.header.sh_size
+            || self.label_at(section, offset + 4).is_some()
+        {
+            return Ok(None);
+        }
+        let paired = section.relocations.iter().any(|r| {
+            r.r_offset == offset + 4
+                && matches!(
+                    r.typ(),
+                    R_RISCV_PCREL_LO12_I | R_RISCV_PCREL_LO12_S
+                )
+                && self.pcrel_lo_target(section, r) == Some(offset)
+        });
+        let auipc = read_u32(contents, offset as usize);
+        let second = read_u32(contents, offset as usize + 4);
+        let temp = rd(auipc);
+        if !paired || opcode(auipc) != 0x17 || rs1(second) != temp {
+            return Ok(None);
+        }
+        let Some(hi) = section
+            .relocations
+            .iter()
+            .find(|r| r.r_offset == offset && r.typ() == R_RISCV_PCREL_HI20)
+        else {
+            return Ok(None);
+        };
+        let target = Box::new(self.target_expression(hi)?);
+        Ok(match opcode(second) {
+            0x13 if funct3(second) == 0 && rd(second) == temp => {
+                Some(PseudoOp::La(temp, target))
+            }
+            0x03 if rd(second) == temp => {
+                load_op(second).map(|op| PseudoOp::LoadGlobal(op, temp, target))
+            }
+            0x23 => store_op(second)
+                .map(|op| PseudoOp::StoreGlobal(op, rs2(second), target, temp)),
+            _ => None,
+        })
+    }
+
+    /// `target - label` for the auipc of a PCREL_HI20 relocation, where
+    /// the label is at the auipc: the offset that its %pcrel_hi and
+    /// %pcrel_lo parts add up to
+    fn pcrel_offset(
+        &mut self,
+        section: &ContentSection,
+        hi: &ElfRela,
+    ) -> Result<Expression> {
+        let label = self.label_at(section, hi.r_offset).ok_or_else(|| {
+            RiscletError::internal("auipc without a label".to_string())
+        })?;
+        Ok(Expression::MinusOp {
+            lhs: Box::new(grouped(self.target_expression(hi)?)),
+            rhs: Box::new(Expression::Identifier(label)),
+        })
+    }
+
+    /// The first label at an offset in a section
+    fn label_at(
+        &self,
+        section: &ContentSection,
+        offset: u32,
+    ) -> Option<String> {
+        self.labels
+            .get(&section.index)
+            .and_then(|labels| labels.get(&offset))
+            .and_then(|names| names.first())
+            .cloned()
+    }
+
+    /// The offset of the auipc a PCREL_LO12 relocation refers to, if it is
+    /// in `section`
+    fn pcrel_lo_target(
+        &self,
+        section: &ContentSection,
+        reloc: &ElfRela,
+    ) -> Option<u32> {
+        let (_, symbol) = self.object.symbol(reloc.symbol()).ok()?;
+        (symbol.st_shndx as usize == section.index)
+            .then(|| symbol.st_value.wrapping_add(reloc.r_addend as u32))
+    }
+}
+
+/// `name`, `name + addend` or `name - addend`
+fn offset_expression(name: String, addend: i32) -> Expression {
+    let symbol = Box::new(Expression::Identifier(name));
+    match addend {
+        0 => *symbol,
+        a if a > 0 => Expression::PlusOp {
+            lhs: symbol,
+            rhs: Box::new(Expression::Literal(a)),
+        },
+        a => Expression::MinusOp {
+            lhs: symbol,
+            rhs: Box::new(Expression::Literal(a.wrapping_neg())),
+        },
+    }
+}
+
+/// An expression as an operand of a binary operator
+fn grouped(expr: Expression) -> Expression {
+    match expr {
+        Expression::Identifier(_) | Expression::Literal(_) => expr,
+        _ => Expression::Parenthesized(Box::new(expr)),
+    }
+}
+
+// ============================================================================
+// Instruction Field Decoding
+// ============================================================================
+
+fn read_u32(contents: &[u8], start: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = contents.get(start + i).copied().unwrap_or(0);
+    }
+    u32::from_le_bytes(bytes)
+}
+
+fn opcode(inst: u32) -> u32 {
+    inst & 0x7f
+}
+
+fn funct3(inst: u32) -> u32 {
+    (inst >> 12) & 0b111
+}
+
+fn rd(inst: u32) -> Register {
+    REGISTERS[((inst >> 7) & 0x1f) as usize]
+}
+
+fn rs1(inst: u32) -> Register {
+    REGISTERS[((inst >> 15) & 0x1f) as usize]
+}
+
+fn rs2(inst: u32) -> Register {
+    REGISTERS[((inst >> 20) & 0x1f) as usize]
+}
+
+fn branch_op(inst: u32) -> Option<BTypeOp> {
+    match funct3(inst) {
+        0b000 => Some(BTypeOp::Beq),
+        0b001 => Some(BTypeOp::Bne),
+        0b100 => Some(BTypeOp::Blt),
+        0b101 => Some(BTypeOp::Bge),
+        0b110 => Some(BTypeOp::Bltu),
+        0b111 => Some(BTypeOp::Bgeu),
+        _ => None,
+    }
+}
+
+fn load_op(inst: u32) -> Option<LoadStoreOp> {
+    match funct3(inst) {
+        0b000 => Some(LoadStoreOp::Lb),
+        0b001 => Some(LoadStoreOp::Lh),
+        0b010 => Some(LoadStoreOp::Lw),
+        0b100 => Some(LoadStoreOp::Lbu),
+        0b101 => Some(LoadStoreOp::Lhu),
+        _ => None,
+    }
+}
+
+fn store_op(inst: u32) -> Option<LoadStoreOp> {
+    match funct3(inst) {
+        0b000 => Some(LoadStoreOp::Sb),
+        0b001 => Some(LoadStoreOp::Sh),
+        0b010 => Some(LoadStoreOp::Sw),
+        _ => None,
+    }
+}
+
+/// The I-type ALU instructions that take a 12-bit immediate (not shifts)
+fn alu_immediate_op(inst: u32) -> Option<ITypeOp> {
+    match funct3(inst) {
+        0b000 => Some(ITypeOp::Addi),
+        0b010 => Some(ITypeOp::Slti),
+        0b011 => Some(ITypeOp::Sltiu),
+        0b100 => Some(ITypeOp::Xori),
+        0b110 => Some(ITypeOp::Ori),
+        0b111 => Some(ITypeOp::Andi),
+        _ => None,
+    }
+}
@@ -0,0 +1,523 @@
+// Tests for relocatable object output (object_builder) and linking
+// (object_loader)
+//
+// Most tests assemble each file to an object, link the objects, and check
+// that the result loads exactly the same bytes as assembling all the sources
+// in one step.
+
+use crate::assembler::{
+    assemble, assemble_object, assemble_object_collecting_warnings,
+    assemble_parsed, parse_source_from_string,
+};
+use crate::ast::{LineContent, SourceFile};
+use crate::config::{Config, Mode, Relax};
+use crate::elf::{
+    ElfHeader, ElfProgramHeader, ElfRela, ElfSectionHeader, PT_LOAD,
+    R_RISCV_ALIGN, R_RISCV_BRANCH, R_RISCV_CALL, R_RISCV_PCREL_HI20,
+    R_RISCV_RELAX, R_RISCV_RVC_JUMP, RELA_ENTRY_SIZE, SHF_EXECINSTR,
+    SHT_PROGBITS, SHT_RELA,
+};
+use crate::elf_loader::{ElfInput, load_elf};
+use crate::execution::Machine;
+use crate::object_loader::load_object;
+use crate::riscv::{Op, fields_to_disassembly};
+use crate::simulator::load_program;
+use crate::test_helpers::{run_to_ecall, run_to_exit};
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn make_config(compressed: bool) -> Config {
+    Config {
+        mode: Mode::Link,
+        max_steps: 1_000_000,
+        relax: Relax { gp: None, pseudo: true, compressed, branches: true },
+        ..Config::assemble_default()
+    }
+}
+
+fn object(file_name: &str, source: &str, compressed: bool) -> Vec<u8> {
+    assemble_object(&mut make_config(compressed), file_name, source)
+        .unwrap_or_else(|e| panic!("{}: {}", file_name, e))
+}
+
+/// Assemble each file to an object, then link the objects
+fn link(files: &[(&str, &str)], compressed: bool) -> Result<Vec<u8>, String> {
+    let mut sources = Vec::new();
+    for (name, code) in files {
+        let object_name = name.replace(".s", ".o");
+        let bytes = object(name, code, compressed);
+        sources.push(
+            load_object(&object_name, &bytes).map_err(|e| e.to_string())?,
+        );
+    }
+    assemble_parsed(&mut make_config(compressed), sources)
+        .map_err(|e| e.to_string())
+}
+
+/// Assemble all files in one step
+fn assemble_directly(files: &[(&str, &str)], compressed: bool) -> Vec<u8> {
+    let sources = files
+        .iter()
+        .map(|(name, code)| (name.to_string(), code.to_string()))
+        .collect();
+    assemble(&mut make_config(compressed), sources).unwrap()
+}
+
+/// The address, memory size and file contents of each PT_LOAD segment
+///
+/// The ELF headers at the start of the text segment are skipped, since
+/// section header offsets depend on the symbol table.
+fn load_segments(elf: &[u8]) -> Vec<(u32, u32, Vec<u8>)> {
+    let header = ElfHeader::decode(elf).unwrap();
+    let headers_end = header.e_phoff as usize
+        + header.e_phnum as usize * header.e_phentsize as usize;
+    (0..header.e_phnum as usize)
+        .map(|i| {
+            let offset =
+                header.e_phoff as usize + i * header.e_phentsize as usize;
+            ElfProgramHeader::decode(&elf[offset..]).unwrap()
+        })
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .map(|ph| {
+            let start = (ph.p_offset as usize).max(headers_end);
+            let bytes = elf[start..ph.p_offset as usize + ph.p_filesz as usize]
+                .to_vec();
+            (ph.p_vaddr, ph.p_memsz, bytes)
+        })
+        .collect()
+}
+
+fn assert_link_matches(files: &[(&str, &str)], compressed: bool) {
+    let linked = link(files, compressed).unwrap();
+    let direct = assemble_directly(files, compressed);
+    assert_eq!(load_segments(&linked), load_segments(&direct));
+}
+
+/// All relocations in an object, as (offset, type)
+fn relocations(object: &[u8]) -> Vec<(u32, u8)> {
+    let header = ElfHeader::decode(object).unwrap();
+    let mut result = Vec::new();
+    for i in 0..header.e_shnum as usize {
+        let offset = header.e_shoff as usize + i * header.e_shentsize as usize;
+        let sh = ElfSectionHeader::decode(&object[offset..]).unwrap();
+        if sh.sh_type != SHT_RELA {
+            continue;
+        }
+        let start = sh.sh_offset as usize;
+        for entry in
+            object[start..start + sh.sh_size as usize].chunks(RELA_ENTRY_SIZE)
+        {
+            let rela = ElfRela::decode(entry).unwrap();
+            result.push((rela.r_offset, rela.typ()));
+        }
+    }
+    result
+}
+
+const MAIN: &str = r#"
+.globl _start
+.text
+_start:
+    la a0, message
+    call print
+    lw t0, counter
+    addi t0, t0, 1
+    sw t0, counter, t1
+    lui a1, %hi(table)
+    addi a1, a1, %lo(table)
+    lw a2, 4(a1)
+    beqz a2, done
+    j done
+done:
+    li a7, 93
+    ecall
+.data
+message: .asciz "hello\n"
+.balign 4
+counter: .4byte 5
+table: .4byte done, print
+"#;
+
+const LIB: &str = r#"
+.globl print
+.text
+print:
+    mv a1, a0
+    li a2, 6
+    li a0, 1
+    li a7, 64
+    ecall
+    ret
+"#;
+
+// ============================================================================
+// OBJECT OUTPUT
+// ============================================================================
+
+#[test]
+fn test_object_relocations() {
+    let relocs = relocations(&object("main.s", MAIN, false));
+    assert!(relocs.contains(&(0, R_RISCV_PCREL_HI20)));
+    assert!(relocs.contains(&(0, R_RISCV_RELAX)));
+    assert!(relocs.contains(&(8, R_RISCV_CALL)));
+}
+
+#[test]
+fn test_object_norelax_omits_relax() {
+    let source = ".option norelax\n.globl f\nf:\n    call g\n";
+    let relocs = relocations(&object("f.s", source, false));
+    assert_eq!(relocs, vec![(0, R_RISCV_CALL)]);
+}
+
+#[test]
+fn test_object_branch_to_external_is_relocated() {
+    // Too far from 0 for the branches to reach an undefined symbol there
+    let a = r#"
+.globl _start
+_start:
+    j 1f
+    .space 8192
+1:  beqz zero, finish
+    c.j finish
+"#;
+    let b = ".globl finish\nfinish:\n    li a7, 93\n    ecall\n";
+    let relocs = relocations(&object("a.s", a, false));
+    assert!(relocs.contains(&(8196, R_RISCV_BRANCH)), "{:?}", relocs);
+    assert!(relocs.contains(&(8200, R_RISCV_RVC_JUMP)), "{:?}", relocs);
+    assert_link_matches(&[("a.s", a), ("b.s", b)], false);
+}
+
+#[test]
+fn test_object_far_branch_is_error_without_flag_advice() {
+    let source = "beq a0, a1, far\n.space 5000\nfar:\nnop\n";
+    let result = assemble_object(&mut make_config(false), "far.s", source);
+    let message = result.unwrap_err().to_string();
+    assert!(message.contains("5004 bytes away"), "{}", message);
+    assert!(!message.contains("--relax-branches"), "{}", message);
+}
+
+#[test]
+fn test_load_rejects_executable() {
+    let elf = assemble_directly(&[("main.s", MAIN), ("lib.s", LIB)], false);
+    assert!(load_object("a.out", &elf).is_err());
+}
+
+#[test]
+fn test_load_rejects_truncated_object() {
+    // Point .text past the end of the file, as in a truncated object
+    let mut bytes = object("main.s", MAIN, false);
+    let header = ElfHeader::decode(&bytes).unwrap();
+    for i in 0..header.e_shnum as usize {
+        let offset = header.e_shoff as usize + i * header.e_shentsize as usize;
+        let mut sh = ElfSectionHeader::decode(&bytes[offset..]).unwrap();
+        if sh.sh_type == SHT_PROGBITS && sh.sh_flags & SHF_EXECINSTR != 0 {
+            sh.sh_offset = bytes.len() as u32 - 4;
+            bytes[offset..offset + 40].copy_from_slice(&sh.encode());
+        }
+    }
+    let Err(error) = load_object("main.o", &bytes) else {
+        panic!("a truncated object should not load");
+    };
+    assert!(error.to_string().contains("beyond end of file"), "{}", error);
+}
+
+// ============================================================================
+// LINKING
+// ============================================================================
+
+#[test]
+fn test_link_matches_direct_assembly() {
+    assert_link_matches(&[("main.s", MAIN), ("lib.s", LIB)], false);
+}
+
+#[test]
+fn test_link_matches_direct_assembly_compressed() {
+    assert_link_matches(&[("main.s", MAIN), ("lib.s", LIB)], true);
+}
+
+#[test]
+fn test_link_common_weak_and_tail() {
+    let a = r#"
+.equ EXIT, 93
+.globl _start
+.comm buffer, 16, 4
+.lcomm scratch, 8
+_start:
+    la a0, buffer
+    li t0, 7
+    sw t0, 0(a0)
+    la a1, scratch
+    sw t0, 4(a1)
+    call helper
+    li a7, EXIT
+    ecall
+.data
+.2byte 3
+.byte 1, 2, 3
+.4byte helper + 4
+"#;
+    let b = r#"
+.comm buffer, 16, 4
+.weak maybe
+.globl helper
+.text
+helper:
+    lw a0, buffer
+    la t0, maybe
+    beqz t0, 1f
+    nop
+1:  tail finish
+finish:
+    ret
+"#;
+    assert_link_matches(&[("a.s", a), ("b.s", b)], false);
+    assert_link_matches(&[("a.s", a), ("b.s", b)], true);
+}
+
+#[test]
+fn test_common_object_has_no_warnings() {
+    // The hidden size and alignment symbols are not reported as unused
+    let source = r#"
+.globl _start
+.comm shared, 16, 4
+_start:
+    la a0, shared
+    li a7, 93
+    ecall
+"#;
+    let mut warnings = Vec::new();
+    assemble_object_collecting_warnings(
+        &mut make_config(false),
+        "common.s",
+        source,
+        &mut warnings,
+    )
+    .unwrap();
+    assert_eq!(warnings, []);
+}
+
+#[test]
+fn test_link_realigns_relaxed_text() {
+    // The call shrinks when linked, and the padding must grow to match
+    let source = r#"
+.globl _start
+_start:
+    call target
+    .balign 16
+target:
+    li a7, 93
+    ecall
+"#;
+    let relocs = relocations(&object("align.s", source, false));
+    assert!(relocs.contains(&(8, R_RISCV_ALIGN)));
+    assert_link_matches(&[("align.s", source)], false);
+    assert_link_matches(&[("align.s", source)], true);
+}
+
+#[test]
+fn test_link_undefined_symbol_is_error() {
+    let result = link(&[("main.s", MAIN)], false);
+    assert!(result.unwrap_err().contains("print"));
+}
+
+const LOCALS: &str = r#"
+.globl _start
+.text
+_start:
+    li a0, 3
+.Lloop:
+    addi a0, a0, -1
+    bnez a0, .Lloop
+    li a7, 93
+    ecall
+.data
+size: .4byte .Lend - .Lloop
+.Lend:
+"#;
+
+#[test]
+fn test_assembler_local_labels_stay_out_of_symbol_tables() {
+    let is_label = |file: &SourceFile, name: &str| {
+        file.lines.iter().any(
+            |line| matches!(&line.content, LineContent::Label(l) if l == name),
+        )
+    };
+    let file =
+        load_object("locals.o", &object("locals.s", LOCALS, false)).unwrap();
+    assert!(is_label(&file, "size"));
+    assert!(!is_label(&file, ".Lloop"));
+
+    let mut config = make_config(false);
+    let sources = vec![("locals.s".to_string(), LOCALS.to_string())];
+    let elf = assemble(&mut config, sources.clone()).unwrap();
+    let machine = load_elf(ElfInput::Bytes(&elf), &mut Vec::new()).unwrap();
+    let names: Vec<&String> = machine.address_symbols.values().collect();
+    assert!(names.contains(&&"size".to_string()));
+    assert!(!names.iter().any(|name| name.starts_with(".L")));
+    let mut machine = Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+    assert_eq!(run_to_ecall(&mut machine), 0);
+
+    config.keep_locals = true;
+    let file = load_object(
+        "locals.o",
+        &assemble_object(&mut config, "locals.s", LOCALS).unwrap(),
+    )
+    .unwrap();
+    assert!(is_label(&file, ".Lloop"));
+    let elf = assemble(&mut config, sources).unwrap();
+    let machine = load_elf(ElfInput::Bytes(&elf), &mut Vec::new()).unwrap();
+    let names: Vec<&String> = machine.address_symbols.values().collect();
+    assert!(names.contains(&&".Lloop".to_string()));
+    assert!(names.contains(&&".Lend".to_string()));
+}
+
+#[test]
+fn test_calls_to_assembler_local_labels_are_labeled() {
+    // Without a symbol for .Lhelper, the call still shows a label
+    let source = r#"
+.globl _start
+_start:
+    call .Lhelper
+    li a7, 93
+    ecall
+.Lhelper:
+    li a0, 0
+    ret
+"#;
+    let mut config = make_config(false);
+    let sources = vec![("call.s".to_string(), source.to_string())];
+    let elf = assemble(&mut config, sources).unwrap();
+    config.mode = Mode::Run;
+    let program =
+        load_program(&config, ElfInput::Bytes(&elf), &mut Vec::new()).unwrap();
+    let call = &program.instructions[0];
+    let Op::Jal { offset, .. } = call.op else {
+        panic!("expected jal, got {:?}", call.op);
+    };
+    let target = call.address.wrapping_add(offset as u32);
+    let label = &program.machine.address_symbols[&target];
+    let text = fields_to_disassembly(
+        &config,
+        &call.pseudo_fields,
+        call.address,
+        0,
+        false,
+        &program.machine.address_symbols,
+    );
+    assert_eq!(text, format!("jal     {}f", label));
+}
+
+// ============================================================================
+// GNU OBJECTS
+// ============================================================================
+
+// test_binaries/helpers.o is test_binaries/helpers.s assembled for rv32imac
+// with relaxation, as riscv64-unknown-elf-as -march=rv32imac would. It has
+// .rodata/.sdata/.sbss sections, an R_RISCV_ALIGN, a non-adjacent
+// %pcrel_hi/%pcrel_lo pair and an R_RISCV_ADD32/SUB32 label difference.
+const HELPERS: &[u8] = include_bytes!("test_binaries/helpers.o");
+
+const HELPERS_MAIN: &str = r#"
+.globl _start
+.text
+_start:
+    call get_message
+    lbu s0, 0(a0)
+    la a0, numbers
+    li a1, 4
+    call sum_array
+    add a0, a0, s0
+    li a7, 93
+    ecall
+.data
+numbers: .4byte 1, 2, 3, 4
+"#;
+
+fn link_with_helpers(compressed: bool) -> Vec<u8> {
+    let files = vec![
+        parse_source_from_string("main.s", HELPERS_MAIN).unwrap(),
+        load_object("helpers.o", HELPERS).unwrap(),
+    ];
+    assemble_parsed(&mut make_config(compressed), files).unwrap()
+}
+
+#[test]
+fn test_link_object_before_source() {
+    // helpers.o ends in .bss, but main.s still starts in .text
+    let main = HELPERS_MAIN.replacen(".text\n", "", 1);
+    let files = vec![
+        load_object("helpers.o", HELPERS).unwrap(),
+        parse_source_from_string("main.s", &main).unwrap(),
+    ];
+    let elf = assemble_parsed(&mut make_config(false), files).unwrap();
+    let mut machine = Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+    assert_eq!(run_to_ecall(&mut machine), 72 + 10);
+}
+
+#[test]
+fn test_load_gnu_object() {
+    let file = load_object("helpers.o", HELPERS).unwrap();
+    let lines: Vec<String> = file
+        .lines
+        .iter()
+        .map(|line| {
+            line.content
+                .to_string()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    for expected in [
+        ".balign 16",
+        "auipc a0, %hi(message - .Lhi)",
+        "addi a0, a0, %lo(message - .Lhi)",
+        "lw t0, counter",
+        "sw t0, counter, t1",
+        "lui t2, %hi(scratch)",
+        "tail finish",
+        ".4byte message_end - message",
+        ".4byte print_string",
+        ".bss",
+    ] {
+        assert!(lines.iter().any(|l| l == expected), "missing '{}'", expected);
+    }
+}
+
+#[test]
+fn test_link_gnu_object() {
+    // 'H' from the message plus the sum 1 + 2 + 3 + 4
+    for compressed in [false, true] {
+        let elf = link_with_helpers(compressed);
+        let mut machine = Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+        assert_eq!(run_to_ecall(&mut machine), 72 + 10);
+    }
+}
+
+// test_binaries/align.o is test_binaries/align.s assembled with
+// llvm-mc -triple=riscv32 -mattr=+m,+a,+c,+relax -filetype=obj. Its code
+// runs through the padding of an R_RISCV_ALIGN.
+const ALIGN: &[u8] = include_bytes!("test_binaries/align.o");
+
+#[test]
+fn test_link_runs_through_relaxed_alignment() {
+    let main = r#"
+.globl _start
+_start:
+    li a0, 4
+    call add_three
+    li a7, 93
+    ecall
+"#;
+    for compressed in [false, true] {
+        let files = vec![
+            parse_source_from_string("main.s", main).unwrap(),
+            load_object("align.o", ALIGN).unwrap(),
+        ];
+        let elf = assemble_parsed(&mut make_config(compressed), files).unwrap();
+        let mut machine = Machine::from_elf(&elf, &mut Vec::new()).unwrap();
+        assert_eq!(run_to_exit(&mut machine), 7);
+    }
+}
@@ -1,25 +1,199 @@
 use crate::ast::{
     AtomicOp, BTypeOp, CompressedOp, CompressedOperands, Directive,
-    DirectiveOp, Expression, ITypeOp, Instruction, JTypeOp, Line, LineContent,
-    LoadStoreOp, Location, MemoryOrdering, NumericLabelRef, OperatorOp,
-    PseudoOp, RTypeOp, Register, SpecialOp, Token, UTypeOp,
+    DirectiveOp, Expression, ExtendOp, ITypeOp, Instruction, JTypeOp, Line,
+    LineContent, LineSpans, LoadStoreOp, Location, MemoryOrdering,
+    NumericLabelRef, OperatorOp, OptionArg, PseudoOp, RTypeOp, Register, Span,
+    SpecialOp, SymbolType, Token, UTypeOp,
 };
 use crate::error::{Result, RiscletError};
+use crate::suggest::did_you_mean;
+use crate::tokenizer::REGISTER_NAMES;
+
+/// Every instruction mnemonic `parse_instruction` accepts, except the
+/// compressed (c.*) forms and the ordering suffixes of atomics. This is the
+/// list misspelled mnemonics are matched against.
+pub const MNEMONICS: &[&str] = &[
+    "add",
+    "sub",
+    "sll",
+    "slt",
+    "sltu",
+    "xor",
+    "srl",
+    "sra",
+    "or",
+    "and",
+    "mul",
+    "mulh",
+    "mulhsu",
+    "mulhu",
+    "div",
+    "divu",
+    "rem",
+    "remu",
+    "addi",
+    "slli",
+    "slti",
+    "sltiu",
+    "xori",
+    "ori",
+    "andi",
+    "srli",
+    "srai",
+    "jalr",
+    "beq",
+    "bne",
+    "blt",
+    "bge",
+    "bltu",
+    "bgeu",
+    "bgez",
+    "bnez",
+    "lui",
+    "auipc",
+    "jal",
+    "fence",
+    "fence.tso",
+    "fence.i",
+    "ecall",
+    "ebreak",
+    "lb",
+    "lh",
+    "lw",
+    "lbu",
+    "lhu",
+    "sb",
+    "sh",
+    "sw",
+    "li",
+    "la",
+    "lla",
+    "lga",
+    "call",
+    "tail",
+    "jump",
+    "mv",
+    "ret",
+    "nop",
+    "neg",
+    "seqz",
+    "snez",
+    "sltz",
+    "sgtz",
+    "sgt",
+    "sgtu",
+    "sle",
+    "sleu",
+    "sext.b",
+    "sext.h",
+    "zext.b",
+    "zext.h",
+    "beqz",
+    "blez",
+    "bltz",
+    "bgtz",
+    "bgt",
+    "ble",
+    "bgtu",
+    "bleu",
+    "j",
+    "jr",
+    "not",
+    "lr.w",
+    "sc.w",
+    "amoswap.w",
+    "amoadd.w",
+    "amoxor.w",
+    "amoand.w",
+    "amoor.w",
+    "amomin.w",
+    "amomax.w",
+    "amominu.w",
+    "amomaxu.w",
+];
 
 pub struct Parser<'a> {
     tokens: &'a [Token],
+    /// The columns of each token, or empty if they are not known
+    spans: &'a [Span],
     pos: usize,
     file: String,
     line: usize,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a [Token], file: String, line: usize) -> Self {
-        Parser { tokens, pos: 0, file, line }
+    pub fn new(
+        tokens: &'a [Token],
+        spans: &'a [Span],
+        file: String,
+        line: usize,
+    ) -> Self {
+        Parser { tokens, spans, pos: 0, file, line }
     }
 
+    /// The location of the next token, or of the end of the line
     fn location(&self) -> Location {
-        Location { file: self.file.clone(), line: self.line }
+        let location = Location::new(&self.file, self.line);
+        match (self.spans.get(self.pos), self.spans.last()) {
+            (Some(&span), _) => location.with_span(span),
+            (None, Some(last)) => {
+                location.with_span(Span { start: last.end, end: last.end + 1 })
+            }
+            (None, None) => location,
+        }
+    }
+
+    /// The location of the token just consumed
+    fn previous_location(&self) -> Location {
+        let location = Location::new(&self.file, self.line);
+        match self.pos.checked_sub(1).and_then(|i| self.spans.get(i)) {
+            Some(&span) => location.with_span(span),
+            None => location,
+        }
+    }
+
+    /// The spans of the content (mnemonic or directive and its operands)
+    /// in `tokens[start..end]`
+    fn content_spans(&self, start: usize, end: usize) -> LineSpans {
+        if self.spans.len() != self.tokens.len() || start >= end {
+            return LineSpans::default();
+        }
+        let mut spans =
+            LineSpans { head: Some(self.spans[start]), ..Default::default() };
+        let mut depth = 0;
+        let mut operand: Option<Span> = None;
+        for index in start + 1..end {
+            let span = self.spans[index];
+            match &self.tokens[index] {
+                Token::Comma if depth == 0 => {
+                    spans.operands.extend(operand.take());
+                    continue;
+                }
+                Token::OpenParen => depth += 1,
+                Token::CloseParen => depth -= 1,
+                Token::Identifier(name) => {
+                    match (&self.tokens[index - 1], name.as_str()) {
+                        // %hi and %lo are operators, not symbols
+                        (Token::Operator(OperatorOp::Modulo), _) => {}
+                        // Numeric label references are written 1f or 1b
+                        (Token::Integer(n), "f" | "b") if index > start + 1 => {
+                            spans.symbols.push((
+                                format!("{}{}", n, name),
+                                self.spans[index - 1].to(span),
+                            ));
+                        }
+                        _ => spans.symbols.push((name.clone(), span)),
+                    }
+                }
+                Token::Register(register) => {
+                    spans.registers.push((*register, spans.operands.len()))
+                }
+                _ => {}
+            }
+            operand = Some(operand.map_or(span, |o| o.to(span)));
+        }
+        spans.operands.extend(operand);
+        spans
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -85,13 +259,14 @@ impl<'a> Parser<'a> {
     // Grammar: ident
     // Example: foo
     fn parse_identifier(&mut self) -> Result<String> {
+        let location = self.location();
         if let Some(Token::Identifier(s)) = self.next() {
             Ok(s)
         } else {
             Err(RiscletError::from_context(
                 "Expected an identifier (label, symbol, or directive name)"
                     .to_string(),
-                self.location(),
+                location,
             ))
         }
     }
@@ -99,21 +274,79 @@ impl<'a> Parser<'a> {
     // Grammar: reg
     // Example: a0
     fn parse_register(&mut self) -> Result<Register> {
-        if let Some(Token::Register(r)) = self.next() {
-            Ok(r)
-        } else {
-            Err(RiscletError::from_context(
-                "Expected a register name (x0-x31, a0-a7, sp, ra, etc.)"
-                    .to_string(),
-                self.location(),
-            ))
+        let location = self.location();
+        match self.next() {
+            Some(Token::Register(r)) => Ok(r),
+            token => {
+                let hint = match token {
+                    Some(Token::Identifier(s)) => {
+                        did_you_mean(&s, REGISTER_NAMES.iter().copied())
+                    }
+                    _ => String::new(),
+                };
+                Err(RiscletError::from_context(
+                    format!(
+                        "Expected a register name (x0-x31, a0-a7, sp, ra, etc.){}",
+                        hint
+                    ),
+                    location,
+                ))
+            }
         }
     }
 
-    // Grammar: exp (calls parse_bitwise_or) (part of expression grammar)
+    // Grammar: exp (calls parse_conditional) (part of expression grammar)
     // Example: a + b * c
     fn parse_expression(&mut self) -> Result<Expression> {
-        self.parse_bitwise_or()
+        self.parse_conditional()
+    }
+
+    // Grammar: conditional ::= logical_or ( ? exp : conditional )? (part of expression grammar)
+    // Examples: a ? b : c, a > 0 ? a : -a
+    fn parse_conditional(&mut self) -> Result<Expression> {
+        let condition = self.parse_logical_or()?;
+        if let Some(Token::Operator(OperatorOp::Question)) = self.peek() {
+            self.next();
+            let then_expr = self.parse_expression()?;
+            self.expect(&Token::Colon)?;
+            let else_expr = self.parse_conditional()?;
+            return Ok(Expression::ConditionalOp {
+                condition: Box::new(condition),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            });
+        }
+        Ok(condition)
+    }
+
+    // Grammar: logical_or ::= logical_and ( || logical_and )* (part of expression grammar)
+    // Example: a || b
+    fn parse_logical_or(&mut self) -> Result<Expression> {
+        let mut left = self.parse_logical_and()?;
+        while let Some(Token::Operator(OperatorOp::LogicalOr)) = self.peek() {
+            self.next();
+            let right = self.parse_logical_and()?;
+            left = Expression::LogicalOrOp {
+                lhs: Box::new(left),
+                rhs: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    // Grammar: logical_and ::= bitwise_or ( && bitwise_or )* (part of expression grammar)
+    // Example: a && b
+    fn parse_logical_and(&mut self) -> Result<Expression> {
+        let mut left = self.parse_bitwise_or()?;
+        while let Some(Token::Operator(OperatorOp::LogicalAnd)) = self.peek() {
+            self.next();
+            let right = self.parse_bitwise_or()?;
+            left = Expression::LogicalAndOp {
+                lhs: Box::new(left),
+                rhs: Box::new(right),
+            };
+        }
+        Ok(left)
     }
 
     // Grammar: bitwise_or ::= bitwise_xor ( | bitwise_xor )* (part of expression grammar)
@@ -156,15 +389,15 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
-    // Grammar: bitwise_and ::= shift ( & shift )* (part of expression grammar)
+    // Grammar: bitwise_and ::= equality ( & equality )* (part of expression grammar)
     // Example: a & b & c
     fn parse_bitwise_and(&mut self) -> Result<Expression> {
-        let mut left = self.parse_shift()?;
+        let mut left = self.parse_equality()?;
         while let Some(op) = self.peek() {
             match op {
                 Token::Operator(OperatorOp::BitwiseAnd) => {
                     self.next();
-                    let right = self.parse_shift()?;
+                    let right = self.parse_equality()?;
                     left = Expression::BitwiseAndOp {
                         lhs: Box::new(left),
                         rhs: Box::new(right),
@@ -176,6 +409,62 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
+    // Grammar: equality ::= relational ( == relational | != relational )* (part of expression grammar)
+    // Examples: a == b, c != d
+    fn parse_equality(&mut self) -> Result<Expression> {
+        let mut left = self.parse_relational()?;
+        while let Some(op) = self.peek() {
+            match op {
+                Token::Operator(OperatorOp::Equal) => {
+                    self.next();
+                    let right = self.parse_relational()?;
+                    left = Expression::EqualOp {
+                        lhs: Box::new(left),
+                        rhs: Box::new(right),
+                    };
+                }
+                Token::Operator(OperatorOp::NotEqual) => {
+                    self.next();
+                    let right = self.parse_relational()?;
+                    left = Expression::NotEqualOp {
+                        lhs: Box::new(left),
+                        rhs: Box::new(right),
+                    };
+                }
+                _ => break,
+            }
+        }
+        Ok(left)
+    }
+
+    // Grammar: relational ::= shift ( < shift | <= shift | > shift | >= shift )* (part of expression grammar)
+    // Examples: a < b, c >= d
+    fn parse_relational(&mut self) -> Result<Expression> {
+        let mut left = self.parse_shift()?;
+        while let Some(Token::Operator(op)) = self.peek() {
+            let op = *op;
+            if !matches!(
+                op,
+                OperatorOp::Less
+                    | OperatorOp::LessEqual
+                    | OperatorOp::Greater
+                    | OperatorOp::GreaterEqual
+            ) {
+                break;
+            }
+            self.next();
+            let lhs = Box::new(left);
+            let rhs = Box::new(self.parse_shift()?);
+            left = match op {
+                OperatorOp::Less => Expression::LessOp { lhs, rhs },
+                OperatorOp::LessEqual => Expression::LessEqualOp { lhs, rhs },
+                OperatorOp::Greater => Expression::GreaterOp { lhs, rhs },
+                _ => Expression::GreaterEqualOp { lhs, rhs },
+            };
+        }
+        Ok(left)
+    }
+
     // Grammar: shift ::= additive ( << additive | >> additive )* (part of expression grammar)
     // Examples: a << 1, b >> 2, c << 1 >> 2
     fn parse_shift(&mut self) -> Result<Expression> {
@@ -268,8 +557,8 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
-    // Grammar: unary ::= - unary | ~ unary | operand (part of expression grammar)
-    // Examples: -a (negation), ~b (bitwise not), c (no unary op)
+    // Grammar: unary ::= - unary | ~ unary | ! unary | operand (part of expression grammar)
+    // Examples: -a (negation), ~b (bitwise not), !c (logical not), d (no unary op)
     fn parse_unary(&mut self) -> Result<Expression> {
         if let Some(Token::Operator(OperatorOp::Minus)) = self.peek() {
             self.next();
@@ -281,6 +570,12 @@ impl<'a> Parser<'a> {
             self.next();
             let expr = self.parse_unary()?;
             Ok(Expression::BitwiseNotOp { expr: Box::new(expr) })
+        } else if let Some(Token::Operator(OperatorOp::LogicalNot)) =
+            self.peek()
+        {
+            self.next();
+            let expr = self.parse_unary()?;
+            Ok(Expression::LogicalNotOp { expr: Box::new(expr) })
         } else {
             self.parse_operand()
         }
@@ -330,6 +625,10 @@ impl<'a> Parser<'a> {
                     self.next();
                     Ok(Expression::CurrentAddress) // .
                 }
+                Token::Operator(OperatorOp::Modulo) => {
+                    self.next();
+                    self.parse_relocation_operator() // %hi(exp) | %lo(exp)
+                }
                 _ => Err(RiscletError::from_context(
                     "Expected an operand (number, label, register, or parenthesized expression)".to_string(),
                     self.location(),
@@ -343,10 +642,37 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Grammar: reloc_op ::= % (hi | lo) ( exp ) (the % has been consumed)
+    // Examples: %hi(msg) (upper 20 bits), %lo(msg) (lower 12 bits)
+    fn parse_relocation_operator(&mut self) -> Result<Expression> {
+        let location = self.location();
+        let name = match self.next() {
+            Some(Token::Identifier(s)) if s == "hi" || s == "lo" => s,
+            _ => {
+                return Err(RiscletError::from_context(
+                    "Expected %hi(...) or %lo(...)".to_string(),
+                    location,
+                ));
+            }
+        };
+        self.expect(&Token::OpenParen)?;
+        let expr = Box::new(self.parse_expression()?);
+        self.expect(&Token::CloseParen)?;
+        if name == "hi" {
+            Ok(Expression::HiOp { expr })
+        } else {
+            Ok(Expression::LoOp { expr })
+        }
+    }
+
     // Grammar: [ident | int :] [directive | instruction]
     // Examples: loop: add a0, a1, a2 (labeled instruction), .global foo (directive), add a0, a1, a2 (unlabeled instruction)
     fn parse_line(&mut self) -> Result<Vec<Line>> {
-        let location = Location { file: self.file.clone(), line: self.line };
+        let location = Location::new(&self.file, self.line);
+        let label_spans = LineSpans {
+            head: self.spans.first().copied(),
+            ..Default::default()
+        };
         let mut lines = Vec::new();
         // Check for label: [ident | int :] (peekahead and backtrack if no colon)
         let mut label = None;
@@ -372,6 +698,7 @@ impl<'a> Parser<'a> {
             }
         }
         // Parse content if any
+        let content_start = self.pos;
         let content = if self.pos < self.tokens.len() {
             Some(if let Some(Token::Directive(_)) = self.peek() {
                 LineContent::Directive(self.parse_directive()?)
@@ -386,10 +713,12 @@ impl<'a> Parser<'a> {
             lines.push(Line {
                 location: location.clone(),
                 content: LineContent::Label(l),
+                spans: label_spans,
             });
         }
         if let Some(c) = content {
-            lines.push(Line { location, content: c });
+            let spans = self.content_spans(content_start, self.pos);
+            lines.push(Line { location, content: c, spans });
         }
         if lines.is_empty() {
             return Err(RiscletError::from_context(
@@ -400,7 +729,7 @@ impl<'a> Parser<'a> {
         Ok(lines)
     }
 
-    // Grammar: .global ident | .equ ident , exp | .text | .data | .bss | .space exp | .balign exp | .string string [, string]* | .asciz string [, string]* | .byte exp [, exp]* | .2byte exp [, exp]* | .4byte exp [, exp]* | .8byte exp [, exp]*
+    // Grammar: .global ident | .equ ident , exp | .type ident , @function | .size ident , exp | .equiv ident , exp | .weak ident | .local ident | .comm ident , exp [, exp] | .lcomm ident , exp [, exp] | .option ident | .text | .data | .bss | .space exp | .balign exp | .string string [, string]* | .asciz string [, string]* | .byte exp [, exp]* | .2byte exp [, exp]* | .4byte exp [, exp]* | .8byte exp [, exp]*
     // Examples: .global main, .equ SIZE, 100, .text, .data, .bss, .space 4, .balign 8, .string "hello", "world", .asciz "foo", .byte 1, 2, 3, .2byte 10, 20, .4byte 100, .8byte 1000
     fn parse_directive(&mut self) -> Result<Directive> {
         if let Some(Token::Directive(d)) = self.next() {
@@ -420,6 +749,41 @@ impl<'a> Parser<'a> {
                     let expr = self.parse_expression()?;
                     Ok(Directive::Equ(name, expr))
                 }
+                DirectiveOp::Equiv => {
+                    let name = self.parse_identifier()?;
+                    self.expect(&Token::Comma)?;
+                    let expr = self.parse_expression()?;
+                    Ok(Directive::Equiv(name, expr))
+                }
+                DirectiveOp::Weak | DirectiveOp::Local => {
+                    let mut names = Vec::new();
+                    names.push(self.parse_identifier()?);
+                    while let Some(Token::Comma) = self.peek() {
+                        self.next();
+                        names.push(self.parse_identifier()?);
+                    }
+                    Ok(if d == DirectiveOp::Weak {
+                        Directive::Weak(names)
+                    } else {
+                        Directive::Local(names)
+                    })
+                }
+                DirectiveOp::Comm | DirectiveOp::Lcomm => {
+                    let name = self.parse_identifier()?;
+                    self.expect(&Token::Comma)?;
+                    let size = self.parse_expression()?;
+                    let align = if let Some(Token::Comma) = self.peek() {
+                        self.next();
+                        Some(self.parse_expression()?)
+                    } else {
+                        None
+                    };
+                    Ok(if d == DirectiveOp::Comm {
+                        Directive::Comm(name, size, align)
+                    } else {
+                        Directive::Lcomm(name, size, align)
+                    })
+                }
                 DirectiveOp::Text => Ok(Directive::Text),
                 DirectiveOp::Data => Ok(Directive::Data),
                 DirectiveOp::Bss => Ok(Directive::Bss),
@@ -484,6 +848,63 @@ impl<'a> Parser<'a> {
                     }
                     Ok(Directive::FourByte(exprs))
                 }
+                DirectiveOp::Option => {
+                    let name = self.parse_identifier()?;
+                    let arg = match name.as_str() {
+                        "push" => OptionArg::Push,
+                        "pop" => OptionArg::Pop,
+                        "rvc" => OptionArg::Rvc,
+                        "norvc" => OptionArg::NoRvc,
+                        "relax" => OptionArg::Relax,
+                        "norelax" => OptionArg::NoRelax,
+                        _ => {
+                            return Err(RiscletError::from_context(
+                                format!(
+                                    "Unknown .option '{}' (expected push, pop, rvc, norvc, relax, or norelax)",
+                                    name
+                                ),
+                                self.previous_location(),
+                            ));
+                        }
+                    };
+                    Ok(Directive::Option(arg))
+                }
+                DirectiveOp::Type => {
+                    let name = self.parse_identifier()?;
+                    self.expect(&Token::Comma)?;
+                    // GNU as also spells the type %function or "function"
+                    let kind = match self.next() {
+                        Some(Token::Identifier(kind)) => {
+                            kind.strip_prefix('@').map(str::to_string)
+                        }
+                        Some(Token::Operator(OperatorOp::Modulo)) => match self
+                            .next()
+                        {
+                            Some(Token::Identifier(kind)) => Some(kind.clone()),
+                            _ => None,
+                        },
+                        Some(Token::StringLiteral(kind)) => Some(kind.clone()),
+                        _ => None,
+                    };
+                    let kind = match kind.as_deref() {
+                        Some("function") => SymbolType::Function,
+                        Some("object") => SymbolType::Object,
+                        _ => {
+                            return Err(RiscletError::from_context(
+                                "Expected a symbol type (@function or @object)"
+                                    .to_string(),
+                                self.previous_location(),
+                            ));
+                        }
+                    };
+                    Ok(Directive::Type(name, kind))
+                }
+                DirectiveOp::Size => {
+                    let name = self.parse_identifier()?;
+                    self.expect(&Token::Comma)?;
+                    let size = self.parse_expression()?;
+                    Ok(Directive::Size(name, size))
+                }
             }
         } else {
             Err(RiscletError::from_context(
@@ -611,19 +1032,41 @@ impl<'a> Parser<'a> {
                 let imm = self.parse_expression()?;
                 Ok(Instruction::Pseudo(PseudoOp::Li(rd, Box::new(imm))))
             }
-            "la" => {
+            // No GOT: lga is lla, and both are la
+            "la" | "lla" | "lga" => {
                 let rd = self.parse_register()?;
                 self.expect(&Token::Comma)?;
                 let expr = self.parse_expression()?;
                 Ok(Instruction::Pseudo(PseudoOp::La(rd, Box::new(expr))))
             }
             "call" => {
+                // Optional rd: [reg ,] expression
+                let rd = if let Some(Token::Register(_)) = self.peek() {
+                    let rd = self.parse_pseudo_temp("call")?;
+                    self.expect(&Token::Comma)?;
+                    rd
+                } else {
+                    Register::X1 // ra
+                };
                 let expr = self.parse_expression()?;
-                Ok(Instruction::Pseudo(PseudoOp::Call(Box::new(expr))))
+                Ok(Instruction::Pseudo(PseudoOp::Call(rd, Box::new(expr))))
             }
             "tail" => {
+                // Optional temporary: expression [, reg]
+                let expr = self.parse_expression()?;
+                let temp = if let Some(Token::Comma) = self.peek() {
+                    self.next();
+                    self.parse_pseudo_temp("tail")?
+                } else {
+                    Register::X6 // t1
+                };
+                Ok(Instruction::Pseudo(PseudoOp::Tail(Box::new(expr), temp)))
+            }
+            "jump" => {
                 let expr = self.parse_expression()?;
-                Ok(Instruction::Pseudo(PseudoOp::Tail(Box::new(expr))))
+                self.expect(&Token::Comma)?;
+                let temp = self.parse_pseudo_temp("jump")?;
+                Ok(Instruction::Pseudo(PseudoOp::Jump(Box::new(expr), temp)))
             }
             "mv" => {
                 let rd = self.parse_register()?;
@@ -683,6 +1126,48 @@ impl<'a> Parser<'a> {
                 let rs = self.parse_register()?;
                 Ok(Instruction::RType(RTypeOp::Slt, rd, Register::X0, rs))
             }
+            "sgt" | "sgtu" => {
+                let op =
+                    if opcode == "sgt" { RTypeOp::Slt } else { RTypeOp::Sltu };
+                let rd = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs1 = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs2 = self.parse_register()?;
+                Ok(Instruction::RType(op, rd, rs2, rs1))
+            }
+            "sle" | "sleu" => {
+                let op =
+                    if opcode == "sle" { RTypeOp::Slt } else { RTypeOp::Sltu };
+                let rd = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs1 = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs2 = self.parse_register()?;
+                Ok(Instruction::Pseudo(PseudoOp::Sle(op, rd, rs1, rs2)))
+            }
+            "zext.b" => {
+                let rd = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs = self.parse_register()?;
+                Ok(Instruction::IType(
+                    ITypeOp::Andi,
+                    rd,
+                    rs,
+                    Box::new(Expression::Literal(0xff)),
+                ))
+            }
+            "sext.b" | "sext.h" | "zext.h" => {
+                let op = match opcode.as_str() {
+                    "sext.b" => ExtendOp::SextB,
+                    "sext.h" => ExtendOp::SextH,
+                    _ => ExtendOp::ZextH,
+                };
+                let rd = self.parse_register()?;
+                self.expect(&Token::Comma)?;
+                let rs = self.parse_register()?;
+                Ok(Instruction::Pseudo(PseudoOp::Extend(op, rd, rs)))
+            }
             "beqz" => {
                 let rs = self.parse_register()?;
                 self.expect(&Token::Comma)?;
@@ -768,12 +1253,12 @@ impl<'a> Parser<'a> {
                 ))
             }
             "jr" => {
-                let rs = self.parse_register()?;
+                let (rs, offset) = self.parse_jump_register()?;
                 Ok(Instruction::IType(
                     ITypeOp::Jalr,
                     Register::X0,
                     rs,
-                    Box::new(Expression::Literal(0)),
+                    Box::new(offset),
                 ))
             }
             "not" => {
@@ -795,10 +1280,11 @@ impl<'a> Parser<'a> {
 
                 Err(RiscletError::from_context(
                     format!(
-                        "Unknown instruction '{}': check spelling or consult the RISC-V ISA reference",
-                        opcode
+                        "Unknown instruction '{}': check spelling or consult the RISC-V ISA reference{}",
+                        opcode,
+                        did_you_mean(&opcode, MNEMONICS.iter().copied())
                     ),
-                    self.location(),
+                    self.previous_location(),
                 ))
             }
         }
@@ -826,10 +1312,24 @@ impl<'a> Parser<'a> {
         Ok(Instruction::IType(op, rd, rs1, Box::new(imm)))
     }
 
-    // Grammar: reg , [offset] ( reg ) | reg , reg [, offset]
-    // Examples: jalr ra, 0(t0), jalr ra, (t0), jalr ra, t0, jalr ra, t0, 0
+    // Grammar: reg , [offset] ( reg ) | reg , reg [, offset] | reg [, offset] | [offset] ( reg )
+    // Examples: jalr ra, 0(t0), jalr ra, (t0), jalr ra, t0, jalr ra, t0, 0, jalr t0, jalr t0, 4, jalr 4(t0)
     fn parse_jalr(&mut self) -> Result<Instruction> {
-        let rd = self.parse_register()?;
+        // The one-operand forms link through ra
+        let pos_backup = self.pos;
+        let rd = match self.parse_register() {
+            Ok(rd) if self.peek() == Some(&Token::Comma) => rd,
+            _ => {
+                self.pos = pos_backup;
+                let (rs1, offset) = self.parse_jump_register()?;
+                return Ok(Instruction::IType(
+                    ITypeOp::Jalr,
+                    Register::X1,
+                    rs1,
+                    Box::new(offset),
+                ));
+            }
+        };
         self.expect(&Token::Comma)?;
 
         // Lookahead for ( reg ) to handle zero offset: jalr rd, (rs1)
@@ -886,14 +1386,63 @@ impl<'a> Parser<'a> {
             self.expect(&Token::CloseParen)?;
             Ok(Instruction::IType(ITypeOp::Jalr, rd, rs1, Box::new(offset)))
         } else {
-            Err(RiscletError::from_context(
-                "jalr expects offset(rs1), (rs1), rs1, or rs1, offset syntax"
-                    .to_string(),
-                self.location(),
+            // jalr rs1, offset links through ra, like jr rs1, offset
+            Ok(Instruction::IType(
+                ITypeOp::Jalr,
+                Register::X1,
+                rd,
+                Box::new(offset),
             ))
         }
     }
 
+    // Grammar: reg [, offset] | [offset] ( reg )
+    // Examples: jr t0, jr t0, 4, jr 4(t0), jr (t0)
+    fn parse_jump_register(&mut self) -> Result<(Register, Expression)> {
+        if let Some(Token::Register(_)) = self.peek() {
+            let rs = self.parse_register()?;
+            let offset = if let Some(Token::Comma) = self.peek() {
+                self.next();
+                self.parse_expression()?
+            } else {
+                Expression::Literal(0)
+            };
+            return Ok((rs, offset));
+        }
+        // Peekahead for ( reg ) to handle zero offset
+        if let Some(Token::OpenParen) = self.peek() {
+            let pos_backup = self.pos;
+            self.next();
+            if let Ok(rs) = self.parse_register()
+                && self.expect(&Token::CloseParen).is_ok()
+            {
+                return Ok((rs, Expression::Literal(0)));
+            }
+            self.pos = pos_backup;
+        }
+        let offset = self.parse_expression()?;
+        self.expect(&Token::OpenParen)?;
+        let rs = self.parse_register()?;
+        self.expect(&Token::CloseParen)?;
+        Ok((rs, offset))
+    }
+
+    // The register call, tail and jump build the target address in, which
+    // cannot be x0
+    fn parse_pseudo_temp(&mut self, mnemonic: &str) -> Result<Register> {
+        let register = self.parse_register()?;
+        if register == Register::X0 {
+            return Err(RiscletError::from_context(
+                format!(
+                    "{} cannot use zero to hold the target address",
+                    mnemonic
+                ),
+                self.previous_location(),
+            ));
+        }
+        Ok(register)
+    }
+
     // Grammar: reg , reg , expression
     // Examples: beq a0, a1, loop (symbolic), beq a0, a1, . + 8 (expression)
     fn parse_btype(&mut self, op: BTypeOp) -> Result<Instruction> {
@@ -911,12 +1460,10 @@ impl<'a> Parser<'a> {
         // Parse optional pred, succ parameters
         let (pred, succ) = if let Some(Token::Identifier(_)) = self.peek() {
             let pred_str = self.parse_identifier()?;
+            let pred = self.parse_fence_bits(&pred_str)?;
             self.expect(&Token::Comma)?;
             let succ_str = self.parse_identifier()?;
-            (
-                self.parse_fence_bits(&pred_str)?,
-                self.parse_fence_bits(&succ_str)?,
-            )
+            (pred, self.parse_fence_bits(&succ_str)?)
         } else {
             // Default: iorw, iorw (0xF, 0xF)
             (0xF, 0xF)
@@ -947,7 +1494,7 @@ impl<'a> Parser<'a> {
                             "Invalid fence ordering character '{}': must be 'i' (input), 'o' (output), 'r' (read), or 'w' (write)",
                             ch
                         ),
-                        self.location(),
+                        self.previous_location(),
                     ));
                 }
             }
@@ -968,7 +1515,7 @@ impl<'a> Parser<'a> {
                     "{} must be in compressed set (x8-x15/s0-s1 and a0-a5), got {}",
                     subject, reg
                 ),
-                self.location(),
+                self.previous_location(),
             ))
         }
     }
@@ -1207,7 +1754,7 @@ impl<'a> Parser<'a> {
                             "c.addi16sp destination must be sp (x2), got {}",
                             rd
                         ),
-                        self.location(),
+                        self.previous_location(),
                     ));
                 }
                 self.expect(&Token::Comma)?;
@@ -1229,7 +1776,7 @@ impl<'a> Parser<'a> {
                             "c.addi4spn base register must be sp (x2), got {}",
                             base
                         ),
-                        self.location(),
+                        self.previous_location(),
                     ));
                 }
                 self.expect(&Token::Comma)?;
@@ -1266,7 +1813,7 @@ impl<'a> Parser<'a> {
                             "c.lwsp base register must be sp (x2), got {}",
                             base
                         ),
-                        self.location(),
+                        self.previous_location(),
                     ));
                 }
                 self.expect(&Token::CloseParen)?;
@@ -1292,7 +1839,7 @@ impl<'a> Parser<'a> {
                             "c.swsp base register must be sp (x2), got {}",
                             base
                         ),
-                        self.location(),
+                        self.previous_location(),
                     ));
                 }
                 self.expect(&Token::CloseParen)?;
@@ -1493,7 +2040,7 @@ impl<'a> Parser<'a> {
                         "Unknown compressed instruction 'c.{}': check spelling or consult the RISC-V ISA reference",
                         op
                     ),
-                    self.location(),
+                    self.previous_location(),
                 ));
             }
         };
@@ -1502,8 +2049,20 @@ impl<'a> Parser<'a> {
     }
 }
 
+#[cfg(test)]
 pub fn parse(tokens: &[Token], file: String, line: usize) -> Result<Vec<Line>> {
-    let mut parser = Parser::new(tokens, file.clone(), line);
+    parse_with_spans(tokens, &[], file, line)
+}
+
+/// Parses a line whose token spans are known, so errors and the resulting
+/// lines carry columns
+pub fn parse_with_spans(
+    tokens: &[Token],
+    spans: &[Span],
+    file: String,
+    line: usize,
+) -> Result<Vec<Line>> {
+    let mut parser = Parser::new(tokens, spans, file, line);
     let lines = parser.parse_line()?;
 
     // Check for leftover tokens
@@ -1512,12 +2071,16 @@ pub fn parse(tokens: &[Token], file: String, line: usize) -> Result<Vec<Line>> {
             .iter()
             .map(|t| format!("{:?}", t))
             .collect();
+        let mut location = parser.location();
+        if let (Some(span), Some(&last)) = (location.span, spans.last()) {
+            location.span = Some(span.to(last));
+        }
         return Err(RiscletError::from_context(
             format!(
                 "Extra tokens after instruction: '{}' (each line should have at most one instruction or directive)",
                 remaining.join(" ")
             ),
-            Location { file: file.clone(), line },
+            location,
         ));
     }
 
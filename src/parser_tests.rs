@@ -83,6 +83,18 @@ fn test_parse_expression() {
     }
 }
 
+#[test]
+fn test_parse_assembler_local_label() {
+    let tokens = tokenize(".Lloop: bnez a0, .Lloop").unwrap();
+    let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+    assert_eq!(ast.len(), 2);
+    assert_eq!(ast[0].content, LineContent::Label(".Lloop".to_string()));
+    assert_eq!(
+        ast[1].content.to_string().split_whitespace().last(),
+        Some(".Lloop")
+    );
+}
+
 #[test]
 fn test_parse_numeric_label() {
     let line = "bnez a0, 1f";
@@ -190,6 +202,50 @@ fn test_parse_pseudo_li() {
     }
 }
 
+#[test]
+fn test_parse_pseudo_registers() {
+    let parse_line = |line: &str| {
+        let tokens = tokenize(line).unwrap();
+        parse(&tokens, "test".to_string(), 1).map(|mut lines| {
+            match lines.remove(0).content {
+                LineContent::Instruction(Instruction::Pseudo(pseudo)) => pseudo,
+                content => panic!("Unexpected AST: {:?}", content),
+            }
+        })
+    };
+    let target = Box::new(Expression::Identifier("f".to_string()));
+
+    assert_eq!(
+        parse_line("call f").unwrap(),
+        PseudoOp::Call(Register::X1, target.clone())
+    );
+    assert_eq!(
+        parse_line("call t0, f").unwrap(),
+        PseudoOp::Call(Register::X5, target.clone())
+    );
+    assert_eq!(
+        parse_line("tail f").unwrap(),
+        PseudoOp::Tail(target.clone(), Register::X6)
+    );
+    assert_eq!(
+        parse_line("tail f, t2").unwrap(),
+        PseudoOp::Tail(target.clone(), Register::X7)
+    );
+    assert_eq!(
+        parse_line("jump f, t3").unwrap(),
+        PseudoOp::Jump(target.clone(), Register::X28)
+    );
+    assert_eq!(
+        parse_line("lga a0, f").unwrap(),
+        PseudoOp::La(Register::X10, target)
+    );
+
+    // The address cannot be built in zero, and jump needs a register
+    assert!(parse_line("jump f, zero").is_err());
+    assert!(parse_line("call x0, f").is_err());
+    assert!(parse_line("jump f").is_err());
+}
+
 #[test]
 fn test_parse_directive_space() {
     let line = ".space 4";
@@ -338,3 +394,192 @@ fn test_parse_error_leftover_tokens() {
         err
     );
 }
+
+#[test]
+fn test_parse_option_directive() {
+    for (text, expected) in [
+        (".option push", OptionArg::Push),
+        (".option pop", OptionArg::Pop),
+        (".option rvc", OptionArg::Rvc),
+        (".option norvc", OptionArg::NoRvc),
+        (".option relax", OptionArg::Relax),
+        (".option norelax", OptionArg::NoRelax),
+    ] {
+        let tokens = tokenize(text).unwrap();
+        let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+        assert_eq!(
+            ast[0].content,
+            LineContent::Directive(Directive::Option(expected))
+        );
+    }
+}
+
+#[test]
+fn test_parse_option_unknown_is_error() {
+    let tokens = tokenize(".option pic").unwrap();
+    assert!(parse(&tokens, "test".to_string(), 1).is_err());
+}
+
+#[test]
+fn test_parse_type_directive() {
+    for (text, expected) in [
+        (".type main, @function", SymbolType::Function),
+        (".type main, %function", SymbolType::Function),
+        (".type main, \"function\"", SymbolType::Function),
+        (".type main, @object", SymbolType::Object),
+    ] {
+        let tokens = tokenize(text).unwrap();
+        let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+        assert_eq!(
+            ast[0].content,
+            LineContent::Directive(Directive::Type(
+                "main".to_string(),
+                expected
+            ))
+        );
+    }
+
+    let tokens = tokenize(".type main, @tls_object").unwrap();
+    assert!(parse(&tokens, "test".to_string(), 1).is_err());
+}
+
+#[test]
+fn test_parse_size_directive() {
+    let tokens = tokenize(".size main, . - main").unwrap();
+    let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+    let LineContent::Directive(Directive::Size(name, expr)) = &ast[0].content
+    else {
+        panic!("expected .size, got {:?}", ast[0].content);
+    };
+    assert_eq!(name, "main");
+    assert_eq!(expr.to_string(), ". - main");
+
+    let tokens = tokenize(".size main").unwrap();
+    assert!(parse(&tokens, "test".to_string(), 1).is_err());
+}
+
+#[test]
+fn test_mnemonic_table_matches_parser() {
+    // Every suggested mnemonic must be one the parser accepts
+    for mnemonic in MNEMONICS {
+        let tokens = tokenize(mnemonic).unwrap();
+        if let Err(e) = parse(&tokens, "test".to_string(), 1) {
+            assert!(
+                !e.message().starts_with("Unknown instruction"),
+                "{}: {}",
+                mnemonic,
+                e.message()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_parse_line_spans() {
+    let line = "loop: sw a0, %lo(value + 4)(t0)  # store";
+    let (tokens, spans) = crate::tokenizer::tokenize_with_spans(line).unwrap();
+    let ast = parse_with_spans(&tokens, &spans, "test".to_string(), 1).unwrap();
+    let text = |span: Span| -> String {
+        line.chars().skip(span.start).take(span.end - span.start).collect()
+    };
+
+    assert_eq!(text(ast[0].spans.head.unwrap()), "loop");
+    let store = &ast[1].spans;
+    assert_eq!(text(store.head.unwrap()), "sw");
+    let operands: Vec<String> =
+        store.operands.iter().map(|&s| text(s)).collect();
+    assert_eq!(operands, ["a0", "%lo(value + 4)(t0)"]);
+    let symbols: Vec<&str> =
+        store.symbols.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(symbols, ["value"]);
+
+    let tokens_and_spans =
+        crate::tokenizer::tokenize_with_spans("bnez a0, 1b").unwrap();
+    let ast = parse_with_spans(
+        &tokens_and_spans.0,
+        &tokens_and_spans.1,
+        "test".to_string(),
+        1,
+    )
+    .unwrap();
+    assert_eq!(
+        ast[0].symbol_location("1b").span,
+        Some(Span { start: 9, end: 11 })
+    );
+}
+
+#[test]
+fn test_parse_comparison_logical_and_conditional_precedence() {
+    let parse_equ = |expr: &str| {
+        let tokens = tokenize(&format!(".equ x, {}", expr)).unwrap();
+        let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+        match &ast[0].content {
+            LineContent::Directive(Directive::Equ(_, expr)) => expr.clone(),
+            other => panic!("Unexpected AST: {:?}", other),
+        }
+    };
+    let lit = |i| Box::new(Expression::Literal(i));
+
+    // Relational binds tighter than equality, which binds tighter than &
+    assert_eq!(
+        parse_equ("1 < 2 == 3 & 4"),
+        Expression::BitwiseAndOp {
+            lhs: Box::new(Expression::EqualOp {
+                lhs: Box::new(Expression::LessOp { lhs: lit(1), rhs: lit(2) }),
+                rhs: lit(3),
+            }),
+            rhs: lit(4),
+        }
+    );
+
+    // && binds tighter than ||, and | tighter than both
+    assert_eq!(
+        parse_equ("1 || 2 && 3 | 4"),
+        Expression::LogicalOrOp {
+            lhs: lit(1),
+            rhs: Box::new(Expression::LogicalAndOp {
+                lhs: lit(2),
+                rhs: Box::new(Expression::BitwiseOrOp {
+                    lhs: lit(3),
+                    rhs: lit(4),
+                }),
+            }),
+        }
+    );
+
+    // Conditionals are lowest and group to the right
+    assert_eq!(
+        parse_equ("!1 ? 2 : 3 >= 4 ? 5 : 6"),
+        Expression::ConditionalOp {
+            condition: Box::new(Expression::LogicalNotOp { expr: lit(1) }),
+            then_expr: lit(2),
+            else_expr: Box::new(Expression::ConditionalOp {
+                condition: Box::new(Expression::GreaterEqualOp {
+                    lhs: lit(3),
+                    rhs: lit(4),
+                }),
+                then_expr: lit(5),
+                else_expr: lit(6),
+            }),
+        }
+    );
+
+    let tokens = tokenize(".equ x, 1 ? 2").unwrap();
+    assert!(parse(&tokens, "test".to_string(), 1).is_err());
+}
+
+#[test]
+fn test_tail_and_jump_keep_their_mnemonics() {
+    for (line, expected) in [
+        ("tail target", "tail    target"),
+        ("tail target, t2", "tail    target, t2"),
+        ("jump target, t1", "jump    target, t1"),
+    ] {
+        let tokens = tokenize(line).unwrap();
+        let ast = parse(&tokens, "test".to_string(), 1).unwrap();
+        let LineContent::Instruction(instruction) = &ast[0].content else {
+            panic!("expected an instruction, got {:?}", ast[0].content);
+        };
+        assert_eq!(instruction.to_string(), expected);
+    }
+}
@@ -537,11 +537,9 @@ impl Op {
                 Op::Addi { rd, rs1: rd, imm }
             }
             (1, 1) => {
-                // C.ADDIW - RV64 specific, not supported in RV32
-                Op::Unimplemented {
-                    inst,
-                    note: String::from("C.ADDIW is not supported in RV32"),
-                }
+                // C.JAL (RV32 only; C.ADDIW in RV64)
+                let offset = get_c_j_jal_imm(inst);
+                Op::Jal { rd: RA, offset }
             }
             (1, 2) => {
                 // C.LI
@@ -1630,6 +1628,12 @@ impl Op {
             Op::Jalr { rd: RA, rs1, offset: 0 } => {
                 vec![Field::Opcode("jalr"), Field::Reg(rs1)]
             }
+            Op::Jalr { rd: ZERO, rs1, offset } => {
+                vec![Field::Opcode("jr"), Field::Indirect(offset, rs1)]
+            }
+            Op::Jalr { rd: RA, rs1, offset } => {
+                vec![Field::Opcode("jalr"), Field::Indirect(offset, rs1)]
+            }
             Op::Jal { rd: ZERO, offset } => {
                 vec![Field::Opcode("j"), Field::PCRelAddr(offset)]
             }
@@ -1642,6 +1646,10 @@ impl Op {
             Op::Xori { rd, rs1, imm: -1 } => {
                 vec![Field::Opcode("not"), Field::Reg(rd), Field::Reg(rs1)]
             }
+            Op::Andi { rd, rs1, imm: 0xff } => {
+                vec![Field::Opcode("zext.b"), Field::Reg(rd), Field::Reg(rs1)]
+            }
+            Op::Fence { pred: 0xf, succ: 0xf } => vec![Field::Opcode("fence")],
             Op::Sltiu { rd, rs1, imm: 1 } => {
                 vec![Field::Opcode("seqz"), Field::Reg(rd), Field::Reg(rs1)]
             }
@@ -1787,16 +1795,34 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("la"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
-        (Op::Auipc { rd: RA, imm }, Op::Jalr { rd: RA, rs1: RA, offset }) => {
-            Some((
-                2,
-                vec![Field::Opcode("call"), Field::PCRelAddr(imm + offset)],
-            ))
-        }
+        // call: auipc ra, hi + jalr ra, ra, lo
+        (
+            Op::Auipc { rd: RA, imm: imm1 },
+            Op::Jalr { rd: RA, rs1: RA, offset: imm2 },
+        ) => Some((
+            2,
+            vec![
+                Field::Opcode("call"),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
+            ],
+        )),
+
+        // call rd, symbol: auipc rd, hi + jalr rd, rd, lo
+        (
+            Op::Auipc { rd: rd1, imm: imm1 },
+            Op::Jalr { rd: rd2, rs1: rs2, offset: imm2 },
+        ) if rd1 == rd2 && rd2 == rs2 && *rd1 != ZERO => Some((
+            2,
+            vec![
+                Field::Opcode("call"),
+                Field::Reg(*rd1),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
+            ],
+        )),
 
         // tail: auipc t1, hi + jalr zero, t1, lo
         (
@@ -1804,9 +1830,62 @@ pub fn get_pseudo_sequence(
             Op::Jalr { rd: ZERO, rs1: 6, offset: imm2 },
         ) => Some((
             2,
-            vec![Field::Opcode("tail"), Field::PCRelAddr(imm1 + imm2)],
+            vec![
+                Field::Opcode("tail"),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
+            ],
+        )),
+
+        // jump symbol, temp: auipc temp, hi + jalr zero, temp, lo
+        (
+            Op::Auipc { rd: rd1, imm: imm1 },
+            Op::Jalr { rd: ZERO, rs1: rs2, offset: imm2 },
+        ) if rd1 == rs2 && *rd1 != ZERO => Some((
+            2,
+            vec![
+                Field::Opcode("jump"),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
+                Field::Reg(*rd1),
+            ],
         )),
 
+        // sext.b, sext.h and zext.h rd, rs: slli rd, rs, n + srai/srli rd, rd, n
+        (
+            Op::Slli { rd: rd1, rs1, shamt: shamt1 },
+            Op::Srai { rd: rd2, rs1: rs2, shamt: shamt2 }
+            | Op::Srli { rd: rd2, rs1: rs2, shamt: shamt2 },
+        ) if rd1 == rd2 && rd2 == rs2 && shamt1 == shamt2 => {
+            let signed = matches!(inst2.op, Op::Srai { .. });
+            let name = match (signed, *shamt1) {
+                (true, 24) => "sext.b",
+                (true, 16) => "sext.h",
+                (false, 16) => "zext.h",
+                _ => return None,
+            };
+            Some((
+                2,
+                vec![Field::Opcode(name), Field::Reg(*rd1), Field::Reg(*rs1)],
+            ))
+        }
+
+        // sle and sleu rd, rs1, rs2: slt(u) rd, rs2, rs1 + xori rd, rd, 1
+        (
+            Op::Slt { rd: rd1, rs1, rs2 } | Op::Sltu { rd: rd1, rs1, rs2 },
+            Op::Xori { rd: rd2, rs1: rs3, imm: 1 },
+        ) if rd1 == rd2 && rd2 == rs3 => {
+            let name =
+                if matches!(inst1.op, Op::Slt { .. }) { "sle" } else { "sleu" };
+            Some((
+                2,
+                vec![
+                    Field::Opcode(name),
+                    Field::Reg(*rd1),
+                    Field::Reg(*rs2),
+                    Field::Reg(*rs1),
+                ],
+            ))
+        }
+
         // lb rd, symbol: auipc rd, hi + lb rd, lo(rd)
         (
             Op::Auipc { rd: rd1, imm: imm1 },
@@ -1816,7 +1895,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("lb"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1829,7 +1908,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("lh"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1842,7 +1921,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("lw"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1855,7 +1934,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("lbu"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1868,7 +1947,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("lhu"),
                 Field::Reg(*rd1),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1881,7 +1960,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("sb"),
                 Field::Reg(*data_reg),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1894,7 +1973,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("sh"),
                 Field::Reg(*data_reg),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1907,7 +1986,7 @@ pub fn get_pseudo_sequence(
             vec![
                 Field::Opcode("sw"),
                 Field::Reg(*data_reg),
-                Field::PCRelAddr(imm1 + imm2),
+                Field::PCRelAddr(imm1.wrapping_add(*imm2)),
             ],
         )),
 
@@ -1923,6 +2002,20 @@ pub fn fields_to_string(
     is_compressed: bool,
     arrow: Option<&str>,
     symbols: &HashMap<u32, String>,
+) -> String {
+    let prefix = address_and_label(config, pc, arrow, symbols);
+    let disasm =
+        fields_to_disassembly(config, fields, pc, gp, is_compressed, symbols);
+
+    format!("{prefix}{disasm:<48}")
+}
+
+/// The address and label columns that start each line of disassembly
+pub fn address_and_label(
+    config: &crate::config::Config,
+    pc: u32,
+    arrow: Option<&str>,
+    symbols: &HashMap<u32, String>,
 ) -> String {
     let addr_part = if !config.show_addresses {
         String::new()
@@ -1955,6 +2048,18 @@ pub fn fields_to_string(
     }
     let label: String = label.into_iter().collect();
 
+    format!("{addr_part}{label:<16}")
+}
+
+/// The mnemonic and operands of an instruction
+pub fn fields_to_disassembly(
+    config: &crate::config::Config,
+    fields: &[Field],
+    pc: u32,
+    gp: u32,
+    is_compressed: bool,
+    symbols: &HashMap<u32, String>,
+) -> String {
     let mut inst = fields[0].to_string(
         pc,
         gp,
@@ -1978,11 +2083,10 @@ pub fn fields_to_string(
         })
         .collect::<Vec<_>>()
         .join(", ");
-    let disasm = format!("{:<8}{}", inst, operands);
-
-    format!("{addr_part}{label:<16}{disasm:<48}")
+    format!("{:<8}{}", inst, operands)
 }
 
+#[derive(Debug)]
 pub enum Field {
     Opcode(&'static str),
     Reg(usize),
@@ -12,6 +12,7 @@ mod pseudo_instruction_tests {
             pseudo_index: 0,
             verbose_fields: Vec::new(),
             pseudo_fields: Vec::new(),
+            source: None,
         }
     }
 
@@ -223,6 +224,98 @@ mod pseudo_instruction_tests {
         }
     }
 
+    /// The text of the pseudo-instruction a sequence folds into
+    fn fold(ops: Vec<Op>) -> Option<(usize, String)> {
+        let instructions: Vec<_> = ops
+            .into_iter()
+            .enumerate()
+            .map(|(i, op)| make_instruction(op, 0x1000 + 4 * i as u32))
+            .collect();
+        let symbols = HashMap::from([(0x1100, "f".to_string())]);
+        get_pseudo_sequence(&instructions, &symbols).map(|(count, fields)| {
+            let text = fields
+                .iter()
+                .map(|f| f.to_string(0x1000, 0, false, false, &symbols))
+                .collect::<Vec<_>>()
+                .join(" ");
+            (count, text)
+        })
+    }
+
+    #[test]
+    fn test_pseudo_sequence_call_and_jump_with_registers() {
+        let auipc = |rd| Op::Auipc { rd, imm: 0 };
+        assert_eq!(
+            fold(vec![auipc(5), Op::Jalr { rd: 5, rs1: 5, offset: 0x100 }]),
+            Some((2, "call t0 f".to_string()))
+        );
+        assert_eq!(
+            fold(vec![auipc(RA), Op::Jalr { rd: RA, rs1: RA, offset: 0x100 }]),
+            Some((2, "call f".to_string()))
+        );
+        assert_eq!(
+            fold(vec![auipc(7), Op::Jalr { rd: ZERO, rs1: 7, offset: 0x100 }]),
+            Some((2, "jump f t2".to_string()))
+        );
+        assert_eq!(
+            fold(vec![auipc(6), Op::Jalr { rd: ZERO, rs1: 6, offset: 0x100 }]),
+            Some((2, "tail f".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pseudo_sequence_extensions() {
+        let slli = Op::Slli { rd: 10, rs1: 11, shamt: 24 };
+        let srai = Op::Srai { rd: 10, rs1: 10, shamt: 24 };
+        assert_eq!(
+            fold(vec![slli.clone(), srai]),
+            Some((2, "sext.b a0 a1".to_string()))
+        );
+        let slli = Op::Slli { rd: 10, rs1: 11, shamt: 16 };
+        let srai = Op::Srai { rd: 10, rs1: 10, shamt: 16 };
+        assert_eq!(
+            fold(vec![slli.clone(), srai]),
+            Some((2, "sext.h a0 a1".to_string()))
+        );
+        let srli = Op::Srli { rd: 10, rs1: 10, shamt: 16 };
+        assert_eq!(
+            fold(vec![slli.clone(), srli]),
+            Some((2, "zext.h a0 a1".to_string()))
+        );
+
+        // Other shift pairs are left alone
+        let srli = Op::Srli { rd: 10, rs1: 10, shamt: 24 };
+        let slli = Op::Slli { rd: 10, rs1: 11, shamt: 24 };
+        assert_eq!(fold(vec![slli.clone(), srli]), None);
+        let srai = Op::Srai { rd: 12, rs1: 12, shamt: 24 };
+        assert_eq!(fold(vec![slli.clone(), srai]), None);
+    }
+
+    #[test]
+    fn test_pseudo_sequence_sle() {
+        let xori = Op::Xori { rd: 10, rs1: 10, imm: 1 };
+        assert_eq!(
+            fold(vec![Op::Slt { rd: 10, rs1: 12, rs2: 11 }, xori.clone()]),
+            Some((2, "sle a0 a1 a2".to_string()))
+        );
+        assert_eq!(
+            fold(vec![Op::Sltu { rd: 10, rs1: 12, rs2: 11 }, xori]),
+            Some((2, "sleu a0 a1 a2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pseudo_zext_b_jump_offsets_and_fence() {
+        check_pseudo_opcode(&Op::Andi { rd: 10, rs1: 11, imm: 0xff }, "zext.b");
+        check_pseudo_opcode(&Op::Jalr { rd: ZERO, rs1: 5, offset: 8 }, "jr");
+        check_pseudo_opcode(&Op::Jalr { rd: RA, rs1: 5, offset: 8 }, "jalr");
+        let fence = Op::Fence { pred: 0xf, succ: 0xf };
+        assert_eq!(fence.to_pseudo_fields().len(), 1);
+        check_pseudo_opcode(&fence, "fence");
+        let fence = Op::Fence { pred: 0x3, succ: 0x3 };
+        assert_eq!(fence.to_pseudo_fields().len(), 2);
+    }
+
     #[test]
     fn test_pseudo_sequence_lb() {
         let symbols = HashMap::new();
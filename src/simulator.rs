@@ -3,21 +3,169 @@
 // Provides the RISC-V simulation and debugging functionality
 
 use crate::config::{Config, Mode};
+use crate::disassembly::disassemble_to_source;
 use crate::elf_loader::{ElfInput, load_elf};
 use crate::error::{Result, RiscletError};
-use crate::execution::{Instruction, add_local_labels, trace};
-use crate::riscv::{Op, fields_to_string, get_pseudo_sequence};
-use crate::ui::Tui;
+use crate::execution::{
+    Instruction, Machine, SourceLine, add_local_labels, instruction_to_string,
+    trace,
+};
+use crate::image::{load_image, parse_symbol_file};
+use crate::objdump::objdump;
+use crate::riscv::{Op, get_pseudo_sequence};
+use crate::trace::Effects;
+use crate::ui::{Reload, Tui};
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// A program run to completion and rewound, ready to step through in the
+/// debugger
+pub struct Program {
+    pub machine: Machine,
+    pub instructions: Vec<Rc<Instruction>>,
+    pub addresses: HashMap<u32, usize>,
+    pub pseudo_addresses: HashMap<usize, usize>,
+    pub sequence: Vec<Effects>,
+}
+
 /// Run the simulator with the specified ELF input (file or bytes)
-pub fn run_simulator(config: &Config, input: ElfInput) -> Result<()> {
-    let mut m = load_elf(input)?;
+///
+/// Returns the status the process should exit with: the program's exit
+/// code in run and trace modes, after reporting any error it stopped with.
+/// Warnings about parts of an ELF that were skipped are printed once it is
+/// loaded, before the program runs.
+pub fn run_simulator(config: &Config, input: ElfInput) -> Result<i32> {
+    let mut warnings = Vec::new();
+    if config.mode == Mode::Disassemble && config.source_output {
+        let m = load_machine(config, input, &mut warnings)?;
+        print_load_warnings(&warnings);
+        print!("{}", disassemble_to_source(&m));
+        return Ok(0);
+    }
+    if config.mode == Mode::Disassemble && config.objdump_output {
+        let bytes = input.read()?;
+        let m = load_machine(config, ElfInput::Bytes(&bytes), &mut warnings)?;
+        print_load_warnings(&warnings);
+        let elf = config.image_format.is_none().then_some(bytes.as_slice());
+        let no_aliases = config.verbose_instructions;
+        print!("{}", objdump(&m, &config.executable, elf, no_aliases)?);
+        return Ok(0);
+    }
+    if config.mode == Mode::Disassemble {
+        let (m, instructions, _, _) =
+            decode_program(config, input, &mut warnings)?;
+        print_load_warnings(&warnings);
+        disassemble(config, &m, &instructions);
+        return Ok(0);
+    }
+
+    let (m, instructions, addresses, pseudo_addresses) =
+        decode_program(config, input, &mut warnings)?;
+    print_load_warnings(&warnings);
+    let program =
+        run_program(config, m, instructions, addresses, pseudo_addresses);
+    if config.mode == Mode::Debug || config.mode == Mode::Default {
+        debug(program, config, None)?;
+        return Ok(0);
+    }
+    Ok(exit_status(config, &program.sequence))
+}
+
+/// Print the warnings from loading an executable
+pub fn print_load_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Load an ELF, run it to completion and rewind it to the start
+pub fn load_program(
+    config: &Config,
+    input: ElfInput,
+    warnings: &mut Vec<String>,
+) -> Result<Program> {
+    let (m, instructions, addresses, pseudo_addresses) =
+        decode_program(config, input, warnings)?;
+    Ok(run_program(config, m, instructions, addresses, pseudo_addresses))
+}
+
+/// Run a decoded program to completion and rewind it to the start
+fn run_program(
+    config: &Config,
+    mut m: Machine,
+    instructions: Vec<Instruction>,
+    addresses: HashMap<u32, usize>,
+    pseudo_addresses: HashMap<usize, usize>,
+) -> Program {
+    let instructions: Vec<Rc<Instruction>> =
+        instructions.into_iter().map(Rc::new).collect();
+
+    // Unified execution loop for run, debug, and trace modes
+    let sequence = trace(&mut m, &instructions, &addresses, config);
+
+    m.reset();
+    m.set_most_recent_memory(&sequence, 0);
+    Program { machine: m, instructions, addresses, pseudo_addresses, sequence }
+}
+
+/// Step through a program in the debugger until the user quits
+///
+/// With `reload`, the debugger swaps in the program it returns whenever it
+/// returns one.
+pub fn debug(
+    program: Program,
+    config: &Config,
+    reload: Option<Reload>,
+) -> Result<()> {
+    let mut tui = Tui::new(program, config).map_err(RiscletError::ui)?;
+    if let Some(reload) = reload {
+        tui.set_reload(reload);
+    }
+    tui.main_loop().map_err(RiscletError::ui)
+}
+
+/// The status to exit with once a program has run, reporting the error it
+/// stopped with if it did not exit
+fn exit_status(config: &Config, sequence: &[Effects]) -> i32 {
+    if let Some(effects) = sequence.last()
+        && let Some(error) = &effects.other_message
+    {
+        return match error {
+            RiscletError::Exit(code) => *code,
+            _ => {
+                eprintln!("{}", error);
+                1
+            }
+        };
+    }
+    if config.mode == Mode::Trace {
+        return 0;
+    }
+    eprintln!("program ended unexpectedly");
+    1
+}
+
+/// Load an ELF and decode its text segment, with pseudo-instructions folded
+#[allow(clippy::type_complexity)]
+fn decode_program(
+    config: &Config,
+    input: ElfInput,
+    warnings: &mut Vec<String>,
+) -> Result<(
+    Machine,
+    Vec<Instruction>,
+    HashMap<u32, usize>,
+    HashMap<usize, usize>,
+)> {
+    let mut m = load_machine(config, input, warnings)?;
     let mut instructions = Vec::new();
     let mut pc = m.text_start();
     while pc < m.text_end() {
-        let (inst, length) = m.load_instruction(pc)?;
+        // Code can end in data that looks like the start of a 32-bit
+        // instruction, as in a memory image
+        let Ok((inst, length)) = m.load_instruction(pc) else {
+            break;
+        };
         let instruction = Instruction {
             address: pc,
             op: Op::new(inst),
@@ -25,10 +173,12 @@ pub fn run_simulator(config: &Config, input: ElfInput) -> Result<()> {
             pseudo_index: 0,
             verbose_fields: Vec::new(),
             pseudo_fields: Vec::new(),
+            source: None,
         };
         instructions.push(instruction);
         pc += length;
     }
+    attach_source_lines(&m, &mut instructions);
     let mut addresses = HashMap::new();
     for (index, instruction) in instructions.iter().enumerate() {
         addresses.insert(instruction.address, index);
@@ -59,93 +209,117 @@ pub fn run_simulator(config: &Config, input: ElfInput) -> Result<()> {
             j += 1;
         }
     }
+    Ok((m, instructions, addresses, pseudo_addresses))
+}
 
-    if config.mode == Mode::Disassemble {
-        let mut prev = usize::MAX;
-        for instruction in &instructions {
-            if !config.verbose_instructions && instruction.pseudo_index == prev
-            {
-                continue;
-            } else {
-                prev = instruction.pseudo_index;
-            }
-
-            // Choose fields based on verbose_instructions setting
-            let fields = if config.verbose_instructions {
-                &instruction.verbose_fields
-            } else {
-                &instruction.pseudo_fields
-            };
-
-            println!(
-                "{}",
-                fields_to_string(
-                    config,
-                    fields,
-                    instruction.address,
-                    m.global_pointer,
-                    instruction.length == 2,
-                    None,
-                    &m.address_symbols
-                )
-            );
-        }
-        return Ok(());
+/// Load an ELF or memory image, with the stack where the layout puts it
+/// and any names from a symbol file
+fn load_machine(
+    config: &Config,
+    input: ElfInput,
+    warnings: &mut Vec<String>,
+) -> Result<Machine> {
+    let mut m = match config.image_format {
+        Some(format) => load_image(
+            &input.read()?,
+            format,
+            config.image_base.unwrap_or(config.text_start),
+            config.memh_width,
+        )?,
+        None => load_elf(input, warnings)?,
+    };
+    if let Some(path) = &config.symbol_file {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            RiscletError::io(format!("failed to read file '{}': {}", path, e))
+        })?;
+        m.address_symbols.extend(parse_symbol_file(&text)?);
+    }
+    if let Some(memory_map) = &config.memory_map {
+        m.set_stack(memory_map.stack_end(), memory_map.stack.size)?;
     }
+    Ok(m)
+}
 
-    let instructions: Vec<Rc<Instruction>> =
-        instructions.into_iter().map(Rc::new).collect();
+/// Print the disassembly of a decoded program
+fn disassemble(config: &Config, m: &Machine, instructions: &[Instruction]) {
+    let mut prev = usize::MAX;
+    for instruction in instructions {
+        if !config.verbose_instructions && instruction.pseudo_index == prev {
+            continue;
+        } else {
+            prev = instruction.pseudo_index;
+        }
 
-    // Unified execution loop for run, debug, and trace modes
-    let sequence = trace(&mut m, &instructions, &addresses, config);
+        // Choose fields based on verbose_instructions setting
+        let fields = if config.verbose_instructions {
+            &instruction.verbose_fields
+        } else {
+            &instruction.pseudo_fields
+        };
 
-    // Handle exit codes and errors from trace execution
-    if config.mode == Mode::Trace {
-        if let Some(effects) = sequence.last()
-            && let Some(error) = &effects.other_message
-        {
-            match error {
-                RiscletError::Exit(code) => {
-                    std::process::exit(*code);
-                }
-                _ => {
-                    eprintln!("{}", error);
-                    std::process::exit(1);
-                }
-            }
-        }
-        return Ok(());
+        println!(
+            "{}",
+            instruction_to_string(
+                config,
+                instruction,
+                fields,
+                m.global_pointer,
+                None,
+                &m.address_symbols
+            )
+        );
     }
+}
 
-    if config.mode == Mode::Debug || config.mode == Mode::Default {
-        m.reset();
-        m.set_most_recent_memory(&sequence, 0);
-        let mut tui = Tui::new(
-            m,
-            instructions,
-            addresses,
-            pseudo_addresses,
-            sequence,
-            config,
-        )
-        .map_err(RiscletError::ui)?;
-        tui.main_loop().map_err(RiscletError::ui)?;
-        return Ok(());
+/// Find the source line of each instruction in the machine's line table,
+/// reading each source file once
+fn attach_source_lines(m: &Machine, instructions: &mut [Instruction]) {
+    let current_dir = std::env::current_dir().ok();
+    let mut files: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    for instruction in instructions {
+        let table = &m.line_table;
+        let index = table.partition_point(|r| r.end <= instruction.address);
+        let Some(range) =
+            table.get(index).filter(|r| r.start <= instruction.address)
+        else {
+            continue;
+        };
+
+        let lines = files.entry(range.file.clone()).or_insert_with(|| {
+            let text = std::fs::read_to_string(&range.file).ok()?;
+            Some(text.lines().map(String::from).collect())
+        });
+        let text = lines
+            .as_ref()
+            .and_then(|lines| lines.get((range.line as usize).checked_sub(1)?))
+            .map(|line| strip_labels(line).to_string());
+
+        // Show paths relative to where we are running, as they were given
+        let path = std::path::Path::new(&range.file);
+        let file = current_dir
+            .as_ref()
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        instruction.source = Some(SourceLine { file, line: range.line, text });
     }
+}
 
-    if let Some(effects) = sequence.last()
-        && let Some(error) = &effects.other_message
-    {
-        match error {
-            RiscletError::Exit(code) => {
-                std::process::exit(*code);
-            }
-            _ => {
-                eprintln!("{}", error);
-                std::process::exit(1);
-            }
+/// A source line without its leading labels, which the disassembly already
+/// shows, and surrounding whitespace
+fn strip_labels(line: &str) -> &str {
+    let mut rest = line.trim();
+    while let Some(colon) = rest.find(':') {
+        let label = &rest[..colon];
+        if label.is_empty()
+            || !label
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$'))
+        {
+            break;
         }
+        rest = rest[colon + 1..].trim_start();
     }
-    eprintln!("program ended unexpectedly");
-    std::process::exit(1);
+    rest.trim_end()
 }
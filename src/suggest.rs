@@ -0,0 +1,84 @@
+// suggest.rs
+//
+// "Did you mean" suggestions for misspelled mnemonics, registers and symbols
+
+/// The most equally close candidates worth listing in one suggestion
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Finds the candidates closest to a misspelled name
+///
+/// Names are compared by edit distance, ignoring case. A candidate is only
+/// suggested when it is close enough to be a plausible typo: at most one
+/// edit for short names, and at most a third of the name's length
+/// otherwise. All candidates at the smallest distance are returned in
+/// order, unless there are too many for the suggestion to help.
+pub fn closest_matches<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let limit = (name.chars().count() / 3).max(1);
+    let mut best_distance = limit + 1;
+    let mut best = Vec::new();
+    for candidate in candidates {
+        let distance = edit_distance(&name, &candidate.to_lowercase());
+        if distance < best_distance {
+            best_distance = distance;
+            best.clear();
+        }
+        if distance == best_distance && !best.contains(&candidate) {
+            best.push(candidate);
+        }
+    }
+    if best.len() > MAX_SUGGESTIONS {
+        best.clear();
+    }
+    best
+}
+
+/// A hint to append to an error message, or "" if nothing is close
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let quoted: Vec<String> = closest_matches(name, candidates)
+        .iter()
+        .map(|candidate| format!("'{}'", candidate))
+        .collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [only] => format!(" (did you mean {}?)", only),
+        [rest @ .., last] => {
+            format!(" (did you mean {} or {}?)", rest.join(", "), last)
+        }
+    }
+}
+
+/// The number of single-character insertions, deletions, substitutions and
+/// adjacent swaps needed to turn one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // rows[i][j] is the distance between a[..i] and b[..j]
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
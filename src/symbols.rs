@@ -3,17 +3,29 @@
 //! This module connects symbol references to their definitions across all source files.
 //! It handles:
 //!
-//! - **Regular symbols**: Named labels and .equ definitions
+//! - **Regular symbols**: Named labels, .equ/.set/.equiv definitions and
+//!   .comm/.lcomm blocks
 //! - **Numeric labels**: Special labels (1:, 2:, etc.) with limited scope
 //! - **Local symbols**: Visible within a single file
-//! - **Global symbols**: Exported via .global and visible across all files
+//! - **Global symbols**: Exported via .global, .weak or .comm and visible
+//!   across all files
 //!
 //! # Symbol Scoping Rules
 //!
 //! ## Regular Symbols
 //! - Cannot be redefined (duplicate label error)
-//! - .equ can redefine previous .equ definitions
-//! - Can be declared global with .global directive
+//! - .equ (and its alias .set) can redefine previous .equ definitions
+//! - .equiv is like .equ, but fails if the symbol is already defined
+//! - .comm and .lcomm define a symbol at a block of .bss space
+//! - Can be declared global with .global directive, weak with .weak, or
+//!   explicitly file-local with .local
+//!
+//! ## Weak and Common Symbols
+//! - A .global definition overrides .comm and .weak definitions of the same
+//!   symbol in other files, and a .comm definition overrides a .weak one
+//! - References to an overridden definition (even from its own file) are
+//!   redirected to the overriding one
+//! - A .weak symbol that is never defined anywhere resolves to 0
 //!
 //! ## Numeric Labels
 //! - Can be reused (e.g., multiple "1:" labels in a file)
@@ -31,10 +43,12 @@
 
 use crate::ast::{
     CompressedOperands, Directive, Expression, Instruction, Line, LineContent,
-    LinePointer, Location, PseudoOp, Source, SourceFile,
+    LinePointer, LineSpans, Location, PseudoOp, Source, SourceFile,
 };
 use crate::error::RiscletError;
-use std::collections::HashMap;
+use crate::suggest::did_you_mean;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 // ==============================================================================
 // Symbol Types and Constants
@@ -46,6 +60,15 @@ pub const SPECIAL_GLOBAL_POINTER: &str = "__global_pointer$";
 /// Name of the builtin symbols file that's injected at the start of assembly
 pub const BUILTIN_FILE_NAME: &str = "<builtin>";
 
+/// The name of the synthetic file that declares a relocatable object's
+/// undefined symbols.
+pub const EXTERNAL_FILE_NAME: &str = "<external>";
+
+/// Files made up by the assembler rather than written by the user
+pub fn is_synthetic_file(file_name: &str) -> bool {
+    file_name == BUILTIN_FILE_NAME || file_name == EXTERNAL_FILE_NAME
+}
+
 /// A struct representing a symbol definition site in a source file.
 /// This represents where a symbol is actually defined (e.g., a label or .equ).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -65,12 +88,36 @@ pub struct SymbolReference {
     pub definition: SymbolDefinition,
 }
 
+/// How a symbol visible across files binds to its references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    /// Declared with .global; must be defined in exactly one file
+    Global,
+    /// Declared with .weak; yields to .global and .comm definitions
+    Weak,
+    /// Allocated with .comm; yields to .global definitions
+    Common,
+}
+
+impl fmt::Display for SymbolBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolBinding::Global => write!(f, "global"),
+            SymbolBinding::Weak => write!(f, "weak"),
+            SymbolBinding::Common => write!(f, "common"),
+        }
+    }
+}
+
 /// A struct representing a global symbol definition, including where it was defined and declared.
+///
+/// An undefined weak symbol uses its .weak directive as its definition.
 #[derive(Debug, Clone, PartialEq)]
 pub struct GlobalDefinition {
     pub symbol: String,
     pub definition_pointer: LinePointer,
     pub declaration_pointer: LinePointer,
+    pub binding: SymbolBinding,
 }
 
 /// Symbol linking results for the entire source.
@@ -95,6 +142,11 @@ pub struct SymbolLinks {
     /// These are symbols declared with .global and appear in the ELF symbol table.
     /// Each global symbol must be unique across all files.
     pub global_symbols: Vec<GlobalDefinition>,
+
+    /// Weak and common definitions overridden by a stronger definition of
+    /// the same symbol in another file. They are left out of the ELF symbol
+    /// table.
+    pub overridden_symbols: Vec<SymbolDefinition>,
 }
 
 impl SymbolLinks {
@@ -118,6 +170,8 @@ struct UnfinalizedGlobal {
     pub definition: Option<LinePointer>,
     /// Location where .global directive appears
     pub declaration_pointer: LinePointer,
+    /// Binding requested by the declaration
+    pub binding: SymbolBinding,
 }
 
 /// Temporary structure for tracking unresolved symbol references during file processing.
@@ -154,38 +208,83 @@ type LinkFileResult = (
 /// - Build a unified global symbols table
 /// - Resolve remaining references using global symbols
 /// - Report errors for duplicate globals and undefined symbols
+#[cfg(test)]
 pub fn link_symbols(source: &Source) -> Result<SymbolLinks, RiscletError> {
+    let (symbol_links, errors, _) = link_symbols_recovering(source);
+    if errors.is_empty() {
+        Ok(symbol_links)
+    } else {
+        Err(RiscletError::from_errors(errors))
+    }
+}
+
+/// Links symbols and keeps going past errors.
+///
+/// Returns the links found, every error, and the names of all symbols that
+/// are used but never defined. References to those symbols are left out of
+/// the links; defining them (e.g., with `create_external_symbols_file`) and
+/// linking again gives links that later phases can use.
+pub fn link_symbols_recovering(
+    source: &Source,
+) -> (SymbolLinks, Vec<RiscletError>, Vec<String>) {
+    let mut errors = Vec::new();
     let mut globals: HashMap<String, GlobalDefinition> = HashMap::new();
     let mut all_unresolved: Vec<UnresolvedReference> = Vec::new();
     let mut line_refs: Vec<Vec<Vec<SymbolReference>>> = Vec::new();
     let mut local_symbols_by_file: Vec<Vec<SymbolDefinition>> = Vec::new();
+    let mut overridden: Vec<SymbolDefinition> = Vec::new();
 
     // Phase 1: Process each file independently
     for (file_index, file) in source.files.iter().enumerate() {
         let (file_globals, file_unresolved, file_local_symbols, file_line_refs) =
-            link_file(file_index, file)?;
+            link_file(file_index, file, &mut errors);
 
         line_refs.push(file_line_refs);
         local_symbols_by_file.push(file_local_symbols);
 
         // Merge global symbols, checking for duplicates
         for global_def in file_globals {
-            if let Some(existing) = globals.get(&global_def.symbol) {
+            let Some(existing) = globals.get(&global_def.symbol) else {
+                globals.insert(global_def.symbol.clone(), global_def);
+                continue;
+            };
+
+            let existing_strength = binding_strength(source, existing);
+            let new_strength = binding_strength(source, &global_def);
+            if existing_strength == new_strength
+                && global_def.binding == SymbolBinding::Global
+            {
                 let old_location = source.files
                     [existing.declaration_pointer.file_index]
                     .lines[existing.declaration_pointer.line_index]
                     .location
                     .to_string();
-                return Err(RiscletError::from_source_pointer(
+                let pointer = global_def.declaration_pointer;
+                let line =
+                    &source.files[pointer.file_index].lines[pointer.line_index];
+                errors.push(RiscletError::from_context(
                     format!(
                         "Duplicate global symbol: {} (previously declared at {})",
                         global_def.symbol, old_location
                     ),
-                    source,
-                    global_def.declaration_pointer,
+                    line.symbol_location(&global_def.symbol),
                 ));
+                continue;
+            }
+
+            // The stronger definition wins; on a tie the first one is kept,
+            // and common blocks take the largest size when laid out
+            let loser = if new_strength > existing_strength {
+                globals.insert(global_def.symbol.clone(), global_def)
+            } else {
+                Some(global_def)
+            };
+            if let Some(loser) = loser {
+                overridden.push(SymbolDefinition {
+                    symbol: loser.symbol,
+                    pointer: loser.definition_pointer,
+                });
             }
-            globals.insert(global_def.symbol.clone(), global_def);
         }
 
         // Accumulate unresolved references
@@ -193,18 +292,99 @@ pub fn link_symbols(source: &Source) -> Result<SymbolLinks, RiscletError> {
     }
 
     // Phase 2: Resolve cross-file references
-    let cross_file_refs = link_cross_file(source, &globals, all_unresolved)?;
+    let (cross_file_refs, undefined) = link_cross_file(
+        source,
+        &globals,
+        &local_symbols_by_file,
+        all_unresolved,
+        &mut errors,
+    );
 
     // Merge cross-file references into the line references
     for (file_index, line_index, sym_ref) in cross_file_refs {
         line_refs[file_index][line_index].push(sym_ref);
     }
 
-    Ok(SymbolLinks {
+    // Redirect references to overridden weak and common definitions
+    for file_refs in &mut line_refs {
+        for sym_ref in file_refs.iter_mut().flatten() {
+            if overridden.contains(&sym_ref.definition) {
+                let winner = &globals[&sym_ref.definition.symbol];
+                sym_ref.definition.pointer = winner.definition_pointer;
+            }
+        }
+    }
+
+    // Sorted by name, so the symbol table comes out the same every time
+    let mut global_symbols: Vec<GlobalDefinition> =
+        globals.into_values().collect();
+    global_symbols.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    let symbol_links = SymbolLinks {
         line_refs,
         local_symbols_by_file,
-        global_symbols: globals.into_values().collect(),
-    })
+        global_symbols,
+        overridden_symbols: overridden,
+    };
+    (symbol_links, errors, undefined)
+}
+
+/// Finds every symbol that is referenced but defined in no file.
+///
+/// Symbols are listed once each, in order of first use. This is used when
+/// assembling a relocatable object, where such symbols are left for the
+/// linker to resolve. Other linking errors are ignored here; they are
+/// reported when the source is linked.
+pub fn find_undefined_symbols(source: &Source) -> Vec<String> {
+    let mut defined = HashSet::new();
+    let mut unresolved = Vec::new();
+
+    for (file_index, file) in source.files.iter().enumerate() {
+        let (file_globals, file_unresolved, _, _) =
+            link_file(file_index, file, &mut Vec::new());
+        defined.extend(file_globals.into_iter().map(|g| g.symbol));
+        unresolved.extend(file_unresolved.into_iter().map(|u| u.symbol));
+    }
+
+    let mut undefined: Vec<String> = Vec::new();
+    for symbol in unresolved {
+        if !defined.contains(&symbol) && !undefined.contains(&symbol) {
+            undefined.push(symbol);
+        }
+    }
+    undefined
+}
+
+/// Ranks a global definition for resolving symbols defined in several files.
+///
+/// An undefined weak symbol is weakest, followed by weak, common, and finally
+/// ordinary global definitions.
+fn binding_strength(source: &Source, global: &GlobalDefinition) -> u8 {
+    match global.binding {
+        SymbolBinding::Weak if is_undefined_weak(source, global) => 0,
+        SymbolBinding::Weak => 1,
+        SymbolBinding::Common => 2,
+        SymbolBinding::Global => 3,
+    }
+}
+
+/// Checks if a global symbol is weak and was never defined.
+///
+/// Such a symbol uses its .weak directive as its definition and resolves to 0.
+pub fn is_undefined_weak(source: &Source, global: &GlobalDefinition) -> bool {
+    let pointer = global.definition_pointer;
+    matches!(
+        source.files[pointer.file_index].lines[pointer.line_index].content,
+        LineContent::Directive(Directive::Weak(_))
+    )
+}
+
+/// Checks if a symbol is assembler-local (e.g., ".Lloop").
+///
+/// Like numeric labels, these are left out of the ELF symbol table unless
+/// --keep-locals is given.
+pub fn is_assembler_local(symbol: &str) -> bool {
+    symbol.starts_with(".L")
 }
 
 /// Checks if a symbol is a backward numeric label reference (e.g., "1b").
@@ -226,34 +406,31 @@ fn is_numeric_forward_ref(symbol: &str) -> Option<u32> {
 /// - A segment boundary (.text, .data, .bss) is crossed
 ///
 /// This function removes all backward references ("1b", "2b") from the definitions
-/// and checks for any unresolved forward references ("1f", "2f"). If any forward
-/// references remain unresolved, it returns an error for the first one found.
+/// and reports an error for each unresolved forward reference ("1f", "2f").
 fn flush_numeric_labels(
-    locations: &[Location],
+    lines: &[Line],
     definitions: &mut HashMap<String, LinePointer>,
     unresolved: &mut Vec<UnresolvedReference>,
-) -> Result<(), RiscletError> {
+    errors: &mut Vec<RiscletError>,
+) {
     // Remove all backward numeric label definitions (e.g., "1b", "2b")
     definitions.retain(|symbol, _| is_numeric_backward_ref(symbol).is_none());
 
-    // Find the first unresolved forward numeric reference, if any
-    if let Some(pos) = unresolved
-        .iter()
-        .position(|unref| is_numeric_forward_ref(&unref.symbol).is_some())
-    {
-        let unref = unresolved.remove(pos);
-        let error_location =
-            locations[unref.referencing_pointer.line_index].clone();
-        return Err(RiscletError::from_context(
+    // Report and drop the unresolved forward numeric references
+    unresolved.retain(|unref| {
+        if is_numeric_forward_ref(&unref.symbol).is_none() {
+            return true;
+        }
+        errors.push(RiscletError::from_context(
             format!(
                 "Numeric label '{}' (forward) is used but no matching label found in remaining file",
                 unref.symbol
             ),
-            error_location,
+            lines[unref.referencing_pointer.line_index]
+                .symbol_location(&unref.symbol),
         ));
-    }
-
-    Ok(())
+        false
+    });
 }
 
 /// Processes a single file for symbol linking.
@@ -264,6 +441,9 @@ fn flush_numeric_labels(
 /// - Global symbol declarations
 /// - Numeric label scoping (flushed at non-numeric labels and segment boundaries)
 ///
+/// Errors are added to `errors` and the line is skipped, so one pass reports
+/// every problem in the file.
+///
 /// Returns:
 /// - Global definitions exported from this file
 /// - Unresolved references (to be resolved cross-file)
@@ -272,16 +452,14 @@ fn flush_numeric_labels(
 fn link_file(
     file_index: usize,
     file: &SourceFile,
-) -> Result<LinkFileResult, RiscletError> {
-    // Precompute locations for error reporting
-    let locations: Vec<Location> =
-        file.lines.iter().map(|line| line.location.clone()).collect();
-
+    errors: &mut Vec<RiscletError>,
+) -> LinkFileResult {
     // Symbol tracking state
     let mut definitions: HashMap<String, LinePointer> = HashMap::new();
     let mut unresolved: Vec<UnresolvedReference> = Vec::new();
     let mut unfinalized_globals: HashMap<String, UnfinalizedGlobal> =
         HashMap::new();
+    let mut local_declarations: HashMap<String, LinePointer> = HashMap::new();
     let mut line_outgoing_refs: Vec<Vec<SymbolReference>> =
         vec![Vec::new(); file.lines.len()];
 
@@ -295,24 +473,41 @@ fn link_file(
         let line_ptr = LinePointer { file_index, line_index };
 
         // Phase 1: Extract and resolve symbol references
-        process_symbol_references(
+        if let Err(e) = process_symbol_references(
             line,
             line_ptr,
             &definitions,
             &mut unresolved,
             &mut line_outgoing_refs[line_index],
-        )?;
+        ) {
+            errors.push(e);
+        }
 
-        // Phase 2: Handle symbol definitions
+        // Phase 2: Handle symbol definitions. Non-numeric labels flush all
+        // numeric label scopes first.
+        if let LineContent::Label(label) = &line.content
+            && label.parse::<u32>().is_err()
+        {
+            flush_numeric_labels(
+                &file.lines,
+                &mut definitions,
+                &mut unresolved,
+                errors,
+            );
+        }
         let new_definition = process_symbol_definitions(
             line,
             line_ptr,
-            &locations,
             &mut definitions,
             &mut unresolved,
             &mut unfinalized_globals,
+            &local_declarations,
             &mut patches,
-        )?;
+        )
+        .unwrap_or_else(|e| {
+            errors.push(e);
+            None
+        });
 
         // Phase 3: Resolve any forward references to the newly defined symbol
         if let Some(symbol) = new_definition {
@@ -332,23 +527,53 @@ fn link_file(
             )
         ) {
             flush_numeric_labels(
-                &locations,
+                &file.lines,
                 &mut definitions,
                 &mut unresolved,
-            )?;
+                errors,
+            );
         }
 
-        // Phase 5: Handle .global declarations
-        if let LineContent::Directive(Directive::Global(symbols)) =
-            &line.content
-        {
-            process_global_declarations(
-                symbols,
-                line_ptr,
-                &line.location,
-                &definitions,
-                &mut unfinalized_globals,
-            )?;
+        // Phase 5: Handle .global, .weak and .local declarations
+        match &line.content {
+            LineContent::Directive(Directive::Global(symbols)) => {
+                if let Err(e) = process_global_declarations(
+                    symbols,
+                    SymbolBinding::Global,
+                    line_ptr,
+                    line,
+                    &definitions,
+                    &local_declarations,
+                    &mut unfinalized_globals,
+                ) {
+                    errors.push(e);
+                }
+            }
+            LineContent::Directive(Directive::Weak(symbols)) => {
+                if let Err(e) = process_global_declarations(
+                    symbols,
+                    SymbolBinding::Weak,
+                    line_ptr,
+                    line,
+                    &definitions,
+                    &local_declarations,
+                    &mut unfinalized_globals,
+                ) {
+                    errors.push(e);
+                }
+            }
+            LineContent::Directive(Directive::Local(symbols)) => {
+                if let Err(e) = process_local_declarations(
+                    symbols,
+                    line_ptr,
+                    line,
+                    &unfinalized_globals,
+                    &mut local_declarations,
+                ) {
+                    errors.push(e);
+                }
+            }
+            _ => {}
         }
     }
 
@@ -358,12 +583,16 @@ fn link_file(
     }
 
     // Finalize globals and validate they have definitions
-    let global_definitions = finalize_globals(unfinalized_globals, file)?;
+    let global_definitions =
+        finalize_globals(unfinalized_globals, file, errors);
 
     // Flush remaining numeric labels at end of file
-    if file.lines.last().is_some() {
-        flush_numeric_labels(&locations, &mut definitions, &mut unresolved)?;
-    }
+    flush_numeric_labels(
+        &file.lines,
+        &mut definitions,
+        &mut unresolved,
+        errors,
+    );
 
     // Convert local definitions to output format
     let local_symbols: Vec<SymbolDefinition> = definitions
@@ -371,7 +600,7 @@ fn link_file(
         .map(|(symbol, pointer)| SymbolDefinition { symbol, pointer })
         .collect();
 
-    Ok((global_definitions, unresolved, local_symbols, line_outgoing_refs))
+    (global_definitions, unresolved, local_symbols, line_outgoing_refs)
 }
 
 /// Processes symbol references found in a line.
@@ -408,7 +637,7 @@ fn process_symbol_references(
                         "Numeric label '{}' (backward) is used but no matching label found earlier in file",
                         symbol
                     ),
-                    line.location.clone(),
+                    line.symbol_location(&symbol),
                 ));
             }
         } else if is_numeric_forward_ref(&symbol).is_some() {
@@ -440,16 +669,16 @@ fn process_symbol_references(
     Ok(())
 }
 
-/// Processes symbol definitions (labels and .equ directives).
+/// Processes symbol definitions (labels, .equ/.equiv, .comm and .lcomm).
 ///
 /// Returns the newly defined symbol name, if any.
 fn process_symbol_definitions(
     line: &Line,
     line_ptr: LinePointer,
-    locations: &[Location],
     definitions: &mut HashMap<String, LinePointer>,
     unresolved: &mut Vec<UnresolvedReference>,
     unfinalized_globals: &mut HashMap<String, UnfinalizedGlobal>,
+    local_declarations: &HashMap<String, LinePointer>,
     patches: &mut Vec<(usize, SymbolReference)>,
 ) -> Result<Option<String>, RiscletError> {
     match &line.content {
@@ -468,10 +697,8 @@ fn process_symbol_definitions(
                 process_regular_label(
                     label,
                     line_ptr,
-                    &line.location,
-                    locations,
+                    &line.head_location(),
                     definitions,
-                    unresolved,
                     unfinalized_globals,
                 )
             }
@@ -479,8 +706,41 @@ fn process_symbol_definitions(
         LineContent::Directive(Directive::Equ(name, _)) => {
             process_equ_definition(
                 name,
+                true,
                 line_ptr,
-                &line.location,
+                &line.symbol_location(name),
+                definitions,
+                unfinalized_globals,
+            )
+        }
+        LineContent::Directive(Directive::Equiv(name, _)) => {
+            process_equ_definition(
+                name,
+                false,
+                line_ptr,
+                &line.symbol_location(name),
+                definitions,
+                unfinalized_globals,
+            )
+        }
+        LineContent::Directive(Directive::Comm(name, _, _)) => {
+            // .comm makes the symbol global unless it was declared .local
+            let common = !local_declarations.contains_key(name);
+            process_comm_definition(
+                name,
+                common,
+                line_ptr,
+                &line.symbol_location(name),
+                definitions,
+                unfinalized_globals,
+            )
+        }
+        LineContent::Directive(Directive::Lcomm(name, _, _)) => {
+            process_comm_definition(
+                name,
+                false,
+                line_ptr,
+                &line.symbol_location(name),
                 definitions,
                 unfinalized_globals,
             )
@@ -530,14 +790,9 @@ fn process_regular_label(
     label: &str,
     line_ptr: LinePointer,
     line_location: &Location,
-    locations: &[Location],
     definitions: &mut HashMap<String, LinePointer>,
-    unresolved: &mut Vec<UnresolvedReference>,
     unfinalized_globals: &mut HashMap<String, UnfinalizedGlobal>,
 ) -> Result<Option<String>, RiscletError> {
-    // Non-numeric labels flush all numeric label scopes
-    flush_numeric_labels(locations, definitions, unresolved)?;
-
     // Check for duplicate label
     if definitions.contains_key(label) {
         return Err(RiscletError::from_context(
@@ -560,9 +815,12 @@ fn process_regular_label(
     Ok(Some(label.to_string()))
 }
 
-/// Processes an .equ directive definition.
+/// Processes an .equ (or .set) or .equiv directive definition.
+///
+/// Only .equ may redefine an existing symbol.
 fn process_equ_definition(
     name: &str,
+    redefinable: bool,
     line_ptr: LinePointer,
     line_location: &Location,
     definitions: &mut HashMap<String, LinePointer>,
@@ -579,6 +837,16 @@ fn process_equ_definition(
         ));
     }
 
+    if !redefinable && definitions.contains_key(name) {
+        return Err(RiscletError::from_context(
+            format!(
+                "Symbol '{}' is already defined (.equiv cannot redefine a symbol; use .equ or .set)",
+                name
+            ),
+            line_location.clone(),
+        ));
+    }
+
     // .equ can redefine existing symbols (including previous .equ definitions)
     definitions.insert(name.to_string(), line_ptr);
 
@@ -590,6 +858,59 @@ fn process_equ_definition(
     Ok(Some(name.to_string()))
 }
 
+/// Processes a .comm or .lcomm block definition.
+///
+/// The block defines its symbol like a label does. A common block is also
+/// declared global with common binding.
+fn process_comm_definition(
+    name: &str,
+    common: bool,
+    line_ptr: LinePointer,
+    line_location: &Location,
+    definitions: &mut HashMap<String, LinePointer>,
+    unfinalized_globals: &mut HashMap<String, UnfinalizedGlobal>,
+) -> Result<Option<String>, RiscletError> {
+    if name.parse::<u32>().is_ok() {
+        return Err(RiscletError::from_context(
+            format!(
+                "Numeric labels (like '{}:') cannot be used with .comm or .lcomm",
+                name
+            ),
+            line_location.clone(),
+        ));
+    }
+
+    if definitions.contains_key(name) {
+        return Err(RiscletError::from_context(
+            format!(
+                "Symbol '{}' is already defined (labels must be unique within a file)",
+                name
+            ),
+            line_location.clone(),
+        ));
+    }
+
+    definitions.insert(name.to_string(), line_ptr);
+
+    if let Some(global) = unfinalized_globals.get_mut(name) {
+        global.definition = Some(line_ptr);
+        if common && global.binding == SymbolBinding::Global {
+            global.binding = SymbolBinding::Common;
+        }
+    } else if common {
+        unfinalized_globals.insert(
+            name.to_string(),
+            UnfinalizedGlobal {
+                definition: Some(line_ptr),
+                declaration_pointer: line_ptr,
+                binding: SymbolBinding::Common,
+            },
+        );
+    }
+
+    Ok(Some(name.to_string()))
+}
+
 /// Resolves forward references to a newly defined symbol.
 fn resolve_forward_references(
     symbol: &str,
@@ -617,23 +938,30 @@ fn resolve_forward_references(
     });
 }
 
-/// Processes .global declarations.
+/// Processes .global and .weak declarations.
 fn process_global_declarations(
     symbols: &[String],
+    binding: SymbolBinding,
     line_ptr: LinePointer,
-    line_location: &Location,
+    line: &Line,
     definitions: &HashMap<String, LinePointer>,
+    local_declarations: &HashMap<String, LinePointer>,
     unfinalized_globals: &mut HashMap<String, UnfinalizedGlobal>,
 ) -> Result<(), RiscletError> {
+    let directive = match binding {
+        SymbolBinding::Weak => ".weak",
+        _ => ".global",
+    };
+
     for symbol in symbols {
         // Cannot declare numeric labels as global
         if symbol.parse::<u32>().is_ok() {
             return Err(RiscletError::from_context(
                 format!(
-                    "Numeric labels (like '{}:') cannot be declared global with .global",
-                    symbol
+                    "Numeric labels (like '{}:') cannot be declared global with {}",
+                    symbol, directive
                 ),
-                line_location.clone(),
+                line.symbol_location(symbol),
             ));
         }
 
@@ -641,10 +969,21 @@ fn process_global_declarations(
         if unfinalized_globals.contains_key(symbol) {
             return Err(RiscletError::from_context(
                 format!(
-                    "Symbol '{}' is declared global (.global) more than once",
+                    "Symbol '{}' is declared global (.global, .weak or .comm) more than once",
                     symbol
                 ),
-                line_location.clone(),
+                line.symbol_location(symbol),
+            ));
+        }
+
+        // Cannot make a symbol both local and global
+        if local_declarations.contains_key(symbol) {
+            return Err(RiscletError::from_context(
+                format!(
+                    "Symbol '{}' is declared .local and cannot also be declared {}",
+                    symbol, directive
+                ),
+                line.symbol_location(symbol),
             ));
         }
 
@@ -654,6 +993,7 @@ fn process_global_declarations(
             UnfinalizedGlobal {
                 definition: definitions.get(symbol).copied(),
                 declaration_pointer: line_ptr,
+                binding,
             },
         );
     }
@@ -661,34 +1001,73 @@ fn process_global_declarations(
     Ok(())
 }
 
+/// Processes .local declarations.
+///
+/// Symbols are file-local by default, so .local only guards against the
+/// symbol also being declared global and keeps a later .comm block local.
+fn process_local_declarations(
+    symbols: &[String],
+    line_ptr: LinePointer,
+    line: &Line,
+    unfinalized_globals: &HashMap<String, UnfinalizedGlobal>,
+    local_declarations: &mut HashMap<String, LinePointer>,
+) -> Result<(), RiscletError> {
+    for symbol in symbols {
+        if unfinalized_globals.contains_key(symbol) {
+            return Err(RiscletError::from_context(
+                format!(
+                    "Symbol '{}' is declared global and cannot also be declared .local",
+                    symbol
+                ),
+                line.symbol_location(symbol),
+            ));
+        }
+        local_declarations.insert(symbol.clone(), line_ptr);
+    }
+
+    Ok(())
+}
+
 /// Finalizes global symbols and validates they all have definitions.
+///
+/// Globals without a definition are reported and left out.
 fn finalize_globals(
     unfinalized_globals: HashMap<String, UnfinalizedGlobal>,
     file: &SourceFile,
-) -> Result<Vec<GlobalDefinition>, RiscletError> {
+    errors: &mut Vec<RiscletError>,
+) -> Vec<GlobalDefinition> {
     let mut global_definitions = Vec::new();
 
     for (symbol, ug) in unfinalized_globals {
-        let Some(definition_pointer) = ug.definition else {
-            let decl_location =
-                file.lines[ug.declaration_pointer.line_index].location.clone();
-            return Err(RiscletError::from_context(
+        // An undefined weak symbol is defined by its own .weak directive
+        let definition = match ug.binding {
+            SymbolBinding::Weak => {
+                ug.definition.or(Some(ug.declaration_pointer))
+            }
+            _ => ug.definition,
+        };
+        let Some(definition_pointer) = definition else {
+            let decl_location = file.lines[ug.declaration_pointer.line_index]
+                .symbol_location(&symbol);
+            errors.push(RiscletError::from_context(
                 format!(
                     "Symbol '{}' is declared global (.global) but never defined with a label",
                     symbol
                 ),
                 decl_location,
             ));
+            continue;
         };
 
         global_definitions.push(GlobalDefinition {
             symbol,
             definition_pointer,
             declaration_pointer: ug.declaration_pointer,
+            binding: ug.binding,
         });
     }
 
-    Ok(global_definitions)
+    global_definitions
 }
 
 /// Extracts all symbol references from a line's AST.
@@ -777,13 +1156,15 @@ fn extract_refs_from_pseudo(pseudo: &PseudoOp, refs: &mut Vec<String>) {
         PseudoOp::Li(_, expr)
         | PseudoOp::La(_, expr)
         | PseudoOp::LoadGlobal(_, _, expr)
-        | PseudoOp::Call(expr)
-        | PseudoOp::Tail(expr) => {
+        | PseudoOp::Call(_, expr)
+        | PseudoOp::Tail(expr, _)
+        | PseudoOp::Jump(expr, _) => {
             refs.extend(extract_from_expression(expr));
         }
         PseudoOp::StoreGlobal(_, _, expr, _) => {
             refs.extend(extract_from_expression(expr));
         }
+        PseudoOp::Extend(..) | PseudoOp::Sle(..) => {}
     }
 }
 
@@ -792,11 +1173,21 @@ fn extract_refs_from_directive(dir: &Directive, refs: &mut Vec<String>) {
     match dir {
         // Directives with a single expression
         Directive::Equ(_, expr)
+        | Directive::Equiv(_, expr)
+        | Directive::Size(_, expr)
         | Directive::Space(expr)
         | Directive::Balign(expr) => {
             refs.extend(extract_from_expression(expr));
         }
 
+        // Common blocks with a size and optional alignment
+        Directive::Comm(_, size, align) | Directive::Lcomm(_, size, align) => {
+            refs.extend(extract_from_expression(size));
+            if let Some(align) = align {
+                refs.extend(extract_from_expression(align));
+            }
+        }
+
         // Directives with multiple expressions
         Directive::Byte(exprs)
         | Directive::TwoByte(exprs)
@@ -841,14 +1232,32 @@ fn extract_from_expression(expr: &Expression) -> Vec<String> {
         | Expression::RightShiftOp { lhs, rhs }
         | Expression::BitwiseOrOp { lhs, rhs }
         | Expression::BitwiseAndOp { lhs, rhs }
-        | Expression::BitwiseXorOp { lhs, rhs } => {
+        | Expression::BitwiseXorOp { lhs, rhs }
+        | Expression::EqualOp { lhs, rhs }
+        | Expression::NotEqualOp { lhs, rhs }
+        | Expression::LessOp { lhs, rhs }
+        | Expression::LessEqualOp { lhs, rhs }
+        | Expression::GreaterOp { lhs, rhs }
+        | Expression::GreaterEqualOp { lhs, rhs }
+        | Expression::LogicalAndOp { lhs, rhs }
+        | Expression::LogicalOrOp { lhs, rhs } => {
             refs.extend(extract_from_expression(lhs));
             refs.extend(extract_from_expression(rhs));
         }
 
+        // Conditional: recurse on all three operands
+        Expression::ConditionalOp { condition, then_expr, else_expr } => {
+            refs.extend(extract_from_expression(condition));
+            refs.extend(extract_from_expression(then_expr));
+            refs.extend(extract_from_expression(else_expr));
+        }
+
         // Unary operations: recurse on operand
         Expression::NegateOp { expr }
         | Expression::BitwiseNotOp { expr }
+        | Expression::LogicalNotOp { expr }
+        | Expression::HiOp { expr }
+        | Expression::LoOp { expr }
         | Expression::Parenthesized(expr) => {
             refs.extend(extract_from_expression(expr));
         }
@@ -863,13 +1272,18 @@ fn extract_from_expression(expr: &Expression) -> Vec<String> {
 /// references must be resolved against global symbols. This function:
 /// - Matches unresolved references with global symbol definitions
 /// - Returns cross-file references as tuples: (file_index, line_index, SymbolReference)
-/// - Reports errors for any truly undefined symbols
+/// - Reports an error for each use of a truly undefined symbol, suggesting
+///   any similar symbol visible from the using file, and returns the names
+///   of those symbols
 fn link_cross_file(
     source: &Source,
     globals: &HashMap<String, GlobalDefinition>,
+    local_symbols_by_file: &[Vec<SymbolDefinition>],
     unresolved: Vec<UnresolvedReference>,
-) -> Result<Vec<(usize, usize, SymbolReference)>, RiscletError> {
+    errors: &mut Vec<RiscletError>,
+) -> (Vec<(usize, usize, SymbolReference)>, Vec<String>) {
     let mut cross_file_refs = Vec::new();
+    let mut undefined: Vec<String> = Vec::new();
 
     for unref in unresolved {
         if let Some(global_def) = globals.get(&unref.symbol) {
@@ -885,25 +1299,71 @@ fn link_cross_file(
             ));
         } else {
             // Symbol is truly undefined
-            let file = &source.files[unref.referencing_pointer.file_index];
-            let line = &file.lines[unref.referencing_pointer.line_index];
-            return Err(RiscletError::from_context(
+            let file_index = unref.referencing_pointer.file_index;
+            let line = &source.files[file_index].lines
+                [unref.referencing_pointer.line_index];
+            let mut known: Vec<&str> = local_symbols_by_file[file_index]
+                .iter()
+                .map(|local| local.symbol.as_str())
+                .filter(|symbol| is_numeric_backward_ref(symbol).is_none())
+                .chain(globals.keys().map(String::as_str))
+                .collect();
+            known.sort();
+            known.dedup();
+            let hint = did_you_mean(&unref.symbol, known);
+            let message = if hint.is_empty() {
                 format!(
                     "Symbol '{}' is used but never defined (define with a label, or declare with .global if defined elsewhere)",
                     unref.symbol
-                ),
-                line.location.clone(),
+                )
+            } else {
+                format!(
+                    "Symbol '{}' is used but never defined{}",
+                    unref.symbol, hint
+                )
+            };
+            errors.push(RiscletError::from_context(
+                message,
+                line.symbol_location(&unref.symbol),
             ));
+            if !undefined.contains(&unref.symbol) {
+                undefined.push(unref.symbol);
+            }
         }
     }
 
-    Ok(cross_file_refs)
+    (cross_file_refs, undefined)
 }
 
 // ==============================================================================
 // Builtin Symbols File Generation
 // ==============================================================================
 
+/// Creates a synthetic source file declaring a relocatable object's undefined
+/// symbols.
+///
+/// Each symbol is declared `.weak`, so it links like an undefined weak symbol
+/// and evaluates to address 0 while the object is assembled. The object
+/// builder recognizes definitions in this file and emits them as undefined
+/// global symbols for the linker to resolve.
+pub fn create_external_symbols_file(symbols: &[String]) -> SourceFile {
+    SourceFile {
+        file: EXTERNAL_FILE_NAME.to_string(),
+        lines: symbols
+            .iter()
+            .enumerate()
+            .map(|(index, symbol)| Line {
+                location: Location::new(EXTERNAL_FILE_NAME, index + 1),
+                content: LineContent::Directive(Directive::Weak(vec![
+                    symbol.clone(),
+                ])),
+                spans: LineSpans::default(),
+            })
+            .collect(),
+        warning_flags: Vec::new(),
+    }
+}
+
 /// Creates a synthetic source file containing builtin symbol definitions.
 /// This file is appended to the source file list after parsing and provides
 /// definitions for linker-provided symbols like __global_pointer$.
@@ -924,30 +1384,25 @@ pub fn create_builtin_symbols_file() -> SourceFile {
         lines: vec![
             // .data directive
             Line {
-                location: Location {
-                    file: BUILTIN_FILE_NAME.to_string(),
-                    line: 1,
-                },
+                location: Location::new(BUILTIN_FILE_NAME, 1),
                 content: LineContent::Directive(Directive::Data),
+                spans: LineSpans::default(),
             },
             // .global __global_pointer$
             Line {
-                location: Location {
-                    file: BUILTIN_FILE_NAME.to_string(),
-                    line: 2,
-                },
+                location: Location::new(BUILTIN_FILE_NAME, 2),
                 content: LineContent::Directive(Directive::Global(vec![
                     SPECIAL_GLOBAL_POINTER.to_string(),
                 ])),
+                spans: LineSpans::default(),
             },
             // __global_pointer$: label at offset 2048
             Line {
-                location: Location {
-                    file: BUILTIN_FILE_NAME.to_string(),
-                    line: 3,
-                },
+                location: Location::new(BUILTIN_FILE_NAME, 3),
                 content: LineContent::Label(SPECIAL_GLOBAL_POINTER.to_string()),
+                spans: LineSpans::default(),
             },
         ],
+        warning_flags: Vec::new(),
     }
 }
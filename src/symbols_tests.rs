@@ -3,8 +3,8 @@ mod tests {
     use crate::ast::*;
     use crate::parser;
     use crate::symbols::{
-        SymbolLinks, create_builtin_symbols_file, extract_references_from_line,
-        link_symbols,
+        SymbolBinding, SymbolLinks, create_builtin_symbols_file,
+        extract_references_from_line, link_symbols,
     };
     use crate::tokenizer;
 
@@ -36,7 +36,11 @@ mod tests {
             }
         }
 
-        Ok(SourceFile { file: filename.to_string(), lines })
+        Ok(SourceFile {
+            file: filename.to_string(),
+            lines,
+            warning_flags: Vec::new(),
+        })
     }
 
     /// Helper: Create a Source from multiple file contents
@@ -2453,4 +2457,196 @@ mod tests {
 
         assert_eq!(ref_count, 2, "Should have found 2 references to '1f'");
     }
+
+    // ============================================================================
+    // .set, .equiv, .weak, .local, .comm and .lcomm Tests
+    // ============================================================================
+
+    #[test]
+    fn test_set_can_redefine() {
+        let source_text = "
+            .set counter, 1
+            .set counter, counter + 1
+            li a0, counter
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        // li references the second definition (line index 1)
+        let refs =
+            symbols.get_line_refs(LinePointer { file_index: 0, line_index: 2 });
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].definition.pointer.line_index, 1);
+    }
+
+    #[test]
+    fn test_equiv_cannot_redefine() {
+        let source_text = "
+            .equ value, 1
+            .equiv value, 2
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let err = link_symbols(&source).unwrap_err().to_string();
+        assert!(
+            err.contains("value") && err.contains(".equiv"),
+            "Error should mention .equiv: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_equiv_defines_new_symbol() {
+        let source_text = "
+            .equiv value, 2
+            li a0, value
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let symbols = link_symbols(&source).unwrap();
+        assert_reference(
+            &symbols,
+            LinePointer { file_index: 0, line_index: 1 },
+            "value",
+            LinePointer { file_index: 0, line_index: 0 },
+        );
+    }
+
+    #[test]
+    fn test_weak_undefined_uses_weak_directive() {
+        let source_text = "
+            .weak handler
+            call handler
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        let weak_ptr = LinePointer { file_index: 0, line_index: 0 };
+        let global = symbols
+            .global_symbols
+            .iter()
+            .find(|g| g.symbol == "handler")
+            .unwrap();
+        assert_eq!(global.binding, SymbolBinding::Weak);
+        assert_eq!(global.definition_pointer, weak_ptr);
+        assert_reference(
+            &symbols,
+            LinePointer { file_index: 0, line_index: 1 },
+            "handler",
+            weak_ptr,
+        );
+    }
+
+    #[test]
+    fn test_global_overrides_weak_in_other_file() {
+        let file1 = "
+            .weak handler
+            handler:
+                ret
+            main:
+                call handler
+        ";
+        let file2 = "
+            .global handler
+            handler:
+                nop
+        ";
+
+        let source =
+            create_source(vec![("file1.s", file1), ("file2.s", file2)])
+                .unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        // The call in file1 is redirected to the strong definition in file2
+        let strong_ptr = LinePointer { file_index: 1, line_index: 1 };
+        let call_ptr = find_referencing_line(&source, "handler").unwrap();
+        assert_reference(&symbols, call_ptr, "handler", strong_ptr);
+
+        let global = symbols
+            .global_symbols
+            .iter()
+            .find(|g| g.symbol == "handler")
+            .unwrap();
+        assert_eq!(global.binding, SymbolBinding::Global);
+        assert_eq!(global.definition_pointer, strong_ptr);
+        assert_eq!(symbols.overridden_symbols.len(), 1);
+    }
+
+    #[test]
+    fn test_comm_is_common_global() {
+        let source_text = "
+            .comm buffer, 64, 8
+            la a0, buffer
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        let global = symbols
+            .global_symbols
+            .iter()
+            .find(|g| g.symbol == "buffer")
+            .unwrap();
+        assert_eq!(global.binding, SymbolBinding::Common);
+        assert_reference(
+            &symbols,
+            LinePointer { file_index: 0, line_index: 1 },
+            "buffer",
+            LinePointer { file_index: 0, line_index: 0 },
+        );
+    }
+
+    #[test]
+    fn test_duplicate_comm_keeps_first() {
+        let file1 = ".comm buffer, 64";
+        let file2 = ".comm buffer, 64";
+
+        let source =
+            create_source(vec![("file1.s", file1), ("file2.s", file2)])
+                .unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        let global = symbols
+            .global_symbols
+            .iter()
+            .find(|g| g.symbol == "buffer")
+            .unwrap();
+        assert_eq!(global.definition_pointer.file_index, 0);
+    }
+
+    #[test]
+    fn test_lcomm_and_local_comm_stay_local() {
+        let source_text = "
+            .lcomm scratch, 16
+            .local buffer
+            .comm buffer, 64
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let symbols = link_symbols(&source).unwrap();
+
+        assert!(
+            !symbols
+                .global_symbols
+                .iter()
+                .any(|g| g.symbol == "scratch" || g.symbol == "buffer"),
+            ".lcomm and .local .comm symbols should not be global"
+        );
+    }
+
+    #[test]
+    fn test_local_conflicts_with_global() {
+        let source_text = "
+            .global main
+            .local main
+            main:
+                ret
+        ";
+
+        let source = create_source(vec![("test.s", source_text)]).unwrap();
+        let err = link_symbols(&source).unwrap_err().to_string();
+        assert!(err.contains("main") && err.contains(".local"), "{}", err);
+    }
 }
@@ -0,0 +1,8 @@
+# A helper whose code runs through alignment padding, for linking tests
+    .text
+    .globl add_three
+add_three:                  # a0 = a0 + 3
+    addi a0, a0, 1
+    .p2align 3
+    addi a0, a0, 2
+    ret
@@ -0,0 +1,63 @@
+# Helper routines for linking tests
+    .text
+    .globl print_string
+    .globl sum_array
+    .globl get_message
+print_string:               # a0 = address of NUL-terminated string
+    mv t0, a0
+1:  lbu t1, 0(t0)
+    beqz t1, 2f
+    addi t0, t0, 1
+    j 1b
+2:  sub a2, t0, a0
+    mv a1, a0
+    li a0, 1
+    li a7, 64
+    ecall
+    ret
+
+    .balign 16
+sum_array:                  # a0 = address, a1 = count
+    li t0, 0
+.Lloop:
+    beqz a1, .Ldone
+    lw t1, 0(a0)
+    add t0, t0, t1
+    addi a0, a0, 4
+    addi a1, a1, -1
+    j .Lloop
+.Ldone:
+    mv a0, t0
+    ret
+
+get_message:
+.Lhi:
+    auipc a0, %pcrel_hi(message)
+    nop
+    addi a0, a0, %pcrel_lo(.Lhi)
+    lw t0, counter
+    addi t0, t0, 1
+    sw t0, counter, t1
+    lui t2, %hi(scratch)
+    sw t0, %lo(scratch)(t2)
+    tail finish
+finish:
+    ret
+
+    .section .rodata
+message:
+    .asciz "Hello from helpers\n"
+    .balign 4
+length:
+    .word message_end - message
+message_end:
+    .section .sdata,"aw"
+    .balign 4
+counter:
+    .word 41
+table:
+    .word print_string, sum_array
+    .section .sbss,"aw",@nobits
+    .balign 8
+scratch:
+    .zero 8
@@ -0,0 +1,76 @@
+// test_helpers.rs
+//
+// Helpers shared by the test modules
+
+use crate::execution::Machine;
+use crate::riscv::Op;
+use crate::trace::SyscallInfo;
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+
+/// Step a machine until the program exits, returning its exit status
+pub(crate) fn run_to_exit(machine: &mut Machine) -> i32 {
+    for _ in 0..10_000 {
+        let effects = machine.step().expect("step succeeds");
+        if let Some(SyscallInfo::Exit(status)) = effects.syscall {
+            return status;
+        }
+        assert!(!effects.terminate, "terminated without exiting");
+    }
+    panic!("program did not exit");
+}
+
+/// Step a machine up to its first ecall, returning a0 there
+pub(crate) fn run_to_ecall(machine: &mut Machine) -> i32 {
+    for _ in 0..10_000 {
+        let (inst, _) = machine.load_instruction(machine.pc()).unwrap();
+        if matches!(Op::new(inst), Op::Ecall) {
+            return machine.get_reg(10);
+        }
+        machine.step().expect("step succeeds");
+    }
+    panic!("program did not reach an ecall");
+}
+
+/// A file in the temporary directory, removed when dropped
+pub(crate) struct TempFile(pub(crate) String);
+
+impl TempFile {
+    /// Name a file without creating it
+    pub(crate) fn new(name: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!(
+            "risclet-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        TempFile(path.to_string_lossy().into_owned())
+    }
+
+    /// Create a file holding `contents`
+    pub(crate) fn with_contents(name: &str, contents: &str) -> TempFile {
+        let file = TempFile::new(name);
+        std::fs::write(&file.0, contents).unwrap();
+        file
+    }
+
+    pub(crate) fn read(&self) -> Vec<u8> {
+        std::fs::read(&self.0).unwrap()
+    }
+
+    /// Give the file a modification time some seconds from now
+    pub(crate) fn touch(&self, seconds: u64) {
+        let time = SystemTime::now() + Duration::from_secs(seconds);
+        File::options()
+            .write(true)
+            .open(&self.0)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
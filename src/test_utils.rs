@@ -31,6 +31,7 @@ mod tests {
             pseudo_index: 0,
             verbose_fields: Vec::new(),
             pseudo_fields: Vec::new(),
+            source: None,
         });
 
         // Execute the instruction
@@ -61,6 +62,7 @@ mod tests {
             pseudo_index: 0,
             verbose_fields: Vec::new(),
             pseudo_fields: Vec::new(),
+            source: None,
         });
 
         let initial_pc = machine.pc();
@@ -100,6 +102,7 @@ mod tests {
             pseudo_index: 0,
             verbose_fields: Vec::new(),
             pseudo_fields: Vec::new(),
+            source: None,
         });
 
         // Execute the instruction (this will trigger the exit syscall)
@@ -1,175 +1,279 @@
-use crate::ast::{DirectiveOp, OperatorOp, Register, Token};
+use crate::ast::{DirectiveOp, OperatorOp, Register, Span, Token};
 
+#[cfg(test)]
 pub fn tokenize(line: &str) -> Result<Vec<Token>, String> {
-    let mut tokens = Vec::new();
+    tokenize_with_spans(line)
+        .map(|(tokens, _)| tokens)
+        .map_err(|(message, _)| message)
+}
+
+/// Tokenizes a line and records the columns each token covers
+///
+/// On error, returns the message along with the span of the text that
+/// could not be tokenized.
+pub fn tokenize_with_spans(
+    line: &str,
+) -> Result<(Vec<Token>, Vec<Span>), (String, Span)> {
+    tokenize_with_comment(line).map(|(tokens, spans, _)| (tokens, spans))
+}
+
+/// Tokenizes a line like `tokenize_with_spans`, also returning its comment
+/// (from `#` to the end of the line), which the other functions discard
+#[allow(clippy::type_complexity)]
+pub fn tokenize_with_comment(
+    line: &str,
+) -> Result<(Vec<Token>, Vec<Span>, Option<String>), (String, Span)> {
+    let length = line.chars().count();
     let mut chars = line.chars().peekable();
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut comment = None;
 
-    while let Some(&ch) = chars.peek() {
-        match ch {
-            ' ' | '\t' | '\r' | '\n' => {
-                chars.next();
-            }
-            '#' => {
-                // Comment to end of line
-                for _c in chars.by_ref() {
-                    // discard comment
-                }
-            }
-            ':' => {
-                tokens.push(Token::Colon);
-                chars.next();
-            }
-            ',' => {
-                tokens.push(Token::Comma);
-                chars.next();
-            }
-            '(' => {
-                tokens.push(Token::OpenParen);
-                chars.next();
-            }
-            ')' => {
-                tokens.push(Token::CloseParen);
-                chars.next();
-            }
-            '+' => {
-                tokens.push(Token::Operator(OperatorOp::Plus));
-                chars.next();
-            }
-            '-' => {
-                tokens.push(Token::Operator(OperatorOp::Minus));
-                chars.next();
-            }
-            '*' => {
-                tokens.push(Token::Operator(OperatorOp::Multiply));
-                chars.next();
-            }
-            '/' => {
-                tokens.push(Token::Operator(OperatorOp::Divide));
+    loop {
+        // Skip whitespace and comments
+        match chars.peek() {
+            Some(' ' | '\t' | '\r' | '\n') => {
                 chars.next();
+                continue;
             }
-            '%' => {
-                tokens.push(Token::Operator(OperatorOp::Modulo));
-                chars.next();
+            Some('#') => {
+                comment =
+                    Some(chars.collect::<String>().trim_end().to_string());
+                break;
             }
-            '|' => {
-                tokens.push(Token::Operator(OperatorOp::BitwiseOr));
-                chars.next();
+            None => break,
+            Some(_) => {}
+        }
+
+        let start = length - chars.clone().count();
+        let token = next_token(&mut chars);
+        let end = length - chars.clone().count();
+        match token {
+            Ok(token) => {
+                tokens.push(token);
+                spans.push(Span { start, end });
             }
-            '&' => {
-                tokens.push(Token::Operator(OperatorOp::BitwiseAnd));
-                chars.next();
+            Err(message) => {
+                return Err((message, Span { start, end: end.max(start + 1) }));
             }
-            '^' => {
-                tokens.push(Token::Operator(OperatorOp::BitwiseXor));
+        }
+    }
+    Ok((tokens, spans, comment))
+}
+
+/// Reads the token that starts at the next (non-blank) character
+fn next_token(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<Token, String> {
+    let ch = *chars.peek().expect("next_token called at end of line");
+    match ch {
+        ':' => {
+            chars.next();
+            Ok(Token::Colon)
+        }
+        ',' => {
+            chars.next();
+            Ok(Token::Comma)
+        }
+        '(' => {
+            chars.next();
+            Ok(Token::OpenParen)
+        }
+        ')' => {
+            chars.next();
+            Ok(Token::CloseParen)
+        }
+        '+' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Plus))
+        }
+        '-' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Minus))
+        }
+        '*' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Multiply))
+        }
+        '/' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Divide))
+        }
+        '%' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Modulo))
+        }
+        '|' => {
+            chars.next();
+            if chars.peek() == Some(&'|') {
                 chars.next();
+                Ok(Token::Operator(OperatorOp::LogicalOr))
+            } else {
+                Ok(Token::Operator(OperatorOp::BitwiseOr))
             }
-            '~' => {
-                tokens.push(Token::Operator(OperatorOp::BitwiseNot));
+        }
+        '&' => {
+            chars.next();
+            if chars.peek() == Some(&'&') {
                 chars.next();
+                Ok(Token::Operator(OperatorOp::LogicalAnd))
+            } else {
+                Ok(Token::Operator(OperatorOp::BitwiseAnd))
             }
-            '<' => {
-                chars.next();
-                if chars.peek() == Some(&'<') {
+        }
+        '^' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::BitwiseXor))
+        }
+        '~' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::BitwiseNot))
+        }
+        '<' => {
+            chars.next();
+            match chars.peek() {
+                Some('<') => {
                     chars.next();
-                    tokens.push(Token::Operator(OperatorOp::LeftShift));
-                } else {
-                    return Err("Unexpected '<': shift operators must be two characters (use << or >>)".to_string());
+                    Ok(Token::Operator(OperatorOp::LeftShift))
                 }
-            }
-            '>' => {
-                chars.next();
-                if chars.peek() == Some(&'>') {
+                Some('=') => {
                     chars.next();
-                    tokens.push(Token::Operator(OperatorOp::RightShift));
-                } else {
-                    return Err("Unexpected '>': shift operators must be two characters (use << or >>)".to_string());
+                    Ok(Token::Operator(OperatorOp::LessEqual))
                 }
+                _ => Ok(Token::Operator(OperatorOp::Less)),
             }
-            '\'' => {
-                chars.next();
-                let ch =
-                    chars.next().ok_or("Unexpected end in char literal")?;
-                let c = if ch == '\\' {
-                    let esc = chars
-                        .next()
-                        .ok_or("Unexpected end in escape sequence")?;
-                    match esc {
-                        'n' => '\n',
-                        't' => '\t',
-                        'r' => '\r',
-                        '\\' => '\\',
-                        '\'' => '\'',
-                        '"' => '"',
-                        '0' => '\0',
-                        _ => {
-                            return Err(format!(
-                                "Unknown escape sequence \\{}",
-                                esc
-                            ));
-                        }
-                    }
-                } else {
-                    ch
-                };
-                if chars.next() != Some('\'') {
-                    return Err("Character literal contains multiple characters or is unclosed".to_string());
+        }
+        '>' => {
+            chars.next();
+            match chars.peek() {
+                Some('>') => {
+                    chars.next();
+                    Ok(Token::Operator(OperatorOp::RightShift))
+                }
+                Some('=') => {
+                    chars.next();
+                    Ok(Token::Operator(OperatorOp::GreaterEqual))
                 }
-                tokens.push(Token::Integer(c as i32));
+                _ => Ok(Token::Operator(OperatorOp::Greater)),
             }
-            '"' => {
+        }
+        '=' => {
+            chars.next();
+            if chars.peek() == Some(&'=') {
                 chars.next();
-                let s = parse_string_literal(&mut chars)?;
-                tokens.push(Token::StringLiteral(s));
+                Ok(Token::Operator(OperatorOp::Equal))
+            } else {
+                Err("Unexpected '=': use == to compare values".to_string())
             }
-            '.' => {
+        }
+        '!' => {
+            chars.next();
+            if chars.peek() == Some(&'=') {
                 chars.next();
-                if chars.peek().is_some()
-                    && chars.peek().unwrap().is_alphanumeric()
-                {
-                    let ident = parse_identifier(&mut chars)?;
-                    let dir = match ident.as_str() {
-                        "global" => DirectiveOp::Global,
-                        "globl" => DirectiveOp::Global,
-                        "equ" => DirectiveOp::Equ,
-                        "set" => DirectiveOp::Equ,
-                        "text" => DirectiveOp::Text,
-                        "data" => DirectiveOp::Data,
-                        "bss" => DirectiveOp::Bss,
-                        "space" => DirectiveOp::Space,
-                        "balign" => DirectiveOp::Balign,
-                        "string" => DirectiveOp::String,
-                        "asciz" => DirectiveOp::Asciz,
-                        "byte" => DirectiveOp::Byte,
-                        "2byte" => DirectiveOp::TwoByte,
-                        "4byte" => DirectiveOp::FourByte,
-                        _ => {
-                            return Err(format!(
-                                "Unknown directive .{}",
-                                ident
-                            ));
-                        }
-                    };
-                    tokens.push(Token::Directive(dir));
-                } else {
-                    tokens.push(Token::Dot);
-                }
+                Ok(Token::Operator(OperatorOp::NotEqual))
+            } else {
+                Ok(Token::Operator(OperatorOp::LogicalNot))
             }
-            '0'..='9' => {
-                let num = parse_number(&mut chars)?;
-                tokens.push(Token::Integer(num));
+        }
+        '?' => {
+            chars.next();
+            Ok(Token::Operator(OperatorOp::Question))
+        }
+        '\'' => {
+            chars.next();
+            let ch = chars.next().ok_or("Unexpected end in char literal")?;
+            let c = if ch == '\\' {
+                let esc =
+                    chars.next().ok_or("Unexpected end in escape sequence")?;
+                match esc {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '\\' => '\\',
+                    '\'' => '\'',
+                    '"' => '"',
+                    '0' => '\0',
+                    _ => {
+                        return Err(format!(
+                            "Unknown escape sequence \\{}",
+                            esc
+                        ));
+                    }
+                }
+            } else {
+                ch
+            };
+            if chars.next() != Some('\'') {
+                return Err("Character literal contains multiple characters or is unclosed".to_string());
             }
-            'a'..='z' | 'A'..='Z' | '_' | '$' => {
-                let ident = parse_identifier(&mut chars)?;
-                if let Some(reg) = parse_register(&ident) {
-                    tokens.push(Token::Register(reg));
-                } else {
-                    tokens.push(Token::Identifier(ident));
+            Ok(Token::Integer(c as i32))
+        }
+        '"' => {
+            chars.next();
+            let s = parse_string_literal(chars)?;
+            Ok(Token::StringLiteral(s))
+        }
+        '.' => {
+            chars.next();
+            if chars.peek().is_some() && chars.peek().unwrap().is_alphanumeric()
+            {
+                let ident = parse_identifier(chars)?;
+                // Assembler-local labels, as in `.Lloop`
+                if ident.starts_with('L') {
+                    return Ok(Token::Identifier(format!(".{}", ident)));
                 }
+                let dir = match ident.as_str() {
+                    "global" => DirectiveOp::Global,
+                    "globl" => DirectiveOp::Global,
+                    "equ" => DirectiveOp::Equ,
+                    "set" => DirectiveOp::Equ,
+                    "equiv" => DirectiveOp::Equiv,
+                    "weak" => DirectiveOp::Weak,
+                    "local" => DirectiveOp::Local,
+                    "comm" => DirectiveOp::Comm,
+                    "lcomm" => DirectiveOp::Lcomm,
+                    "text" => DirectiveOp::Text,
+                    "data" => DirectiveOp::Data,
+                    "bss" => DirectiveOp::Bss,
+                    "space" => DirectiveOp::Space,
+                    "balign" => DirectiveOp::Balign,
+                    "string" => DirectiveOp::String,
+                    "asciz" => DirectiveOp::Asciz,
+                    "byte" => DirectiveOp::Byte,
+                    "2byte" => DirectiveOp::TwoByte,
+                    "4byte" => DirectiveOp::FourByte,
+                    "option" => DirectiveOp::Option,
+                    "type" => DirectiveOp::Type,
+                    "size" => DirectiveOp::Size,
+                    _ => {
+                        return Err(format!("Unknown directive .{}", ident));
+                    }
+                };
+                Ok(Token::Directive(dir))
+            } else {
+                Ok(Token::Dot)
             }
-            _ => return Err(format!("Unexpected character '{}'", ch)),
         }
+        '0'..='9' => {
+            let num = parse_number(chars)?;
+            Ok(Token::Integer(num))
+        }
+        '@' => {
+            // Symbol types, as in `.type main, @function`
+            chars.next();
+            let ident = parse_identifier(chars)?;
+            Ok(Token::Identifier(format!("@{}", ident)))
+        }
+        'a'..='z' | 'A'..='Z' | '_' | '$' => {
+            let ident = parse_identifier(chars)?;
+            if let Some(reg) = parse_register(&ident) {
+                Ok(Token::Register(reg))
+            } else {
+                Ok(Token::Identifier(ident))
+            }
+        }
+        _ => Err(format!("Unexpected character '{}'", ch)),
     }
-    Ok(tokens)
 }
 
 fn parse_identifier(
@@ -284,6 +388,13 @@ fn parse_string_literal(
     Err("Unclosed string literal".to_string())
 }
 
+/// The ABI register names, which misspelled registers are matched against
+pub const REGISTER_NAMES: &[&str] = &[
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s0", "s1", "a0",
+    "a1", "a2", "a3", "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6",
+    "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
 fn parse_register(ident: &str) -> Option<Register> {
     match ident {
         "zero" | "x0" => Some(Register::X0),
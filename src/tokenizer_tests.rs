@@ -84,3 +84,64 @@ fn test_tokenize_comment() {
     let tokens = tokenize(line).unwrap();
     assert_eq!(tokens.len(), 6); // comment removed
 }
+
+#[test]
+fn test_tokenize_keeps_comment() {
+    let (tokens, _, comment) =
+        tokenize_with_comment(".string \"a # b\"  # the # rest  ").unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(comment.as_deref(), Some("# the # rest"));
+
+    let (tokens, _, comment) = tokenize_with_comment("  nop").unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(comment, None);
+}
+
+#[test]
+fn test_tokenize_spans() {
+    let line = "loop:\tlw a0, -8(sp)  # comment";
+    let (tokens, spans) = tokenize_with_spans(line).unwrap();
+    assert_eq!(tokens.len(), spans.len());
+    let text: Vec<String> = spans
+        .iter()
+        .map(|span| line.chars().skip(span.start).take(span.end - span.start))
+        .map(|chars| chars.collect())
+        .collect();
+    assert_eq!(text, ["loop", ":", "lw", "a0", ",", "-", "8", "(", "sp", ")"]);
+}
+
+#[test]
+fn test_tokenize_error_span() {
+    let (message, span) = tokenize_with_spans("li a0, 1 = 2").unwrap_err();
+    assert!(message.contains("Unexpected '='"));
+    assert_eq!(span, Span { start: 9, end: 10 });
+
+    let (_, span) = tokenize_with_spans("  .strng \"x\"").unwrap_err();
+    assert_eq!(span, Span { start: 2, end: 8 });
+}
+
+#[test]
+fn test_tokenize_comparison_and_logical_operators() {
+    let tokens = tokenize("< <= << > >= >> == != ! && & || | ? :").unwrap();
+    let op = Token::Operator;
+    assert_eq!(
+        tokens,
+        [
+            op(OperatorOp::Less),
+            op(OperatorOp::LessEqual),
+            op(OperatorOp::LeftShift),
+            op(OperatorOp::Greater),
+            op(OperatorOp::GreaterEqual),
+            op(OperatorOp::RightShift),
+            op(OperatorOp::Equal),
+            op(OperatorOp::NotEqual),
+            op(OperatorOp::LogicalNot),
+            op(OperatorOp::LogicalAnd),
+            op(OperatorOp::BitwiseAnd),
+            op(OperatorOp::LogicalOr),
+            op(OperatorOp::BitwiseOr),
+            op(OperatorOp::Question),
+            Token::Colon,
+        ]
+    );
+}
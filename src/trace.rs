@@ -4,26 +4,26 @@ use crate::Instruction;
 use crate::error::RiscletError;
 use crate::riscv::R;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct MemoryValue {
     pub address: u32,
     pub value: Vec<u8>,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct RegisterValue {
     pub register: usize,
     pub value: i32,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum SyscallInfo {
     Exit(i32),
     Write { fd: i32, buf_addr: u32, count: i32, data: Vec<u8> },
     Read { fd: i32, buf_addr: u32, count: i32, data: Vec<u8> },
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Effects {
     pub instruction: Rc<Instruction>,
 
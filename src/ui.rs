@@ -4,6 +4,7 @@ use std::fmt::{self, Write};
 use std::io::{self, Write as IoWrite};
 use std::mem::take;
 use std::rc::Rc;
+use std::time::Duration;
 
 use crossterm::{
     cursor::MoveTo,
@@ -14,8 +15,9 @@ use crossterm::{
 };
 
 use crate::config::Config;
-use crate::execution::{Instruction, Machine};
-use crate::riscv::{Op, R, RA, SP, ZERO, fields_to_string};
+use crate::execution::{Instruction, Machine, instruction_to_string};
+use crate::riscv::{Op, R, RA, SP, ZERO};
+use crate::simulator::Program;
 use crate::trace::Effects;
 
 macro_rules! serr {
@@ -24,6 +26,14 @@ macro_rules! serr {
     };
 }
 
+/// Asked between keystrokes whether to replace the program being debugged:
+/// gives `None` while it is current, and otherwise the new program or the
+/// reason it could not be built
+pub type Reload = Box<dyn FnMut() -> Option<Result<Program, String>>>;
+
+/// How long to wait for a key before asking whether to reload
+const RELOAD_INTERVAL: Duration = Duration::from_millis(250);
+
 pub struct Tui {
     machine: Machine,
     instructions: Vec<Rc<Instruction>>,
@@ -46,17 +56,21 @@ pub struct Tui {
     show_stack: bool,
     show_data: bool,
     show_help: bool,
+
+    reload: Option<Reload>,
+    reload_error: Option<String>,
 }
 
 impl Tui {
-    pub fn new(
-        machine: Machine,
-        instructions: Vec<Rc<Instruction>>,
-        addresses: HashMap<u32, usize>,
-        pseudo_addresses: HashMap<usize, usize>,
-        sequence: Vec<Effects>,
-        config: &Config,
-    ) -> Result<Self, String> {
+    pub fn new(program: Program, config: &Config) -> Result<Self, String> {
+        let Program {
+            machine,
+            instructions,
+            addresses,
+            pseudo_addresses,
+            sequence,
+        } = program;
+
         // make sure stdout is connected to a tty
         if !io::stdout().is_tty() {
             return Err("debug mode only works in an interactive terminal/tty"
@@ -91,20 +105,7 @@ impl Tui {
             Colors::new(Color::AnsiValue(139), black),
         ];
 
-        // create cycling color pairs for address symbols
-        let mut data_colors = Vec::new();
-        let mut address_symbols: Vec<u32> =
-            machine.address_symbols.keys().copied().collect();
-        address_symbols.sort_unstable();
-        for (i, &address) in address_symbols.iter().enumerate() {
-            let color = pastels[i % pastels.len()];
-            data_colors.push((address, color));
-        }
-
-        // if there is no data segment
-        if data_colors.is_empty() {
-            data_colors.push((0, normal_color));
-        }
+        let data_colors = symbol_colors(&machine, &pastels, normal_color);
 
         // setup over terminal
         serr!(crossterm::terminal::enable_raw_mode())?;
@@ -141,14 +142,33 @@ impl Tui {
             show_stack: true,
             show_data: true,
             show_help: false,
+
+            reload: None,
+            reload_error: None,
         })
     }
 
+    /// Watch for a new program to debug while waiting for keys
+    pub fn set_reload(&mut self, reload: Reload) {
+        self.reload = Some(reload);
+    }
+
     pub fn main_loop(&mut self) -> Result<(), String> {
         loop {
             // Draw the current state
             let source_height = self.draw()?;
-            if let Event::Key(key_event) = serr!(event::read())?
+
+            // Wait for a key, or for a reload that needs a redraw
+            let event = loop {
+                if self.reload.is_none() || serr!(event::poll(RELOAD_INTERVAL))?
+                {
+                    break Some(serr!(event::read())?);
+                }
+                if self.check_reload() {
+                    break None;
+                }
+            };
+            if let Some(Event::Key(key_event)) = event
                 && self.handle_key(key_event, source_height)?
             {
                 break;
@@ -157,6 +177,68 @@ impl Tui {
         Ok(())
     }
 
+    /// Ask whether there is a new program, and switch to it if there is.
+    /// Returns whether anything changed.
+    fn check_reload(&mut self) -> bool {
+        let Some(reload) = &mut self.reload else {
+            return false;
+        };
+        match reload() {
+            None => false,
+            Some(Ok(program)) => {
+                self.replace_program(program);
+                self.reload_error = None;
+                true
+            }
+            Some(Err(message)) => {
+                self.reload_error = Some(message);
+                true
+            }
+        }
+    }
+
+    /// Start over with a new program, keeping the cursor at the same offset
+    /// from the same label if the new program still has it
+    fn replace_program(&mut self, program: Program) {
+        let cursor_address = self.instructions[self.cursor_index].address;
+        let anchor = self
+            .machine
+            .address_symbols
+            .iter()
+            .filter(|&(&address, _)| address <= cursor_address)
+            .max_by_key(|&(&address, _)| address)
+            .map(|(&address, name)| (name.clone(), cursor_address - address));
+
+        let Program {
+            machine,
+            instructions,
+            addresses,
+            pseudo_addresses,
+            sequence,
+        } = program;
+        self.machine = machine;
+        self.instructions = instructions;
+        self.addresses = addresses;
+        self.pseudo_addresses = pseudo_addresses;
+        self.sequence = sequence;
+        self.sequence_index = 0;
+        self.data_colors =
+            symbol_colors(&self.machine, &self.pastels, self.normal_color);
+
+        let entry_point = self.machine.entry_point();
+        let anchored = anchor.and_then(|(name, offset)| {
+            let (&address, _) = self
+                .machine
+                .address_symbols
+                .iter()
+                .find(|&(_, symbol)| *symbol == name)?;
+            self.addresses.get(&(address + offset)).copied()
+        });
+        self.cursor_index = anchored
+            .or_else(|| self.addresses.get(&entry_point).copied())
+            .unwrap_or(0);
+    }
+
     fn handle_key(
         &mut self,
         key_event: KeyEvent,
@@ -166,6 +248,9 @@ impl Tui {
             _ if self.show_help => {
                 self.show_help = false;
             }
+            _ if self.reload_error.is_some() => {
+                self.reload_error = None;
+            }
 
             // cursor motion
             KeyCode::Up => {
@@ -218,29 +303,23 @@ impl Tui {
             }
 
             // stepping and jumping
-            KeyCode::Left => {
-                if self.sequence_index > 0 {
-                    self.sequence_index -= 1;
-                    self.machine
-                        .apply(&self.sequence[self.sequence_index], false);
-                    self.machine.set_most_recent_memory(
-                        &self.sequence,
-                        self.sequence_index,
-                    );
-                    self.set_cursor_to_current();
-                }
+            KeyCode::Left if self.sequence_index > 0 => {
+                self.sequence_index -= 1;
+                self.machine.apply(&self.sequence[self.sequence_index], false);
+                self.machine.set_most_recent_memory(
+                    &self.sequence,
+                    self.sequence_index,
+                );
+                self.set_cursor_to_current();
             }
-            KeyCode::Right => {
-                if self.sequence_index + 1 < self.sequence.len() {
-                    self.machine
-                        .apply(&self.sequence[self.sequence_index], true);
-                    self.sequence_index += 1;
-                    self.machine.set_most_recent_memory(
-                        &self.sequence,
-                        self.sequence_index,
-                    );
-                    self.set_cursor_to_current();
-                }
+            KeyCode::Right if self.sequence_index + 1 < self.sequence.len() => {
+                self.machine.apply(&self.sequence[self.sequence_index], true);
+                self.sequence_index += 1;
+                self.machine.set_most_recent_memory(
+                    &self.sequence,
+                    self.sequence_index,
+                );
+                self.set_cursor_to_current();
             }
             KeyCode::Home => {
                 // jump back to where the current function was entered
@@ -391,6 +470,10 @@ impl Tui {
                 self.config.show_addresses = !self.config.show_addresses;
             }
 
+            KeyCode::Char('l') => {
+                self.config.show_source = !self.config.show_source;
+            }
+
             KeyCode::Char('q') => {
                 return Ok(true);
             }
@@ -577,6 +660,30 @@ impl Tui {
             );
             self.render_help(&mut help);
             out = take(&mut help.out);
+        } else if let Some(message) = &self.reload_error {
+            let lines: Vec<&str> = message.lines().collect();
+            let longest = lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0);
+            let width = (longest as u16 + 4).max(20).min(size_x);
+            let height = (lines.len() as u16 + 2).min(size_y);
+            let mut errors = Pane::new(
+                out,
+                self.normal_color,
+                (size_x - width) / 2,
+                (size_y - height) / 2,
+                width,
+                height,
+                true,
+            );
+            errors.label("Reload failed");
+            for line in lines {
+                writeln!(errors, " {:<width$}", line, width = longest + 1)
+                    .unwrap();
+            }
+            out = take(&mut errors.out);
         }
 
         let mut stdout = io::stdout();
@@ -683,16 +790,15 @@ impl Tui {
                 None
             };
 
-            let mut line: Vec<char> = fields_to_string(
+            let mut line: Vec<char> = instruction_to_string(
                 &self.config,
+                inst,
                 if self.config.verbose_instructions {
                     &inst.verbose_fields
                 } else {
                     &inst.pseudo_fields
                 },
-                addr,
                 self.machine.global_pointer,
-                inst.length == 2,
                 arrow,
                 &self.machine.address_symbols,
             )
@@ -945,6 +1051,7 @@ impl Tui {
         writeln!(pane, " To toggle what is displayed:                                  ").unwrap();
         writeln!(pane, "   (r)egister pane, (o)utput pane, (s)tack pane, (d)ata pane   ").unwrap();
         writeln!(pane, "   (v)erbose mode, show (a)ddresses, use he(x)adecimal         ").unwrap();
+        writeln!(pane, "   source (l)ines in place of disassembly                      ").unwrap();
     }
 }
 
@@ -1162,6 +1269,28 @@ impl fmt::Write for Pane {
     }
 }
 
+/// Cycling colors for the memory at each symbol's address
+fn symbol_colors(
+    machine: &Machine,
+    pastels: &[Colors],
+    normal_color: Colors,
+) -> Vec<(u32, Colors)> {
+    let mut data_colors = Vec::new();
+    let mut address_symbols: Vec<u32> =
+        machine.address_symbols.keys().copied().collect();
+    address_symbols.sort_unstable();
+    for (i, &address) in address_symbols.iter().enumerate() {
+        let color = pastels[i % pastels.len()];
+        data_colors.push((address, color));
+    }
+
+    // if there is no data segment
+    if data_colors.is_empty() {
+        data_colors.push((0, normal_color));
+    }
+    data_colors
+}
+
 fn get_last_n_lines(data: &[u8], n: usize) -> Vec<String> {
     // split on newlines
     let chunks: Vec<_> = data.split(|&b| b == b'\n').collect();
@@ -0,0 +1,196 @@
+// watch.rs
+//
+// Watch mode: build and run a program again each time one of its input
+// files changes
+//
+// Files are polled for a new modification time, which needs no support
+// from the operating system. Each round starts from the configuration the
+// command line gave, so nothing one build decides (such as GP relaxation)
+// leaks into the next. Errors are reported and the watch goes on.
+
+use crate::assembler::{assemble_and_save, assemble_files_collecting_warnings};
+use crate::config::{Config, Mode};
+use crate::elf_loader::ElfInput;
+use crate::error::{Result, RiscletError};
+use crate::lint::{Warning, print_warnings};
+use crate::memory_map::MemoryMap;
+use crate::simulator::{
+    Program, debug, load_program, print_load_warnings, run_simulator,
+};
+use crossterm::{
+    cursor::MoveTo,
+    execute,
+    terminal::{Clear, ClearType},
+};
+use std::io;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// How often the input files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The modification times of the files a program is built from
+pub struct Watcher {
+    files: Vec<(String, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    /// Watch a configuration's input files (its sources and objects, or the
+    /// executable it runs) and its layout file
+    pub fn new(config: &Config) -> Watcher {
+        let mut names = config.input_files.clone();
+        if names.is_empty() {
+            names.push(config.executable.clone());
+        }
+        if let Some(memory_map) = &config.memory_map {
+            names.push(memory_map.file_name.clone());
+        }
+        let files = names
+            .into_iter()
+            .map(|name| {
+                let time = modified(&name);
+                (name, time)
+            })
+            .collect();
+        Watcher { files }
+    }
+
+    /// Whether any file has changed since the last call
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (name, time) in &mut self.files {
+            let now = modified(name);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Wait until a file changes
+    pub fn wait(&mut self) {
+        while !self.changed() {
+            sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// A file's modification time, or `None` while it cannot be read (as
+/// happens in the middle of some editors' saves)
+fn modified(name: &str) -> Option<SystemTime> {
+    std::fs::metadata(name).and_then(|m| m.modified()).ok()
+}
+
+/// Run the configured subcommand, then again after each change to its input
+/// files, until interrupted
+pub fn watch(config: &Config) -> Result<()> {
+    let mut watcher = Watcher::new(config);
+    if matches!(config.mode, Mode::Debug | Mode::Default) {
+        return watch_debugger(config, watcher);
+    }
+
+    loop {
+        clear_screen();
+        match run_once(config) {
+            Ok(Some(code)) => eprintln!("\n[exited with status {}]", code),
+            Ok(None) => eprintln!("[assembled {}]", config.output_file),
+            Err(e) => eprintln!("{}", e),
+        }
+        eprintln!("[watching for changes, Ctrl-C to stop]");
+        watcher.wait();
+    }
+}
+
+/// Build and run the program once, giving its exit status (or `None` when
+/// only assembling)
+fn run_once(config: &Config) -> Result<Option<i32>> {
+    let mut config = fresh_config(config)?;
+    if matches!(config.mode, Mode::Assemble | Mode::Link) {
+        assemble_and_save(&mut config)?;
+        return Ok(None);
+    }
+    let mut warnings = Vec::new();
+    let result = build(&mut config, &mut warnings);
+    print_warnings(&warnings);
+    run_simulator(&config, ElfInput::Bytes(&result?)).map(Some)
+}
+
+/// Debug the program, swapping in a new build whenever the files change
+///
+/// Until the first build succeeds there is nothing to debug, so its errors
+/// are printed as in the other modes.
+fn watch_debugger(config: &Config, mut watcher: Watcher) -> Result<()> {
+    let program = loop {
+        clear_screen();
+        let mut warnings = Vec::new();
+        let mut load_warnings = Vec::new();
+        let result = load(config, &mut warnings, &mut load_warnings);
+        print_warnings(&warnings);
+        print_load_warnings(&load_warnings);
+        match result {
+            Ok(program) => break program,
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("[watching for changes, Ctrl-C to stop]");
+                watcher.wait();
+            }
+        }
+    };
+
+    let base = config.clone();
+    let reload = Box::new(move || {
+        if !watcher.changed() {
+            return None;
+        }
+        // The debugger has the screen, so warnings are not shown
+        let result = load(&base, &mut Vec::new(), &mut Vec::new());
+        Some(result.map_err(|e| e.to_string()))
+    });
+    debug(program, config, Some(reload))
+}
+
+/// Build the program and run it to completion for the debugger, adding
+/// the assembler's warnings to `warnings` and the loader's to
+/// `load_warnings`
+fn load(
+    config: &Config,
+    warnings: &mut Vec<Warning>,
+    load_warnings: &mut Vec<String>,
+) -> Result<Program> {
+    let mut config = fresh_config(config)?;
+    let elf_bytes = build(&mut config, warnings)?;
+    load_program(&config, ElfInput::Bytes(&elf_bytes), load_warnings)
+}
+
+/// The configuration for one round, with the layout file read again
+fn fresh_config(config: &Config) -> Result<Config> {
+    let mut config = config.clone();
+    if let Some(memory_map) = &config.memory_map {
+        config.memory_map = Some(
+            MemoryMap::load(&memory_map.file_name).map_err(RiscletError::io)?,
+        );
+    }
+    Ok(config)
+}
+
+/// The ELF to run: the sources and objects assembled and linked, or the
+/// executable as it is now
+///
+/// Assembler warnings are added to `warnings` rather than printed.
+fn build(config: &mut Config, warnings: &mut Vec<Warning>) -> Result<Vec<u8>> {
+    if !config.input_files.is_empty() {
+        return assemble_files_collecting_warnings(config, warnings);
+    }
+    std::fs::read(&config.executable).map_err(|e| {
+        RiscletError::io(format!(
+            "failed to read file '{}': {}",
+            config.executable, e
+        ))
+    })
+}
+
+fn clear_screen() {
+    // Nothing useful can be done if the terminal will not clear
+    let _ = execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0));
+}
@@ -0,0 +1,54 @@
+// watch_tests.rs
+//
+// Tests for the file watcher behind --watch
+
+use crate::config::{Config, Mode};
+use crate::test_helpers::TempFile;
+use crate::watch::Watcher;
+
+fn config_for(files: &[&TempFile]) -> Config {
+    let mut config = Config::simulator_default(Mode::Run);
+    config.input_files = files.iter().map(|file| file.0.clone()).collect();
+    config
+}
+
+#[test]
+fn test_watcher_sees_each_change_once() {
+    let main = TempFile::with_contents("main.s", "        nop\n");
+    let lib = TempFile::with_contents("lib.s", "        nop\n");
+    let mut watcher = Watcher::new(&config_for(&[&main, &lib]));
+    assert!(!watcher.changed());
+
+    lib.touch(10);
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+
+    main.touch(20);
+    lib.touch(20);
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+}
+
+#[test]
+fn test_watcher_sees_files_removed_and_restored() {
+    let main = TempFile::with_contents("gone.s", "        nop\n");
+    let mut watcher = Watcher::new(&config_for(&[&main]));
+
+    std::fs::remove_file(&main.0).unwrap();
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+
+    std::fs::write(&main.0, "        ret\n").unwrap();
+    assert!(watcher.changed());
+}
+
+#[test]
+fn test_watcher_watches_executable_without_sources() {
+    let program = TempFile::with_contents("a.out", "        nop\n");
+    let mut config = config_for(&[]);
+    config.executable = program.0.clone();
+    let mut watcher = Watcher::new(&config);
+
+    program.touch(10);
+    assert!(watcher.changed());
+}